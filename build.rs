@@ -0,0 +1,12 @@
+fn main() {
+    // The service of grpc-serve, compiled with a bundled protoc so that none has to be installed.
+    #[cfg(feature = "grpc")]
+    {
+        let protoc = protoc_bin_vendored::protoc_bin_path().expect("No bundled protoc");
+        std::env::set_var("PROTOC", protoc);
+        tonic_build::configure()
+            .build_client(false)
+            .compile_protos(&["proto/flv_dump.proto"], &["proto"])
+            .expect("Failed to compile proto/flv_dump.proto");
+    }
+}
@@ -0,0 +1,218 @@
+use crate::bits::{BitReader, BitWriter};
+use crate::Exception;
+use bytes::Bytes;
+use std::convert::TryFrom;
+
+const SAMPLING_FREQUENCIES: [u32; 13] = [
+    96000, 88200, 64000, 48000, 44100, 32000, 24000, 22050, 16000, 12000, 11025, 8000, 7350,
+];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AacPacketType {
+    SequenceHeader = 0,
+    Raw = 1,
+}
+
+impl TryFrom<u8> for AacPacketType {
+    type Error = Exception;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        use AacPacketType::*;
+        Ok(match value {
+            0 => SequenceHeader,
+            1 => Raw,
+            n => return Err(format!("Invalid AAC packet type: {}", n).into()),
+        })
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct AudioSpecificConfig {
+    pub object_type: u8,
+    /// sampling frequency of the core coder
+    pub sampling_frequency: u32,
+    /// output sampling frequency of SBR (HE-AAC), usually twice the core one
+    pub extension_sampling_frequency: Option<u32>,
+    pub channel_configuration: u8,
+    pub frame_length: u32,
+}
+
+impl TryFrom<&[u8]> for AudioSpecificConfig {
+    type Error = Exception;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        let mut reader = BitReader::new(value);
+
+        let object_type = read_object_type(&mut reader)?;
+        let sampling_frequency = read_sampling_frequency(&mut reader)?;
+        let channel_configuration = reader.read_bits(4)? as u8;
+
+        // SBR / PS: the core coder is described after the extension sampling frequency.
+        let (extension_sampling_frequency, core_object_type) =
+            if object_type == 5 || object_type == 29 {
+                let extension = read_sampling_frequency(&mut reader)?;
+                (Some(extension), read_object_type(&mut reader)?)
+            } else {
+                (None, object_type)
+            };
+
+        // GASpecificConfig: frameLengthFlag
+        let frame_length = match core_object_type {
+            1 | 2 | 3 | 4 | 6 | 7 | 17 | 19 | 20 | 21 | 22 | 23 if reader.read_bit()? => 960,
+            _ => 1024,
+        };
+
+        Ok(Self {
+            object_type,
+            sampling_frequency,
+            extension_sampling_frequency,
+            channel_configuration,
+            frame_length,
+        })
+    }
+}
+
+/// Name of an audio object type, e.g. 2 is AAC LC and 5 is HE-AAC.
+pub fn object_type_name(object_type: u8) -> Option<&'static str> {
+    Some(match object_type {
+        1 => "AAC Main",
+        2 => "AAC LC",
+        3 => "AAC SSR",
+        4 => "AAC LTP",
+        5 => "HE-AAC",
+        6 => "AAC Scalable",
+        23 => "AAC LD",
+        29 => "HE-AAC v2",
+        39 => "AAC ELD",
+        _ => return None,
+    })
+}
+
+fn read_object_type(reader: &mut BitReader) -> Result<u8, Exception> {
+    Ok(match reader.read_bits(5)? as u8 {
+        31 => 32 + reader.read_bits(6)? as u8,
+        n => n,
+    })
+}
+
+fn read_sampling_frequency(reader: &mut BitReader) -> Result<u32, Exception> {
+    match reader.read_bits(4)? as usize {
+        15 => reader.read_bits(24),
+        n => SAMPLING_FREQUENCIES
+            .get(n)
+            .copied()
+            .ok_or_else(|| format!("Invalid sampling frequency index: {}", n).into()),
+    }
+}
+
+// Syntactic elements of a raw_data_block, ISO/IEC 14496-3 Table 4.3.
+const ID_SCE: u32 = 0;
+const ID_CPE: u32 = 1;
+const ID_LFE: u32 = 3;
+const ID_END: u32 = 7;
+
+impl AudioSpecificConfig {
+    /// Duration of one access unit in milliseconds.
+    pub fn frame_duration(&self) -> f64 {
+        self.frame_length as f64 * 1000.0 / self.sampling_frequency as f64
+    }
+
+    /// Builds a raw_data_block that decodes to silence on every channel of this configuration.
+    pub fn silent_frame(&self) -> Result<Bytes, Exception> {
+        match self.object_type {
+            1..=4 | 5 | 29 => {}
+            n => return Err(format!("Unsupported AAC object type: {}", n).into()),
+        }
+
+        let elements: &[u32] = match self.channel_configuration {
+            1 => &[ID_SCE],
+            2 => &[ID_CPE],
+            3 => &[ID_SCE, ID_CPE],
+            4 => &[ID_SCE, ID_CPE, ID_SCE],
+            5 => &[ID_SCE, ID_CPE, ID_CPE],
+            6 => &[ID_SCE, ID_CPE, ID_CPE, ID_LFE],
+            7 => &[ID_SCE, ID_CPE, ID_CPE, ID_CPE, ID_LFE],
+            n => return Err(format!("Unsupported AAC channel configuration: {}", n).into()),
+        };
+
+        let mut writer = BitWriter::default();
+        let mut instance_tags = [0u32; 4];
+        for &element in elements {
+            writer.write_bits(element, 3);
+            writer.write_bits(instance_tags[element as usize], 4);
+            instance_tags[element as usize] += 1;
+            if element == ID_CPE {
+                // common_window
+                writer.write_bits(0, 1);
+                write_empty_channel_stream(&mut writer);
+            }
+            write_empty_channel_stream(&mut writer);
+        }
+        writer.write_bits(ID_END, 3);
+
+        Ok(writer.into_bytes().into())
+    }
+}
+
+// An individual_channel_stream with max_sfb = 0, i.e. no spectral data at all.
+fn write_empty_channel_stream(writer: &mut BitWriter) {
+    // global_gain
+    writer.write_bits(0, 8);
+    // ics_info: ics_reserved_bit, window_sequence (ONLY_LONG), window_shape, max_sfb, predictor_data_present
+    writer.write_bits(0, 1 + 2 + 1 + 6 + 1);
+    // pulse_data_present, tns_data_present, gain_control_data_present
+    writer.write_bits(0, 3);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // object type, sampling frequency, extension sampling frequency, channels, frame length
+    fn fields(bytes: &[u8]) -> (u8, u32, Option<u32>, u8, u32) {
+        let config = AudioSpecificConfig::try_from(bytes).unwrap();
+        (
+            config.object_type,
+            config.sampling_frequency,
+            config.extension_sampling_frequency,
+            config.channel_configuration,
+            config.frame_length,
+        )
+    }
+
+    #[test]
+    fn lc() {
+        assert_eq!(fields(&[0x12, 0x10]), (2, 44100, None, 2, 1024));
+        // frameLengthFlag set
+        assert_eq!(fields(&[0x12, 0x14]), (2, 44100, None, 2, 960));
+        // explicit sampling frequency of 96000 Hz, mono
+        assert_eq!(
+            fields(&[0x17, 0x80, 0xbb, 0x80, 0x08]),
+            (2, 96000, None, 1, 1024)
+        );
+        assert_eq!(object_type_name(2), Some("AAC LC"));
+    }
+
+    #[test]
+    fn he_aac_explicit_sbr() {
+        // SBR at 48000 Hz over an AAC LC core at 24000 Hz
+        assert_eq!(
+            fields(&[0x2b, 0x11, 0x88]),
+            (5, 24000, Some(48000), 2, 1024)
+        );
+        assert_eq!(object_type_name(5), Some("HE-AAC"));
+    }
+
+    #[test]
+    fn escaped_object_type() {
+        // object type 31 escapes to 32 + 10, USAC
+        assert_eq!(fields(&[0xf9, 0x46, 0x40]), (42, 48000, None, 2, 1024));
+    }
+
+    #[test]
+    fn invalid() {
+        let error = AudioSpecificConfig::try_from(&[0x16, 0x90][..]).unwrap_err();
+        assert_eq!(error.to_string(), "Invalid sampling frequency index: 13");
+        assert!(AudioSpecificConfig::try_from(&[0x12][..]).is_err());
+    }
+}
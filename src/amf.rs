@@ -0,0 +1,723 @@
+use crate::gbk;
+use crate::reader::ScriptData;
+use crate::time;
+use crate::Exception;
+use bytes::{Buf, BufMut, BytesMut};
+use serde_json::{Number, Value};
+use std::convert::TryFrom;
+
+// AMF0 type markers
+const NUMBER: u8 = 0x00;
+const BOOLEAN: u8 = 0x01;
+const STRING: u8 = 0x02;
+const OBJECT: u8 = 0x03;
+const NULL: u8 = 0x05;
+const UNDEFINED: u8 = 0x06;
+const REFERENCE: u8 = 0x07;
+const ECMA_ARRAY: u8 = 0x08;
+const OBJECT_END: u8 = 0x09;
+const STRICT_ARRAY: u8 = 0x0a;
+const DATE: u8 = 0x0b;
+const LONG_STRING: u8 = 0x0c;
+const UNSUPPORTED: u8 = 0x0d;
+const XML_DOCUMENT: u8 = 0x0f;
+const TYPED_OBJECT: u8 = 0x10;
+const AVMPLUS_OBJECT: u8 = 0x11;
+
+// Doubles represent every integer up to 2^53 exactly.
+const MAX_SAFE_INTEGER: f64 = 9007199254740992.0;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum AmfValue {
+    Number(f64),
+    Boolean(bool),
+    String(String),
+    Object(Vec<(String, AmfValue)>),
+    Null,
+    Undefined,
+    EcmaArray(Vec<(String, AmfValue)>),
+    StrictArray(Vec<AmfValue>),
+    /// milliseconds since the Unix epoch, the time zone in minutes is reserved and should be 0
+    Date {
+        millis: f64,
+        timezone: i16,
+    },
+    Xml(String),
+    /// an object with the name of its class
+    TypedObject(String, Vec<(String, AmfValue)>),
+    /// the index of an earlier object or array that can not be resolved, because it does not
+    /// exist or contains the reference
+    Reference(u16),
+}
+
+/// The encoding to fall back to for AMF strings that are not UTF-8, as written by some legacy
+/// encoders.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum LegacyEncoding {
+    /// GBK (code page 936), from older Chinese encoders
+    Gbk,
+}
+
+/// An AMF string that is not well-formed UTF-8.
+#[derive(Debug, Clone, PartialEq)]
+pub struct InvalidString {
+    /// of the string's bytes in the script data
+    pub offset: usize,
+    /// of the first invalid byte in the string
+    pub position: usize,
+    /// the invalid sequence
+    pub bytes: Vec<u8>,
+    /// as decoded
+    pub text: String,
+}
+
+impl std::fmt::Display for InvalidString {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let bytes: Vec<String> = self.bytes.iter().map(|b| format!("{:02x}", b)).collect();
+        write!(
+            f,
+            "AMF string {:?} at byte {} is not valid UTF-8: {} at byte {} of the string",
+            self.text,
+            self.offset,
+            bytes.join(" "),
+            self.position
+        )
+    }
+}
+
+/// Where and why AMF0 data stopped decoding.
+#[derive(Debug, Clone, PartialEq)]
+pub struct MalformedAmf {
+    /// in the script data
+    pub position: usize,
+    pub message: String,
+}
+
+impl std::fmt::Display for MalformedAmf {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{} at byte {}", self.message, self.position)
+    }
+}
+
+/// The values of AMF0 data as far as they could be decoded. A value that breaks off, e.g. an
+/// ECMA array in truncated data, is kept with the properties before the break.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Decoded {
+    pub values: Vec<AmfValue>,
+    /// strings that are not UTF-8
+    pub invalid_strings: Vec<InvalidString>,
+    pub error: Option<MalformedAmf>,
+}
+
+// Decodes the values of one script data: how strings are decoded, the complex values references
+// point to, and where decoding stopped.
+struct Decoder {
+    len: usize,
+    legacy: Option<LegacyEncoding>,
+    invalid: Vec<InvalidString>,
+    // objects, typed objects, ECMA and strict arrays in the order they start, None until complete
+    objects: Vec<Option<AmfValue>>,
+    error: Option<MalformedAmf>,
+}
+
+impl Decoder {
+    fn new(data: &[u8], legacy: Option<LegacyEncoding>) -> Self {
+        Self {
+            len: data.len(),
+            legacy,
+            invalid: vec![],
+            objects: vec![],
+            error: None,
+        }
+    }
+
+    fn position(&self, src: &[u8]) -> usize {
+        self.len - src.len()
+    }
+
+    fn fail<T>(&mut self, position: usize, message: impl ToString) -> Option<T> {
+        self.error.get_or_insert(MalformedAmf {
+            position,
+            message: message.to_string(),
+        });
+        None
+    }
+
+    fn read<T>(
+        &mut self,
+        src: &mut &[u8],
+        read: fn(&mut &[u8]) -> Result<T, Exception>,
+    ) -> Option<T> {
+        let position = self.position(src);
+        match read(src) {
+            Ok(value) => Some(value),
+            Err(e) => self.fail(position, e),
+        }
+    }
+
+    fn string(&mut self, src: &mut &[u8], len: usize) -> Option<String> {
+        if src.remaining() < len {
+            return self.fail(self.len, "Unexpected end of AMF0 data");
+        }
+        let bytes = &src[..len];
+        let s = match std::str::from_utf8(bytes) {
+            Ok(s) => s.to_owned(),
+            Err(e) => {
+                let text = match self.legacy {
+                    Some(LegacyEncoding::Gbk) => gbk::decode(bytes),
+                    None => String::from_utf8_lossy(bytes).into_owned(),
+                };
+                let end = e.valid_up_to() + e.error_len().unwrap_or(len - e.valid_up_to());
+                self.invalid.push(InvalidString {
+                    offset: self.position(src),
+                    position: e.valid_up_to(),
+                    bytes: bytes[e.valid_up_to()..end].to_vec(),
+                    text: text.clone(),
+                });
+                text
+            }
+        };
+        src.advance(len);
+        Some(s)
+    }
+
+    fn short_string(&mut self, src: &mut &[u8]) -> Option<String> {
+        let len = self.read(src, read_u16)?;
+        self.string(src, len as usize)
+    }
+
+    fn long_string(&mut self, src: &mut &[u8]) -> Option<String> {
+        let len = self.read(src, read_u32)?;
+        self.string(src, len as usize)
+    }
+
+    // Properties up to the object end, those before an error if there is one.
+    fn properties(&mut self, src: &mut &[u8]) -> Vec<(String, AmfValue)> {
+        let mut properties = Vec::new();
+        while self.error.is_none() {
+            let key = match self.short_string(src) {
+                Some(key) => key,
+                None => break,
+            };
+            if key.is_empty() && src.first() == Some(&OBJECT_END) {
+                src.advance(1);
+                break;
+            }
+            if let Some(value) = self.value(src) {
+                properties.push((key, value));
+            }
+        }
+        properties
+    }
+
+    // Decodes a complex value and keeps it for the references that follow it.
+    fn complex(
+        &mut self,
+        src: &mut &[u8],
+        decode: fn(&mut Self, &mut &[u8]) -> AmfValue,
+    ) -> AmfValue {
+        let index = self.objects.len();
+        self.objects.push(None);
+        let value = decode(self, src);
+        if self.error.is_none() {
+            self.objects[index] = Some(value.clone());
+        }
+        value
+    }
+
+    // None if nothing of the value could be decoded, check `error` for whether all of it was.
+    fn value(&mut self, src: &mut &[u8]) -> Option<AmfValue> {
+        let position = self.position(src);
+        Some(match self.read(src, read_u8)? {
+            NUMBER => AmfValue::Number(f64::from_bits(self.read(src, read_u64)?)),
+            BOOLEAN => AmfValue::Boolean(self.read(src, read_u8)? != 0),
+            STRING => AmfValue::String(self.short_string(src)?),
+            OBJECT => self.complex(src, |d, src| AmfValue::Object(d.properties(src))),
+            NULL => AmfValue::Null,
+            UNDEFINED | UNSUPPORTED => AmfValue::Undefined,
+            REFERENCE => {
+                let index = self.read(src, read_u16)?;
+                match self.objects.get(index as usize) {
+                    Some(Some(value)) => value.clone(),
+                    // circular, or to an object that does not exist
+                    _ => AmfValue::Reference(index),
+                }
+            }
+            ECMA_ARRAY => self.complex(src, |d, src| {
+                // The associative count is only a hint, the properties end with an object end.
+                if d.read(src, read_u32).is_none() {
+                    return AmfValue::EcmaArray(vec![]);
+                }
+                AmfValue::EcmaArray(d.properties(src))
+            }),
+            STRICT_ARRAY => self.complex(src, |d, src| {
+                let count = d.read(src, read_u32).unwrap_or(0);
+                let mut items = Vec::new();
+                for _ in 0..count {
+                    match d.value(src) {
+                        Some(item) => items.push(item),
+                        None => break,
+                    }
+                    if d.error.is_some() {
+                        break;
+                    }
+                }
+                AmfValue::StrictArray(items)
+            }),
+            DATE => {
+                let millis = f64::from_bits(self.read(src, read_u64)?);
+                let timezone = self.read(src, read_u16)? as i16;
+                AmfValue::Date { millis, timezone }
+            }
+            LONG_STRING => AmfValue::String(self.long_string(src)?),
+            XML_DOCUMENT => AmfValue::Xml(self.long_string(src)?),
+            TYPED_OBJECT => self.complex(src, |d, src| match d.short_string(src) {
+                Some(class) => AmfValue::TypedObject(class, d.properties(src)),
+                None => AmfValue::TypedObject(String::new(), vec![]),
+            }),
+            AVMPLUS_OBJECT => return self.fail(position, "AMF3 data is not supported"),
+            n => return self.fail(position, format!("Unsupported AMF0 type marker: {}", n)),
+        })
+    }
+}
+
+impl AmfValue {
+    /// Decodes every value in `data`, failing if any of it does not decode.
+    pub fn decode_all(data: &[u8]) -> Result<Vec<AmfValue>, Exception> {
+        let decoded = Self::decode_lenient(data, None);
+        match decoded.error {
+            Some(error) => Err(error.to_string().into()),
+            None => Ok(decoded.values),
+        }
+    }
+
+    /// Decodes the values in `data` up to the first error, decoding strings that are not UTF-8
+    /// with the legacy encoding if given.
+    pub fn decode_lenient(mut data: &[u8], legacy: Option<LegacyEncoding>) -> Decoded {
+        let mut decoder = Decoder::new(data, legacy);
+        let mut values = Vec::new();
+        while data.has_remaining() && decoder.error.is_none() {
+            if let Some(value) = decoder.value(&mut data) {
+                values.push(value);
+            }
+        }
+        Decoded {
+            values,
+            invalid_strings: decoder.invalid,
+            error: decoder.error,
+        }
+    }
+
+    pub fn decode(src: &mut &[u8]) -> Result<AmfValue, Exception> {
+        let mut decoder = Decoder::new(src, None);
+        match (decoder.value(src), decoder.error) {
+            (Some(value), None) => Ok(value),
+            (_, error) => Err(error
+                .map_or("No AMF0 value".into(), |e| e.to_string())
+                .into()),
+        }
+    }
+
+    pub fn encode(&self, dst: &mut BytesMut) {
+        match self {
+            AmfValue::Number(n) => {
+                dst.put_u8(NUMBER);
+                dst.put_f64(*n);
+            }
+            AmfValue::Boolean(b) => {
+                dst.put_u8(BOOLEAN);
+                dst.put_u8(*b as u8);
+            }
+            AmfValue::String(s) if s.len() > u16::MAX as usize => {
+                dst.put_u8(LONG_STRING);
+                write_long_string(dst, s);
+            }
+            AmfValue::String(s) => {
+                dst.put_u8(STRING);
+                write_string(dst, s);
+            }
+            AmfValue::Object(properties) => {
+                dst.put_u8(OBJECT);
+                write_properties(dst, properties);
+            }
+            AmfValue::Null => dst.put_u8(NULL),
+            AmfValue::Undefined => dst.put_u8(UNDEFINED),
+            AmfValue::EcmaArray(properties) => {
+                dst.put_u8(ECMA_ARRAY);
+                dst.put_u32(properties.len() as u32);
+                write_properties(dst, properties);
+            }
+            AmfValue::StrictArray(items) => {
+                dst.put_u8(STRICT_ARRAY);
+                dst.put_u32(items.len() as u32);
+                for item in items {
+                    item.encode(dst);
+                }
+            }
+            AmfValue::Date { millis, timezone } => {
+                dst.put_u8(DATE);
+                dst.put_f64(*millis);
+                dst.put_i16(*timezone);
+            }
+            AmfValue::Xml(s) => {
+                dst.put_u8(XML_DOCUMENT);
+                write_long_string(dst, s);
+            }
+            AmfValue::TypedObject(class, properties) => {
+                dst.put_u8(TYPED_OBJECT);
+                write_string(dst, class);
+                write_properties(dst, properties);
+            }
+            AmfValue::Reference(index) => {
+                dst.put_u8(REFERENCE);
+                dst.put_u16(*index);
+            }
+        }
+    }
+
+    pub fn as_number(&self) -> Option<f64> {
+        match self {
+            AmfValue::Number(n) => Some(*n),
+            _ => None,
+        }
+    }
+
+    pub fn as_bool(&self) -> Option<bool> {
+        match self {
+            AmfValue::Boolean(b) => Some(*b),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> Option<&str> {
+        match self {
+            AmfValue::String(s) => Some(s),
+            _ => None,
+        }
+    }
+
+    /// Looks up a property of an object, typed object or ECMA array.
+    pub fn property(&self, name: &str) -> Option<&AmfValue> {
+        match self {
+            AmfValue::Object(properties)
+            | AmfValue::EcmaArray(properties)
+            | AmfValue::TypedObject(_, properties) => properties
+                .iter()
+                .find(|(key, _)| key == name)
+                .map(|(_, value)| value),
+            _ => None,
+        }
+    }
+
+    /// Looks up a nested value by a dotted path of property names and strict array indexes,
+    /// e.g. `keyframes.times.0`.
+    pub fn get(&self, path: &str) -> Option<&AmfValue> {
+        path.split('.').try_fold(self, |value, key| match value {
+            AmfValue::StrictArray(items) => items.get(key.parse::<usize>().ok()?),
+            _ => value.property(key),
+        })
+    }
+}
+
+/// Objects, typed objects and ECMA arrays all become JSON objects, null, undefined and unresolved
+/// references become null, dates become UTC time strings and XML documents strings. Integral
+/// numbers are written without a fraction, those JSON can not represent (NaN and infinities)
+/// become null.
+impl From<AmfValue> for Value {
+    fn from(value: AmfValue) -> Self {
+        match value {
+            AmfValue::Number(n) if n.fract() == 0.0 && n.abs() < MAX_SAFE_INTEGER => {
+                Value::Number((n as i64).into())
+            }
+            AmfValue::Number(n) => Number::from_f64(n).map_or(Value::Null, Value::Number),
+            AmfValue::Boolean(b) => Value::Bool(b),
+            AmfValue::String(s) | AmfValue::Xml(s) => Value::String(s),
+            AmfValue::Object(properties)
+            | AmfValue::EcmaArray(properties)
+            | AmfValue::TypedObject(_, properties) => Value::Object(
+                properties
+                    .into_iter()
+                    .map(|(key, value)| (key, value.into()))
+                    .collect(),
+            ),
+            AmfValue::Date { millis, .. } if millis.is_finite() => {
+                Value::String(time::format_millis_utc(millis as i64))
+            }
+            AmfValue::Null
+            | AmfValue::Undefined
+            | AmfValue::Reference(_)
+            | AmfValue::Date { .. } => Value::Null,
+            AmfValue::StrictArray(items) => {
+                Value::Array(items.into_iter().map(Value::from).collect())
+            }
+        }
+    }
+}
+
+/// JSON objects become AMF objects, wrap the top level in an ECMA array where one is expected,
+/// e.g. for onMetaData. Integers that do not fit a double exactly are rejected.
+impl TryFrom<Value> for AmfValue {
+    type Error = Exception;
+
+    fn try_from(value: Value) -> Result<Self, Self::Error> {
+        Ok(match value {
+            Value::Null => AmfValue::Null,
+            Value::Bool(b) => AmfValue::Boolean(b),
+            Value::Number(n) => {
+                let f = n.as_f64().ok_or("Number is not representable")?;
+                let exact = match (n.as_i64(), n.as_u64()) {
+                    (Some(i), _) => f as i64 == i && f.abs() < 2f64.powi(63),
+                    (_, Some(u)) => f as u64 == u && f < 2f64.powi(64),
+                    _ => true,
+                };
+                if !exact {
+                    return Err(format!("{} can not be represented as an AMF0 number", n).into());
+                }
+                AmfValue::Number(f)
+            }
+            Value::String(s) => AmfValue::String(s),
+            Value::Array(items) => AmfValue::StrictArray(
+                items
+                    .into_iter()
+                    .map(AmfValue::try_from)
+                    .collect::<Result<_, _>>()?,
+            ),
+            Value::Object(properties) => AmfValue::Object(
+                properties
+                    .into_iter()
+                    .map(|(key, value)| Ok((key, AmfValue::try_from(value)?)))
+                    .collect::<Result<_, Exception>>()?,
+            ),
+        })
+    }
+}
+
+/// The common onMetaData properties. Any of them may be missing or of an unexpected type.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct Metadata {
+    /// seconds
+    pub duration: Option<f64>,
+    pub width: Option<f64>,
+    pub height: Option<f64>,
+    /// `framerate`, or `videoframerate` as written by some encoders
+    pub frame_rate: Option<f64>,
+    /// kbps
+    pub video_data_rate: Option<f64>,
+    pub video_codec_id: Option<f64>,
+    /// kbps
+    pub audio_data_rate: Option<f64>,
+    pub audio_sample_rate: Option<f64>,
+    pub audio_sample_size: Option<f64>,
+    pub audio_codec_id: Option<f64>,
+    pub stereo: Option<bool>,
+    pub file_size: Option<f64>,
+    pub encoder: Option<String>,
+    /// `keyframes.times` and `keyframes.filepositions`, as added by flvmeta or yamdi, are present
+    pub keyframe_index: bool,
+}
+
+impl From<&AmfValue> for Metadata {
+    fn from(properties: &AmfValue) -> Self {
+        let number = |name| properties.property(name).and_then(AmfValue::as_number);
+        Self {
+            duration: number("duration"),
+            width: number("width"),
+            height: number("height"),
+            frame_rate: number("framerate").or_else(|| number("videoframerate")),
+            video_data_rate: number("videodatarate"),
+            video_codec_id: number("videocodecid"),
+            audio_data_rate: number("audiodatarate"),
+            audio_sample_rate: number("audiosamplerate"),
+            audio_sample_size: number("audiosamplesize"),
+            audio_codec_id: number("audiocodecid"),
+            stereo: properties.property("stereo").and_then(AmfValue::as_bool),
+            file_size: number("filesize"),
+            encoder: properties
+                .property("encoder")
+                .and_then(AmfValue::as_str)
+                .map(str::to_owned),
+            keyframe_index: ["keyframes.times", "keyframes.filepositions"]
+                .iter()
+                .all(|path| matches!(properties.get(path), Some(AmfValue::StrictArray(_)))),
+        }
+    }
+}
+
+impl ScriptData {
+    /// Decodes the AMF0 values of the script tag as far as they are well-formed, with the
+    /// strings that are not UTF-8 and where decoding stopped.
+    pub fn decode(&self) -> Decoded {
+        AmfValue::decode_lenient(&self.raw, self.legacy_encoding)
+    }
+
+    /// The AMF0 values of the script tag, e.g. `onMetaData` followed by an ECMA array, up to the
+    /// first malformed byte.
+    pub fn values(&self) -> Vec<AmfValue> {
+        self.decode().values
+    }
+
+    /// The name the script tag starts with, e.g. `onMetaData` or `onCuePoint`.
+    pub fn name(&self) -> Option<String> {
+        match self.values().into_iter().next()? {
+            AmfValue::String(name) => Some(name),
+            _ => None,
+        }
+    }
+
+    /// Looks up a value in the data following the name, see `AmfValue::get`. The script data is
+    /// decoded on every call, hold on to `values()` for repeated lookups.
+    pub fn get(&self, path: &str) -> Option<AmfValue> {
+        let values = self.values();
+        values.get(1)?.get(path).cloned()
+    }
+
+    /// The properties of an `onMetaData` tag.
+    pub fn metadata(&self) -> Option<AmfValue> {
+        let mut values = self.values().into_iter();
+        match values.next()?.as_str() {
+            Some("onMetaData") => values.next(),
+            _ => None,
+        }
+    }
+
+    /// The common properties of an `onMetaData` tag.
+    pub fn as_metadata(&self) -> Option<Metadata> {
+        self.metadata().as_ref().map(Metadata::from)
+    }
+}
+
+fn ensure(src: &[u8], len: usize) -> Result<(), Exception> {
+    if src.remaining() < len {
+        Err("Unexpected end of AMF0 data".into())
+    } else {
+        Ok(())
+    }
+}
+
+fn read_u8(src: &mut &[u8]) -> Result<u8, Exception> {
+    ensure(src, 1)?;
+    Ok(src.get_u8())
+}
+
+fn read_u16(src: &mut &[u8]) -> Result<u16, Exception> {
+    ensure(src, 2)?;
+    Ok(src.get_u16())
+}
+
+fn read_u32(src: &mut &[u8]) -> Result<u32, Exception> {
+    ensure(src, 4)?;
+    Ok(src.get_u32())
+}
+
+fn read_u64(src: &mut &[u8]) -> Result<u64, Exception> {
+    ensure(src, 8)?;
+    Ok(src.get_u64())
+}
+
+/// Reads an AMF0 string without its type marker: the UI16 length and the bytes.
+pub fn read_string(src: &mut &[u8]) -> Result<String, Exception> {
+    let mut decoder = Decoder::new(src, None);
+    match decoder.short_string(src) {
+        Some(s) => Ok(s),
+        None => Err(decoder.error.map_or(String::new(), |e| e.message).into()),
+    }
+}
+
+/// Writes an AMF0 string without its type marker, as in object keys and FLV filter names.
+pub fn write_string(dst: &mut BytesMut, s: &str) {
+    dst.put_u16(s.len() as u16);
+    dst.put_slice(s.as_bytes());
+}
+
+fn write_long_string(dst: &mut BytesMut, s: &str) {
+    dst.put_u32(s.len() as u32);
+    dst.put_slice(s.as_bytes());
+}
+
+fn write_properties(dst: &mut BytesMut, properties: &[(String, AmfValue)]) {
+    for (key, value) in properties {
+        write_string(dst, key);
+        value.encode(dst);
+    }
+    dst.put_u16(0);
+    dst.put_u8(OBJECT_END);
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn encode(value: &AmfValue) -> Vec<u8> {
+        let mut dst = BytesMut::new();
+        value.encode(&mut dst);
+        dst.to_vec()
+    }
+
+    #[test]
+    fn encode_metadata() {
+        let name = AmfValue::String("onMetaData".into());
+        assert_eq!(encode(&name), b"\x02\x00\x0aonMetaData");
+
+        let metadata = AmfValue::EcmaArray(vec![
+            ("duration".into(), AmfValue::Number(42.5)),
+            ("stereo".into(), AmfValue::Boolean(true)),
+        ]);
+        let mut expected = b"\x08\x00\x00\x00\x02".to_vec();
+        expected.extend_from_slice(b"\x00\x08duration\x00\x40\x45\x40\x00\x00\x00\x00\x00");
+        expected.extend_from_slice(b"\x00\x06stereo\x01\x01");
+        expected.extend_from_slice(b"\x00\x00\x09");
+        assert_eq!(encode(&metadata), expected);
+    }
+
+    #[test]
+    fn encode_markers() {
+        assert_eq!(encode(&AmfValue::Null), [0x05]);
+        assert_eq!(encode(&AmfValue::Undefined), [0x06]);
+        assert_eq!(encode(&AmfValue::Reference(0x0102)), [0x07, 0x01, 0x02]);
+        assert_eq!(
+            encode(&AmfValue::StrictArray(vec![AmfValue::Boolean(false)])),
+            [0x0a, 0, 0, 0, 1, 0x01, 0x00]
+        );
+        assert_eq!(
+            encode(&AmfValue::Date {
+                millis: 1.0,
+                timezone: 0,
+            }),
+            [0x0b, 0x3f, 0xf0, 0, 0, 0, 0, 0, 0, 0, 0]
+        );
+        assert_eq!(encode(&AmfValue::Object(vec![])), [0x03, 0x00, 0x00, 0x09]);
+        // a string longer than a UI16 becomes a long string
+        let long = "a".repeat(0x1_0000);
+        let encoded = encode(&AmfValue::String(long));
+        assert_eq!(encoded[..5], [0x0c, 0x00, 0x01, 0x00, 0x00]);
+        assert_eq!(encoded.len(), 5 + 0x1_0000);
+    }
+
+    #[test]
+    fn round_trip() {
+        let values = vec![
+            AmfValue::String("onMetaData".into()),
+            AmfValue::EcmaArray(vec![
+                ("width".into(), AmfValue::Number(1280.0)),
+                (
+                    "keyframes".into(),
+                    AmfValue::Object(vec![(
+                        "times".into(),
+                        AmfValue::StrictArray(vec![AmfValue::Number(0.0), AmfValue::Number(2.0)]),
+                    )]),
+                ),
+                ("encoder".into(), AmfValue::Xml("<x/>".into())),
+                (
+                    "class".into(),
+                    AmfValue::TypedObject("Point".into(), vec![("x".into(), AmfValue::Null)]),
+                ),
+            ]),
+        ];
+        let mut dst = BytesMut::new();
+        for value in &values {
+            value.encode(&mut dst);
+        }
+        assert_eq!(AmfValue::decode_all(&dst).unwrap(), values);
+    }
+}
@@ -0,0 +1,99 @@
+use crate::reader::{read_flv, CodecId, Field, ParseOptions, SoundFormat, Tag, TagData};
+use crate::Exception;
+use std::fs::OpenOptions;
+use std::io::{Seek, SeekFrom, Write};
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs::File;
+use tokio_stream::StreamExt;
+
+/// Copies a FLV file and overwrites the audio and video payloads with zeros, or random bytes if
+/// `random` is set. Everything else is kept byte for byte: the file header, tag headers,
+/// PreviousTagSize fields (broken ones included), script data, codec sequence headers and the
+/// AAC/AVC packet headers, so the copy has the structure of the original without its content.
+pub async fn anonymize<P: AsRef<Path>>(input: P, output: P, random: bool) -> Result<(), Exception> {
+    let (_, mut decoder) = read_flv(File::open(&input).await?, ParseOptions::default()).await?;
+    std::fs::copy(&input, &output)?;
+    let mut out = OpenOptions::new().write(true).open(&output)?;
+
+    let mut fill = Fill::new(random);
+    let (mut tags, mut bytes) = (0, 0);
+    while let Some(field) = decoder.next().await {
+        let tag = match field? {
+            Field::Tag(tag) => tag,
+            Field::Warning(finding) => {
+                eprintln!("{}", finding);
+                continue;
+            }
+            Field::PreTagSize(_) => continue,
+        };
+
+        let kept = kept_len(&tag);
+        let body_len = tag.data.encoded_len();
+        if kept >= body_len {
+            continue;
+        }
+        // The body follows the 11 byte tag header.
+        out.seek(SeekFrom::Start(tag.header.offset + 11 + kept as u64))?;
+        out.write_all(&fill.bytes(body_len - kept))?;
+        tags += 1;
+        bytes += body_len - kept;
+    }
+    out.flush()?;
+
+    println!("AnonymizedTags: {}", tags);
+    println!("AnonymizedBytes: {}", bytes);
+    Ok(())
+}
+
+/// Number of leading body bytes that describe the payload rather than being its content.
+pub fn kept_len(tag: &Tag) -> usize {
+    if tag.data.is_sequence_header() {
+        return tag.data.encoded_len();
+    }
+    match &tag.data {
+        // SoundFormat byte, plus AACPacketType
+        TagData::Audio(audio) if audio.header.sound_format == SoundFormat::AAC => 2,
+        TagData::Audio(_) => 1,
+        // FrameType/CodecID byte, plus AVCPacketType and CompositionTime
+        TagData::Video(video) if video.header.codec_id == CodecId::AVC => 5,
+        TagData::Video(_) => 1,
+        TagData::Script(script) => script.raw().len(),
+        // The header byte that could not be parsed is likely what the report is about.
+        TagData::Invalid(_) => 1,
+        TagData::Reserved(_) => 0,
+    }
+}
+
+enum Fill {
+    Zero,
+    // xorshift64, good enough to make the payload look like noise
+    Random(u64),
+}
+
+impl Fill {
+    fn new(random: bool) -> Self {
+        if !random {
+            return Fill::Zero;
+        }
+        let seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos() as u64)
+            .unwrap_or_default();
+        Fill::Random(seed | 1)
+    }
+
+    fn bytes(&mut self, len: usize) -> Vec<u8> {
+        match self {
+            Fill::Zero => vec![0; len],
+            Fill::Random(state) => (0..len)
+                .map(|_| {
+                    *state ^= *state << 13;
+                    *state ^= *state >> 7;
+                    *state ^= *state << 17;
+                    *state as u8
+                })
+                .collect(),
+        }
+    }
+}
@@ -0,0 +1,330 @@
+use crate::input::open_input;
+use crate::output::{finding_json, header_json, stats_json, tag_json, FileInfo};
+use crate::reader::{read_flv, Field, ParseOptions};
+use crate::stats::Stats;
+use crate::Exception;
+use serde_json::{json, Map, Value};
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+use tokio::io::{AsyncBufReadExt, AsyncRead, AsyncReadExt, AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio_stream::StreamExt;
+
+// Longest request line or header line.
+const MAX_LINE: usize = 8 * 1024;
+
+/// An analyzed file: the JSON document of `--format json --stats` without its tags, which are
+/// kept apart to be served by timestamp range.
+struct Analysis {
+    document: Map<String, Value>,
+    tags: Vec<Value>,
+}
+
+#[derive(Default)]
+struct Files {
+    next_id: u64,
+    files: BTreeMap<u64, Analysis>,
+}
+
+pub(crate) struct Request {
+    pub method: String,
+    pub path: String,
+    pub query: Vec<(String, String)>,
+    pub content_type: Option<String>,
+    pub body: Vec<u8>,
+}
+
+pub(crate) struct Response {
+    pub status: u16,
+    pub body: Value,
+}
+
+impl Response {
+    pub fn ok(body: Value) -> Self {
+        Self { status: 200, body }
+    }
+
+    pub fn error(status: u16, message: impl ToString) -> Self {
+        Self {
+            status,
+            body: json!({ "error": message.to_string() }),
+        }
+    }
+}
+
+/// Serves the analyzer over HTTP/1.1 on `listen`, one request per connection:
+///
+/// * `POST /analyze` with the FLV file as the body, or `{"url": "http(s)://..."}` as
+///   `application/json`, returns the header, statistics and findings of the file, and the `id`
+///   it is kept under,
+/// * `GET /files/{id}` returns the same document again,
+/// * `GET /files/{id}/tags?from=MS&to=MS` returns the tags with a timestamp in the range, both
+///   ends included and optional.
+///
+/// The last `max_files` analyses are kept in memory, uploads are limited to `max_upload` bytes.
+pub async fn api_serve(listen: &str, max_files: usize, max_upload: usize) -> Result<(), Exception> {
+    let listener = TcpListener::bind(listen).await?;
+    eprintln!("Listening on http://{}", listener.local_addr()?);
+    let files = Arc::new(Mutex::new(Files::default()));
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let files = files.clone();
+        tokio::spawn(async move {
+            if let Err(e) = serve(stream, &files, max_files, max_upload).await {
+                eprintln!("{}: {}", peer, e);
+            }
+        });
+    }
+}
+
+async fn serve(
+    stream: TcpStream,
+    files: &Mutex<Files>,
+    max_files: usize,
+    max_upload: usize,
+) -> Result<(), Exception> {
+    let mut stream = BufReader::new(stream);
+    let response = match read_request(&mut stream, max_upload).await {
+        Ok(request) => route(request, files, max_files).await,
+        Err(response) => response,
+    };
+    write_response(stream.get_mut(), &response).await
+}
+
+/// Writes the response as JSON and closes the connection.
+pub(crate) async fn write_response(
+    stream: &mut TcpStream,
+    response: &Response,
+) -> Result<(), Exception> {
+    let body = serde_json::to_vec(&response.body)?;
+    let head = format!(
+        "HTTP/1.1 {} {}\r\nContent-Type: application/json\r\nContent-Length: {}\r\nConnection: close\r\n\r\n",
+        response.status,
+        reason(response.status),
+        body.len()
+    );
+    stream.write_all(head.as_bytes()).await?;
+    stream.write_all(&body).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
+
+pub(crate) fn reason(status: u16) -> &'static str {
+    match status {
+        200 => "OK",
+        400 => "Bad Request",
+        404 => "Not Found",
+        405 => "Method Not Allowed",
+        411 => "Length Required",
+        413 => "Payload Too Large",
+        422 => "Unprocessable Entity",
+        _ => "Internal Server Error",
+    }
+}
+
+async fn read_line(stream: &mut BufReader<TcpStream>) -> Result<String, Response> {
+    let mut line = vec![];
+    let read = (&mut *stream)
+        .take(MAX_LINE as u64 + 1)
+        .read_until(b'\n', &mut line)
+        .await
+        .map_err(|e| Response::error(400, e))?;
+    if read > MAX_LINE {
+        return Err(Response::error(400, "Line too long"));
+    }
+    let line = String::from_utf8(line).map_err(|_| Response::error(400, "Invalid header"))?;
+    Ok(line.trim_end_matches(['\r', '\n']).to_owned())
+}
+
+pub(crate) async fn read_request(
+    stream: &mut BufReader<TcpStream>,
+    max_upload: usize,
+) -> Result<Request, Response> {
+    let line = read_line(stream).await?;
+    let mut parts = line.split(' ');
+    let (method, target) = match (parts.next(), parts.next()) {
+        (Some(method), Some(target)) => (method.to_owned(), target),
+        _ => return Err(Response::error(400, "Invalid request line")),
+    };
+    let (path, query) = target.split_once('?').unwrap_or((target, ""));
+    let query = query
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            (name.to_owned(), value.to_owned())
+        })
+        .collect();
+
+    let (mut content_type, mut length) = (None, None);
+    loop {
+        let line = read_line(stream).await?;
+        if line.is_empty() {
+            break;
+        }
+        let (name, value) = match line.split_once(':') {
+            Some((name, value)) => (name.to_ascii_lowercase(), value.trim()),
+            None => return Err(Response::error(400, "Invalid header")),
+        };
+        match name.as_str() {
+            "content-type" => content_type = Some(value.to_ascii_lowercase()),
+            "content-length" => {
+                let value = value
+                    .parse::<usize>()
+                    .map_err(|_| Response::error(400, "Invalid Content-Length"))?;
+                length = Some(value);
+            }
+            "transfer-encoding" => {
+                return Err(Response::error(411, "Send the body with a Content-Length"))
+            }
+            _ => {}
+        }
+    }
+
+    let length = length.unwrap_or(0);
+    if length > max_upload {
+        return Err(Response::error(
+            413,
+            format!("The body is larger than {} bytes", max_upload),
+        ));
+    }
+    let mut body = vec![0; length];
+    stream
+        .read_exact(&mut body)
+        .await
+        .map_err(|e| Response::error(400, e))?;
+    Ok(Request {
+        method,
+        path: path.to_owned(),
+        query,
+        content_type,
+        body,
+    })
+}
+
+async fn route(request: Request, files: &Mutex<Files>, max_files: usize) -> Response {
+    let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+    match (request.method.as_str(), segments.as_slice()) {
+        ("POST", ["analyze"]) => match analyze_request(&request).await {
+            Ok(mut analysis) => {
+                let mut files = files.lock().unwrap();
+                files.next_id += 1;
+                let id = files.next_id;
+                let mut document = Map::new();
+                document.insert("id".into(), json!(id));
+                document.append(&mut analysis.document);
+                analysis.document = document;
+                let response = Response::ok(Value::Object(analysis.document.clone()));
+                files.files.insert(id, analysis);
+                // forget the oldest analyses
+                while files.files.len() > max_files {
+                    files.files.pop_first();
+                }
+                response
+            }
+            Err(response) => response,
+        },
+        ("GET", ["files", id]) => with_file(files, id, |analysis| {
+            Response::ok(Value::Object(analysis.document.clone()))
+        }),
+        ("GET", ["files", id, "tags"]) => {
+            let (from, to) = match (bound(&request, "from"), bound(&request, "to")) {
+                (Ok(from), Ok(to)) => (from, to),
+                (Err(response), _) | (_, Err(response)) => return response,
+            };
+            with_file(files, id, |analysis| {
+                let tags: Vec<&Value> = analysis
+                    .tags
+                    .iter()
+                    .filter(|tag| {
+                        let timestamp = tag["timestamp"].as_i64().unwrap_or(0);
+                        from.is_none_or(|from| timestamp >= from)
+                            && to.is_none_or(|to| timestamp <= to)
+                    })
+                    .collect();
+                Response::ok(json!(tags))
+            })
+        }
+        (_, ["analyze"]) | (_, ["files", _]) | (_, ["files", _, "tags"]) => {
+            Response::error(405, format!("{} is not allowed", request.method))
+        }
+        _ => Response::error(404, format!("No such endpoint: {}", request.path)),
+    }
+}
+
+fn with_file(files: &Mutex<Files>, id: &str, f: impl FnOnce(&Analysis) -> Response) -> Response {
+    let files = files.lock().unwrap();
+    match id.parse().ok().and_then(|id: u64| files.files.get(&id)) {
+        Some(analysis) => f(analysis),
+        None => Response::error(404, format!("No such file: {}", id)),
+    }
+}
+
+fn bound(request: &Request, name: &str) -> Result<Option<i64>, Response> {
+    match request.query.iter().find(|(key, _)| key == name) {
+        Some((_, value)) => value
+            .parse()
+            .map(Some)
+            .map_err(|_| Response::error(400, format!("Invalid {}: {}", name, value))),
+        None => Ok(None),
+    }
+}
+
+async fn analyze_request(request: &Request) -> Result<Analysis, Response> {
+    let json = request
+        .content_type
+        .as_deref()
+        .is_some_and(|content_type| content_type.starts_with("application/json"));
+    let result = if json {
+        let url = serde_json::from_slice::<Value>(&request.body)
+            .ok()
+            .and_then(|body| body["url"].as_str().map(str::to_owned))
+            .ok_or_else(|| Response::error(400, "Expected {\"url\": \"...\"}"))?;
+        // Paths and the other sources of the command line would expose the server's own files.
+        if !url.starts_with("http://") && !url.starts_with("https://") {
+            return Err(Response::error(400, "Only http(s) URLs can be analyzed"));
+        }
+        match open_input(&url).await {
+            Ok((size, input)) => analyze(&url, size, input).await,
+            Err(e) => return Err(Response::error(422, e)),
+        }
+    } else {
+        let size = request.body.len() as u64;
+        analyze("upload", Some(size), &request.body[..]).await
+    };
+    result.map_err(|e| Response::error(422, e))
+}
+
+async fn analyze<R: AsyncRead + Unpin>(
+    path: &str,
+    size: Option<u64>,
+    input: R,
+) -> Result<Analysis, Exception> {
+    let (header, mut fields) = read_flv(input, ParseOptions::default()).await?;
+    let mut document = header_json(&FileInfo {
+        path,
+        size,
+        header: &header,
+    });
+    let (mut tags, mut warnings) = (vec![], vec![]);
+    let mut stats = Stats::default();
+    let mut previous_tag_size = None;
+    while let Some(field) = fields.next().await {
+        match field? {
+            Field::PreTagSize(size) => previous_tag_size = Some(size),
+            Field::Tag(tag) => {
+                stats.push(&tag);
+                tags.push(Value::Object(tag_json(
+                    tags.len() + 1,
+                    previous_tag_size,
+                    &tag,
+                )));
+            }
+            Field::Warning(finding) => warnings.push(finding_json(&finding)),
+        }
+    }
+    document.insert("tag_count".into(), json!(tags.len()));
+    document.insert("stats".into(), stats_json(&stats));
+    document.insert("warnings".into(), Value::Array(warnings));
+    Ok(Analysis { document, tags })
+}
@@ -0,0 +1,112 @@
+use crate::input::Input;
+use crate::Exception;
+use bytes::Bytes;
+use flate2::read::MultiGzDecoder;
+use futures::executor::block_on;
+use std::fs::File;
+use std::io::{self, Read};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_util::io::StreamReader;
+
+const CHUNK_SIZE: usize = 64 * 1024;
+
+pub struct Entry {
+    pub name: String,
+    pub size: u64,
+    pub input: Input,
+}
+
+pub fn is_archive(path: &str) -> bool {
+    let path = path.to_lowercase();
+    [".tar", ".tar.gz", ".tgz", ".zip"]
+        .iter()
+        .any(|extension| path.ends_with(extension))
+}
+
+/// Walks the `.flv` entries of a local tar (optionally gzip compressed) or zip archive on a
+/// separate thread. Entries are produced one at a time: the next one is only read once the
+/// previous entry's input has been consumed or dropped.
+pub fn open_archive(path: &str) -> mpsc::Receiver<Result<Entry, Exception>> {
+    let (mut entries, rx) = mpsc::channel(1);
+    let path = path.to_owned();
+
+    std::thread::spawn(move || {
+        if let Err(e) = walk(&path, &mut entries) {
+            let _ = block_on(entries.send(Err(e)));
+        }
+    });
+
+    rx
+}
+
+fn walk(path: &str, entries: &mut mpsc::Sender<Result<Entry, Exception>>) -> Result<(), Exception> {
+    let file = File::open(path)?;
+
+    if path.to_lowercase().ends_with(".zip") {
+        let mut archive = zip::ZipArchive::new(file)?;
+        for index in 0..archive.len() {
+            let mut entry = archive.by_index(index)?;
+            if entry.is_file() && is_flv(entry.name()) {
+                let name = entry.name().to_owned();
+                let size = entry.size();
+                send_entry(entries, name, size, &mut entry)?;
+            }
+        }
+    } else {
+        let reader: Box<dyn Read> = if path.to_lowercase().ends_with(".tar") {
+            Box::new(file)
+        } else {
+            Box::new(MultiGzDecoder::new(file))
+        };
+        let mut archive = tar::Archive::new(reader);
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let name = entry.path()?.to_string_lossy().into_owned();
+            if entry.header().entry_type().is_file() && is_flv(&name) {
+                let size = entry.size();
+                send_entry(entries, name, size, &mut entry)?;
+            }
+        }
+    }
+
+    Ok(())
+}
+
+fn is_flv(name: &str) -> bool {
+    name.to_lowercase().ends_with(".flv")
+}
+
+fn send_entry(
+    entries: &mut mpsc::Sender<Result<Entry, Exception>>,
+    name: String,
+    size: u64,
+    reader: &mut dyn Read,
+) -> Result<(), Exception> {
+    let (chunks, rx) = mpsc::channel::<io::Result<Bytes>>(4);
+    let entry = Entry {
+        name,
+        size,
+        input: Box::new(StreamReader::new(ReceiverStream::new(rx))),
+    };
+    if block_on(entries.send(Ok(entry))).is_err() {
+        return Err("Archive reader was closed".into());
+    }
+
+    loop {
+        let mut buf = vec![0; CHUNK_SIZE];
+        let chunk = reader.read(&mut buf).map(|n| {
+            buf.truncate(n);
+            Bytes::from(buf)
+        });
+        let failed = match &chunk {
+            Ok(chunk) if chunk.is_empty() => return Ok(()),
+            Ok(_) => false,
+            Err(_) => true,
+        };
+        // A dropped input means the consumer is done with this entry, move on to the next one.
+        if block_on(chunks.send(chunk)).is_err() || failed {
+            return Ok(());
+        }
+    }
+}
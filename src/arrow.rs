@@ -0,0 +1,485 @@
+//! The per-tag table as an Arrow IPC file (Feather V2), written without the Arrow libraries: the
+//! columns of the CSV export, integers as Int64 and the rest as Utf8, in record batches of
+//! `BATCH_ROWS` rows.
+
+use crate::findings::Finding;
+use crate::output::{tag_json, FileInfo, Formatter, CSV_COLUMNS};
+use crate::reader::Tag;
+use crate::stats::Stats;
+use crate::Exception;
+use serde_json::Value;
+use std::io::Write;
+
+const MAGIC: &[u8; 6] = b"ARROW1";
+const BATCH_ROWS: usize = 64 * 1024;
+// Columns written as Int64, the others are Utf8.
+const INTEGER_COLUMNS: [&str; 4] = ["index", "data_size", "timestamp", "previous_tag_size"];
+
+// MetadataVersion V5
+const VERSION: i16 = 4;
+// MessageHeader union
+const SCHEMA: u8 = 1;
+const RECORD_BATCH: u8 = 3;
+// Type union
+const INT: u8 = 2;
+const UTF8: u8 = 5;
+
+enum Column {
+    Int64(Vec<Option<i64>>),
+    Utf8(Vec<Option<String>>),
+}
+
+impl Column {
+    fn new(name: &str) -> Self {
+        if INTEGER_COLUMNS.contains(&name) {
+            Column::Int64(vec![])
+        } else {
+            Column::Utf8(vec![])
+        }
+    }
+
+    fn push(&mut self, value: Option<&Value>) {
+        match self {
+            Column::Int64(values) => values.push(value.and_then(Value::as_i64)),
+            Column::Utf8(values) => values.push(match value {
+                Some(Value::String(s)) => Some(s.clone()),
+                Some(Value::Null) | None => None,
+                Some(value) => Some(value.to_string()),
+            }),
+        }
+    }
+
+    fn len(&self) -> usize {
+        match self {
+            Column::Int64(values) => values.len(),
+            Column::Utf8(values) => values.len(),
+        }
+    }
+
+    fn null_count(&self) -> usize {
+        match self {
+            Column::Int64(values) => values.iter().filter(|value| value.is_none()).count(),
+            Column::Utf8(values) => values.iter().filter(|value| value.is_none()).count(),
+        }
+    }
+
+    fn clear(&mut self) {
+        match self {
+            Column::Int64(values) => values.clear(),
+            Column::Utf8(values) => values.clear(),
+        }
+    }
+
+    // The validity bitmap followed by the values, or the offsets and the data for Utf8.
+    fn buffers(&self) -> Vec<Vec<u8>> {
+        let mut validity = vec![0u8; self.len().div_ceil(8)];
+        let mut valid = |i: usize| validity[i / 8] |= 1 << (i % 8);
+        match self {
+            Column::Int64(values) => {
+                let mut data = Vec::with_capacity(values.len() * 8);
+                for (i, value) in values.iter().enumerate() {
+                    if value.is_some() {
+                        valid(i);
+                    }
+                    data.extend_from_slice(&value.unwrap_or(0).to_le_bytes());
+                }
+                vec![validity, data]
+            }
+            Column::Utf8(values) => {
+                let mut offsets = Vec::with_capacity((values.len() + 1) * 4);
+                let mut data = vec![];
+                offsets.extend_from_slice(&0i32.to_le_bytes());
+                for (i, value) in values.iter().enumerate() {
+                    if let Some(value) = value {
+                        valid(i);
+                        data.extend_from_slice(value.as_bytes());
+                    }
+                    offsets.extend_from_slice(&(data.len() as i32).to_le_bytes());
+                }
+                vec![validity, offsets, data]
+            }
+        }
+    }
+}
+
+// Where a message was written, for the footer.
+struct Block {
+    offset: u64,
+    metadata_length: u32,
+    body_length: u64,
+}
+
+/// Writes the tags of one input file as an Arrow IPC file. Statistics and findings are not part
+/// of the table, findings go to stderr.
+pub struct Arrow {
+    columns: Vec<(&'static str, Column)>,
+    previous_tag_size: Option<u32>,
+    position: u64,
+    batches: Vec<Block>,
+}
+
+impl Default for Arrow {
+    fn default() -> Self {
+        Self {
+            columns: CSV_COLUMNS
+                .iter()
+                .map(|name| (*name, Column::new(name)))
+                .collect(),
+            previous_tag_size: None,
+            position: 0,
+            batches: vec![],
+        }
+    }
+}
+
+impl Arrow {
+    fn write(&mut self, out: &mut dyn Write, bytes: &[u8]) -> Result<(), Exception> {
+        out.write_all(bytes)?;
+        self.position += bytes.len() as u64;
+        Ok(())
+    }
+
+    fn schema(&self) -> Table {
+        let fields = self
+            .columns
+            .iter()
+            .map(|(name, column)| {
+                let (type_type, type_) = match column {
+                    Column::Int64(_) => {
+                        (INT, Table(vec![(0, Slot::I32(64)), (1, Slot::Bool(true))]))
+                    }
+                    Column::Utf8(_) => (UTF8, Table(vec![])),
+                };
+                Table(vec![
+                    (0, Slot::String(name.to_string())),
+                    (1, Slot::Bool(true)),
+                    (2, Slot::U8(type_type)),
+                    (3, Slot::Table(type_)),
+                    (5, Slot::Tables(vec![])),
+                ])
+            })
+            .collect();
+        Table(vec![(1, Slot::Tables(fields))])
+    }
+
+    // An encapsulated message: continuation marker, metadata length, metadata and body.
+    fn message(
+        &mut self,
+        out: &mut dyn Write,
+        header_type: u8,
+        header: Table,
+        body: &[u8],
+    ) -> Result<Block, Exception> {
+        let metadata = build(&Table(vec![
+            (0, Slot::I16(VERSION)),
+            (1, Slot::U8(header_type)),
+            (2, Slot::Table(header)),
+            (3, Slot::I64(body.len() as i64)),
+        ]));
+        let block = Block {
+            offset: self.position,
+            metadata_length: 8 + metadata.len() as u32,
+            body_length: body.len() as u64,
+        };
+        self.write(out, &0xffff_ffffu32.to_le_bytes())?;
+        self.write(out, &(metadata.len() as u32).to_le_bytes())?;
+        self.write(out, &metadata)?;
+        self.write(out, body)?;
+        Ok(block)
+    }
+
+    fn flush_batch(&mut self, out: &mut dyn Write) -> Result<(), Exception> {
+        let rows = self.columns[0].1.len();
+        if rows == 0 {
+            return Ok(());
+        }
+        let (mut nodes, mut buffers, mut body) = (vec![], vec![], vec![]);
+        for (_, column) in &self.columns {
+            nodes.extend_from_slice(&(column.len() as i64).to_le_bytes());
+            nodes.extend_from_slice(&(column.null_count() as i64).to_le_bytes());
+            for buffer in column.buffers() {
+                buffers.extend_from_slice(&(body.len() as i64).to_le_bytes());
+                buffers.extend_from_slice(&(buffer.len() as i64).to_le_bytes());
+                body.extend_from_slice(&buffer);
+                pad(&mut body, 8);
+            }
+        }
+        let batch = Table(vec![
+            (0, Slot::I64(rows as i64)),
+            (1, Slot::Structs(nodes, 16)),
+            (2, Slot::Structs(buffers, 16)),
+        ]);
+        let block = self.message(out, RECORD_BATCH, batch, &body)?;
+        self.batches.push(block);
+        for (_, column) in &mut self.columns {
+            column.clear();
+        }
+        Ok(())
+    }
+}
+
+impl Formatter for Arrow {
+    fn header(&mut self, out: &mut dyn Write, _: &FileInfo) -> Result<(), Exception> {
+        self.write(out, MAGIC)?;
+        self.write(out, &[0, 0])?;
+        let schema = self.schema();
+        self.message(out, SCHEMA, schema, &[])?;
+        Ok(())
+    }
+
+    fn pre_tag_size(&mut self, _: &mut dyn Write, _: usize, size: u32) -> Result<(), Exception> {
+        self.previous_tag_size = Some(size);
+        Ok(())
+    }
+
+    fn tag(&mut self, out: &mut dyn Write, index: usize, tag: &Tag) -> Result<(), Exception> {
+        let object = tag_json(index, self.previous_tag_size, tag);
+        for (name, column) in &mut self.columns {
+            column.push(object.get(*name));
+        }
+        if self.columns[0].1.len() >= BATCH_ROWS {
+            self.flush_batch(out)?;
+        }
+        Ok(())
+    }
+
+    fn stats(&mut self, _: &mut dyn Write, _: &Stats) -> Result<(), Exception> {
+        Err("Statistics can not be exported as Arrow".into())
+    }
+
+    fn warning(&mut self, _: &mut dyn Write, finding: &Finding) -> Result<(), Exception> {
+        eprintln!("{}", finding);
+        Ok(())
+    }
+
+    fn finish(&mut self, out: &mut dyn Write) -> Result<(), Exception> {
+        self.flush_batch(out)?;
+        // end of the stream, which the footer follows in the file format
+        self.write(out, &0xffff_ffffu32.to_le_bytes())?;
+        self.write(out, &0u32.to_le_bytes())?;
+
+        let mut blocks = vec![];
+        for block in &self.batches {
+            blocks.extend_from_slice(&(block.offset as i64).to_le_bytes());
+            blocks.extend_from_slice(&(block.metadata_length as i32).to_le_bytes());
+            blocks.extend_from_slice(&[0; 4]);
+            blocks.extend_from_slice(&(block.body_length as i64).to_le_bytes());
+        }
+        let footer = build(&Table(vec![
+            (0, Slot::I16(VERSION)),
+            (1, Slot::Table(self.schema())),
+            (2, Slot::Structs(vec![], 24)),
+            (3, Slot::Structs(blocks, 24)),
+        ]));
+        self.write(out, &footer)?;
+        self.write(out, &(footer.len() as u32).to_le_bytes())?;
+        self.write(out, MAGIC)?;
+        Ok(())
+    }
+}
+
+fn pad(buf: &mut Vec<u8>, alignment: usize) {
+    while !buf.len().is_multiple_of(alignment) {
+        buf.push(0);
+    }
+}
+
+// A flatbuffer table: the values of its fields by slot.
+struct Table(Vec<(u16, Slot)>);
+
+enum Slot {
+    U8(u8),
+    Bool(bool),
+    I16(i16),
+    I32(i32),
+    I64(i64),
+    Table(Table),
+    String(String),
+    Tables(Vec<Table>),
+    /// the packed structs, and the size of one
+    Structs(Vec<u8>, usize),
+}
+
+impl Slot {
+    // Size of the field inside the table, offsets to other objects taking 4 bytes.
+    fn inline_size(&self) -> usize {
+        match self {
+            Slot::U8(_) | Slot::Bool(_) => 1,
+            Slot::I16(_) => 2,
+            Slot::I64(_) => 8,
+            _ => 4,
+        }
+    }
+}
+
+// Serializes a flatbuffer front to back: every table is followed by the objects it refers to, so
+// that all offsets point forward as the format requires. The length is a multiple of 8.
+fn build(root: &Table) -> Vec<u8> {
+    let mut buf = vec![0; 4];
+    let position = table(&mut buf, root);
+    patch(&mut buf, 0, position);
+    pad(&mut buf, 8);
+    buf
+}
+
+// Points the offset at `at` to `target`.
+fn patch(buf: &mut [u8], at: usize, target: usize) {
+    buf[at..at + 4].copy_from_slice(&((target - at) as u32).to_le_bytes());
+}
+
+fn table(buf: &mut Vec<u8>, table: &Table) -> usize {
+    let slots = table
+        .0
+        .iter()
+        .map(|(slot, _)| *slot as usize + 1)
+        .max()
+        .unwrap_or(0);
+    pad(buf, 2);
+    let vtable = buf.len();
+    buf.resize(vtable + 4 + 2 * slots, 0);
+
+    // The table is aligned for its largest field, which the fields follow in decreasing size.
+    pad(buf, 8);
+    let start = buf.len();
+    buf.extend_from_slice(&((start - vtable) as i32).to_le_bytes());
+    let mut fields: Vec<&(u16, Slot)> = table.0.iter().collect();
+    fields.sort_by_key(|(_, value)| std::cmp::Reverse(value.inline_size()));
+    let mut references = vec![];
+    for (slot, value) in fields {
+        pad(buf, value.inline_size());
+        let at = buf.len();
+        match value {
+            Slot::U8(v) => buf.push(*v),
+            Slot::Bool(v) => buf.push(*v as u8),
+            Slot::I16(v) => buf.extend_from_slice(&v.to_le_bytes()),
+            Slot::I32(v) => buf.extend_from_slice(&v.to_le_bytes()),
+            Slot::I64(v) => buf.extend_from_slice(&v.to_le_bytes()),
+            _ => {
+                buf.extend_from_slice(&[0; 4]);
+                references.push((at, value));
+            }
+        }
+        let entry = vtable + 4 + 2 * *slot as usize;
+        buf[entry..entry + 2].copy_from_slice(&((at - start) as u16).to_le_bytes());
+    }
+    let vtable_size = (4 + 2 * slots) as u16;
+    let table_size = (buf.len() - start) as u16;
+    buf[vtable..vtable + 2].copy_from_slice(&vtable_size.to_le_bytes());
+    buf[vtable + 2..vtable + 4].copy_from_slice(&table_size.to_le_bytes());
+
+    for (at, value) in references {
+        let target = match value {
+            Slot::Table(child) => self::table(buf, child),
+            Slot::String(s) => {
+                pad(buf, 4);
+                let position = buf.len();
+                buf.extend_from_slice(&(s.len() as u32).to_le_bytes());
+                buf.extend_from_slice(s.as_bytes());
+                buf.push(0);
+                position
+            }
+            Slot::Tables(tables) => {
+                pad(buf, 4);
+                let position = buf.len();
+                buf.extend_from_slice(&(tables.len() as u32).to_le_bytes());
+                buf.resize(position + 4 + 4 * tables.len(), 0);
+                for (i, child) in tables.iter().enumerate() {
+                    let child = self::table(buf, child);
+                    patch(buf, position + 4 + 4 * i, child);
+                }
+                position
+            }
+            Slot::Structs(bytes, size) => {
+                // the structs hold 64 bit fields, they start 8 byte aligned after the length
+                while !(buf.len() + 4).is_multiple_of(8) {
+                    buf.push(0);
+                }
+                let position = buf.len();
+                buf.extend_from_slice(&((bytes.len() / size) as u32).to_le_bytes());
+                buf.extend_from_slice(bytes);
+                position
+            }
+            _ => unreachable!("scalars are stored inline"),
+        };
+        patch(buf, at, target);
+    }
+    start
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::{read_flv, Field, ParseOptions};
+    use arrow_array::cast::AsArray;
+    use arrow_array::types::Int64Type;
+    use arrow_array::Array;
+    use arrow_ipc::reader::FileReader;
+    use std::io::Cursor;
+    use tokio_stream::StreamExt;
+
+    // The tags and PreviousTagSize fields of test.flv.
+    async fn test_flv() -> (crate::reader::Header, Vec<Field>) {
+        let file = tokio::fs::File::open("resources/test.flv").await.unwrap();
+        let (header, fields) = read_flv(file, ParseOptions::default()).await.unwrap();
+        let fields = fields.map(Result::unwrap).collect().await;
+        (header, fields)
+    }
+
+    #[tokio::test]
+    async fn read_back() {
+        let (header, fields) = test_flv().await;
+        let file = FileInfo {
+            path: "test.flv",
+            size: None,
+            header: &header,
+        };
+        // test.flv repeated until the rows take two record batches
+        let repeats = BATCH_ROWS / 2886 + 1;
+        let mut arrow = Arrow::default();
+        let mut out = vec![];
+        arrow.header(&mut out, &file).unwrap();
+        let mut index = 1;
+        for _ in 0..repeats {
+            for field in &fields {
+                match field {
+                    Field::PreTagSize(size) => arrow.pre_tag_size(&mut out, index - 1, *size),
+                    Field::Tag(tag) => {
+                        index += 1;
+                        arrow.tag(&mut out, index - 1, tag)
+                    }
+                    Field::Warning(_) => Ok(()),
+                }
+                .unwrap();
+            }
+        }
+        arrow.finish(&mut out).unwrap();
+
+        let reader = FileReader::try_new(Cursor::new(out), None).unwrap();
+        let schema = reader.schema();
+        let names: Vec<&str> = schema.fields().iter().map(|f| f.name().as_str()).collect();
+        assert_eq!(names, CSV_COLUMNS);
+        assert_eq!(reader.num_batches(), 2);
+
+        let batches: Vec<_> = reader.map(Result::unwrap).collect();
+        let rows: usize = batches.iter().map(|batch| batch.num_rows()).sum();
+        assert_eq!(rows, 2886 * repeats);
+        assert_eq!(batches[0].num_rows(), BATCH_ROWS);
+
+        let first = &batches[0];
+        let column = |name: &str| first.column_by_name(name).unwrap();
+        let indexes = column("index").as_primitive::<Int64Type>();
+        assert_eq!(indexes.value(0), 1);
+        assert_eq!(indexes.value(BATCH_ROWS - 1), BATCH_ROWS as i64);
+        let tag_types = column("tag_type").as_string::<i32>();
+        assert_eq!(tag_types.value(0), "Script");
+        let previous = column("previous_tag_size").as_primitive::<Int64Type>();
+        assert_eq!((previous.value(0), previous.value(1)), (0, 377));
+        // audio columns are null for other tags
+        let sound_format = column("sound_format").as_string::<i32>();
+        assert!(sound_format.is_null(0));
+        assert_eq!(
+            column("data_size").as_primitive::<Int64Type>().value(0),
+            366
+        );
+    }
+}
@@ -0,0 +1,78 @@
+use crate::stats::Stats;
+use crate::Exception;
+
+/// Checks of the analysis results given on the command line, e.g. for gating an encoding
+/// pipeline: any that fails makes the dump exit with an error. Unset ones are not checked.
+#[derive(Debug, Default)]
+pub struct Assertions {
+    /// ms between two keyframes
+    pub max_keyframe_interval: Option<i64>,
+    pub has_audio: bool,
+    pub has_video: bool,
+    /// percent the audio and video may last longer or shorter than onMetaData's duration
+    pub duration_within: Option<f64>,
+}
+
+impl Assertions {
+    pub fn is_empty(&self) -> bool {
+        self.max_keyframe_interval.is_none()
+            && !self.has_audio
+            && !self.has_video
+            && self.duration_within.is_none()
+    }
+
+    /// The assertions the file described by the statistics fails, empty if it passes.
+    pub fn failures(&self, stats: &Stats) -> Vec<String> {
+        let mut failures = Vec::new();
+
+        if let Some(max) = self.max_keyframe_interval {
+            if let Some(interval) = stats.keyframe_map.intervals.iter().max() {
+                if *interval > max {
+                    failures.push(format!(
+                        "keyframes are up to {} ms apart, more than {} ms",
+                        interval, max
+                    ));
+                }
+            }
+        }
+        if self.has_audio && stats.audio.tags == 0 {
+            failures.push("no audio".into());
+        }
+        if self.has_video && stats.video.tags == 0 {
+            failures.push("no video".into());
+        }
+
+        if let Some(percent) = self.duration_within {
+            match (stats.timestamps.media_duration(), stats.metadata_duration) {
+                (Some(duration), Some(claimed)) => {
+                    let claimed = claimed * 1000.0;
+                    let deviation = (duration as f64 - claimed).abs();
+                    if deviation > claimed.abs() * percent / 100.0 {
+                        failures.push(format!(
+                            "the audio and video last {} ms, onMetaData claims {} ms, more than \
+                             {}% apart",
+                            duration, claimed, percent
+                        ));
+                    }
+                }
+                (None, _) => failures.push("no audio or video to measure the duration".into()),
+                (_, None) => failures.push("no duration in onMetaData".into()),
+            }
+        }
+
+        failures
+    }
+}
+
+/// A percentage such as `1%` or `0.5`.
+pub fn parse_percent(s: &str) -> Result<f64, Exception> {
+    let percent: f64 = s
+        .strip_suffix('%')
+        .unwrap_or(s)
+        .parse()
+        .map_err(|_| format!("Invalid percentage {}, expected e.g. 1%", s))?;
+    if !percent.is_finite() || percent < 0.0 {
+        return Err(format!("Invalid percentage {}, expected 0% or more", s).into());
+    }
+    Ok(percent)
+}
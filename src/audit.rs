@@ -0,0 +1,241 @@
+use crate::reader::{read_flv, Field, ParseOptions, Tag, TagData, VideoFrameType};
+use crate::score::Score;
+use crate::stats::Stats;
+use crate::Exception;
+use serde::Deserialize;
+use std::path::{Path, PathBuf};
+use tokio::fs::File;
+use tokio_stream::StreamExt;
+
+/// Requirements every audited file has to meet, read from a TOML file. Unset fields are not
+/// checked.
+///
+/// ```toml
+/// audio_codecs = ["AAC"]
+/// video_codecs = ["AVC"]
+/// max_keyframe_interval = 4000
+/// resolutions = ["1280x720", "1920x1080"]
+/// max_bitrate = 6000
+/// min_score = 80
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Policy {
+    /// The file must have audio in one of these SoundFormats, named as in the dump.
+    pub audio_codecs: Option<Vec<String>>,
+    /// The file must have video with one of these CodecIds, named as in the dump.
+    pub video_codecs: Option<Vec<String>>,
+    /// Milliseconds between two keyframes.
+    pub max_keyframe_interval: Option<i64>,
+    /// WIDTHxHEIGHT as declared by onMetaData.
+    pub resolutions: Option<Vec<String>>,
+    /// Average bitrate of all tags in kbps.
+    pub max_bitrate: Option<f64>,
+    /// Seekability score from 0 to 100, as in the statistics.
+    pub min_score: Option<u32>,
+}
+
+impl Policy {
+    pub fn load(path: &Path) -> Result<Self, Exception> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        toml::from_str(&text)
+            .map_err(|e| format!("Invalid policy {}: {}", path.display(), e).into())
+    }
+}
+
+/// What the policy is checked against, collected in one pass over the file.
+#[derive(Default)]
+struct Findings {
+    audio_codec: Option<String>,
+    video_codec: Option<String>,
+    resolution: Option<(f64, f64)>,
+    last_keyframe: Option<i64>,
+    max_keyframe_interval: i64,
+    // audio and video DataSize
+    media_bytes: u64,
+    first_timestamp: Option<i64>,
+    last_timestamp: i64,
+    truncation: Option<String>,
+    stats: Stats,
+}
+
+impl Findings {
+    fn push(&mut self, tag: Tag) {
+        self.stats.push(&tag);
+        let timestamp = tag.header.timestamp as i64;
+        self.first_timestamp.get_or_insert(timestamp);
+        self.last_timestamp = self.last_timestamp.max(timestamp);
+
+        match &tag.data {
+            TagData::Audio(audio) => {
+                self.media_bytes += tag.header.data_size as u64;
+                self.audio_codec
+                    .get_or_insert_with(|| format!("{:?}", audio.header.sound_format));
+            }
+            TagData::Video(video) => {
+                self.media_bytes += tag.header.data_size as u64;
+                self.video_codec
+                    .get_or_insert_with(|| format!("{:?}", video.header.codec_id));
+                if video.header.frame_type == VideoFrameType::KeyFrame
+                    && !tag.data.is_sequence_header()
+                {
+                    if let Some(last) = self.last_keyframe {
+                        self.max_keyframe_interval =
+                            self.max_keyframe_interval.max(timestamp - last);
+                    }
+                    self.last_keyframe = Some(timestamp);
+                }
+            }
+            TagData::Script(script) => {
+                if let Some(metadata) = script.as_metadata() {
+                    if let (Some(width), Some(height)) = (metadata.width, metadata.height) {
+                        self.resolution.get_or_insert((width, height));
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// kbps over the whole file
+    fn bitrate(&self) -> Option<f64> {
+        let duration = self.last_timestamp - self.first_timestamp?;
+        if duration <= 0 {
+            return None;
+        }
+        Some(self.media_bytes as f64 * 8.0 / duration as f64)
+    }
+
+    /// The violated requirements, empty if the file passes.
+    fn check(&self, policy: &Policy) -> Vec<String> {
+        let mut failures = Vec::new();
+        if let Some(truncation) = &self.truncation {
+            failures.push(truncation.clone());
+        }
+
+        let codecs = [
+            ("audio", &policy.audio_codecs, &self.audio_codec),
+            ("video", &policy.video_codecs, &self.video_codec),
+        ];
+        for (stream, allowed, found) in codecs {
+            if let Some(allowed) = allowed {
+                match found {
+                    None => failures.push(format!("no {}", stream)),
+                    Some(codec) if !allowed.iter().any(|a| a.eq_ignore_ascii_case(codec)) => {
+                        failures.push(format!("{} codec {}", stream, codec))
+                    }
+                    _ => {}
+                }
+            }
+        }
+
+        if let Some(max) = policy.max_keyframe_interval {
+            if self.max_keyframe_interval > max {
+                failures.push(format!(
+                    "keyframe interval {} ms > {} ms",
+                    self.max_keyframe_interval, max
+                ));
+            }
+        }
+
+        if let Some(allowed) = &policy.resolutions {
+            match self.resolution {
+                None => failures.push("no resolution in onMetaData".into()),
+                Some((width, height)) => {
+                    let resolution = format!("{}x{}", width, height);
+                    if !allowed.contains(&resolution) {
+                        failures.push(format!("resolution {}", resolution));
+                    }
+                }
+            }
+        }
+
+        if let (Some(max), Some(bitrate)) = (policy.max_bitrate, self.bitrate()) {
+            if bitrate > max {
+                failures.push(format!("bitrate {:.1} kbps > {} kbps", bitrate, max));
+            }
+        }
+
+        if let Some(min) = policy.min_score {
+            match Score::new(&self.stats) {
+                Some(score) if score.total < min => {
+                    failures.push(format!("score {} < {}", score.total, min))
+                }
+                None => failures.push("no audio or video to score".into()),
+                _ => {}
+            }
+        }
+
+        failures
+    }
+}
+
+/// Checks every FLV file under `dir` against the policy and prints a pass/fail table. Fails if
+/// any file does not pass.
+pub async fn audit(dir: &Path, policy: &Path) -> Result<(), Exception> {
+    let policy = Policy::load(policy)?;
+    let mut files = Vec::new();
+    find_flv_files(dir, &mut files)?;
+    files.sort();
+
+    let mut results = Vec::new();
+    for path in &files {
+        let failures = match audit_file(path, &policy).await {
+            Ok(failures) => failures,
+            Err(e) => vec![e.to_string()],
+        };
+        results.push((path.display().to_string(), failures));
+    }
+
+    let width = results
+        .iter()
+        .map(|(name, _)| name.len())
+        .max()
+        .unwrap_or(0)
+        .max("File".len());
+    println!("{:width$}  Result  Reasons", "File", width = width);
+    for (name, failures) in &results {
+        let result = if failures.is_empty() { "PASS" } else { "FAIL" };
+        println!(
+            "{:width$}  {:6}  {}",
+            name,
+            result,
+            failures.join("; "),
+            width = width
+        );
+    }
+
+    let failed = results.iter().filter(|(_, f)| !f.is_empty()).count();
+    if failed > 0 {
+        return Err(format!("{} of {} files failed the policy", failed, results.len()).into());
+    }
+    Ok(())
+}
+
+async fn audit_file(path: &Path, policy: &Policy) -> Result<Vec<String>, Exception> {
+    let (_, mut fields) = read_flv(File::open(path).await?, ParseOptions::default()).await?;
+    let mut findings = Findings::default();
+    while let Some(field) = fields.next().await {
+        if let Field::Tag(tag) = field? {
+            findings.push(tag);
+        }
+    }
+    findings.truncation = fields.decoder().truncation().map(ToString::to_string);
+    Ok(findings.check(policy))
+}
+
+fn find_flv_files(dir: &Path, files: &mut Vec<PathBuf>) -> Result<(), Exception> {
+    for entry in std::fs::read_dir(dir)? {
+        let path = entry?.path();
+        if path.is_dir() {
+            find_flv_files(&path, files)?;
+        } else if path
+            .extension()
+            .is_some_and(|ext| ext.eq_ignore_ascii_case("flv"))
+        {
+            files.push(path);
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,369 @@
+use crate::bits::BitReader;
+use crate::Exception;
+use bytes::Bytes;
+use std::convert::TryFrom;
+
+// profile_idc values whose SPS carries chroma format, bit depth and scaling matrices.
+const HIGH_PROFILES: [u8; 13] = [100, 110, 122, 244, 44, 83, 86, 118, 128, 138, 139, 134, 135];
+
+/// AVCPacketType, the first byte of the body of an AVC video tag.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum AvcPacketType {
+    SequenceHeader = 0,
+    Nalu = 1,
+    EndOfSequence = 2,
+}
+
+impl TryFrom<u8> for AvcPacketType {
+    type Error = Exception;
+
+    fn try_from(value: u8) -> Result<Self, Self::Error> {
+        use AvcPacketType::*;
+        Ok(match value {
+            0 => SequenceHeader,
+            1 => Nalu,
+            2 => EndOfSequence,
+            n => return Err(format!("Invalid AVC packet type: {}", n).into()),
+        })
+    }
+}
+
+/// AVCDecoderConfigurationRecord, the body of an AVC sequence header after the AVCPacketType and
+/// composition time (ISO/IEC 14496-15).
+#[derive(Debug, Clone)]
+pub struct DecoderConfigurationRecord {
+    pub profile: u8,
+    pub compatibility: u8,
+    pub level: u8,
+    /// size of the NALU length fields in the coded frames
+    pub length_size: u8,
+    pub sps: Vec<Bytes>,
+    pub pps: Vec<Bytes>,
+}
+
+impl TryFrom<&[u8]> for DecoderConfigurationRecord {
+    type Error = Exception;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        let (profile, compatibility, level, length_size, mut rest) = match value {
+            [1, profile, compatibility, level, length_size, rest @ ..] => (
+                *profile,
+                *compatibility,
+                *level,
+                (length_size & 3) + 1,
+                rest,
+            ),
+            [version, ..] if *version != 1 => {
+                return Err(format!("Unsupported AVC configuration version {}", version).into())
+            }
+            _ => return Err("AVCDecoderConfigurationRecord is too short".into()),
+        };
+
+        let mut parameter_sets = |count_mask: u8| -> Result<Vec<Bytes>, Exception> {
+            let (count, tail) = rest
+                .split_first()
+                .ok_or("AVCDecoderConfigurationRecord is too short")?;
+            rest = tail;
+            let mut sets = vec![];
+            for _ in 0..count & count_mask {
+                let length = match rest {
+                    [l1, l2, ..] => u16::from_be_bytes([*l1, *l2]) as usize,
+                    _ => return Err("AVCDecoderConfigurationRecord is too short".into()),
+                };
+                let set = rest
+                    .get(2..2 + length)
+                    .ok_or("AVCDecoderConfigurationRecord is too short")?;
+                sets.push(Bytes::copy_from_slice(set));
+                rest = &rest[2 + length..];
+            }
+            Ok(sets)
+        };
+        let sps = parameter_sets(0x1f)?;
+        let pps = parameter_sets(0xff)?;
+
+        Ok(Self {
+            profile,
+            compatibility,
+            level,
+            length_size,
+            sps,
+            pps,
+        })
+    }
+}
+
+/// The fields of a sequence parameter set that describe the picture.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Sps {
+    pub profile: u8,
+    pub level: u8,
+    pub chroma_format: u32,
+    pub bit_depth: u32,
+    pub width: u32,
+    pub height: u32,
+}
+
+impl TryFrom<&[u8]> for Sps {
+    type Error = Exception;
+
+    /// Parses a SPS NAL unit, including its one byte NAL unit header.
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        match value.first() {
+            Some(header) if header & 0x1f == 7 => {}
+            Some(header) => {
+                return Err(format!("NAL unit type {} is not a SPS", header & 0x1f).into())
+            }
+            None => return Err("Empty SPS".into()),
+        }
+        let rbsp = unescape(&value[1..]);
+        let mut reader = BitReader::new(&rbsp);
+
+        let profile = reader.read_bits(8)? as u8;
+        // constraint_set flags and reserved_zero_2bits
+        reader.read_bits(8)?;
+        let level = reader.read_bits(8)? as u8;
+        // seq_parameter_set_id
+        reader.read_ue()?;
+
+        let (mut chroma_format, mut bit_depth, mut separate_colour_planes) = (1, 8, false);
+        if HIGH_PROFILES.contains(&profile) {
+            chroma_format = reader.read_ue()?;
+            if chroma_format > 3 {
+                return Err(format!("Invalid chroma_format_idc {}", chroma_format).into());
+            }
+            if chroma_format == 3 {
+                separate_colour_planes = reader.read_bit()?;
+            }
+            // bit_depth_luma_minus8 is at most 6
+            bit_depth = match reader.read_ue()? {
+                minus8 @ 0..=6 => minus8 + 8,
+                minus8 => return Err(format!("Invalid bit_depth_luma_minus8 {}", minus8).into()),
+            };
+            // bit_depth_chroma_minus8, qpprime_y_zero_transform_bypass_flag
+            reader.read_ue()?;
+            reader.read_bit()?;
+            if reader.read_bit()? {
+                let lists = if chroma_format == 3 { 12 } else { 8 };
+                for i in 0..lists {
+                    if reader.read_bit()? {
+                        skip_scaling_list(&mut reader, if i < 6 { 16 } else { 64 })?;
+                    }
+                }
+            }
+        }
+
+        // log2_max_frame_num_minus4
+        reader.read_ue()?;
+        match reader.read_ue()? {
+            0 => {
+                // log2_max_pic_order_cnt_lsb_minus4
+                reader.read_ue()?;
+            }
+            1 => {
+                // delta_pic_order_always_zero_flag, offset_for_non_ref_pic,
+                // offset_for_top_to_bottom_field
+                reader.read_bit()?;
+                reader.read_se()?;
+                reader.read_se()?;
+                for _ in 0..reader.read_ue()? {
+                    reader.read_se()?;
+                }
+            }
+            _ => {}
+        }
+        // max_num_ref_frames, gaps_in_frame_num_value_allowed_flag
+        reader.read_ue()?;
+        reader.read_bit()?;
+
+        let width_in_mbs = reader.read_ue()? + 1;
+        let height_in_map_units = reader.read_ue()? + 1;
+        let frame_mbs_only = reader.read_bit()?;
+        if !frame_mbs_only {
+            // mb_adaptive_frame_field_flag
+            reader.read_bit()?;
+        }
+        // direct_8x8_inference_flag
+        reader.read_bit()?;
+
+        let field_factor = if frame_mbs_only { 1 } else { 2 };
+        let too_large = || {
+            format!(
+                "Picture of {}x{} macroblocks is too large",
+                width_in_mbs, height_in_map_units
+            )
+        };
+        let mut width = width_in_mbs.checked_mul(16).ok_or_else(too_large)?;
+        let mut height = height_in_map_units
+            .checked_mul(16 * field_factor)
+            .ok_or_else(too_large)?;
+        if reader.read_bit()? {
+            let (crop_x, crop_y) = match chroma_format {
+                _ if separate_colour_planes => (1, field_factor),
+                0 => (1, field_factor),
+                1 => (2, 2 * field_factor),
+                2 => (2, field_factor),
+                _ => (1, field_factor),
+            };
+            let (left, right) = (reader.read_ue()?, reader.read_ue()?);
+            let (top, bottom) = (reader.read_ue()?, reader.read_ue()?);
+            let crop = |size: u32, unit: u32, start: u32, end: u32| {
+                start
+                    .checked_add(end)
+                    .and_then(|sum| sum.checked_mul(unit))
+                    .and_then(|cropped| size.checked_sub(cropped))
+                    .ok_or_else(|| format!("Cropping {}+{} exceeds the size {}", start, end, size))
+            };
+            width = crop(width, crop_x, left, right)?;
+            height = crop(height, crop_y, top, bottom)?;
+        }
+
+        Ok(Self {
+            profile,
+            level,
+            chroma_format,
+            bit_depth,
+            width,
+            height,
+        })
+    }
+}
+
+// Removes the emulation prevention bytes: 0x000003 stands for 0x0000.
+fn unescape(data: &[u8]) -> Vec<u8> {
+    let mut rbsp = Vec::with_capacity(data.len());
+    let mut zeros = 0;
+    for &byte in data {
+        if zeros >= 2 && byte == 3 {
+            zeros = 0;
+            continue;
+        }
+        zeros = if byte == 0 { zeros + 1 } else { 0 };
+        rbsp.push(byte);
+    }
+    rbsp
+}
+
+fn skip_scaling_list(reader: &mut BitReader, size: usize) -> Result<(), Exception> {
+    let (mut last, mut next) = (8i32, 8i32);
+    for _ in 0..size {
+        if next != 0 {
+            let delta = reader.read_se()?;
+            if !(-128..=127).contains(&delta) {
+                return Err(format!("Invalid delta_scale {}", delta).into());
+            }
+            next = (last + delta + 256) % 256;
+        }
+        if next != 0 {
+            last = next;
+        }
+    }
+    Ok(())
+}
+
+pub fn profile_name(profile: u8) -> Option<&'static str> {
+    Some(match profile {
+        66 => "Baseline",
+        77 => "Main",
+        88 => "Extended",
+        100 => "High",
+        110 => "High 10",
+        122 => "High 4:2:2",
+        244 => "High 4:4:4 Predictive",
+        44 => "CAVLC 4:4:4 Intra",
+        _ => return None,
+    })
+}
+
+/// chroma_format_idc as the sampling of the chroma planes, e.g. 1 is 4:2:0.
+pub fn chroma_format_name(chroma_format: u32) -> Option<&'static str> {
+    Some(match chroma_format {
+        0 => "4:0:0",
+        1 => "4:2:0",
+        2 => "4:2:2",
+        3 => "4:4:4",
+        _ => return None,
+    })
+}
+
+/// level_idc as written in the spec, e.g. 31 is 3.1.
+pub fn level_name(level: u8) -> String {
+    format!("{}.{}", level / 10, level % 10)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sps(bytes: &[u8]) -> Sps {
+        Sps::try_from(bytes).unwrap()
+    }
+
+    fn error(bytes: &[u8]) -> String {
+        Sps::try_from(bytes).unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_flv() {
+        // the SPS of resources/test.flv, with an emulation prevention byte
+        let sps = sps(&[
+            0x67, 0x64, 0x00, 0x1f, 0xac, 0xd9, 0x40, 0x88, 0x1e, 0x68, 0x40, 0x00, 0x00, 0x03,
+            0x00, 0x40, 0x00, 0x00, 0x0f, 0x03, 0xc6, 0x0c, 0x65, 0x80,
+        ]);
+        assert_eq!((sps.width, sps.height), (544, 960));
+        assert_eq!(profile_name(sps.profile), Some("High"));
+        assert_eq!(level_name(sps.level), "3.1");
+        assert_eq!((sps.chroma_format, sps.bit_depth), (1, 8));
+    }
+
+    #[test]
+    fn cropped() {
+        // Baseline 4.0, 120x68 macroblocks with frame_crop_bottom_offset 4
+        let sps = sps(&[
+            0x67, 0x42, 0x00, 0x28, 0xed, 0x00, 0xf0, 0x04, 0x4f, 0xca, 0x80,
+        ]);
+        assert_eq!((sps.width, sps.height), (1920, 1080));
+        assert_eq!((sps.profile, sps.level), (66, 40));
+    }
+
+    #[test]
+    fn interlaced() {
+        // Main 3.0, 45x18 macroblock pairs, frame_mbs_only_flag 0
+        let sps = sps(&[0x67, 0x4d, 0x00, 0x1e, 0xed, 0x01, 0x68, 0x48, 0x90]);
+        assert_eq!((sps.width, sps.height), (720, 576));
+    }
+
+    #[test]
+    fn hostile() {
+        assert_eq!(error(&[]), "Empty SPS");
+        assert_eq!(error(&[0x68, 0xce]), "NAL unit type 8 is not a SPS");
+        assert_eq!(
+            error(&[0x67, 0x64, 0x00, 0x1f, 0x88, 0xcd, 0xa7, 0x90]),
+            "Invalid chroma_format_idc 7"
+        );
+        assert_eq!(
+            error(&[0x67, 0x64, 0x00, 0x1f, 0xa0, 0xa8, 0x54, 0xda, 0x79]),
+            "Invalid bit_depth_luma_minus8 20"
+        );
+        // pic_width_in_mbs_minus1 of 2^32 - 2
+        assert_eq!(
+            error(&[
+                0x67, 0x42, 0x00, 0x1f, 0xed, 0x00, 0x00, 0x03, 0x00, 0x00, 0x7f, 0xff, 0xff, 0xff,
+                0xf2,
+            ]),
+            "Picture of 4294967295x1 macroblocks is too large"
+        );
+        // 9 columns cropped from a picture of one macroblock
+        assert_eq!(
+            error(&[0x67, 0x42, 0x00, 0x1f, 0xed, 0x3e, 0x62, 0xe8]),
+            "Cropping 5+4 exceeds the size 16"
+        );
+        assert_eq!(
+            error(&[0x67, 0x64, 0x00, 0x1f, 0xad, 0x80, 0x64, 0x00, 0x6d, 0x3c, 0x80]),
+            "Invalid delta_scale 200"
+        );
+        assert_eq!(
+            error(&[0x67, 0x64, 0x00, 0x1f]),
+            "Unexpected end of bitstream"
+        );
+    }
+}
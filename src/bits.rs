@@ -0,0 +1,122 @@
+use crate::Exception;
+
+#[derive(Debug)]
+pub struct BitReader<'a> {
+    data: &'a [u8],
+    position: usize,
+}
+
+impl<'a> BitReader<'a> {
+    pub fn new(data: &'a [u8]) -> Self {
+        Self { data, position: 0 }
+    }
+
+    pub fn read_bit(&mut self) -> Result<bool, Exception> {
+        let byte = self
+            .data
+            .get(self.position / 8)
+            .ok_or("Unexpected end of bitstream")?;
+        let bit = (byte >> (7 - self.position % 8)) & 1;
+        self.position += 1;
+        Ok(bit == 1)
+    }
+
+    pub fn read_bits(&mut self, count: u32) -> Result<u32, Exception> {
+        assert!(count <= 32);
+        let mut value = 0u32;
+        for _ in 0..count {
+            value = (value << 1) | self.read_bit()? as u32;
+        }
+        Ok(value)
+    }
+
+    /// Unsigned Exp-Golomb code, ue(v) in the H.264 spec.
+    pub fn read_ue(&mut self) -> Result<u32, Exception> {
+        let mut leading_zeros = 0;
+        while !self.read_bit()? {
+            leading_zeros += 1;
+            if leading_zeros > 31 {
+                return Err("Invalid Exp-Golomb code".into());
+            }
+        }
+        Ok((1u64 << leading_zeros) as u32 - 1 + self.read_bits(leading_zeros)?)
+    }
+
+    /// Signed Exp-Golomb code, se(v) in the H.264 spec.
+    pub fn read_se(&mut self) -> Result<i32, Exception> {
+        let value = self.read_ue()? as i64;
+        Ok(if value % 2 == 1 {
+            (value + 1) / 2
+        } else {
+            -value / 2
+        } as i32)
+    }
+
+    pub fn skip_bits(&mut self, count: usize) -> Result<(), Exception> {
+        if count > self.remaining() {
+            return Err("Unexpected end of bitstream".into());
+        }
+        self.position += count;
+        Ok(())
+    }
+
+    pub fn remaining(&self) -> usize {
+        self.data.len() * 8 - self.position
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct BitWriter {
+    data: Vec<u8>,
+    position: usize,
+}
+
+impl BitWriter {
+    pub fn write_bits(&mut self, value: u32, count: u32) {
+        for i in (0..count).rev() {
+            if self.position.is_multiple_of(8) {
+                self.data.push(0);
+            }
+            if (value >> i) & 1 == 1 {
+                let last = self.data.len() - 1;
+                self.data[last] |= 1 << (7 - self.position % 8);
+            }
+            self.position += 1;
+        }
+    }
+
+    // The trailing bits of the last byte are left as zero.
+    pub fn into_bytes(self) -> Vec<u8> {
+        self.data
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // The Exp-Golomb codes 1, 010, 011, 00100, 00111 and 0001000.
+    const CODES: [u8; 3] = [0xa6, 0x43, 0x88];
+
+    #[test]
+    fn read_ue() {
+        let mut reader = BitReader::new(&CODES);
+        let values: Vec<u32> = (0..6).map(|_| reader.read_ue().unwrap()).collect();
+        assert_eq!(values, [0, 1, 2, 3, 6, 7]);
+        assert_eq!(reader.remaining(), 0);
+
+        // 31 leading zeros, the longest code that fits in 32 bits
+        let mut reader = BitReader::new(&[0, 0, 0, 1, 0xff, 0xff, 0xff, 0xfe]);
+        assert_eq!(reader.read_ue().unwrap(), u32::MAX - 1);
+
+        assert!(BitReader::new(&[0, 0, 0, 0, 0xff]).read_ue().is_err());
+        assert!(BitReader::new(&[0x00, 0x01]).read_ue().is_err());
+    }
+
+    #[test]
+    fn read_se() {
+        let mut reader = BitReader::new(&CODES);
+        let values: Vec<i32> = (0..6).map(|_| reader.read_se().unwrap()).collect();
+        assert_eq!(values, [0, 1, -1, 2, -3, 4]);
+    }
+}
@@ -0,0 +1,102 @@
+//! Fan-out of parsed tags, so several consumers can share a single pass over the input.
+
+use crate::reader::Tag;
+use crate::Exception;
+use std::sync::Arc;
+use tokio::sync::mpsc;
+use tokio_stream::{Stream, StreamExt};
+
+/// Sends every tag of a stream to each subscriber. Subscribers get the tags in order through
+/// bounded channels, the slowest one sets the pace. A subscriber that drops its receiver is
+/// skipped from then on.
+///
+/// ```ignore
+/// let mut broadcast = Broadcast::new(16);
+/// let stats = broadcast.subscribe();
+/// let recorder = broadcast.subscribe();
+/// tokio::spawn(broadcast.run(fields.tags_only()));
+/// ```
+pub struct Broadcast {
+    capacity: usize,
+    senders: Vec<mpsc::Sender<Arc<Tag>>>,
+}
+
+impl Broadcast {
+    /// `capacity` tags can be buffered for each subscriber.
+    pub fn new(capacity: usize) -> Self {
+        Self {
+            capacity: capacity.max(1),
+            senders: Vec::new(),
+        }
+    }
+
+    pub fn subscribe(&mut self) -> mpsc::Receiver<Arc<Tag>> {
+        let (tx, rx) = mpsc::channel(self.capacity);
+        self.senders.push(tx);
+        rx
+    }
+
+    /// Reads the tags until the end of the stream or until every subscriber is gone. The
+    /// receivers are closed when this returns, an error while reading is returned here and not
+    /// to the subscribers.
+    pub async fn run<S>(mut self, tags: S) -> Result<(), Exception>
+    where
+        S: Stream<Item = Result<Tag, Exception>>,
+    {
+        tokio::pin!(tags);
+        while let Some(tag) = tags.next().await {
+            let tag = Arc::new(tag?);
+            let mut index = 0;
+            while index < self.senders.len() {
+                if self.senders[index].send(tag.clone()).await.is_err() {
+                    self.senders.remove(index);
+                } else {
+                    index += 1;
+                }
+            }
+            if self.senders.is_empty() {
+                break;
+            }
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::{TagData, TagHeader, TagType};
+    use bytes::Bytes;
+
+    fn tag(timestamp: i32) -> Result<Tag, Exception> {
+        Ok(Tag {
+            header: TagHeader {
+                tag_type: TagType::Reserved(0),
+                data_size: 0,
+                timestamp,
+                offset: 0,
+            },
+            data: TagData::Reserved(Bytes::new()),
+            raw: None,
+            received: None,
+        })
+    }
+
+    #[tokio::test]
+    async fn subscriber_dropped() {
+        let mut broadcast = Broadcast::new(1);
+        let mut dropped = broadcast.subscribe();
+        let mut kept = broadcast.subscribe();
+        let run = tokio::spawn(broadcast.run(tokio_stream::iter((0..5).map(tag))));
+
+        for timestamp in 0..2 {
+            assert_eq!(dropped.recv().await.unwrap().header.timestamp, timestamp);
+        }
+        drop(dropped);
+        for timestamp in 0..5 {
+            assert_eq!(kept.recv().await.unwrap().header.timestamp, timestamp);
+        }
+        assert!(kept.recv().await.is_none());
+        run.await.unwrap().unwrap();
+    }
+}
@@ -0,0 +1,141 @@
+//! Just enough CBOR (RFC 8949) to encode JSON values, for the CBOR output format.
+
+use serde_json::Value;
+use std::io::{self, Write};
+
+const UNSIGNED: u8 = 0;
+const NEGATIVE: u8 = 1;
+const TEXT: u8 = 3;
+const ARRAY: u8 = 4;
+const MAP: u8 = 5;
+
+// Tag 55799, which marks the data as CBOR without changing its meaning.
+pub const SELF_DESCRIBED: [u8; 3] = [0xd9, 0xd9, 0xf7];
+pub const ARRAY_START: u8 = 0x9f;
+pub const MAP_START: u8 = 0xbf;
+/// Ends an array or map of indefinite length.
+pub const BREAK: u8 = 0xff;
+
+// The initial byte with the shortest encoding of the argument.
+fn write_head(out: &mut dyn Write, major: u8, argument: u64) -> io::Result<()> {
+    let major = major << 5;
+    match argument {
+        0..=23 => out.write_all(&[major | argument as u8]),
+        24..=0xff => out.write_all(&[major | 24, argument as u8]),
+        0x100..=0xffff => {
+            out.write_all(&[major | 25])?;
+            out.write_all(&(argument as u16).to_be_bytes())
+        }
+        0x1_0000..=0xffff_ffff => {
+            out.write_all(&[major | 26])?;
+            out.write_all(&(argument as u32).to_be_bytes())
+        }
+        _ => {
+            out.write_all(&[major | 27])?;
+            out.write_all(&argument.to_be_bytes())
+        }
+    }
+}
+
+pub fn write_str(out: &mut dyn Write, s: &str) -> io::Result<()> {
+    write_head(out, TEXT, s.len() as u64)?;
+    out.write_all(s.as_bytes())
+}
+
+pub fn write_value(out: &mut dyn Write, value: &Value) -> io::Result<()> {
+    match value {
+        Value::Null => out.write_all(&[0xf6]),
+        Value::Bool(false) => out.write_all(&[0xf4]),
+        Value::Bool(true) => out.write_all(&[0xf5]),
+        Value::Number(n) => {
+            if let Some(n) = n.as_u64() {
+                write_head(out, UNSIGNED, n)
+            } else if let Some(n) = n.as_i64() {
+                // -1 - n, without overflow for i64::MIN
+                write_head(out, NEGATIVE, !n as u64)
+            } else {
+                out.write_all(&[0xfb])?;
+                out.write_all(&n.as_f64().unwrap_or(f64::NAN).to_be_bytes())
+            }
+        }
+        Value::String(s) => write_str(out, s),
+        Value::Array(items) => {
+            write_head(out, ARRAY, items.len() as u64)?;
+            for item in items {
+                write_value(out, item)?;
+            }
+            Ok(())
+        }
+        Value::Object(object) => {
+            write_head(out, MAP, object.len() as u64)?;
+            for (key, value) in object {
+                write_str(out, key)?;
+                write_value(out, value)?;
+            }
+            Ok(())
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use serde_json::json;
+
+    fn encode(value: Value) -> Vec<u8> {
+        let mut out = vec![];
+        write_value(&mut out, &value).unwrap();
+        out
+    }
+
+    // The examples of RFC 8949, Appendix A.
+    #[test]
+    fn rfc_8949_examples() {
+        let examples: Vec<(Value, &[u8])> = vec![
+            (json!(0), &[0x00]),
+            (json!(23), &[0x17]),
+            (json!(24), &[0x18, 0x18]),
+            (json!(100), &[0x18, 0x64]),
+            (json!(1000), &[0x19, 0x03, 0xe8]),
+            (json!(1000000), &[0x1a, 0x00, 0x0f, 0x42, 0x40]),
+            (
+                json!(1000000000000u64),
+                &[0x1b, 0x00, 0x00, 0x00, 0xe8, 0xd4, 0xa5, 0x10, 0x00],
+            ),
+            (
+                json!(u64::MAX),
+                &[0x1b, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff],
+            ),
+            (json!(-1), &[0x20]),
+            (json!(-100), &[0x38, 0x63]),
+            (json!(-1000), &[0x39, 0x03, 0xe7]),
+            (
+                json!(1.1),
+                &[0xfb, 0x3f, 0xf1, 0x99, 0x99, 0x99, 0x99, 0x99, 0x9a],
+            ),
+            (json!(false), &[0xf4]),
+            (json!(true), &[0xf5]),
+            (json!(null), &[0xf6]),
+            (json!(""), &[0x60]),
+            (json!("IETF"), &[0x64, 0x49, 0x45, 0x54, 0x46]),
+            (json!("\u{fc}"), &[0x62, 0xc3, 0xbc]),
+            (json!([]), &[0x80]),
+            (json!([1, 2, 3]), &[0x83, 0x01, 0x02, 0x03]),
+            (
+                json!({"a": 1, "b": [2, 3]}),
+                &[0xa2, 0x61, 0x61, 0x01, 0x61, 0x62, 0x82, 0x02, 0x03],
+            ),
+        ];
+        for (value, expected) in examples {
+            assert_eq!(encode(value.clone()), expected, "{}", value);
+        }
+    }
+
+    #[test]
+    fn i64_min() {
+        assert_eq!(
+            encode(json!(i64::MIN)),
+            [0x3b, 0x7f, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff, 0xff]
+        );
+    }
+}
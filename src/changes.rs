@@ -0,0 +1,149 @@
+use crate::aac::AudioSpecificConfig;
+use crate::avc::{self, DecoderConfigurationRecord, Sps};
+use crate::findings::{Finding, Rule};
+use crate::reader::{SoundFormat, Tag, TagData};
+use std::convert::TryFrom;
+
+/// What a player has to reinitialize its video decoder for.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct VideoParameters {
+    width: u32,
+    height: u32,
+    profile: u8,
+    level: u8,
+}
+
+impl VideoParameters {
+    fn profile(&self) -> String {
+        match avc::profile_name(self.profile) {
+            Some(name) => format!("{} {}", name, avc::level_name(self.level)),
+            None => format!("{} {}", self.profile, avc::level_name(self.level)),
+        }
+    }
+}
+
+/// What a player has to reinitialize its audio decoder and output for, according to the
+/// AudioSpecificConfig for AAC and to the audio data header for the other formats.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+struct AudioParameters {
+    sound_format: SoundFormat,
+    sample_rate: u32,
+    channels: u8,
+}
+
+/// Tells when a tag changes the parameters of its stream mid-stream, such as the resolution or the
+/// audio sample rate, a frequent cause of player crashes. Tags are numbered from 1 like in the
+/// dump.
+#[derive(Debug, Default)]
+pub struct ParameterChanges {
+    video: Option<VideoParameters>,
+    audio: Option<AudioParameters>,
+}
+
+impl ParameterChanges {
+    /// A change of the sample rate or channel count by an AAC sequence header, or by the audio
+    /// data header of the other formats. A change of the format starts over.
+    pub fn audio(&mut self, index: usize, tag: &Tag) -> Option<Finding> {
+        let audio = match &tag.data {
+            TagData::Audio(audio) => audio,
+            _ => return None,
+        };
+        let header = &audio.header;
+        let (source, parameters) = match header.sound_format {
+            SoundFormat::AAC if tag.data.is_sequence_header() => {
+                let config = AudioSpecificConfig::try_from(&audio.data[1..]).ok()?;
+                let parameters = AudioParameters {
+                    sound_format: SoundFormat::AAC,
+                    sample_rate: config.sampling_frequency,
+                    channels: config.channel_configuration,
+                };
+                ("AAC sequence header", parameters)
+            }
+            // the header of AAC tags is always 44 kHz stereo
+            SoundFormat::AAC => return None,
+            format => {
+                let parameters = AudioParameters {
+                    sound_format: format,
+                    sample_rate: header.sound_rate.hz(),
+                    channels: header.sound_type.channels(),
+                };
+                ("Audio data header", parameters)
+            }
+        };
+        let previous = self.audio.replace(parameters)?;
+        if previous.sound_format != parameters.sound_format {
+            return None;
+        }
+        let mut changes = vec![];
+        if previous.sample_rate != parameters.sample_rate {
+            changes.push(format!(
+                "the sample rate from {} Hz to {} Hz",
+                previous.sample_rate, parameters.sample_rate
+            ));
+        }
+        if previous.channels != parameters.channels {
+            changes.push(format!(
+                "the channels from {} to {}",
+                previous.channels, parameters.channels
+            ));
+        }
+        if changes.is_empty() {
+            return None;
+        }
+        let message = format!(
+            "{} in tag {} at {} ms changes {}",
+            source,
+            index,
+            tag.header.timestamp,
+            changes.join(" and ")
+        );
+        Some(Finding::new(Rule::AudioChange, message))
+    }
+
+    /// A change of the resolution or profile by an AVC sequence header.
+    pub fn video(&mut self, index: usize, tag: &Tag) -> Option<Finding> {
+        match &tag.data {
+            TagData::Video(video) if tag.data.is_sequence_header() => {
+                let parameters = match &video.data[..] {
+                    [_, _, _, _, record @ ..] => {
+                        let record = DecoderConfigurationRecord::try_from(record).ok()?;
+                        let sps = Sps::try_from(&record.sps.first()?[..]).ok()?;
+                        VideoParameters {
+                            width: sps.width,
+                            height: sps.height,
+                            profile: sps.profile,
+                            level: sps.level,
+                        }
+                    }
+                    _ => return None,
+                };
+                let previous = self.video.replace(parameters)?;
+                let mut changes = vec![];
+                if (previous.width, previous.height) != (parameters.width, parameters.height) {
+                    changes.push(format!(
+                        "the resolution from {}x{} to {}x{}",
+                        previous.width, previous.height, parameters.width, parameters.height
+                    ));
+                }
+                if (previous.profile, previous.level) != (parameters.profile, parameters.level) {
+                    changes.push(format!(
+                        "the profile from {} to {}",
+                        previous.profile(),
+                        parameters.profile()
+                    ));
+                }
+                if changes.is_empty() {
+                    return None;
+                }
+                let message = format!(
+                    "AVC sequence header in tag {} at {} ms changes {}",
+                    index,
+                    tag.header.timestamp,
+                    changes.join(" and ")
+                );
+                Some(Finding::new(Rule::ResolutionChange, message))
+            }
+            _ => None,
+        }
+    }
+}
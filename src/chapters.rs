@@ -0,0 +1,188 @@
+use crate::amf::AmfValue;
+use crate::reader::{CodecId, Tag, TagData, VideoFrameType};
+use crate::stats::Stats;
+use std::io::{self, Write};
+
+// Frame rate of the EDL timecodes when neither onMetaData nor the video tells it.
+const DEFAULT_EDL_FPS: u32 = 25;
+
+/// The files `export --chapters` writes.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, clap::ValueEnum)]
+pub enum ChapterFormat {
+    /// FFmpeg metadata with one [CHAPTER] per marker, for `ffmpeg -i FILE -i CHAPTERS
+    /// -map_metadata 1 -map_chapters 1`
+    Ffmetadata,
+    /// CMX 3600 edit decision list with one event per marker, for video editors
+    Edl,
+}
+
+/// A point of the stream to jump to, in ms of the stream.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Marker {
+    pub start: i64,
+    pub end: i64,
+    pub title: String,
+}
+
+/// The structure of a stream as markers: a video keyframe starts every GOP, and `onCuePoint`
+/// tags and the `cuePoints` of onMetaData name points of their own. A cue point at the time of a
+/// keyframe takes its place.
+#[derive(Debug, Default)]
+pub struct Chapters {
+    keyframes: Vec<i64>,
+    cue_points: Vec<(i64, String)>,
+    last: Option<i64>,
+}
+
+impl Chapters {
+    pub fn push(&mut self, tag: &Tag) {
+        let timestamp = tag.header.timestamp as i64;
+        match &tag.data {
+            TagData::Video(video) if !tag.data.is_sequence_header() => {
+                // AVCPacketType 1, not the end of sequence
+                let frame = video.header.codec_id != CodecId::AVC || video.data.first() == Some(&1);
+                if video.header.frame_type == VideoFrameType::KeyFrame && frame {
+                    self.keyframes.push(timestamp);
+                }
+            }
+            TagData::Script(script) => {
+                let values = script.values();
+                match values.first().and_then(AmfValue::as_str) {
+                    Some("onCuePoint") => {
+                        if let Some(cue_point) = values.get(1) {
+                            self.cue_point(cue_point, timestamp);
+                        }
+                    }
+                    Some("onMetaData") => {
+                        if let Some(AmfValue::StrictArray(cue_points)) =
+                            values.get(1).and_then(|data| data.property("cuePoints"))
+                        {
+                            for cue_point in cue_points {
+                                self.cue_point(cue_point, timestamp);
+                            }
+                        }
+                    }
+                    _ => {}
+                }
+                return;
+            }
+            TagData::Audio(_) => {}
+            _ => return,
+        }
+        self.last = Some(self.last.map_or(timestamp, |last| last.max(timestamp)));
+    }
+
+    // `time` is in seconds, the tag timestamp is taken without it.
+    fn cue_point(&mut self, cue_point: &AmfValue, timestamp: i64) {
+        let time = cue_point
+            .property("time")
+            .and_then(AmfValue::as_number)
+            .map_or(timestamp, |seconds| (seconds * 1000.0).round() as i64);
+        let name = cue_point
+            .property("name")
+            .and_then(AmfValue::as_str)
+            .unwrap_or("Cue point")
+            .to_owned();
+        if !self.cue_points.contains(&(time, name.clone())) {
+            self.cue_points.push((time, name));
+        }
+    }
+
+    /// The markers in the order of time, each lasting until the next one or the last frame.
+    pub fn markers(&self) -> Vec<Marker> {
+        let mut points: Vec<(i64, String)> = self.cue_points.clone();
+        for (number, keyframe) in self.keyframes.iter().enumerate() {
+            if !points.iter().any(|(time, _)| time == keyframe) {
+                points.push((*keyframe, format!("Keyframe {}", number + 1)));
+            }
+        }
+        // stable, so cue points at the same time keep their order
+        points.sort_by_key(|(time, _)| *time);
+        let end = self
+            .last
+            .into_iter()
+            .chain(points.last().map(|(time, _)| *time))
+            .max();
+        (0..points.len())
+            .map(|i| Marker {
+                start: points[i].0,
+                end: points
+                    .get(i + 1)
+                    .map_or(end.unwrap_or(0), |(time, _)| *time),
+                title: points[i].1.clone(),
+            })
+            .collect()
+    }
+}
+
+/// Writes the markers as an FFmetadata file with millisecond chapters.
+pub fn write_ffmetadata(out: &mut dyn Write, markers: &[Marker]) -> io::Result<()> {
+    writeln!(out, ";FFMETADATA1")?;
+    for marker in markers {
+        writeln!(out)?;
+        writeln!(out, "[CHAPTER]")?;
+        writeln!(out, "TIMEBASE=1/1000")?;
+        writeln!(out, "START={}", marker.start)?;
+        writeln!(out, "END={}", marker.end)?;
+        writeln!(out, "title={}", escape_ffmetadata(&marker.title))?;
+    }
+    Ok(())
+}
+
+// '=', ';', '#', '\' and line breaks are special in FFmetadata values.
+fn escape_ffmetadata(value: &str) -> String {
+    let mut escaped = String::with_capacity(value.len());
+    for c in value.chars() {
+        if matches!(c, '=' | ';' | '#' | '\\' | '\n') {
+            escaped.push('\\');
+        }
+        escaped.push(c);
+    }
+    escaped
+}
+
+/// The whole number of frames per second of the EDL timecodes: the onMetaData frame rate, or the
+/// one of the most common frame interval.
+pub fn edl_fps(stats: &Stats) -> u32 {
+    stats
+        .metadata_frame_rate
+        .or_else(|| stats.frame_intervals.frame_rate()?.mode())
+        .map(|fps| fps.round() as u32)
+        .filter(|fps| *fps > 0)
+        .unwrap_or(DEFAULT_EDL_FPS)
+}
+
+fn timecode(ms: i64, fps: u32) -> String {
+    let frames = ms.max(0) * fps as i64 / 1000;
+    let fps = fps as i64;
+    format!(
+        "{:02}:{:02}:{:02}:{:02}",
+        frames / fps / 3600,
+        frames / fps / 60 % 60,
+        frames / fps % 60,
+        frames % fps
+    )
+}
+
+/// Writes the markers as the events of a CMX 3600 EDL on a non-drop-frame timecode, the source
+/// and record times both being the times of the stream.
+pub fn write_edl(out: &mut dyn Write, markers: &[Marker], title: &str, fps: u32) -> io::Result<()> {
+    writeln!(out, "TITLE: {}", title)?;
+    writeln!(out, "FCM: NON-DROP FRAME")?;
+    for (number, marker) in markers.iter().enumerate() {
+        let (start, end) = (timecode(marker.start, fps), timecode(marker.end, fps));
+        writeln!(out)?;
+        writeln!(
+            out,
+            "{:03}  AX       V     C        {} {} {} {}",
+            number + 1,
+            start,
+            end,
+            start,
+            end
+        )?;
+        writeln!(out, "* FROM CLIP NAME: {}", title)?;
+        writeln!(out, "* COMMENT: {}", marker.title)?;
+    }
+    Ok(())
+}
@@ -0,0 +1,250 @@
+use crate::reader::{Tag, TagData, VideoFrameType};
+use std::fmt;
+use std::sync::{Arc, Mutex};
+use std::time::{Duration, Instant};
+
+// Reads this close to the previous one found their bytes already buffered, they belong to the
+// same chunk.
+const SAME_CHUNK: Duration = Duration::from_millis(5);
+
+/// Chunks of a HTTP response body, in the order they were received.
+pub type ChunkLog = Arc<Mutex<Vec<Chunk>>>;
+
+/// Bytes returned by one read of a response body.
+#[derive(Debug, Clone, Copy)]
+pub struct Chunk {
+    /// since the first request was issued
+    pub arrival: Duration,
+    /// position of the first byte in the body
+    pub offset: u64,
+    pub size: usize,
+}
+
+/// Records chunks as they arrive, time is measured from its creation.
+#[derive(Debug, Clone)]
+pub struct ChunkRecorder {
+    log: ChunkLog,
+    start: Instant,
+}
+
+impl ChunkRecorder {
+    pub fn new(log: ChunkLog) -> Self {
+        Self {
+            log,
+            start: Instant::now(),
+        }
+    }
+
+    pub fn record(&self, offset: u64, size: usize) {
+        let chunk = Chunk {
+            arrival: self.start.elapsed(),
+            offset,
+            size,
+        };
+        self.log
+            .lock()
+            .expect("chunk log is not poisoned")
+            .push(chunk);
+    }
+}
+
+// The end of a video frame in the input, and what the frame is.
+#[derive(Debug)]
+struct Frame {
+    end: u64,
+    timestamp: i32,
+    keyframe: bool,
+}
+
+/// Correlates the chunks of a HTTP-FLV response with the video frames they complete, to tell
+/// how the origin paces its output: a chunk per frame, a chunk per GOP, or in bursts.
+#[derive(Debug, Default)]
+pub struct ChunkTiming {
+    log: ChunkLog,
+    frames: Vec<Frame>,
+}
+
+impl ChunkTiming {
+    pub fn log(&self) -> ChunkLog {
+        self.log.clone()
+    }
+
+    pub fn push(&mut self, tag: &Tag) {
+        let keyframe = match &tag.data {
+            TagData::Video(video) if !tag.data.is_sequence_header() => {
+                video.header.frame_type == VideoFrameType::KeyFrame
+            }
+            _ => return,
+        };
+        self.frames.push(Frame {
+            end: tag.header.offset + 11 + tag.header.data_size as u64,
+            timestamp: tag.header.timestamp,
+            keyframe,
+        });
+    }
+
+    pub fn report(&self) -> ChunkReport {
+        let chunks = merge(&self.log.lock().expect("chunk log is not poisoned"));
+        let mut report = ChunkReport {
+            chunks: chunks.len(),
+            bytes: chunks.iter().map(|chunk| chunk.size as u64).sum(),
+            min_size: chunks.iter().map(|chunk| chunk.size).min(),
+            max_size: chunks.iter().map(|chunk| chunk.size).max(),
+            ..ChunkReport::default()
+        };
+
+        let gaps: Vec<f64> = chunks
+            .windows(2)
+            .map(|pair| (pair[1].arrival - pair[0].arrival).as_secs_f64() * 1000.0)
+            .collect();
+        if !gaps.is_empty() {
+            let mean = gaps.iter().sum::<f64>() / gaps.len() as f64;
+            let variance =
+                gaps.iter().map(|gap| (gap - mean).powi(2)).sum::<f64>() / gaps.len() as f64;
+            report.mean_gap = Some(mean);
+            report.max_gap = gaps.iter().copied().fold(None, |max: Option<f64>, gap| {
+                Some(max.map_or(gap, |max| max.max(gap)))
+            });
+            if mean > 0.0 {
+                report.burstiness = Some(variance.sqrt() / mean);
+            }
+        }
+
+        // Frames are complete once the chunk holding their last byte has arrived.
+        let mut frames_per_chunk = vec![0usize; chunks.len()];
+        let mut chunk = 0;
+        let mut first: Option<(Duration, i32)> = None;
+        for frame in &self.frames {
+            while chunk < chunks.len()
+                && chunks[chunk].offset + (chunks[chunk].size as u64) < frame.end
+            {
+                chunk += 1;
+            }
+            let arrival = match chunks.get(chunk) {
+                Some(chunk) => chunk.arrival,
+                None => break,
+            };
+            frames_per_chunk[chunk] += 1;
+            let (first_arrival, first_timestamp) = *first.get_or_insert((arrival, frame.timestamp));
+            let media = (frame.timestamp as i64 - first_timestamp as i64) as f64;
+            let wallclock = (arrival - first_arrival).as_secs_f64() * 1000.0;
+            let lead = media - wallclock;
+            report.max_lead = Some(report.max_lead.map_or(lead, |max| max.max(lead)));
+            report.final_lead = Some(lead);
+        }
+
+        let delivering: Vec<usize> = frames_per_chunk
+            .into_iter()
+            .filter(|frames| *frames > 0)
+            .collect();
+        if !delivering.is_empty() {
+            report.frames_per_chunk =
+                Some(delivering.iter().sum::<usize>() as f64 / delivering.len() as f64);
+        }
+        let keyframes = self.frames.iter().filter(|frame| frame.keyframe).count();
+        if keyframes > 0 {
+            report.gop_length = Some(self.frames.len() as f64 / keyframes as f64);
+        }
+        report
+    }
+}
+
+// Merges reads that came back to back, the HTTP client reads a burst in several pieces.
+fn merge(reads: &[Chunk]) -> Vec<Chunk> {
+    let mut chunks: Vec<Chunk> = vec![];
+    let mut previous: Option<Duration> = None;
+    for read in reads {
+        match chunks.last_mut() {
+            Some(chunk)
+                if read.offset == chunk.offset + chunk.size as u64
+                    && previous.is_some_and(|previous| read.arrival - previous < SAME_CHUNK) =>
+            {
+                chunk.size += read.size;
+            }
+            _ => chunks.push(*read),
+        }
+        previous = Some(read.arrival);
+    }
+    chunks
+}
+
+/// How the origin paces its output.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Pacing {
+    /// about one video frame per chunk, the lowest latency
+    PerFrame,
+    /// a GOP or more per chunk, the player waits for whole GOPs
+    PerGop,
+    /// several frames per chunk
+    Batched,
+}
+
+#[derive(Debug, Default)]
+pub struct ChunkReport {
+    pub chunks: usize,
+    pub bytes: u64,
+    pub min_size: Option<usize>,
+    pub max_size: Option<usize>,
+    /// ms between consecutive chunks
+    pub mean_gap: Option<f64>,
+    pub max_gap: Option<f64>,
+    /// coefficient of variation of the gaps, 0 for perfectly regular chunks
+    pub burstiness: Option<f64>,
+    /// video frames completed per chunk that completes at least one
+    pub frames_per_chunk: Option<f64>,
+    /// video frames per keyframe
+    pub gop_length: Option<f64>,
+    /// ms, how far the received media ran ahead of the wallclock since the first frame
+    pub max_lead: Option<f64>,
+    pub final_lead: Option<f64>,
+}
+
+impl ChunkReport {
+    pub fn pacing(&self) -> Option<Pacing> {
+        let frames = self.frames_per_chunk?;
+        Some(match self.gop_length {
+            Some(gop) if gop > 1.0 && frames >= gop / 2.0 => Pacing::PerGop,
+            _ if frames <= 1.5 => Pacing::PerFrame,
+            _ => Pacing::Batched,
+        })
+    }
+}
+
+impl fmt::Display for ChunkReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Chunks: {}", self.chunks)?;
+        writeln!(f, "ChunkBytes: {}", self.bytes)?;
+        if let (Some(min), Some(max)) = (self.min_size, self.max_size) {
+            writeln!(
+                f,
+                "ChunkSize: min {}, mean {:.0}, max {}",
+                min,
+                self.bytes as f64 / self.chunks as f64,
+                max
+            )?;
+        }
+        if let (Some(mean), Some(max)) = (self.mean_gap, self.max_gap) {
+            writeln!(f, "ChunkGap: mean {:.1} ms, max {:.1} ms", mean, max)?;
+        }
+        if let Some(burstiness) = self.burstiness {
+            writeln!(f, "Burstiness: {:.2}", burstiness)?;
+        }
+        if let Some(frames) = self.frames_per_chunk {
+            writeln!(f, "FramesPerChunk: {:.2}", frames)?;
+        }
+        if let Some(gop) = self.gop_length {
+            writeln!(f, "GopLength: {:.1} frames", gop)?;
+        }
+        if let Some(pacing) = self.pacing() {
+            writeln!(f, "Pacing: {:?}", pacing)?;
+        }
+        if let (Some(max), Some(last)) = (self.max_lead, self.final_lead) {
+            writeln!(
+                f,
+                "MediaAheadOfWallclock: max {:.0} ms, final {:.0} ms",
+                max, last
+            )?;
+        }
+        Ok(())
+    }
+}
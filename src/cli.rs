@@ -0,0 +1,575 @@
+use crate::amf::LegacyEncoding;
+use crate::assertions::{parse_percent, Assertions};
+use crate::chapters::ChapterFormat;
+use crate::encrypt::{parse_key, KEY_SIZE};
+use crate::findings::{Rule, Severity};
+use crate::generate::{AudioCodec, GenerateOptions, TimestampJump, VideoCodec};
+use crate::lint::Profile;
+use crate::output::Format;
+use crate::reader::{
+    BufferGrowth, Overflow, ParseMode, ParseOptions, DEFAULT_BUFFER_SIZE, DEFAULT_MAX_TAG_SIZE,
+};
+use crate::rewrite::RewriteOptions;
+use crate::rotate::Rotation;
+use crate::sample::Sampling;
+use crate::time;
+use crate::Exception;
+use clap::{Args, Parser, Subcommand};
+use std::path::PathBuf;
+use std::time::Duration;
+
+#[derive(Debug, Parser)]
+#[command(version, about, args_conflicts_with_subcommands = true)]
+pub struct Opts {
+    #[command(subcommand)]
+    pub command: Option<Command>,
+
+    /// FLV file, named pipe, unix://<path> socket, tcp:// or udp:// address, HTTP(S)/S3/SRT URL
+    /// or tar/zip archive of FLV files to dump
+    #[arg(default_value = "./resources/test.flv")]
+    pub path: String,
+
+    /// More inputs to summarize with --oneline, one line each
+    #[arg(value_name = "PATH", requires = "oneline")]
+    pub paths: Vec<String>,
+
+    /// Print stream statistics instead of dumping every tag
+    #[arg(long)]
+    pub stats: bool,
+
+    /// Output format
+    #[arg(long, value_enum, default_value = "text")]
+    pub format: Format,
+
+    /// Print one line per tag from a template such as '{index}\t{type}\t{ts}\t{size}'.
+    /// Placeholders are the CSV column names plus the aliases type, ts and size
+    #[arg(long, conflicts_with = "format")]
+    pub template: Option<String>,
+
+    /// Export the DTS, CompositionTime and PTS of every audio and video frame instead of the tags,
+    /// as CSV or JSON according to --format
+    #[arg(long, conflicts_with_all = ["stats", "template"])]
+    pub timeline: bool,
+
+    /// Print a single summary line per file instead of the tags: path, duration, codecs,
+    /// resolution, bitrate and the number of errors and warnings, e.g. to audit the files of an
+    /// archive or all the PATHs given with grep
+    #[arg(
+        long,
+        conflicts_with_all = ["format", "template", "timeline", "stats", "sample_every"]
+    )]
+    pub oneline: bool,
+
+    /// Print the JSON Schema of the JSON and NDJSON output and exit
+    #[arg(long)]
+    pub schema: bool,
+
+    /// Write the output to this file instead of stdout
+    #[arg(short, long, value_name = "FILE")]
+    pub output: Option<PathBuf>,
+
+    /// Gzip compress the output, e.g. for large JSON, NDJSON or CSV exports
+    #[arg(long)]
+    pub gzip_output: bool,
+
+    /// Start a new --output file once it has this size, renaming the full one by appending the
+    /// UTC time, e.g. for an NDJSON log of a live input that runs for days. Rotation happens
+    /// between lines only
+    #[arg(
+        long,
+        value_name = "BYTES",
+        requires = "output",
+        conflicts_with = "gzip_output"
+    )]
+    pub rotate_size: Option<u64>,
+
+    /// Start a new --output file after this long, such as 1h (units ms, s, m and h)
+    #[arg(
+        long,
+        value_name = "INTERVAL",
+        value_parser = time::parse_duration,
+        requires = "output",
+        conflicts_with = "gzip_output"
+    )]
+    pub rotate_interval: Option<Duration>,
+
+    /// Number of rotated --output files to keep, older ones are deleted
+    #[arg(long, value_name = "COUNT", default_value_t = 10)]
+    pub retain: usize,
+
+    /// Also write the input to this file exactly as received, e.g. to keep a copy of a network
+    /// stream for offline debugging
+    #[arg(long, value_name = "FILE", conflicts_with_all = ["tail", "seek_time"])]
+    pub tee: Option<PathBuf>,
+
+    /// Copy the input to stdout unchanged and write the dump to stderr (unless --output is
+    /// given), e.g. in `curl ... | flv-dump --passthrough /dev/stdin | ffplay -`
+    #[arg(long, conflicts_with_all = ["tail", "seek_time"])]
+    pub passthrough: bool,
+
+    /// For HTTP(S) and S3 inputs, record when each chunk of the response arrives and print a
+    /// report of the origin's pacing (burstiness, chunks per frame or per GOP) to stderr after
+    /// the dump
+    #[arg(long, conflicts_with_all = ["tail", "seek_time"])]
+    pub chunk_timing: bool,
+
+    /// Print to stderr after the dump how long reading the input, decoding the tags, parsing the
+    /// audio and video codecs, formatting and writing the output took, and the peak memory, e.g.
+    /// to find out why the analysis of a large file is slow
+    #[arg(long)]
+    pub profile: bool,
+
+    /// Record when each tag is received and print it with the tag in the text, JSON and NDJSON
+    /// output, e.g. to correlate a live stream with other logs
+    #[arg(long)]
+    pub wallclock: bool,
+
+    /// Stop after this many tags
+    #[arg(short = 'n', long, value_name = "COUNT")]
+    pub limit: Option<usize>,
+
+    /// Skip this many tags before dumping
+    #[arg(long, value_name = "COUNT", default_value_t = 0)]
+    pub skip: usize,
+
+    /// Only dump representative tags of long recordings: the first audio, video and script tag of
+    /// every interval such as 10s or 500ms (units ms, s, m and h), or every Nth tag for a plain
+    /// number N. PreviousTagSize fields are left out
+    #[arg(long, value_name = "INTERVAL", conflicts_with = "stats")]
+    pub sample_every: Option<Sampling>,
+
+    /// Instead of the tags, print rolling statistics of a live input at the end of every
+    /// interval such as 10s (units ms, s, m and h): bitrates, the largest timestamp gap, the
+    /// latency behind real time and the number of errors and warnings. Text or NDJSON
+    #[arg(
+        long,
+        value_name = "INTERVAL",
+        value_parser = time::parse_duration,
+        conflicts_with_all = ["stats", "template", "timeline", "oneline", "sample_every"]
+    )]
+    pub stats_interval: Option<Duration>,
+
+    /// Only dump the last COUNT tags of a local file, found by walking the PreviousTagSize fields
+    /// backwards from the end
+    #[arg(long, value_name = "COUNT", conflicts_with = "seek_time")]
+    pub tail: Option<usize>,
+
+    /// Start at the first tag of a local file with at least this timestamp, found by bisection
+    /// instead of reading the file from the start
+    #[arg(long, value_name = "MS")]
+    pub seek_time: Option<i32>,
+
+    /// Abort on spec violations: a PreviousTagSize that does not match the previous tag, an
+    /// invalid audio or video data header (e.g. an unknown sound format) or a non-zero StreamID
+    #[arg(long, conflicts_with = "lenient")]
+    pub strict: bool,
+
+    /// Report spec violations as warnings and keep parsing (default)
+    #[arg(long)]
+    pub lenient: bool,
+
+    /// Do not report the findings of this rule, even in strict mode, e.g. stream-id. Can be
+    /// repeated
+    #[arg(long, value_name = "RULE")]
+    pub suppress: Vec<Rule>,
+
+    /// Decode AMF strings that are not valid UTF-8 with this encoding, e.g. gbk for the titles
+    /// written by older Chinese encoders. They are reported as amf-string findings either way
+    #[arg(long, value_enum, value_name = "ENCODING")]
+    pub legacy_encoding: Option<LegacyEncoding>,
+
+    /// Check every file against the stream described in this TOML file (video codec,
+    /// resolution, frame rate range, audio codec, sample rate and channels, bitrate range) and
+    /// report each deviation as an expectation finding
+    #[arg(long, value_name = "FILE")]
+    pub expect: Option<PathBuf>,
+
+    /// Fail if two keyframes are further apart than this. The --assert-* checks are reported on
+    /// stderr after each file, any that fails makes the exit status an error
+    #[arg(long, value_name = "MS")]
+    pub assert_max_keyframe_interval: Option<i64>,
+
+    /// Fail if the file has no audio
+    #[arg(long)]
+    pub assert_has_audio: bool,
+
+    /// Fail if the file has no video
+    #[arg(long)]
+    pub assert_has_video: bool,
+
+    /// Fail if the audio and video last longer or shorter than the onMetaData duration by more
+    /// than this, e.g. 1%
+    #[arg(long, value_name = "PERCENT", value_parser = parse_percent)]
+    pub assert_duration_within: Option<f64>,
+
+    /// Exit with an error after the dump if a finding is at least this severe
+    #[arg(long, value_enum, value_name = "SEVERITY")]
+    pub fail_on: Option<Severity>,
+
+    /// POST a JSON alert to this URL for findings of at least --webhook-severity, stalls and
+    /// codec changes, e.g. to page the on-call while monitoring a live stream
+    #[arg(long, value_name = "URL")]
+    pub webhook: Option<String>,
+
+    /// Least severity of the findings sent to the webhook
+    #[arg(
+        long,
+        value_enum,
+        value_name = "SEVERITY",
+        default_value = "error",
+        requires = "webhook"
+    )]
+    pub webhook_severity: Severity,
+
+    /// Alert the webhook when no data arrives for this many seconds
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        default_value_t = 10,
+        requires = "webhook"
+    )]
+    pub stall_timeout: u64,
+
+    /// Tags with a larger DataSize are treated as corrupted, in lenient mode parsing resumes at
+    /// the next plausible tag
+    #[arg(long, value_name = "BYTES", default_value_t = DEFAULT_MAX_TAG_SIZE)]
+    pub max_tag_size: u32,
+
+    /// Size of the read buffer, e.g. small for live streams that arrive in tiny chunks and large
+    /// for files with big keyframes
+    #[arg(long, value_name = "BYTES", default_value_t = DEFAULT_BUFFER_SIZE)]
+    pub buffer_size: usize,
+
+    /// How the buffer grows while a tag is incomplete: a little at a time, or to the size of the
+    /// whole tag as soon as its header has been read
+    #[arg(long, value_enum, default_value = "incremental")]
+    pub buffer_growth: BufferGrowth,
+
+    /// Most bytes to buffer for an incomplete tag, e.g. to bound the memory taken by live inputs
+    #[arg(long, value_name = "BYTES")]
+    pub max_buffered: Option<usize>,
+
+    /// What to do with a tag that is larger than --max-buffered: stop with an error, or skip it
+    /// without buffering it and report a warning
+    #[arg(long, value_enum, default_value = "error", requires = "max_buffered")]
+    pub on_overflow: Overflow,
+}
+
+impl Opts {
+    pub fn assertions(&self) -> Assertions {
+        Assertions {
+            max_keyframe_interval: self.assert_max_keyframe_interval,
+            has_audio: self.assert_has_audio,
+            has_video: self.assert_has_video,
+            duration_within: self.assert_duration_within,
+        }
+    }
+
+    pub fn rotation(&self) -> Option<Rotation> {
+        if self.rotate_size.is_none() && self.rotate_interval.is_none() {
+            return None;
+        }
+        Some(Rotation {
+            max_size: self.rotate_size,
+            interval: self.rotate_interval,
+            keep: self.retain,
+        })
+    }
+
+    pub fn parse_options(&self) -> ParseOptions {
+        ParseOptions {
+            mode: if self.strict && !self.lenient {
+                ParseMode::Strict
+            } else {
+                ParseMode::Lenient
+            },
+            max_tag_size: self.max_tag_size,
+            buffer_size: self.buffer_size,
+            buffer_growth: self.buffer_growth,
+            max_buffered: self.max_buffered,
+            overflow: self.on_overflow,
+            wallclock: self.wallclock,
+            suppressed: self.suppress.iter().copied().collect(),
+            legacy_encoding: self.legacy_encoding,
+            ..ParseOptions::default()
+        }
+    }
+}
+
+#[derive(Debug, Subcommand)]
+pub enum Command {
+    /// Rewrite a FLV file, applying the selected repairs
+    Rewrite(RewriteArgs),
+    /// Publish a FLV file to an RTMP server in real time
+    Publish {
+        /// rtmp://host[:port]/app/stream_key
+        url: String,
+        /// FLV file, named pipe, unix://<path> socket or URL
+        input: String,
+    },
+    /// Check every FLV file in a directory against a policy and print a pass/fail table
+    Audit {
+        dir: PathBuf,
+        /// TOML file with the requirements: audio_codecs, video_codecs, max_keyframe_interval
+        /// (ms), resolutions ("WIDTHxHEIGHT") and max_bitrate (kbps)
+        #[arg(long)]
+        policy: PathBuf,
+    },
+    /// Generate a synthetic FLV file with filler payloads and optional defects
+    Generate(GenerateArgs),
+    /// Copy a FLV file with the payloads of its audio and video frames encrypted with AES-128,
+    /// marked with the Filter bit of the FLV specification
+    Encrypt {
+        /// FLV file, named pipe, unix://<path> socket or URL
+        input: String,
+        output: PathBuf,
+        #[command(flatten)]
+        key: KeyArgs,
+    },
+    /// Copy a FLV file encrypted by `encrypt`, or with the same Filter scheme, decrypting its tags
+    Decrypt {
+        /// FLV file, named pipe, unix://<path> socket or URL
+        input: String,
+        output: PathBuf,
+        #[command(flatten)]
+        key: KeyArgs,
+    },
+    /// Copy a FLV file with its audio and video payloads blanked out, keeping all headers, sizes,
+    /// timestamps and script data, e.g. to attach to a bug report
+    Anonymize {
+        input: PathBuf,
+        output: PathBuf,
+        /// Fill the payloads with random bytes instead of zeros
+        #[arg(long)]
+        random: bool,
+    },
+    /// Copy a FLV file with the cues of an SRT file added as onTextData script tags, e.g. to
+    /// test the caption rendering of a player
+    InjectSubs {
+        /// FLV file, named pipe, unix://<path> socket or URL
+        input: String,
+        output: PathBuf,
+        /// SRT file with the subtitles
+        #[arg(long)]
+        srt: PathBuf,
+        /// Language code of the onTextData tags
+        #[arg(long, default_value = "eng")]
+        language: String,
+        /// Milliseconds added to the SRT times to get the timestamps, may be negative
+        #[arg(
+            long,
+            value_name = "MS",
+            default_value_t = 0,
+            allow_negative_numbers = true
+        )]
+        offset: i64,
+    },
+    /// Write the per-tag table, the columns of `--format csv`, to a file for data analysis
+    Export {
+        /// FLV file, named pipe, unix://<path> socket or URL
+        input: String,
+        output: PathBuf,
+        /// Write an Arrow IPC file (Feather V2) instead of CSV, e.g. for pandas.read_feather
+        #[arg(long)]
+        arrow: bool,
+        /// Write the keyframes and cue points as chapters instead of the tags
+        #[arg(long, value_enum, value_name = "FORMAT", conflicts_with = "arrow")]
+        chapters: Option<ChapterFormat>,
+    },
+    /// Check a FLV file against the rules of a profile and print a pass/fail table
+    Lint {
+        /// FLV file, named pipe, unix://<path> socket or URL
+        input: String,
+        #[arg(long, value_enum, default_value = "low-latency")]
+        profile: Profile,
+    },
+    /// Keep the last minutes of a live stream in memory and serve them over HTTP-FLV from any
+    /// point: GET /dvr.flv?from=MS or ?ago=MS, or the live edge without either, and GET /window
+    Dvr {
+        /// FLV file, named pipe, unix://<path> socket or URL
+        input: String,
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8081")]
+        listen: String,
+        /// Length of the time-shift window, e.g. 10m
+        #[arg(long, value_name = "DURATION", value_parser = time::parse_duration, default_value = "10m")]
+        window: Duration,
+    },
+    /// Record the hashes of a FLV file and its tags in a sidecar manifest, or check a file
+    /// against one, e.g. to show that an archived recording was not altered
+    #[command(subcommand)]
+    Manifest(ManifestCommand),
+    /// Serve the analyzer as a REST API: POST /analyze with a FLV file or {"url": ...} returns
+    /// its statistics and findings, GET /files/{id}/tags?from=MS&to=MS its tags
+    ApiServe {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:8080")]
+        listen: String,
+        /// Number of analyzed files kept for GET /files, the oldest are forgotten first
+        #[arg(long, default_value_t = 100)]
+        max_files: usize,
+        /// Largest FLV file accepted by POST /analyze
+        #[arg(long, value_name = "BYTES", default_value_t = 1 << 30)]
+        max_upload: usize,
+    },
+    /// Serve the analyzer over gRPC (proto/flv_dump.proto): Analyze streams the tags and findings
+    /// of a file, socket or URL the server can read, AnalyzeStream those of the bytes sent to it
+    #[cfg(feature = "grpc")]
+    GrpcServe {
+        /// Address to listen on
+        #[arg(long, default_value = "127.0.0.1:50051")]
+        listen: String,
+    },
+}
+
+#[derive(Debug, Subcommand)]
+pub enum ManifestCommand {
+    /// Write the SHA-256 of the file and of every tag to the manifest
+    Create {
+        input: PathBuf,
+        /// Manifest file, <INPUT>.manifest.json by default
+        #[arg(long)]
+        manifest: Option<PathBuf>,
+    },
+    /// Parse the file again and fail if it or any tag differs from the manifest
+    Verify {
+        input: PathBuf,
+        /// Manifest file, <INPUT>.manifest.json by default
+        #[arg(long)]
+        manifest: Option<PathBuf>,
+    },
+}
+
+#[derive(Debug, Args)]
+#[group(required = true, multiple = false)]
+pub struct KeyArgs {
+    /// AES-128 key as 32 hexadecimal digits
+    #[arg(long, value_name = "HEX")]
+    pub key: Option<String>,
+
+    /// File holding the key as 32 hexadecimal digits, which keeps it out of the process list
+    #[arg(long, value_name = "FILE")]
+    pub key_file: Option<PathBuf>,
+}
+
+impl KeyArgs {
+    pub fn key(&self) -> Result<[u8; KEY_SIZE], Exception> {
+        match (&self.key, &self.key_file) {
+            (Some(key), _) => parse_key(key),
+            (None, Some(path)) => {
+                let key = std::fs::read_to_string(path)
+                    .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+                parse_key(&key)
+            }
+            (None, None) => Err("No key given".into()),
+        }
+    }
+}
+
+#[derive(Debug, Args)]
+pub struct RewriteArgs {
+    /// FLV file, named pipe or unix://<path> socket
+    pub input: String,
+    pub output: PathBuf,
+
+    /// Trim leading audio or video so both streams start within this many milliseconds of each
+    /// other, rebasing timestamps to 0
+    #[arg(long, value_name = "MS", num_args = 0..=1, default_missing_value = "100")]
+    pub align_start: Option<u32>,
+
+    /// Fill audio gaps longer than one frame plus this many milliseconds with silent AAC frames
+    #[arg(long, value_name = "MS", num_args = 0..=1, default_missing_value = "50")]
+    pub fill_audio_gaps: Option<u32>,
+
+    /// Re-interleave tags so that no stream runs ahead of the others by more than this many
+    /// milliseconds
+    #[arg(long, value_name = "MS", num_args = 0..=1, default_missing_value = "500")]
+    pub interleave: Option<u32>,
+
+    /// Remove the DisposableInterFrame video tags, e.g. for a lower bitrate preview copy that
+    /// needs no re-encoding
+    #[arg(long)]
+    pub drop_disposable: bool,
+
+    /// Sort tags whose timestamps are out of order, e.g. after naive concatenation, holding back
+    /// each tag until one this many milliseconds later has been read
+    #[arg(long, value_name = "MS", num_args = 0..=1, default_missing_value = "1000")]
+    pub reorder: Option<u32>,
+}
+
+#[derive(Debug, Args)]
+pub struct GenerateArgs {
+    pub output: PathBuf,
+
+    /// Duration in milliseconds
+    #[arg(long, value_name = "MS", default_value_t = 10000)]
+    pub duration: u32,
+
+    /// Audio codec, 44.1 kHz stereo
+    #[arg(long, value_enum, default_value = "aac")]
+    pub audio: AudioCodec,
+
+    /// Video codec
+    #[arg(long, value_enum, default_value = "avc")]
+    pub video: VideoCodec,
+
+    /// Video frames per second
+    #[arg(long, value_name = "FPS", default_value_t = 30.0)]
+    pub frame_rate: f64,
+
+    /// Number of frames from one keyframe to the next
+    #[arg(long, value_name = "FRAMES", default_value_t = 60)]
+    pub gop: u32,
+
+    /// DataSize of keyframe tags
+    #[arg(long, value_name = "BYTES", default_value_t = 20000)]
+    pub keyframe_size: usize,
+
+    /// DataSize of the other video tags
+    #[arg(long, value_name = "BYTES", default_value_t = 4000)]
+    pub video_tag_size: usize,
+
+    /// DataSize of AAC frame tags, a silent AAC frame by default. MP3 tags always hold one
+    /// 128 kbps frame
+    #[arg(long, value_name = "BYTES")]
+    pub audio_tag_size: Option<usize>,
+
+    /// Write a wrong PreviousTagSize after this tag (counted from 1, the onMetaData tag), can be
+    /// repeated
+    #[arg(long, value_name = "INDEX")]
+    pub bad_pre_tag_size: Vec<usize>,
+
+    /// Shift the timestamps of all tags from AT milliseconds on by DELTA milliseconds, can be
+    /// repeated
+    #[arg(long, value_name = "AT:DELTA")]
+    pub ts_jump: Vec<TimestampJump>,
+}
+
+impl From<&GenerateArgs> for GenerateOptions {
+    fn from(args: &GenerateArgs) -> Self {
+        Self {
+            duration: args.duration,
+            audio: args.audio,
+            video: args.video,
+            frame_rate: args.frame_rate,
+            gop: args.gop,
+            keyframe_size: args.keyframe_size,
+            video_tag_size: args.video_tag_size,
+            audio_tag_size: args.audio_tag_size,
+            bad_pre_tag_sizes: args.bad_pre_tag_size.clone(),
+            timestamp_jumps: args.ts_jump.clone(),
+        }
+    }
+}
+
+impl From<&RewriteArgs> for RewriteOptions {
+    fn from(args: &RewriteArgs) -> Self {
+        Self {
+            align_start: args.align_start,
+            fill_audio_gaps: args.fill_audio_gaps,
+            interleave: args.interleave,
+            drop_disposable: args.drop_disposable,
+            reorder: args.reorder,
+        }
+    }
+}
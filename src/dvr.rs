@@ -0,0 +1,365 @@
+use crate::api::{read_request, write_response, Request, Response};
+use crate::input::open_input;
+use crate::reader::{read_flv, Field, Header, ParseOptions, Tag, TagData, TagType, VideoFrameType};
+use crate::Exception;
+use bytes::{BufMut, Bytes, BytesMut};
+use serde_json::json;
+use std::collections::VecDeque;
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
+use tokio::io::{AsyncWriteExt, BufReader};
+use tokio::net::{TcpListener, TcpStream};
+use tokio::sync::watch;
+use tokio::time::{sleep_until, Instant};
+use tokio_stream::StreamExt;
+
+// Clients get this much ahead of the timestamps at first, for the player to fill its buffer.
+const BURST: Duration = Duration::from_secs(1);
+// Tags copied out of the window at a time, so that the reader is not held up by slow clients.
+const BATCH: usize = 64;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+enum Kind {
+    Metadata,
+    AudioConfig,
+    VideoConfig,
+    /// A tag a client can start from: a video keyframe, or any audio frame without video.
+    Start,
+    Frame,
+}
+
+struct Entry {
+    kind: Kind,
+    timestamp: i32,
+    raw: Bytes,
+}
+
+/// The tags of the last `length` of the stream, numbered in the order they came. The onMetaData
+/// and sequence headers that were in effect at the first of them are kept aside, since every
+/// client needs them whatever point it starts from.
+struct Window {
+    length: i64,
+    first: u64,
+    entries: VecDeque<Entry>,
+    metadata: Option<Bytes>,
+    audio_config: Option<Bytes>,
+    video_config: Option<Bytes>,
+    video: bool,
+    bytes: usize,
+    ended: bool,
+}
+
+impl Window {
+    fn new(length: Duration) -> Self {
+        Self {
+            length: length.as_millis() as i64,
+            first: 0,
+            entries: VecDeque::new(),
+            metadata: None,
+            audio_config: None,
+            video_config: None,
+            video: false,
+            bytes: 0,
+            ended: false,
+        }
+    }
+
+    fn end(&self) -> u64 {
+        self.first + self.entries.len() as u64
+    }
+
+    fn push(&mut self, tag: &Tag) {
+        let raw = match &tag.raw {
+            Some(raw) => raw.clone(),
+            None => return,
+        };
+        let kind = match &tag.data {
+            TagData::Script(script) if script.metadata().is_some() => Kind::Metadata,
+            TagData::Audio(_) if tag.data.is_sequence_header() => Kind::AudioConfig,
+            TagData::Video(_) if tag.data.is_sequence_header() => Kind::VideoConfig,
+            TagData::Video(video) => {
+                self.video = true;
+                if video.header.frame_type == VideoFrameType::KeyFrame {
+                    Kind::Start
+                } else {
+                    Kind::Frame
+                }
+            }
+            TagData::Audio(_) if !self.video => Kind::Start,
+            _ if matches!(tag.header.tag_type, TagType::Reserved(_)) => return,
+            _ => Kind::Frame,
+        };
+        let timestamp = tag.header.timestamp;
+        self.bytes += raw.len();
+        self.entries.push_back(Entry {
+            kind,
+            timestamp,
+            raw,
+        });
+
+        // a jump back in time keeps everything until the timestamps catch up
+        while let Some(entry) = self.entries.front() {
+            if timestamp as i64 - entry.timestamp as i64 <= self.length {
+                break;
+            }
+            let entry = self.entries.pop_front().unwrap();
+            self.first += 1;
+            self.bytes -= entry.raw.len();
+            match entry.kind {
+                Kind::Metadata => self.metadata = Some(entry.raw),
+                Kind::AudioConfig => self.audio_config = Some(entry.raw),
+                Kind::VideoConfig => self.video_config = Some(entry.raw),
+                _ => {}
+            }
+        }
+    }
+
+    fn entry(&self, index: u64) -> Option<&Entry> {
+        self.entries.get(index.checked_sub(self.first)? as usize)
+    }
+
+    /// The first tag to start from at or after `timestamp`, the last one before the end of the
+    /// window if there is none, or the last one for the live edge.
+    fn start(&self, timestamp: Option<i64>) -> Option<u64> {
+        let mut starts = (self.first..self.end()).filter(|index| {
+            let entry = self.entry(*index).unwrap();
+            // audio frames are only a start until the video begins
+            entry.kind == Kind::Start && (self.video == (entry.raw[0] & 0x1f == 9))
+        });
+        match timestamp {
+            Some(timestamp) => {
+                let mut last = None;
+                for index in starts {
+                    if self.entry(index).unwrap().timestamp as i64 >= timestamp {
+                        return Some(index);
+                    }
+                    last = Some(index);
+                }
+                last
+            }
+            None => starts.next_back(),
+        }
+    }
+
+    /// The onMetaData and sequence headers in effect at `index`.
+    fn configs(&self, index: u64) -> Vec<Bytes> {
+        let (mut metadata, mut audio, mut video) = (
+            self.metadata.clone(),
+            self.audio_config.clone(),
+            self.video_config.clone(),
+        );
+        for entry in self.entries.range(..(index - self.first) as usize) {
+            match entry.kind {
+                Kind::Metadata => metadata = Some(entry.raw.clone()),
+                Kind::AudioConfig => audio = Some(entry.raw.clone()),
+                Kind::VideoConfig => video = Some(entry.raw.clone()),
+                _ => {}
+            }
+        }
+        vec![metadata, video, audio].into_iter().flatten().collect()
+    }
+}
+
+/// Reads a live stream and keeps the last `window` of it in memory, serving it over HTTP on
+/// `listen` to play it back from any point, like the time shift of a DVR:
+///
+/// * `GET /dvr.flv` starts at the last keyframe, the live edge,
+/// * `GET /dvr.flv?from=MS` at the first keyframe from that timestamp of the stream on,
+/// * `GET /dvr.flv?ago=MS` that many milliseconds behind the live edge,
+/// * `GET /window` returns the timestamps, tags and bytes in the window.
+///
+/// The onMetaData and sequence headers in effect are sent first, then the tags in real time with
+/// their timestamps rebased to start at 0. A client that falls behind the window skips to the
+/// next keyframe still in it. The window is served until interrupted, also after the input ended.
+pub async fn dvr(source: &str, listen: &str, window: Duration) -> Result<(), Exception> {
+    let (_, input) = open_input(source).await?;
+    let options = ParseOptions {
+        keep_raw: true,
+        ..ParseOptions::default()
+    };
+    let (header, mut fields) = read_flv(input, options).await?;
+    let header = Arc::new(header);
+    let shared = Arc::new(Mutex::new(Window::new(window)));
+    let (pushed, updates) = watch::channel(0u64);
+
+    let listener = TcpListener::bind(listen).await?;
+    eprintln!("Listening on http://{}/dvr.flv", listener.local_addr()?);
+
+    let reader = shared.clone();
+    tokio::spawn(async move {
+        while let Some(field) = fields.next().await {
+            match field {
+                Ok(Field::Tag(tag)) => {
+                    let end = {
+                        let mut window = reader.lock().unwrap();
+                        window.push(&tag);
+                        window.end()
+                    };
+                    pushed.send_replace(end);
+                }
+                Ok(Field::PreTagSize(_)) => {}
+                Ok(Field::Warning(finding)) => eprintln!("{}", finding),
+                Err(e) => {
+                    eprintln!("{}", e);
+                    break;
+                }
+            }
+        }
+        reader.lock().unwrap().ended = true;
+        eprintln!("The input ended, serving the window until interrupted");
+        // dropping the sender wakes up the clients
+    });
+
+    loop {
+        let (stream, peer) = listener.accept().await?;
+        let (header, shared, updates) = (header.clone(), shared.clone(), updates.clone());
+        tokio::spawn(async move {
+            if let Err(e) = serve(stream, &header, &shared, updates).await {
+                eprintln!("{}: {}", peer, e);
+            }
+        });
+    }
+}
+
+async fn serve(
+    stream: TcpStream,
+    header: &Header,
+    shared: &Mutex<Window>,
+    updates: watch::Receiver<u64>,
+) -> Result<(), Exception> {
+    let mut stream = BufReader::new(stream);
+    let request = match read_request(&mut stream, 0).await {
+        Ok(request) => request,
+        Err(response) => return write_response(stream.get_mut(), &response).await,
+    };
+    let segments: Vec<&str> = request.path.trim_matches('/').split('/').collect();
+    let response = match (request.method.as_str(), segments.as_slice()) {
+        ("GET", ["dvr.flv"]) => match start(&request, shared) {
+            Ok(index) => return stream_flv(stream.get_mut(), header, shared, updates, index).await,
+            Err(response) => response,
+        },
+        ("GET", ["window"]) => {
+            let window = shared.lock().unwrap();
+            let front = window.entries.front();
+            let back = window.entries.back();
+            Response::ok(json!({
+                "from": front.map(|entry| entry.timestamp),
+                "to": back.map(|entry| entry.timestamp),
+                "tags": window.entries.len(),
+                "bytes": window.bytes,
+                "ended": window.ended,
+            }))
+        }
+        (_, ["dvr.flv"]) | (_, ["window"]) => {
+            Response::error(405, format!("{} is not allowed", request.method))
+        }
+        _ => Response::error(404, format!("No such endpoint: {}", request.path)),
+    };
+    write_response(stream.get_mut(), &response).await
+}
+
+// Where the request starts in the window.
+fn start(request: &Request, shared: &Mutex<Window>) -> Result<u64, Response> {
+    let parameter = |name| -> Result<Option<i64>, Response> {
+        match request.query.iter().find(|(key, _)| key == name) {
+            Some((_, value)) => value
+                .parse()
+                .map(Some)
+                .map_err(|_| Response::error(400, format!("Invalid {}: {}", name, value))),
+            None => Ok(None),
+        }
+    };
+    let (from, ago) = (parameter("from")?, parameter("ago")?);
+    let window = shared.lock().unwrap();
+    let timestamp = match (from, ago) {
+        (Some(_), Some(_)) => return Err(Response::error(400, "Give either from or ago")),
+        (Some(from), None) => Some(from),
+        (None, Some(ago)) => window
+            .entries
+            .back()
+            .map(|entry| entry.timestamp as i64 - ago),
+        (None, None) => None,
+    };
+    window
+        .start(timestamp)
+        .ok_or_else(|| Response::error(404, "No keyframe in the window yet"))
+}
+
+// Changes the timestamp in the tag header of a raw tag.
+fn retimed(raw: &Bytes, timestamp: i32, dst: &mut BytesMut) -> u32 {
+    let start = dst.len();
+    dst.extend_from_slice(raw);
+    let bytes = (timestamp as u32).to_be_bytes();
+    dst[start + 4..start + 7].copy_from_slice(&bytes[1..]);
+    dst[start + 7] = bytes[0];
+    raw.len() as u32
+}
+
+async fn stream_flv(
+    stream: &mut TcpStream,
+    header: &Header,
+    shared: &Mutex<Window>,
+    mut updates: watch::Receiver<u64>,
+    mut next: u64,
+) -> Result<(), Exception> {
+    let mut buf = BytesMut::new();
+    buf.extend_from_slice(
+        b"HTTP/1.1 200 OK\r\nContent-Type: video/x-flv\r\nCache-Control: no-cache\r\nConnection: close\r\n\r\n",
+    );
+    header.encode(&mut buf);
+    let (configs, first_timestamp) = {
+        let window = shared.lock().unwrap();
+        let first = window.entry(next).map_or(0, |entry| entry.timestamp);
+        (window.configs(next), first)
+    };
+    let mut pre_tag_size = 0;
+    for config in &configs {
+        buf.put_u32(pre_tag_size);
+        pre_tag_size = retimed(config, 0, &mut buf);
+    }
+    stream.write_all(&buf).await?;
+
+    let started = Instant::now();
+    loop {
+        updates.borrow_and_update();
+        let (batch, ended) = {
+            let window = shared.lock().unwrap();
+            if next < window.first {
+                let skipped = next;
+                let front = window.entries.front().map(|entry| entry.timestamp as i64);
+                next = window.start(front).unwrap_or_else(|| window.end());
+                eprintln!(
+                    "A client fell behind the window, skipping {} tags",
+                    next - skipped
+                );
+            }
+            let batch: Vec<(i32, Bytes)> = (next..window.end())
+                .take(BATCH)
+                .filter_map(|index| window.entry(index))
+                .map(|entry| (entry.timestamp, entry.raw.clone()))
+                .collect();
+            (batch, window.ended)
+        };
+        if batch.is_empty() {
+            if ended || updates.changed().await.is_err() {
+                break;
+            }
+            continue;
+        }
+        next += batch.len() as u64;
+        for (timestamp, raw) in batch {
+            let timestamp = (timestamp as i64 - first_timestamp as i64).max(0);
+            let due = started + Duration::from_millis(timestamp as u64);
+            sleep_until(due.checked_sub(BURST).unwrap_or(started)).await;
+            buf.clear();
+            buf.put_u32(pre_tag_size);
+            pre_tag_size = retimed(&raw, timestamp as i32, &mut buf);
+            stream.write_all(&buf).await?;
+        }
+    }
+    buf.clear();
+    buf.put_u32(pre_tag_size);
+    stream.write_all(&buf).await?;
+    stream.shutdown().await?;
+    Ok(())
+}
@@ -0,0 +1,447 @@
+//! Tag body encryption after Annex F of the FLV specification 10.1. Encrypted tags have the
+//! Filter bit of their TagType byte set, and their body carries, after the clear audio or video
+//! tag header, an EncryptionTagHeader naming the filter and its FilterParams, followed by the
+//! payload encrypted with AES-128-CBC. The key itself is not stored in the file.
+
+use crate::amf::{read_string, write_string, AmfValue};
+use crate::anonymize::kept_len;
+use crate::input::open_input;
+use crate::reader::{
+    read_flv, AudioData, AudioDataHeader, CodecId, Field, ParseOptions, ScriptData, SoundFormat,
+    Tag, TagData, TagHeader, TagType, VideoData, VideoDataHeader,
+};
+use crate::writer::FlvWriter;
+use crate::Exception;
+use aes::Aes128;
+use bytes::{BufMut, Bytes, BytesMut};
+use cbc::cipher::block_padding::Pkcs7;
+use cbc::cipher::{BlockDecryptMut, BlockEncryptMut, KeyIvInit};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::convert::TryFrom;
+use std::path::Path;
+use std::time::{SystemTime, UNIX_EPOCH};
+use tokio::fs::File;
+use tokio::io::BufWriter;
+use tokio_stream::StreamExt;
+
+pub const KEY_SIZE: usize = 16;
+const BLOCK_SIZE: usize = 16;
+
+// Bit of the TagType byte telling that the body went through a filter.
+const FILTER: u8 = 0x20;
+const TAG_TYPE_MASK: u8 = 0x1f;
+const ENCRYPTION_FILTER: &str = "Encryption";
+const SELECTIVE_ENCRYPTION_FILTER: &str = "SE";
+// Script tag announcing that the file is encrypted.
+const ADDITIONAL_HEADER: &str = "|AdditionalHeader";
+
+/// Parses an AES-128 key written as 32 hexadecimal digits.
+pub fn parse_key(hex: &str) -> Result<[u8; KEY_SIZE], Exception> {
+    let hex = hex.trim();
+    if hex.len() != 2 * KEY_SIZE {
+        return Err(format!("Expected a key of {} hexadecimal digits", 2 * KEY_SIZE).into());
+    }
+    let mut key = [0u8; KEY_SIZE];
+    for (i, byte) in key.iter_mut().enumerate() {
+        *byte = u8::from_str_radix(&hex[2 * i..2 * i + 2], 16)
+            .map_err(|_| format!("Invalid hexadecimal key: {}", hex))?;
+    }
+    Ok(key)
+}
+
+/// Copies a FLV file with the payloads of its audio and video frames encrypted. Sequence headers,
+/// script tags and the audio and video tag headers stay in the clear, so the file keeps its
+/// structure, and a `|AdditionalHeader` script tag in front announces the encryption.
+pub async fn encrypt<P: AsRef<Path>>(
+    input: &str,
+    output: P,
+    key: &[u8; KEY_SIZE],
+) -> Result<(), Exception> {
+    let (_, input) = open_input(input).await?;
+    let (header, mut decoder) = read_flv(input, ParseOptions::default()).await?;
+    let file = File::create(output).await?;
+    let mut writer = FlvWriter::new(BufWriter::new(file), &header).await?;
+    writer.write_tag(&additional_header()).await?;
+
+    let mut ivs = Ivs::new(key);
+    let (mut tags, mut encrypted) = (0, 0);
+    while let Some(field) = decoder.next().await {
+        let tag = match field? {
+            Field::Tag(tag) => tag,
+            Field::Warning(finding) => {
+                eprintln!("{}", finding);
+                continue;
+            }
+            Field::PreTagSize(_) => continue,
+        };
+        check_not_encrypted(&tag)?;
+        tags += 1;
+        let tag_type = match tag.header.tag_type {
+            TagType::Audio => 8,
+            TagType::Video => 9,
+            _ => {
+                writer.write_tag(&tag).await?;
+                continue;
+            }
+        };
+        let kept = kept_len(&tag);
+        if kept >= tag.data.encoded_len() {
+            writer.write_tag(&tag).await?;
+            continue;
+        }
+
+        let mut plain = BytesMut::with_capacity(tag.data.encoded_len());
+        tag.data.encode(&mut plain);
+        let iv = ivs.next();
+        let mut body = BytesMut::with_capacity(plain.len() + 64);
+        body.put_slice(&plain[..kept]);
+        // EncryptionTagHeader: NumFilters, FilterName and the Length of the FilterParams
+        body.put_u8(1);
+        write_string(&mut body, ENCRYPTION_FILTER);
+        body.put_slice(&(BLOCK_SIZE as u32).to_be_bytes()[1..]);
+        // EncryptionFilterParams
+        body.put_slice(&iv);
+        body.put_slice(&encrypt_payload(key, &iv, &plain[kept..]));
+
+        let tag = Tag {
+            header: TagHeader {
+                tag_type: TagType::Reserved(tag_type | FILTER),
+                ..tag.header
+            },
+            data: TagData::Reserved(body.freeze()),
+            raw: None,
+            received: None,
+        };
+        writer.write_tag(&tag).await?;
+        encrypted += 1;
+    }
+    writer.finish().await?;
+
+    println!("Tags: {}", tags);
+    println!("EncryptedTags: {}", encrypted);
+    Ok(())
+}
+
+/// Reverses `encrypt`: decrypts the tags that have the Filter bit set, with the "Encryption" or
+/// the selective "SE" filter, and drops the `|AdditionalHeader` script tag.
+pub async fn decrypt<P: AsRef<Path>>(
+    input: &str,
+    output: P,
+    key: &[u8; KEY_SIZE],
+) -> Result<(), Exception> {
+    let (_, input) = open_input(input).await?;
+    let (header, mut decoder) = read_flv(input, ParseOptions::default()).await?;
+    let file = File::create(output).await?;
+    let mut writer = FlvWriter::new(BufWriter::new(file), &header).await?;
+
+    let (mut tags, mut decrypted) = (0, 0);
+    while let Some(field) = decoder.next().await {
+        let tag = match field? {
+            Field::Tag(tag) => tag,
+            Field::Warning(finding) => {
+                eprintln!("{}", finding);
+                continue;
+            }
+            Field::PreTagSize(_) => continue,
+        };
+        let (tag_type, body) = match (&tag.header.tag_type, &tag.data) {
+            (TagType::Script, TagData::Script(script))
+                if script.name().as_deref() == Some(ADDITIONAL_HEADER) =>
+            {
+                continue;
+            }
+            (TagType::Reserved(n), TagData::Reserved(body)) if n & FILTER != 0 => {
+                (n & TAG_TYPE_MASK, body)
+            }
+            _ => {
+                writer.write_tag(&tag).await?;
+                tags += 1;
+                continue;
+            }
+        };
+        let index = tags + 1;
+        let data = decrypt_body(key, tag_type, body)
+            .map_err(|e| format!("Tag {} at offset {}: {}", index, tag.header.offset, e))?;
+        let tag = Tag {
+            header: TagHeader {
+                tag_type: match tag_type {
+                    8 => TagType::Audio,
+                    9 => TagType::Video,
+                    _ => TagType::Script,
+                },
+                ..tag.header
+            },
+            data,
+            raw: None,
+            received: None,
+        };
+        writer.write_tag(&tag).await?;
+        tags += 1;
+        decrypted += 1;
+    }
+    writer.finish().await?;
+
+    println!("Tags: {}", tags);
+    println!("DecryptedTags: {}", decrypted);
+    Ok(())
+}
+
+fn check_not_encrypted(tag: &Tag) -> Result<(), Exception> {
+    let encrypted = match (&tag.header.tag_type, &tag.data) {
+        (TagType::Reserved(n), _) => n & FILTER != 0,
+        (TagType::Script, TagData::Script(script)) => {
+            script.name().as_deref() == Some(ADDITIONAL_HEADER)
+        }
+        _ => false,
+    };
+    if encrypted {
+        return Err("The input is already encrypted".into());
+    }
+    Ok(())
+}
+
+fn decrypt_body(key: &[u8; KEY_SIZE], tag_type: u8, body: &[u8]) -> Result<TagData, Exception> {
+    let kept = match (tag_type, body.first()) {
+        (8, Some(header)) if header >> 4 == SoundFormat::AAC as u8 => 2,
+        (9, Some(header)) if header & 0x0f == CodecId::AVC as u8 => 5,
+        (8, Some(_)) | (9, Some(_)) => 1,
+        (18, _) => 0,
+        (n, _) => return Err(format!("Unsupported encrypted tag type {}", n).into()),
+    };
+    let clear = body.get(..kept).ok_or("Encrypted tag is too short")?;
+
+    let mut src = &body[kept..];
+    match src.first() {
+        Some(1) => src = &src[1..],
+        Some(n) => return Err(format!("Unsupported number of filters: {}", n).into()),
+        None => return Err("Missing EncryptionTagHeader".into()),
+    }
+    let filter = read_string(&mut src)?;
+    let length = match src {
+        [l1, l2, l3, ..] => u32::from_be_bytes([0, *l1, *l2, *l3]) as usize,
+        _ => return Err("Truncated EncryptionTagHeader".into()),
+    };
+    let params = src.get(3..3 + length).ok_or("Truncated FilterParams")?;
+    let payload = &src[3 + length..];
+
+    // The IV, or None if the payload is not encrypted.
+    let iv = match filter.as_str() {
+        ENCRYPTION_FILTER => Some(params),
+        // EncryptedAU flag, followed by the IV if set
+        SELECTIVE_ENCRYPTION_FILTER => match params.first() {
+            Some(flags) if flags & 0x80 != 0 => Some(&params[1..]),
+            Some(_) => None,
+            None => return Err("Empty SelectiveEncryptionFilterParams".into()),
+        },
+        name => return Err(format!("Unsupported filter: {}", name).into()),
+    };
+    let payload = match iv {
+        Some(iv) => {
+            let iv = <[u8; BLOCK_SIZE]>::try_from(iv.get(..BLOCK_SIZE).ok_or("Truncated IV")?)
+                .expect("IV has the block size");
+            decrypt_payload(key, &iv, payload)?
+        }
+        None => payload.to_vec(),
+    };
+
+    let mut plain = BytesMut::with_capacity(kept + payload.len());
+    plain.put_slice(clear);
+    plain.put_slice(&payload);
+    let plain = plain.freeze();
+    Ok(match tag_type {
+        8 => TagData::Audio(AudioData {
+            header: AudioDataHeader::try_from(plain[0])?,
+            data: plain.slice(1..),
+        }),
+        9 => TagData::Video(VideoData {
+            header: VideoDataHeader::try_from(plain[0])?,
+            data: plain.slice(1..),
+        }),
+        _ => TagData::Script(ScriptData::new(plain)),
+    })
+}
+
+// AES-128-CBC with PKCS#7 padding.
+fn encrypt_payload(key: &[u8; KEY_SIZE], iv: &[u8; BLOCK_SIZE], payload: &[u8]) -> Vec<u8> {
+    cbc::Encryptor::<Aes128>::new(key.into(), iv.into()).encrypt_padded_vec_mut::<Pkcs7>(payload)
+}
+
+fn decrypt_payload(
+    key: &[u8; KEY_SIZE],
+    iv: &[u8; BLOCK_SIZE],
+    payload: &[u8],
+) -> Result<Vec<u8>, Exception> {
+    if payload.is_empty() || !payload.len().is_multiple_of(BLOCK_SIZE) {
+        return Err(format!(
+            "Encrypted data of {} bytes is not a whole number of AES blocks",
+            payload.len()
+        )
+        .into());
+    }
+    cbc::Decryptor::<Aes128>::new(key.into(), iv.into())
+        .decrypt_padded_vec_mut::<Pkcs7>(payload)
+        .map_err(|_| "Invalid padding, the key is probably wrong".into())
+}
+
+fn additional_header() -> Tag {
+    let params = AmfValue::Object(vec![
+        ("Version".into(), AmfValue::Number(1.0)),
+        (
+            "EncryptionAlgorithm".into(),
+            AmfValue::String("AES-CBC".into()),
+        ),
+        (
+            "EncryptionParams".into(),
+            AmfValue::Object(vec![(
+                "KeyLength".into(),
+                AmfValue::Number(KEY_SIZE as f64),
+            )]),
+        ),
+    ]);
+    let encryption = AmfValue::Object(vec![
+        ("Version".into(), AmfValue::Number(2.0)),
+        ("Method".into(), AmfValue::String("Standard".into())),
+        ("Flags".into(), AmfValue::Number(0.0)),
+        ("Params".into(), params),
+    ]);
+
+    let mut raw = BytesMut::new();
+    AmfValue::String(ADDITIONAL_HEADER.into()).encode(&mut raw);
+    AmfValue::EcmaArray(vec![("Encryption".into(), encryption)]).encode(&mut raw);
+    let raw: Bytes = raw.freeze();
+    Tag {
+        header: TagHeader {
+            tag_type: TagType::Script,
+            data_size: raw.len() as u32,
+            timestamp: 0,
+            offset: 0,
+        },
+        data: TagData::Script(ScriptData::new(raw)),
+        raw: None,
+        received: None,
+    }
+}
+
+// Derives a fresh IV for every tag from the key, a per file salt and a counter, so IVs are never
+// reused and can not be predicted without the key.
+struct Ivs {
+    mac: Hmac<Sha256>,
+    salt: [u8; 32],
+    counter: u64,
+}
+
+impl Ivs {
+    fn new(key: &[u8; KEY_SIZE]) -> Self {
+        let nanos = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|d| d.as_nanos())
+            .unwrap_or_default();
+        let mut hasher = Sha256::new();
+        hasher.update(nanos.to_be_bytes());
+        hasher.update(std::process::id().to_be_bytes());
+        Self {
+            mac: Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size"),
+            salt: hasher.finalize().into(),
+            counter: 0,
+        }
+    }
+
+    fn next(&mut self) -> [u8; BLOCK_SIZE] {
+        self.counter += 1;
+        let mut mac = self.mac.clone();
+        mac.update(&self.salt);
+        mac.update(&self.counter.to_be_bytes());
+        let digest = mac.finalize().into_bytes();
+        let mut iv = [0u8; BLOCK_SIZE];
+        iv.copy_from_slice(&digest[..BLOCK_SIZE]);
+        iv
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn hex(s: &str) -> Vec<u8> {
+        (0..s.len())
+            .step_by(2)
+            .map(|i| u8::from_str_radix(&s[i..i + 2], 16).unwrap())
+            .collect()
+    }
+
+    #[test]
+    fn fips_197_aes_128() {
+        // Appendix C.1, one block with a zero IV is plain AES
+        let key = parse_key("000102030405060708090a0b0c0d0e0f").unwrap();
+        let plain = hex("00112233445566778899aabbccddeeff");
+        let encrypted = encrypt_payload(&key, &[0; BLOCK_SIZE], &plain);
+        assert_eq!(
+            encrypted[..BLOCK_SIZE],
+            hex("69c4e0d86a7b0430d8cdb78070b4c55a")[..]
+        );
+        // and a block of padding
+        assert_eq!(encrypted.len(), 2 * BLOCK_SIZE);
+        assert_eq!(
+            decrypt_payload(&key, &[0; BLOCK_SIZE], &encrypted).unwrap(),
+            plain
+        );
+    }
+
+    #[test]
+    fn sp800_38a_cbc_aes_128() {
+        // F.2.1 CBC-AES128.Encrypt
+        let key = parse_key("2b7e151628aed2a6abf7158809cf4f3c").unwrap();
+        let iv =
+            <[u8; BLOCK_SIZE]>::try_from(&hex("000102030405060708090a0b0c0d0e0f")[..]).unwrap();
+        let plain = hex(concat!(
+            "6bc1bee22e409f96e93d7e117393172a",
+            "ae2d8a571e03ac9c9eb76fac45af8e51",
+            "30c81c46a35ce411e5fbc1191a0a52ef",
+            "f69f2445df4f9b17ad2b417be66c3710",
+        ));
+        let cipher = hex(concat!(
+            "7649abac8119b246cee98e9b12e9197d",
+            "5086cb9b507219ee95db113a917678b2",
+            "73bed6b8e3c1743b7116e69e22229516",
+            "3ff1caa1681fac09120eca307586e1a7",
+        ));
+        let encrypted = encrypt_payload(&key, &iv, &plain);
+        assert_eq!(encrypted[..plain.len()], cipher[..]);
+        assert_eq!(decrypt_payload(&key, &iv, &encrypted).unwrap(), plain);
+    }
+
+    #[test]
+    fn invalid_payloads() {
+        let key = [7; KEY_SIZE];
+        let iv = [9; BLOCK_SIZE];
+        assert!(decrypt_payload(&key, &iv, &[]).is_err());
+        assert!(decrypt_payload(&key, &iv, &[0; BLOCK_SIZE + 1]).is_err());
+        let encrypted = encrypt_payload(&key, &iv, b"payload");
+        assert!(decrypt_payload(&[8; KEY_SIZE], &iv, &encrypted).is_err());
+    }
+
+    #[tokio::test]
+    async fn round_trip() {
+        let dir = std::env::temp_dir().join(format!("flv-dump-encrypt-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (encrypted, decrypted) = (dir.join("encrypted.flv"), dir.join("decrypted.flv"));
+        let key = parse_key("000102030405060708090a0b0c0d0e0f").unwrap();
+
+        encrypt("resources/test.flv", &encrypted, &key)
+            .await
+            .unwrap();
+        assert_ne!(
+            std::fs::read(&encrypted).unwrap(),
+            std::fs::read("resources/test.flv").unwrap()
+        );
+        decrypt(encrypted.to_str().unwrap(), &decrypted, &key)
+            .await
+            .unwrap();
+        assert_eq!(
+            std::fs::read(&decrypted).unwrap(),
+            std::fs::read("resources/test.flv").unwrap()
+        );
+        std::fs::remove_dir_all(&dir).unwrap();
+    }
+}
@@ -0,0 +1,144 @@
+//! Script events that media servers and encoders inject into a stream besides onMetaData.
+use crate::amf::AmfValue;
+use crate::reader::ScriptData;
+use serde_json::{json, Value};
+use std::fmt;
+
+/// A known script event with its decoded fields. Fields missing from the tag are `None`.
+#[derive(Debug, Clone, PartialEq)]
+pub enum ScriptEvent {
+    /// `onLastSecond`: sent by media servers when a recorded stream has one second left to play.
+    LastSecond,
+    /// `onPlayStatus`: e.g. `NetStream.Play.Complete` at the end of a recorded stream, with its
+    /// size and duration, or `NetStream.Play.Switch` at a bitrate switch.
+    PlayStatus {
+        code: Option<String>,
+        level: Option<String>,
+        bytes: Option<f64>,
+        /// seconds
+        duration: Option<f64>,
+    },
+    /// `onFI`: frame information written by Flash Media Live Encoder, the SMPTE timecode and the
+    /// date and time of the encoder's system clock.
+    FrameInfo {
+        /// `tc`, hh:mm:ss:ff
+        timecode: Option<String>,
+        /// `sd`, dd-mm-yy
+        date: Option<String>,
+        /// `st`, hh:mm:ss.mmm
+        time: Option<String>,
+    },
+    /// `|RtmpSampleAccess`: whether players may read the decoded audio and video samples.
+    SampleAccess {
+        audio: Option<bool>,
+        video: Option<bool>,
+    },
+}
+
+impl ScriptEvent {
+    /// Recognizes the event in the values of a script tag: its name, then its data.
+    pub fn from_values(values: &[AmfValue]) -> Option<Self> {
+        let data = values.get(1);
+        let string = |name| {
+            data.and_then(|data| data.property(name))
+                .and_then(AmfValue::as_str)
+                .map(str::to_owned)
+        };
+        let number = |name| {
+            data.and_then(|data| data.property(name))
+                .and_then(AmfValue::as_number)
+        };
+        Some(match values.first()?.as_str()? {
+            "onLastSecond" => ScriptEvent::LastSecond,
+            "onPlayStatus" => ScriptEvent::PlayStatus {
+                code: string("code"),
+                level: string("level"),
+                bytes: number("bytes"),
+                duration: number("duration"),
+            },
+            "onFI" => ScriptEvent::FrameInfo {
+                timecode: string("tc"),
+                date: string("sd"),
+                time: string("st"),
+            },
+            // two booleans rather than an object
+            "|RtmpSampleAccess" => ScriptEvent::SampleAccess {
+                audio: values.get(1).and_then(AmfValue::as_bool),
+                video: values.get(2).and_then(AmfValue::as_bool),
+            },
+            _ => return None,
+        })
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            ScriptEvent::LastSecond => "onLastSecond",
+            ScriptEvent::PlayStatus { .. } => "onPlayStatus",
+            ScriptEvent::FrameInfo { .. } => "onFI",
+            ScriptEvent::SampleAccess { .. } => "|RtmpSampleAccess",
+        }
+    }
+
+    /// The fields that are present, by their name in JSON.
+    pub fn fields(&self) -> Vec<(&'static str, Value)> {
+        let fields = match self {
+            ScriptEvent::LastSecond => vec![],
+            ScriptEvent::PlayStatus {
+                code,
+                level,
+                bytes,
+                duration,
+            } => vec![
+                ("code", code.as_ref().map(|code| json!(code))),
+                ("level", level.as_ref().map(|level| json!(level))),
+                ("bytes", bytes.map(|bytes| AmfValue::Number(bytes).into())),
+                (
+                    "duration",
+                    duration.map(|duration| AmfValue::Number(duration).into()),
+                ),
+            ],
+            ScriptEvent::FrameInfo {
+                timecode,
+                date,
+                time,
+            } => vec![
+                (
+                    "timecode",
+                    timecode.as_ref().map(|timecode| json!(timecode)),
+                ),
+                ("date", date.as_ref().map(|date| json!(date))),
+                ("time", time.as_ref().map(|time| json!(time))),
+            ],
+            ScriptEvent::SampleAccess { audio, video } => vec![
+                ("audio", audio.map(|audio| json!(audio))),
+                ("video", video.map(|video| json!(video))),
+            ],
+        };
+        fields
+            .into_iter()
+            .filter_map(|(name, value)| Some((name, value?)))
+            .collect()
+    }
+}
+
+/// The name followed by the fields, e.g. `onPlayStatus code=NetStream.Play.Complete
+/// level=status`.
+impl fmt::Display for ScriptEvent {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.name())?;
+        for (name, value) in self.fields() {
+            match value {
+                Value::String(s) => write!(f, " {}={}", name, s)?,
+                value => write!(f, " {}={}", name, value)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+impl ScriptData {
+    /// The known script event of the tag, if it is one.
+    pub fn event(&self) -> Option<ScriptEvent> {
+        ScriptEvent::from_values(&self.values())
+    }
+}
@@ -0,0 +1,176 @@
+use crate::stats::Stats;
+use crate::Exception;
+use serde::Deserialize;
+use std::path::Path;
+
+/// The stream a file is expected to carry, read from a TOML file. Unset fields are not checked.
+///
+/// ```toml
+/// video_codec = "AVC"
+/// resolution = "1280x720"
+/// min_fps = 24
+/// max_fps = 30
+/// audio_codec = "AAC"
+/// audio_sample_rate = 44100
+/// audio_channels = 2
+/// min_bitrate = 1000
+/// max_bitrate = 4000
+/// ```
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Expectation {
+    /// CodecId of every video tag, named as in the dump.
+    pub video_codec: Option<String>,
+    /// WIDTHxHEIGHT according to the SPS of the AVC sequence header.
+    pub resolution: Option<String>,
+    /// Average frame rate.
+    pub min_fps: Option<f64>,
+    pub max_fps: Option<f64>,
+    /// SoundFormat of every audio tag, named as in the dump.
+    pub audio_codec: Option<String>,
+    /// Hz, according to the codec if it tells, or else the SoundRate.
+    pub audio_sample_rate: Option<u32>,
+    /// According to the codec if it tells, or else the SoundType.
+    pub audio_channels: Option<u8>,
+    /// Average bitrate of the audio and video tags in kbps.
+    pub min_bitrate: Option<f64>,
+    pub max_bitrate: Option<f64>,
+}
+
+impl Expectation {
+    pub fn load(path: &Path) -> Result<Self, Exception> {
+        let text = std::fs::read_to_string(path)
+            .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+        toml::from_str(&text)
+            .map_err(|e| format!("Invalid expectation {}: {}", path.display(), e).into())
+    }
+
+    /// How the file described by the statistics deviates, empty if it matches.
+    pub fn deviations(&self, stats: &Stats) -> Vec<String> {
+        let mut deviations = Vec::new();
+
+        if let Some(expected) = &self.video_codec {
+            let codecs = &stats.video_summary.codecs;
+            if codecs.is_empty() {
+                deviations.push(format!("Expected {} video, found no video", expected));
+            }
+            for codec in codecs {
+                let found = format!("{:?}", codec.codec_id);
+                if !found.eq_ignore_ascii_case(expected) {
+                    deviations.push(format!("Expected {} video, found {}", expected, found));
+                }
+            }
+        }
+
+        if let Some(expected) = &self.resolution {
+            let sps = stats
+                .video_summary
+                .codecs
+                .iter()
+                .rev()
+                .find_map(|codec| codec.sps.as_ref());
+            match sps.map(|sps| format!("{}x{}", sps.width, sps.height)) {
+                Some(found) if found == *expected => {}
+                Some(found) => deviations.push(format!(
+                    "Expected a resolution of {}, found {}",
+                    expected, found
+                )),
+                None => deviations.push(format!(
+                    "Expected a resolution of {}, found no AVC SPS to read it from",
+                    expected
+                )),
+            }
+        }
+
+        if self.min_fps.is_some() || self.max_fps.is_some() {
+            let range = range(self.min_fps, self.max_fps, "fps");
+            match stats.frame_intervals.frame_rate() {
+                Some(frame_rate) => {
+                    // timestamps in whole milliseconds make e.g. 30 fps measure as 30.02
+                    let fps = (frame_rate.average * 10.0).round() / 10.0;
+                    if !within(fps, self.min_fps, self.max_fps) {
+                        deviations.push(format!(
+                            "Expected a frame rate of {}, found {:.1} fps",
+                            range, fps
+                        ));
+                    }
+                }
+                None => deviations.push(format!(
+                    "Expected a frame rate of {}, found too few frames to measure it",
+                    range
+                )),
+            }
+        }
+
+        let audio = &stats.audio_summary.codecs;
+        if (self.audio_codec.is_some()
+            || self.audio_sample_rate.is_some()
+            || self.audio_channels.is_some())
+            && audio.is_empty()
+        {
+            deviations.push("Expected audio, found no audio".into());
+        }
+        for codec in audio {
+            if let Some(expected) = &self.audio_codec {
+                let found = format!("{:?}", codec.sound_format);
+                if !found.eq_ignore_ascii_case(expected) {
+                    deviations.push(format!("Expected {} audio, found {}", expected, found));
+                }
+            }
+            let sample_rate = codec.codec_sample_rate.unwrap_or(codec.header_sample_rate);
+            if let Some(expected) = self.audio_sample_rate.filter(|hz| *hz != sample_rate) {
+                deviations.push(format!(
+                    "Expected audio at {} Hz, found {} Hz",
+                    expected, sample_rate
+                ));
+            }
+            let channels = codec.codec_channels.unwrap_or(codec.header_channels);
+            if let Some(expected) = self.audio_channels.filter(|n| *n != channels) {
+                deviations.push(format!(
+                    "Expected {} audio channels, found {}",
+                    expected, channels
+                ));
+            }
+        }
+
+        if self.min_bitrate.is_some() || self.max_bitrate.is_some() {
+            let range = range(self.min_bitrate, self.max_bitrate, "kbps");
+            match bitrate(stats) {
+                Some(bitrate) if !within(bitrate, self.min_bitrate, self.max_bitrate) => deviations
+                    .push(format!(
+                        "Expected a bitrate of {}, found {:.1} kbps",
+                        range, bitrate
+                    )),
+                Some(_) => {}
+                None => deviations.push(format!(
+                    "Expected a bitrate of {}, found too little audio and video to measure it",
+                    range
+                )),
+            }
+        }
+
+        deviations
+    }
+}
+
+fn within(value: f64, min: Option<f64>, max: Option<f64>) -> bool {
+    min.is_none_or(|min| value >= min) && max.is_none_or(|max| value <= max)
+}
+
+fn range(min: Option<f64>, max: Option<f64>, unit: &str) -> String {
+    match (min, max) {
+        (Some(min), Some(max)) => format!("{} to {} {}", min, max, unit),
+        (Some(min), None) => format!("at least {} {}", min, unit),
+        (None, Some(max)) => format!("at most {} {}", max, unit),
+        (None, None) => format!("any {}", unit),
+    }
+}
+
+/// kbps of the audio and video tags from the first to the last frame of either.
+fn bitrate(stats: &Stats) -> Option<f64> {
+    let duration = stats.timestamps.media_duration()?;
+    if duration <= 0 {
+        return None;
+    }
+    Some((stats.audio.bytes + stats.video.bytes) as f64 * 8.0 / duration as f64)
+}
@@ -0,0 +1,94 @@
+use crate::arrow::Arrow;
+use crate::chapters::{self, ChapterFormat, Chapters};
+use crate::input::open_input;
+use crate::output::{formatter, FileInfo, Format, Formatter};
+use crate::reader::{read_flv, Field, ParseOptions};
+use crate::stats::Stats;
+use crate::Exception;
+use std::fs::File;
+use std::io::{BufWriter, Write};
+use std::path::Path;
+use tokio_stream::StreamExt;
+
+/// Writes the per-tag table of a FLV file, the columns of `--format csv`, to a file: as CSV, or
+/// as an Arrow IPC file (Feather V2) that pandas and other dataframe libraries read directly.
+/// With `chapters`, writes its keyframes and cue points as chapters instead.
+pub async fn export<P: AsRef<Path>>(
+    input: &str,
+    output: P,
+    arrow: bool,
+    chapters: Option<ChapterFormat>,
+) -> Result<(), Exception> {
+    if let Some(format) = chapters {
+        return export_chapters(input, output.as_ref(), format).await;
+    }
+    let (size, source) = open_input(input).await?;
+    let (header, mut decoder) = read_flv(source, ParseOptions::default()).await?;
+    let mut out = BufWriter::new(File::create(output)?);
+    let mut formatter: Box<dyn Formatter> = if arrow {
+        Box::new(Arrow::default())
+    } else {
+        formatter(Format::Csv)
+    };
+    formatter.header(
+        &mut out,
+        &FileInfo {
+            path: input,
+            size,
+            header: &header,
+        },
+    )?;
+
+    let mut index = 1;
+    while let Some(field) = decoder.next().await {
+        match field? {
+            Field::PreTagSize(size) => formatter.pre_tag_size(&mut out, index - 1, size)?,
+            Field::Tag(tag) => {
+                formatter.tag(&mut out, index, &tag)?;
+                index += 1;
+            }
+            Field::Warning(finding) => formatter.warning(&mut out, &finding)?,
+        }
+    }
+    formatter.finish(&mut out)?;
+    out.flush()?;
+
+    println!("ExportedTags: {}", index - 1);
+    Ok(())
+}
+
+async fn export_chapters(
+    input: &str,
+    output: &Path,
+    format: ChapterFormat,
+) -> Result<(), Exception> {
+    let (_, source) = open_input(input).await?;
+    let (_, mut decoder) = read_flv(source, ParseOptions::default()).await?;
+    let (mut chapters, mut stats) = (Chapters::default(), Stats::default());
+    while let Some(field) = decoder.next().await {
+        match field? {
+            Field::Tag(tag) => {
+                chapters.push(&tag);
+                stats.push(&tag);
+            }
+            Field::Warning(finding) => eprintln!("{}", finding),
+            Field::PreTagSize(_) => {}
+        }
+    }
+
+    let markers = chapters.markers();
+    let mut out = BufWriter::new(File::create(output)?);
+    match format {
+        ChapterFormat::Ffmetadata => chapters::write_ffmetadata(&mut out, &markers)?,
+        ChapterFormat::Edl => {
+            let title = Path::new(input)
+                .file_name()
+                .map_or_else(|| input.into(), |name| name.to_string_lossy());
+            chapters::write_edl(&mut out, &markers, &title, chapters::edl_fps(&stats))?
+        }
+    }
+    out.flush()?;
+
+    println!("ExportedChapters: {}", markers.len());
+    Ok(())
+}
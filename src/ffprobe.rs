@@ -0,0 +1,218 @@
+use crate::aac::{AacPacketType, AudioSpecificConfig};
+use crate::findings::Finding;
+use crate::g711;
+use crate::mp3::FrameHeader;
+use crate::output::{FileInfo, Formatter};
+use crate::reader::{
+    AudioData, AudioDataHeader, CodecId, SoundFormat, SoundSize, Tag, TagData, VideoData,
+    VideoFrameType,
+};
+use crate::stats::Stats;
+use crate::time::SignedDuration;
+use crate::Exception;
+use serde_json::{json, Map, Value};
+use std::convert::TryFrom;
+use std::io::Write;
+
+/// JSON shaped like the output of `ffprobe -show_packets -show_streams -of json`. Packets are
+/// written as tags are decoded and streams at the end, once everything about them is known.
+/// FLV timestamps are milliseconds, so every stream has a time base of 1/1000.
+#[derive(Default)]
+pub struct Ffprobe {
+    packets: usize,
+    // audio and video, in the order they first appear
+    streams: Vec<Stream>,
+}
+
+struct Stream {
+    codec_type: &'static str,
+    codec_name: &'static str,
+    start_pts: Option<i64>,
+    sample_rate: Option<u32>,
+    channels: Option<u8>,
+}
+
+impl Ffprobe {
+    fn stream(&mut self, codec_type: &'static str, codec_name: &'static str) -> usize {
+        match self.streams.iter().position(|s| s.codec_type == codec_type) {
+            Some(index) => index,
+            None => {
+                self.streams.push(Stream {
+                    codec_type,
+                    codec_name,
+                    start_pts: None,
+                    sample_rate: None,
+                    channels: None,
+                });
+                self.streams.len() - 1
+            }
+        }
+    }
+
+    // Returns (stream index, composition time, payload size, key frame), or None for tags that carry no
+    // packet, such as sequence headers.
+    fn audio_packet(&mut self, audio: &AudioData) -> Option<(usize, i64, usize, bool)> {
+        let header = &audio.header;
+        let index = self.stream("audio", sound_format_name(header));
+        let stream = &mut self.streams[index];
+
+        let mut payload = &audio.data[..];
+        match header.sound_format {
+            SoundFormat::AAC => {
+                let packet_type = AacPacketType::try_from(*payload.first()?).ok()?;
+                if packet_type == AacPacketType::SequenceHeader {
+                    if let Ok(config) = AudioSpecificConfig::try_from(&payload[1..]) {
+                        stream.sample_rate = Some(config.sampling_frequency);
+                        stream.channels = Some(config.channel_configuration);
+                    }
+                    return None;
+                }
+                payload = &payload[1..];
+            }
+            SoundFormat::G711ALaw | SoundFormat::G711MuLaw => {
+                stream.sample_rate = Some(g711::SAMPLE_RATE);
+            }
+            SoundFormat::MP3 | SoundFormat::MP38kHz if stream.sample_rate.is_none() => {
+                stream.sample_rate = FrameHeader::try_from(payload)
+                    .ok()
+                    .map(|frame| frame.sample_rate);
+            }
+            _ => {}
+        }
+
+        stream.sample_rate.get_or_insert(header.sound_rate.hz());
+        stream.channels.get_or_insert(header.sound_type.channels());
+
+        Some((index, 0, payload.len(), true))
+    }
+
+    fn video_packet(&mut self, video: &VideoData) -> Option<(usize, i64, usize, bool)> {
+        let index = self.stream("video", codec_id_name(video.header.codec_id));
+        let mut payload = &video.data[..];
+        let mut composition_time = 0;
+        if video.header.codec_id == CodecId::AVC {
+            // AVCPacketType 1 is a NALU, 0 the sequence header and 2 the end of sequence
+            composition_time = video.composition_time()?;
+            payload = &payload[4..];
+        }
+        let key = video.header.frame_type == VideoFrameType::KeyFrame;
+        Some((index, composition_time as i64, payload.len(), key))
+    }
+}
+
+fn time(ts: i64) -> String {
+    format!("{:.6}", SignedDuration::from_millis(ts).as_secs_f64())
+}
+
+fn sound_format_name(header: &AudioDataHeader) -> &'static str {
+    use SoundFormat::*;
+    match (header.sound_format, header.sound_size) {
+        (LinearPCMPlatformEndian, SoundSize::S8Bit) | (LinearPCMLittleEndian, SoundSize::S8Bit) => {
+            "pcm_u8"
+        }
+        (LinearPCMPlatformEndian, _) | (LinearPCMLittleEndian, _) => "pcm_s16le",
+        (ADPCM, _) => "adpcm_swf",
+        (MP3, _) | (MP38kHz, _) => "mp3",
+        (Nellymoser16, _) | (Nellymoser8, _) | (Nellymoser, _) => "nellymoser",
+        (G711ALaw, _) => "pcm_alaw",
+        (G711MuLaw, _) => "pcm_mulaw",
+        (AAC, _) => "aac",
+        (Speex, _) => "speex",
+        (Reserved, _) | (DeviceSpecific, _) => "none",
+    }
+}
+
+fn codec_id_name(codec_id: CodecId) -> &'static str {
+    match codec_id {
+        CodecId::JPEG => "none",
+        CodecId::SorensonH263 => "flv1",
+        CodecId::ScreenVideo => "flashsv",
+        CodecId::On2VP6 => "vp6f",
+        CodecId::On2VP6WithAlpha => "vp6a",
+        CodecId::ScreenVideoVersion2 => "flashsv2",
+        CodecId::AVC => "h264",
+    }
+}
+
+impl Formatter for Ffprobe {
+    fn header(&mut self, out: &mut dyn Write, _: &FileInfo) -> Result<(), Exception> {
+        write!(out, "{{\"packets\":[")?;
+        Ok(())
+    }
+
+    fn pre_tag_size(&mut self, _: &mut dyn Write, _: usize, _: u32) -> Result<(), Exception> {
+        Ok(())
+    }
+
+    fn tag(&mut self, out: &mut dyn Write, _: usize, tag: &Tag) -> Result<(), Exception> {
+        let packet = match &tag.data {
+            TagData::Audio(audio) => self.audio_packet(audio),
+            TagData::Video(video) => self.video_packet(video),
+            _ => None,
+        };
+        let (index, composition_time, size, key) = match packet {
+            Some(packet) => packet,
+            None => return Ok(()),
+        };
+
+        let stream = &mut self.streams[index];
+        let dts = tag.header.timestamp as i64;
+        let pts = dts + composition_time;
+        stream.start_pts.get_or_insert(pts);
+
+        let mut packet = Map::new();
+        packet.insert("codec_type".into(), json!(stream.codec_type));
+        packet.insert("stream_index".into(), json!(index));
+        packet.insert("pts".into(), json!(pts));
+        packet.insert("pts_time".into(), json!(time(pts)));
+        packet.insert("dts".into(), json!(dts));
+        packet.insert("dts_time".into(), json!(time(dts)));
+        packet.insert("size".into(), json!(size.to_string()));
+        packet.insert("pos".into(), json!(tag.header.offset.to_string()));
+        packet.insert("flags".into(), json!(if key { "K__" } else { "___" }));
+
+        if self.packets > 0 {
+            out.write_all(b",")?;
+        }
+        serde_json::to_writer(&mut *out, &packet)?;
+        self.packets += 1;
+        Ok(())
+    }
+
+    fn stats(&mut self, _: &mut dyn Write, _: &Stats) -> Result<(), Exception> {
+        Err("Statistics can not be exported in the ffprobe format".into())
+    }
+
+    fn warning(&mut self, _: &mut dyn Write, finding: &Finding) -> Result<(), Exception> {
+        eprintln!("{}", finding);
+        Ok(())
+    }
+
+    fn finish(&mut self, out: &mut dyn Write) -> Result<(), Exception> {
+        let streams: Vec<Value> = self
+            .streams
+            .iter()
+            .enumerate()
+            .map(|(index, stream)| {
+                let mut object = Map::new();
+                object.insert("index".into(), json!(index));
+                object.insert("codec_name".into(), json!(stream.codec_name));
+                object.insert("codec_type".into(), json!(stream.codec_type));
+                if let Some(sample_rate) = stream.sample_rate {
+                    object.insert("sample_rate".into(), json!(sample_rate.to_string()));
+                }
+                if let Some(channels) = stream.channels {
+                    object.insert("channels".into(), json!(channels));
+                }
+                object.insert("time_base".into(), json!("1/1000"));
+                if let Some(start_pts) = stream.start_pts {
+                    object.insert("start_pts".into(), json!(start_pts));
+                    object.insert("start_time".into(), json!(time(start_pts)));
+                }
+                Value::Object(object)
+            })
+            .collect();
+        writeln!(out, "],\"streams\":{}}}", Value::Array(streams))?;
+        Ok(())
+    }
+}
@@ -0,0 +1,148 @@
+use crate::reader::{Field, Tag, TagData, TagType, VideoFrameType};
+use crate::Exception;
+use std::collections::VecDeque;
+use std::pin::Pin;
+use std::task::{Context, Poll};
+use tokio_stream::{Stream, StreamExt};
+
+/// Combinators for the fields decoded by `read_flv` and friends.
+pub trait FieldStreamExt: Stream<Item = Result<Field, Exception>> + Sized {
+    /// Only the tags, PreviousTagSize fields and warnings are dropped. Errors are passed on.
+    fn tags_only(self) -> impl Stream<Item = Result<Tag, Exception>> {
+        self.filter_map(|field| match field {
+            Ok(Field::Tag(tag)) => Some(Ok(tag)),
+            Ok(_) => None,
+            Err(e) => Some(Err(e)),
+        })
+    }
+}
+
+impl<S: Stream<Item = Result<Field, Exception>>> FieldStreamExt for S {}
+
+/// Filters for a stream of tags, e.g. `fields.tags_only().video().keyframes()`. Errors always
+/// pass the filters.
+pub trait TagStreamExt: Stream<Item = Result<Tag, Exception>> + Sized {
+    fn audio(self) -> impl Stream<Item = Result<Tag, Exception>> {
+        self.filter(|tag| is_err_or(tag, |tag| tag.header.tag_type == TagType::Audio))
+    }
+
+    fn video(self) -> impl Stream<Item = Result<Tag, Exception>> {
+        self.filter(|tag| is_err_or(tag, |tag| tag.header.tag_type == TagType::Video))
+    }
+
+    /// Video keyframes, not counting AVC sequence headers.
+    fn keyframes(self) -> impl Stream<Item = Result<Tag, Exception>> {
+        self.filter(|tag| {
+            is_err_or(tag, |tag| match &tag.data {
+                TagData::Video(video) => {
+                    video.header.frame_type == VideoFrameType::KeyFrame
+                        && !tag.data.is_sequence_header()
+                }
+                _ => false,
+            })
+        })
+    }
+
+    /// Tags with a timestamp in `from..to` milliseconds.
+    fn between(self, from: i32, to: i32) -> impl Stream<Item = Result<Tag, Exception>> {
+        self.filter(move |tag| is_err_or(tag, |tag| (from..to).contains(&tag.header.timestamp)))
+    }
+}
+
+impl<S: Stream<Item = Result<Tag, Exception>>> TagStreamExt for S {}
+
+fn is_err_or(tag: &Result<Tag, Exception>, predicate: impl FnOnce(&Tag) -> bool) -> bool {
+    tag.as_ref().map_or(true, predicate)
+}
+
+pub trait LookaheadExt: Stream + Unpin + Sized {
+    /// Allows looking at upcoming fields or tags before consuming them.
+    fn lookahead(self) -> Lookahead<Self> {
+        Lookahead::new(self)
+    }
+}
+
+impl<S: Stream + Unpin> LookaheadExt for S {}
+
+/// A stream that can decode items ahead of time and show them without consuming them, for
+/// decisions that depend on what comes next. Items are buffered until they are consumed.
+pub struct Lookahead<S: Stream> {
+    inner: S,
+    buffer: VecDeque<S::Item>,
+}
+
+impl<S: Stream + Unpin> Lookahead<S> {
+    pub fn new(inner: S) -> Self {
+        Self {
+            inner,
+            buffer: VecDeque::new(),
+        }
+    }
+
+    /// The next item, without consuming it.
+    pub async fn peek(&mut self) -> Option<&S::Item> {
+        self.peek_nth(0).await
+    }
+
+    /// The item `n` positions ahead, `peek_nth(0)` being the next one.
+    pub async fn peek_nth(&mut self, n: usize) -> Option<&S::Item> {
+        while self.buffer.len() <= n {
+            match self.inner.next().await {
+                Some(item) => self.buffer.push_back(item),
+                None => break,
+            }
+        }
+        self.buffer.get(n)
+    }
+}
+
+impl<S: Stream + Unpin> Stream for Lookahead<S>
+where
+    S::Item: Unpin,
+{
+    type Item = S::Item;
+
+    fn poll_next(mut self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+        match self.buffer.pop_front() {
+            Some(item) => Poll::Ready(Some(item)),
+            None => Pin::new(&mut self.inner).poll_next(cx),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+
+    #[test]
+    fn peek_past_the_end() {
+        block_on(async {
+            let mut items = tokio_stream::iter(1..=3).lookahead();
+            assert_eq!(items.peek_nth(5).await, None);
+            // everything decoded to get there is still to be consumed
+            assert_eq!(items.peek().await, Some(&1));
+            assert_eq!(items.peek_nth(2).await, Some(&3));
+            assert_eq!((&mut items).collect::<Vec<_>>().await, vec![1, 2, 3]);
+            assert_eq!(items.peek().await, None);
+            assert_eq!(items.next().await, None);
+        });
+    }
+
+    #[test]
+    fn peek_between_items() {
+        block_on(async {
+            let mut items = tokio_stream::iter(1..=5).lookahead();
+            assert_eq!(items.peek_nth(1).await, Some(&2));
+            assert_eq!(items.next().await, Some(1));
+            assert_eq!(items.peek_nth(1).await, Some(&3));
+            assert_eq!(items.peek_nth(2).await, Some(&4));
+            assert_eq!(items.next().await, Some(2));
+            assert_eq!(items.next().await, Some(3));
+            // past the buffer, the next item comes from the stream again
+            assert_eq!(items.next().await, Some(4));
+            assert_eq!(items.next().await, Some(5));
+            assert_eq!(items.peek_nth(0).await, None);
+        });
+    }
+}
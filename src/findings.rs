@@ -0,0 +1,208 @@
+use crate::Exception;
+use std::fmt;
+use std::iter::FromIterator;
+use std::str::FromStr;
+
+/// How serious a finding is, in increasing order.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Severity {
+    /// Unusual, but harmless for players.
+    Info,
+    /// Against the specification or good practice, most players cope with it.
+    Warning,
+    /// Corrupted or undecodable data.
+    Error,
+}
+
+impl Severity {
+    /// Lower case, as on the command line and in JSON.
+    pub fn name(self) -> &'static str {
+        match self {
+            Severity::Info => "info",
+            Severity::Warning => "warning",
+            Severity::Error => "error",
+        }
+    }
+}
+
+impl fmt::Display for Severity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Severity::Info => "Info",
+            Severity::Warning => "Warning",
+            Severity::Error => "Error",
+        })
+    }
+}
+
+/// A validation rule. Every finding names the rule it breaks, rules can be suppressed by ID.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Rule {
+    /// A PreviousTagSize that does not match the size of the previous tag.
+    PreTagSize,
+    /// A DataSize above `ParseOptions::max_tag_size`.
+    TagSize,
+    /// A tag larger than `ParseOptions::max_buffered`, skipped.
+    Overflow,
+    StreamId,
+    /// An audio or video data header that fails to parse, reported as `TagData::Invalid`.
+    InvalidData,
+    /// A Speex or Nellymoser header with a rate or channel count the codec does not allow.
+    AudioHeader,
+    /// An AAC frame or AVC NALU before the sequence header of its stream.
+    SequenceOrder,
+    /// An audio or video frame before onMetaData, only checked in strict mode.
+    MetadataOrder,
+    /// Bytes skipped to find the next tag after corrupted data.
+    Resync,
+    /// An input that ends in the middle of a tag.
+    Truncated,
+    ReservedTypeFlags,
+    /// Header TypeFlags that do not match the streams in the file.
+    TypeFlags,
+    /// An AMF string in a script tag that is not well-formed UTF-8.
+    AmfString,
+    /// AMF data in a script tag that breaks off or has an unknown type, decoded up to there.
+    AmfData,
+    /// A stream that deviates from the `--expect` profile.
+    Expectation,
+    /// An AVC sequence header that changes the resolution or profile mid-stream.
+    ResolutionChange,
+    /// A sample rate or channel count that changes mid-stream.
+    AudioChange,
+}
+
+impl Rule {
+    pub const ALL: [Rule; 17] = [
+        Rule::PreTagSize,
+        Rule::TagSize,
+        Rule::Overflow,
+        Rule::StreamId,
+        Rule::InvalidData,
+        Rule::AudioHeader,
+        Rule::SequenceOrder,
+        Rule::MetadataOrder,
+        Rule::Resync,
+        Rule::Truncated,
+        Rule::ReservedTypeFlags,
+        Rule::TypeFlags,
+        Rule::AmfString,
+        Rule::AmfData,
+        Rule::Expectation,
+        Rule::ResolutionChange,
+        Rule::AudioChange,
+    ];
+
+    pub fn id(self) -> &'static str {
+        match self {
+            Rule::PreTagSize => "pre-tag-size",
+            Rule::TagSize => "tag-size",
+            Rule::Overflow => "overflow",
+            Rule::StreamId => "stream-id",
+            Rule::InvalidData => "invalid-data",
+            Rule::AudioHeader => "audio-header",
+            Rule::SequenceOrder => "sequence-order",
+            Rule::MetadataOrder => "metadata-order",
+            Rule::Resync => "resync",
+            Rule::Truncated => "truncated",
+            Rule::ReservedTypeFlags => "reserved-type-flags",
+            Rule::TypeFlags => "type-flags",
+            Rule::AmfString => "amf-string",
+            Rule::AmfData => "amf-data",
+            Rule::Expectation => "expectation",
+            Rule::ResolutionChange => "resolution-change",
+            Rule::AudioChange => "audio-change",
+        }
+    }
+
+    pub fn severity(self) -> Severity {
+        match self {
+            Rule::PreTagSize
+            | Rule::TagSize
+            | Rule::InvalidData
+            | Rule::Resync
+            | Rule::Truncated
+            | Rule::AmfData => Severity::Error,
+            Rule::Overflow
+            | Rule::StreamId
+            | Rule::AudioHeader
+            | Rule::SequenceOrder
+            | Rule::TypeFlags
+            | Rule::AmfString
+            | Rule::Expectation
+            | Rule::ResolutionChange
+            | Rule::AudioChange => Severity::Warning,
+            Rule::MetadataOrder | Rule::ReservedTypeFlags => Severity::Info,
+        }
+    }
+}
+
+impl FromStr for Rule {
+    type Err = Exception;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Rule::ALL
+            .iter()
+            .copied()
+            .find(|rule| rule.id() == s)
+            .ok_or_else(|| {
+                let ids: Vec<&str> = Rule::ALL.iter().map(|rule| rule.id()).collect();
+                format!("Unknown rule {}, expected one of {}", s, ids.join(", ")).into()
+            })
+    }
+}
+
+impl fmt::Display for Rule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.id())
+    }
+}
+
+/// A broken rule, with what was found where.
+#[derive(Debug, Clone)]
+pub struct Finding {
+    pub rule: Rule,
+    pub message: String,
+}
+
+impl Finding {
+    pub fn new(rule: Rule, message: String) -> Self {
+        Self { rule, message }
+    }
+
+    pub fn severity(&self) -> Severity {
+        self.rule.severity()
+    }
+}
+
+/// Shown as "Severity: message [rule]".
+impl fmt::Display for Finding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}: {} [{}]", self.severity(), self.message, self.rule)
+    }
+}
+
+/// A set of rules, e.g. the suppressed ones.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq)]
+pub struct RuleSet(u32);
+
+impl RuleSet {
+    pub fn insert(&mut self, rule: Rule) {
+        self.0 |= 1 << rule as u32;
+    }
+
+    pub fn contains(self, rule: Rule) -> bool {
+        self.0 & (1 << rule as u32) != 0
+    }
+}
+
+impl FromIterator<Rule> for RuleSet {
+    fn from_iter<I: IntoIterator<Item = Rule>>(iter: I) -> Self {
+        let mut set = RuleSet::default();
+        for rule in iter {
+            set.insert(rule);
+        }
+        set
+    }
+}
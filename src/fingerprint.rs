@@ -0,0 +1,298 @@
+use crate::amf::AmfValue;
+use crate::reader::{Tag, TagData, TagType};
+use std::collections::BTreeMap;
+use std::fmt;
+
+// onMetaData `encoder` strings, matched case-insensitively in this order.
+const ENCODERS: [(&str, &str); 8] = [
+    ("streamlabs", "Streamlabs Desktop"),
+    ("obs", "OBS Studio"),
+    ("lavf", "FFmpeg"),
+    ("fmle", "FMLE"),
+    ("flash media live encoder", "FMLE"),
+    ("wirecast", "Wirecast"),
+    ("vmix", "vMix"),
+    ("xsplit", "XSplit"),
+];
+
+// onMetaData properties only FMLE writes.
+const FMLE_PROPERTIES: [&str; 3] = ["videodevice", "audiodevice", "presetname"];
+
+// Video timestamps that step by the same number of milliseconds, give or take the rounding, at
+// least this often come from a constant frame rate rather than a capture clock.
+const CONSTANT_CADENCE: f64 = 0.9;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Confidence {
+    Low,
+    Medium,
+    High,
+}
+
+impl Confidence {
+    pub fn name(self) -> &'static str {
+        match self {
+            Confidence::Low => "low",
+            Confidence::Medium => "medium",
+            Confidence::High => "high",
+        }
+    }
+}
+
+/// The software that most likely produced a file, and the server it was relayed through, with
+/// what points to them.
+#[derive(Debug)]
+pub struct Identification {
+    pub software: Option<&'static str>,
+    pub confidence: Option<Confidence>,
+    pub relay: Option<String>,
+    pub evidence: Vec<String>,
+}
+
+impl fmt::Display for Identification {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match (self.software, self.confidence) {
+            (Some(software), Some(confidence)) => {
+                write!(f, "{} ({} confidence)", software, confidence.name())
+            }
+            _ => write!(f, "unknown"),
+        }
+    }
+}
+
+/// Traits of a file that tell apart the software producing it: the onMetaData strings, the order
+/// of the first tags, quirks of the AVC sequence header and the timestamp cadence. Each points to
+/// a candidate with a weight, 3 for a name written into the file down to 1 for habits that other
+/// software may share.
+#[derive(Debug, Default)]
+pub struct Fingerprint {
+    encoder: Option<String>,
+    creator: Option<String>,
+    server: Option<String>,
+    properties: Vec<String>,
+    // "audio" or "video", in the order the sequence headers came
+    sequence_headers: Vec<&'static str>,
+    metadata_first: Option<bool>,
+    first_timestamp: Option<i32>,
+    // reserved bits of the AVCDecoderConfigurationRecord that are not set, lengthSizeMinusOne
+    avc_config: Option<(bool, u8)>,
+    last_video: Option<i32>,
+    video_steps: BTreeMap<i64, usize>,
+    audio_run: usize,
+    max_audio_run: usize,
+}
+
+impl Fingerprint {
+    pub fn push(&mut self, tag: &Tag) {
+        let timestamp = tag.header.timestamp;
+        match &tag.data {
+            TagData::Script(script) => {
+                self.metadata_first.get_or_insert(true);
+                if let Some(metadata) = script.metadata() {
+                    self.metadata(&metadata);
+                }
+                return;
+            }
+            TagData::Audio(_) | TagData::Video(_) => {
+                self.metadata_first.get_or_insert(false);
+                self.first_timestamp.get_or_insert(timestamp);
+            }
+            _ => return,
+        }
+        if tag.data.is_sequence_header() {
+            let kind = match tag.header.tag_type {
+                TagType::Audio => "audio",
+                _ => "video",
+            };
+            if !self.sequence_headers.contains(&kind) {
+                self.sequence_headers.push(kind);
+            }
+            if let TagData::Video(video) = &tag.data {
+                // AVCPacketType and CompositionTime, then the record
+                if let [_, _, _, _, _, _, _, _, length_size, sps_count, ..] = &video.data[..] {
+                    let cleared = length_size & 0xfc != 0xfc || sps_count & 0xe0 != 0xe0;
+                    self.avc_config.get_or_insert((cleared, length_size & 0x03));
+                }
+            }
+            return;
+        }
+        match tag.header.tag_type {
+            TagType::Audio => {
+                self.audio_run += 1;
+                self.max_audio_run = self.max_audio_run.max(self.audio_run);
+            }
+            TagType::Video => {
+                self.audio_run = 0;
+                if let Some(last) = self.last_video.replace(timestamp) {
+                    let step = timestamp as i64 - last as i64;
+                    *self.video_steps.entry(step).or_default() += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn metadata(&mut self, metadata: &AmfValue) {
+        let string = |name| {
+            metadata
+                .property(name)
+                .and_then(AmfValue::as_str)
+                .map(str::to_owned)
+        };
+        self.encoder = self.encoder.take().or_else(|| string("encoder"));
+        self.creator = self.creator.take().or_else(|| string("metadatacreator"));
+        self.server = self.server.take().or_else(|| string("server"));
+        if let AmfValue::Object(properties)
+        | AmfValue::EcmaArray(properties)
+        | AmfValue::TypedObject(_, properties) = metadata
+        {
+            for (key, _) in properties {
+                if !self.properties.contains(key) {
+                    self.properties.push(key.clone());
+                }
+            }
+        }
+    }
+
+    // Milliseconds of the steps between video frames, if nearly all are n or n + 1.
+    fn constant_cadence(&self) -> Option<i64> {
+        let total: usize = self.video_steps.values().sum();
+        if total < 10 {
+            return None;
+        }
+        let (step, _) = self.video_steps.iter().max_by_key(|(_, count)| **count)?;
+        let count = |step| self.video_steps.get(&step).copied().unwrap_or(0);
+        let steady = count(*step) + count(step - 1).max(count(step + 1));
+        if steady as f64 >= total as f64 * CONSTANT_CADENCE && *step > 0 {
+            Some(*step)
+        } else {
+            None
+        }
+    }
+
+    pub fn identify(&self) -> Option<Identification> {
+        let mut scores: Vec<(&'static str, u32)> = vec![];
+        let mut evidence = vec![];
+        let mut point = |software: &'static str, weight: u32| match scores
+            .iter_mut()
+            .find(|(name, _)| *name == software)
+        {
+            Some((_, score)) => *score += weight,
+            None => scores.push((software, weight)),
+        };
+
+        if let Some(encoder) = &self.encoder {
+            let lowercase = encoder.to_lowercase();
+            let software = ENCODERS
+                .iter()
+                .find(|(pattern, _)| lowercase.contains(pattern))
+                .map(|(_, software)| *software);
+            if let Some(software) = software {
+                point(software, 3);
+            }
+            evidence.push(format!("onMetaData encoder is \"{}\"", encoder));
+        }
+        let fmle: Vec<&str> = FMLE_PROPERTIES
+            .iter()
+            .copied()
+            .filter(|name| self.properties.iter().any(|key| key == name))
+            .collect();
+        if !fmle.is_empty() {
+            point("FMLE", 2);
+            evidence.push(format!(
+                "onMetaData has {}, as written by FMLE",
+                fmle.join(", ")
+            ));
+        }
+        if let Some(creator) = &self.creator {
+            evidence.push(format!(
+                "onMetaData was rewritten by \"{}\", the encoder may be older",
+                creator
+            ));
+        }
+
+        let relay = match &self.server {
+            Some(server) => Some(server.clone()),
+            None if self.properties.iter().any(|key| key.starts_with("srs_")) => {
+                Some("SRS".to_owned())
+            }
+            None => None,
+        };
+        if let Some(relay) = &relay {
+            evidence.push(format!("onMetaData names the server {}", relay));
+        }
+
+        match self.sequence_headers[..] {
+            ["audio", "video"] => {
+                point("OBS Studio", 1);
+                evidence.push("the audio sequence header precedes the video one".into());
+            }
+            ["video", "audio"] => {
+                point("FFmpeg", 1);
+                evidence.push("the video sequence header precedes the audio one".into());
+            }
+            _ => {}
+        }
+        if self.metadata_first == Some(false) && !self.sequence_headers.is_empty() {
+            evidence.push(
+                "the stream starts without onMetaData, as when a relay or recorder drops it".into(),
+            );
+        }
+        if let Some(first) = self.first_timestamp.filter(|first| *first >= 1000) {
+            evidence.push(format!(
+                "timestamps start at {} ms, as when a relay joins a stream in progress",
+                first
+            ));
+        }
+        if let Some((cleared, length_size)) = self.avc_config {
+            if cleared {
+                evidence.push(
+                    "the AVC sequence header has reserved bits cleared, which FFmpeg and OBS set"
+                        .into(),
+                );
+            }
+            if length_size != 3 {
+                evidence.push(format!(
+                    "the AVC sequence header uses {}-byte NAL unit lengths",
+                    length_size + 1
+                ));
+            }
+        }
+        if let Some(step) = self.constant_cadence() {
+            evidence.push(format!(
+                "video timestamps step steadily by about {} ms, a constant frame rate",
+                step
+            ));
+        } else if self.video_steps.values().sum::<usize>() >= 10 {
+            evidence.push("video timestamps step irregularly, as from a capture clock".into());
+        }
+        if self.max_audio_run >= 10 {
+            evidence.push(format!(
+                "audio comes in runs of up to {} tags between video frames",
+                self.max_audio_run
+            ));
+        }
+
+        if evidence.is_empty() {
+            return None;
+        }
+        // the first of the best, so that names written into the file win ties
+        let best = scores.iter().filter(|(_, score)| *score > 0).fold(
+            None,
+            |best: Option<(&'static str, u32)>, (name, score)| match best {
+                Some((_, max)) if max >= *score => best,
+                _ => Some((name, *score)),
+            },
+        );
+        Some(Identification {
+            software: best.map(|(name, _)| name),
+            confidence: best.map(|(_, score)| match score {
+                0..=1 => Confidence::Low,
+                2 => Confidence::Medium,
+                _ => Confidence::High,
+            }),
+            relay,
+            evidence,
+        })
+    }
+}
@@ -0,0 +1,270 @@
+use crate::aac::AacPacketType;
+use crate::amf::AmfValue;
+use crate::findings::Finding;
+use crate::output::{FileInfo, Formatter};
+use crate::reader::{
+    AudioData, CodecId, SoundFormat, SoundRate, SoundSize, SoundType, Tag, TagData, TagType,
+    VideoData, VideoFrameType,
+};
+use crate::stats::Stats;
+use crate::time;
+use crate::Exception;
+use std::convert::TryFrom;
+use std::io::Write;
+
+/// The raw output of flvmeta: `flvmeta --dump --raw` prints the onMetaData properties only,
+/// `flvmeta --full-dump --raw` every tag of the file.
+pub struct Flvmeta {
+    full: bool,
+    metadata_dumped: bool,
+}
+
+impl Flvmeta {
+    pub fn dump() -> Self {
+        Self {
+            full: false,
+            metadata_dumped: false,
+        }
+    }
+
+    pub fn full_dump() -> Self {
+        Self {
+            full: true,
+            metadata_dumped: false,
+        }
+    }
+}
+
+impl Formatter for Flvmeta {
+    fn header(&mut self, out: &mut dyn Write, file: &FileInfo) -> Result<(), Exception> {
+        self.metadata_dumped = false;
+        if self.full {
+            let yes_no = |b| if b { "yes" } else { "no" };
+            writeln!(out, "Magic: FLV")?;
+            writeln!(out, "Version: {}", file.header.version)?;
+            writeln!(out, "Has audio: {}", yes_no(file.header.has_audio()))?;
+            writeln!(out, "Has video: {}", yes_no(file.header.has_video()))?;
+            writeln!(out, "Offset: {}", file.header.offset)?;
+        }
+        Ok(())
+    }
+
+    fn pre_tag_size(
+        &mut self,
+        out: &mut dyn Write,
+        index: usize,
+        size: u32,
+    ) -> Result<(), Exception> {
+        // flvmeta reports the size after each tag, not the zero in front of the first one
+        if self.full && index > 0 {
+            writeln!(out, "Previous tag size: {}", size)?;
+        }
+        Ok(())
+    }
+
+    fn tag(&mut self, out: &mut dyn Write, index: usize, tag: &Tag) -> Result<(), Exception> {
+        if !self.full {
+            if let TagData::Script(script) = &tag.data {
+                if !self.metadata_dumped {
+                    if let Some(metadata) = script.metadata() {
+                        write_amf(out, &metadata, 0)?;
+                        writeln!(out)?;
+                        self.metadata_dumped = true;
+                    }
+                }
+            }
+            return Ok(());
+        }
+
+        let offset = tag.header.offset;
+        writeln!(out, "--- Tag #{} at 0x{:X} ({}) ---", index, offset, offset)?;
+        let tag_type = match tag.header.tag_type {
+            TagType::Audio => "audio",
+            TagType::Video => "video",
+            TagType::Script => "scriptData",
+            _ => "Unknown",
+        };
+        writeln!(out, "Tag type: {}", tag_type)?;
+        writeln!(out, "Body length: {}", tag.header.data_size)?;
+        writeln!(out, "Timestamp: {}", tag.header.timestamp)?;
+
+        match &tag.data {
+            TagData::Audio(audio) => write_audio(out, audio)?,
+            TagData::Video(video) => write_video(out, video)?,
+            TagData::Script(script) => {
+                let mut values = script.values().into_iter();
+                if let (Some(AmfValue::String(name)), Some(data)) = (values.next(), values.next()) {
+                    writeln!(out, "* Metadata event name: {}", name)?;
+                    write!(out, "* Metadata contents: ")?;
+                    write_amf(out, &data, 0)?;
+                    writeln!(out)?;
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn stats(&mut self, _: &mut dyn Write, _: &Stats) -> Result<(), Exception> {
+        Err("Statistics can not be exported in the flvmeta format".into())
+    }
+
+    fn warning(&mut self, _: &mut dyn Write, finding: &Finding) -> Result<(), Exception> {
+        eprintln!("{}", finding);
+        Ok(())
+    }
+
+    fn finish(&mut self, _: &mut dyn Write) -> Result<(), Exception> {
+        Ok(())
+    }
+}
+
+fn write_audio(out: &mut dyn Write, audio: &AudioData) -> Result<(), Exception> {
+    use SoundFormat::*;
+    let header = &audio.header;
+    let sound_type = match header.sound_type {
+        SoundType::Mono => "mono",
+        SoundType::Stereo => "stereo",
+    };
+    let sound_size = match header.sound_size {
+        SoundSize::S8Bit => "8",
+        SoundSize::S16Bit => "16",
+    };
+    let sound_rate = match header.sound_rate {
+        SoundRate::R5p5kHz => "5.5",
+        SoundRate::R11kHz => "11",
+        SoundRate::R22kHz => "22",
+        SoundRate::R44kHz => "44",
+    };
+    let sound_format = match header.sound_format {
+        LinearPCMPlatformEndian => "Linear PCM, platform endian",
+        ADPCM => "ADPCM",
+        MP3 => "MP3",
+        LinearPCMLittleEndian => "Linear PCM, little endian",
+        Nellymoser16 => "Nellymoser 16-kHz mono",
+        Nellymoser8 => "Nellymoser 8-kHz mono",
+        Nellymoser => "Nellymoser",
+        G711ALaw => "G.711 A-law logarithmic PCM",
+        G711MuLaw => "G.711 mu-law logarithmic PCM",
+        Reserved => "reserved",
+        AAC => "AAC",
+        Speex => "Speex",
+        MP38kHz => "MP3 8-Khz",
+        DeviceSpecific => "Device-specific sound",
+    };
+    writeln!(out, "* Sound type: {}", sound_type)?;
+    writeln!(out, "* Sound size: {}", sound_size)?;
+    writeln!(out, "* Sound rate: {}", sound_rate)?;
+    writeln!(out, "* Sound format: {}", sound_format)?;
+
+    if header.sound_format == AAC {
+        let packet_type = match audio.data.first().map(|b| AacPacketType::try_from(*b)) {
+            Some(Ok(AacPacketType::SequenceHeader)) => "AAC sequence header",
+            Some(Ok(AacPacketType::Raw)) => "AAC raw",
+            _ => "Unknown",
+        };
+        writeln!(out, "* AAC packet type: {}", packet_type)?;
+    }
+    Ok(())
+}
+
+fn write_video(out: &mut dyn Write, video: &VideoData) -> Result<(), Exception> {
+    let codec = match video.header.codec_id {
+        CodecId::JPEG => "JPEG",
+        CodecId::SorensonH263 => "Sorenson H.263",
+        CodecId::ScreenVideo => "Screen video",
+        CodecId::On2VP6 => "On2 VP6",
+        CodecId::On2VP6WithAlpha => "On2 VP6 with alpha channel",
+        CodecId::ScreenVideoVersion2 => "Screen video version 2",
+        CodecId::AVC => "AVC",
+    };
+    let frame_type = match video.header.frame_type {
+        VideoFrameType::KeyFrame => "keyframe",
+        VideoFrameType::InterFrame => "inter frame",
+        VideoFrameType::DisposableInterFrame => "disposable inter frame",
+        VideoFrameType::GeneratedKeyFrame => "generated keyframe",
+        VideoFrameType::VideoInfoOrCommandFrame => "video info/command frame",
+    };
+    writeln!(out, "* Video codec: {}", codec)?;
+    writeln!(out, "* Video frame type: {}", frame_type)?;
+
+    if video.header.codec_id == CodecId::AVC {
+        let packet_type = match video.data.first() {
+            Some(0) => "AVC sequence header",
+            Some(1) => "AVC NALU",
+            Some(2) => "AVC end of sequence",
+            _ => "Unknown",
+        };
+        writeln!(out, "* AVC packet type: {}", packet_type)?;
+    }
+    Ok(())
+}
+
+// Same layout as libamf's amf_data_dump: four spaces per level, quoted strings and names.
+fn write_amf(out: &mut dyn Write, value: &AmfValue, level: usize) -> Result<(), Exception> {
+    match value {
+        AmfValue::Number(n) => write!(out, "{}", format_g12(*n))?,
+        AmfValue::Boolean(b) => write!(out, "{}", b)?,
+        AmfValue::String(s) | AmfValue::Xml(s) => write!(out, "'{}'", s)?,
+        AmfValue::Null => write!(out, "null")?,
+        AmfValue::Undefined => write!(out, "undefined")?,
+        AmfValue::Date { millis, .. } => {
+            write!(out, "{}", time::format_millis_utc(*millis as i64))?
+        }
+        AmfValue::Reference(index) => write!(out, "reference {}", index)?,
+        AmfValue::Object(properties)
+        | AmfValue::EcmaArray(properties)
+        | AmfValue::TypedObject(_, properties) => {
+            writeln!(out, "{{")?;
+            for (name, value) in properties {
+                write!(out, "{:1$}'{2}': ", "", (level + 1) * 4, name)?;
+                write_amf(out, value, level + 1)?;
+                writeln!(out)?;
+            }
+            write!(out, "{:>1$}", "}", level * 4 + 1)?;
+        }
+        AmfValue::StrictArray(items) => {
+            writeln!(out, "[")?;
+            for item in items {
+                write!(out, "{:1$}", "", (level + 1) * 4)?;
+                write_amf(out, item, level + 1)?;
+                writeln!(out)?;
+            }
+            write!(out, "{:>1$}", "]", level * 4 + 1)?;
+        }
+    }
+    Ok(())
+}
+
+// printf's "%.12g": 12 significant digits, trailing zeros removed.
+fn format_g12(n: f64) -> String {
+    const PRECISION: i32 = 12;
+    if n == 0.0 || !n.is_finite() {
+        return match n {
+            n if n.is_nan() => "nan".into(),
+            n if n.is_infinite() && n > 0.0 => "inf".into(),
+            n if n.is_infinite() => "-inf".into(),
+            _ => "0".into(),
+        };
+    }
+
+    let scientific = format!("{:.*e}", PRECISION as usize - 1, n);
+    let (mantissa, exponent) = scientific.split_once('e').expect("exponent");
+    let exponent: i32 = exponent.parse().expect("exponent");
+    if !(-4..PRECISION).contains(&exponent) {
+        let mantissa = trim_zeros(mantissa);
+        let sign = if exponent < 0 { '-' } else { '+' };
+        format!("{}e{}{:02}", mantissa, sign, exponent.abs())
+    } else {
+        let decimals = (PRECISION - 1 - exponent) as usize;
+        trim_zeros(&format!("{:.*}", decimals, n)).to_owned()
+    }
+}
+
+fn trim_zeros(s: &str) -> &str {
+    if s.contains('.') {
+        s.trim_end_matches('0').trim_end_matches('.')
+    } else {
+        s
+    }
+}
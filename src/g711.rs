@@ -0,0 +1,14 @@
+use crate::reader::{AudioDataHeader, SoundFormat};
+
+/// G.711 A-law and mu-law carry one byte per sample at 8 kHz.
+pub const SAMPLE_RATE: u32 = 8000;
+
+pub fn is_g711(format: SoundFormat) -> bool {
+    matches!(format, SoundFormat::G711ALaw | SoundFormat::G711MuLaw)
+}
+
+/// Duration in milliseconds of a tag body, from its size.
+pub fn duration(header: &AudioDataHeader, data: &[u8]) -> f64 {
+    let channels = header.sound_type.channels() as u32;
+    data.len() as f64 * 1000.0 / (SAMPLE_RATE * channels) as f64
+}
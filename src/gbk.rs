@@ -0,0 +1,48 @@
+//! Decoding of GBK (code page 936) text, for metadata written by legacy Chinese encoders.
+
+// The code point of every two byte sequence, lead bytes 0x81 to 0xfe by trail bytes 0x40 to 0xfe,
+// as little-endian u16, 0 where the sequence is unmapped. Generated from Python's codec:
+//
+//     for lead in range(0x81, 0xff):
+//         for trail in range(0x40, 0xff):
+//             try: c = ord(bytes([lead, trail]).decode("gbk"))
+//             except UnicodeDecodeError: c = 0
+//             out += struct.pack("<H", c)
+static TABLE: &[u8; 126 * 191 * 2] = include_bytes!("gbk.bin");
+
+fn lookup(lead: u8, trail: u8) -> Option<char> {
+    if !(0x81..=0xfe).contains(&lead) || !(0x40..=0xfe).contains(&trail) {
+        return None;
+    }
+    let index = ((lead - 0x81) as usize * 191 + (trail - 0x40) as usize) * 2;
+    let code = u16::from_le_bytes([TABLE[index], TABLE[index + 1]]);
+    if code == 0 {
+        return None;
+    }
+    char::from_u32(code as u32)
+}
+
+/// Decodes GBK text, invalid sequences become U+FFFD.
+pub fn decode(bytes: &[u8]) -> String {
+    let mut text = String::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let byte = bytes[i];
+        if byte < 0x80 {
+            text.push(byte as char);
+            i += 1;
+            continue;
+        }
+        match bytes.get(i + 1).and_then(|trail| lookup(byte, *trail)) {
+            Some(c) => {
+                text.push(c);
+                i += 2;
+            }
+            None => {
+                text.push(char::REPLACEMENT_CHARACTER);
+                i += 1;
+            }
+        }
+    }
+    text
+}
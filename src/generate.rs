@@ -0,0 +1,308 @@
+use crate::aac::AudioSpecificConfig;
+use crate::amf::AmfValue;
+use crate::reader::{
+    AudioData, AudioDataHeader, CodecId, Header, ScriptData, SoundFormat, SoundRate, SoundSize,
+    SoundType, Tag, TagData, TagHeader, TagType, VideoData, VideoDataHeader, VideoFrameType,
+    TYPE_FLAGS_AUDIO, TYPE_FLAGS_VIDEO,
+};
+use crate::writer::FlvWriter;
+use crate::Exception;
+use bytes::{BufMut, Bytes, BytesMut};
+use std::convert::TryFrom;
+use std::path::Path;
+use std::str::FromStr;
+use tokio::fs::File;
+use tokio::io::BufWriter;
+
+// AAC LC, 44.1 kHz, stereo
+const AAC_CONFIG: [u8; 2] = [0x12, 0x10];
+// MPEG-1 Layer III, 128 kbps, 44.1 kHz, joint stereo, followed by silence
+const MP3_FRAME_HEADER: [u8; 4] = [0xff, 0xfb, 0x90, 0x44];
+const MP3_FRAME_LENGTH: usize = 417;
+const SAMPLE_RATE: f64 = 44100.0;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, clap::ValueEnum)]
+pub enum AudioCodec {
+    Aac,
+    Mp3,
+    None,
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, clap::ValueEnum)]
+pub enum VideoCodec {
+    Avc,
+    H263,
+    None,
+}
+
+/// Shifts the timestamps of every tag from `at` onwards by `delta` milliseconds, written as
+/// `AT:DELTA` on the command line.
+#[derive(Debug, Copy, Clone)]
+pub struct TimestampJump {
+    pub at: u32,
+    pub delta: i32,
+}
+
+impl FromStr for TimestampJump {
+    type Err = Exception;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (at, delta) = s
+            .split_once(':')
+            .ok_or_else(|| format!("Expected AT:DELTA, got {}", s))?;
+        Ok(Self {
+            at: at.parse()?,
+            delta: delta.parse()?,
+        })
+    }
+}
+
+#[derive(Debug)]
+pub struct GenerateOptions {
+    /// Duration in milliseconds.
+    pub duration: u32,
+    pub audio: AudioCodec,
+    pub video: VideoCodec,
+    pub frame_rate: f64,
+    /// Frames per GOP, every GOP starts with a keyframe.
+    pub gop: u32,
+    /// DataSize of keyframe tags.
+    pub keyframe_size: usize,
+    /// DataSize of the other video tags.
+    pub video_tag_size: usize,
+    /// DataSize of AAC frame tags, a silent frame if not set.
+    pub audio_tag_size: Option<usize>,
+    /// Indexes of the tags followed by a wrong PreviousTagSize.
+    pub bad_pre_tag_sizes: Vec<usize>,
+    pub timestamp_jumps: Vec<TimestampJump>,
+}
+
+/// Writes a synthetic FLV file: an onMetaData tag, the codec sequence headers, then audio and
+/// video frames interleaved by timestamp. Payloads are filler, only the FLV structure and the
+/// codec headers are meaningful.
+pub async fn generate<P: AsRef<Path>>(
+    output: P,
+    options: &GenerateOptions,
+) -> Result<(), Exception> {
+    let mut tags = vec![metadata_tag(options)];
+    tags.extend(sequence_headers(options));
+
+    let mut media = video_tags(options);
+    media.extend(audio_tags(options)?);
+    // stable, so video goes first at equal timestamps
+    media.sort_by_key(|tag| tag.header.timestamp);
+    tags.extend(media);
+
+    for tag in &mut tags {
+        let shift: i32 = options
+            .timestamp_jumps
+            .iter()
+            .filter(|jump| tag.header.timestamp >= jump.at as i32)
+            .map(|jump| jump.delta)
+            .sum();
+        tag.header.timestamp = (tag.header.timestamp + shift).max(0);
+    }
+
+    let mut type_ = 0;
+    if options.audio != AudioCodec::None {
+        type_ |= TYPE_FLAGS_AUDIO;
+    }
+    if options.video != VideoCodec::None {
+        type_ |= TYPE_FLAGS_VIDEO;
+    }
+    let header = Header {
+        version: 1,
+        type_,
+        offset: 9,
+        extra: Bytes::new(),
+    };
+    let file = File::create(output).await?;
+    let mut writer = FlvWriter::new(BufWriter::new(file), &header).await?;
+    for (index, tag) in tags.iter().enumerate() {
+        writer.write_tag(tag).await?;
+        // tags are numbered from 1, like in the dump
+        if options.bad_pre_tag_sizes.contains(&(index + 1)) {
+            writer.corrupt_pre_tag_size();
+        }
+    }
+    writer.finish().await?;
+
+    println!("Tags: {}", tags.len());
+    Ok(())
+}
+
+fn tag(tag_type: TagType, timestamp: i32, data: TagData) -> Tag {
+    Tag {
+        header: TagHeader {
+            tag_type,
+            data_size: data.encoded_len() as u32,
+            timestamp,
+            offset: 0,
+        },
+        data,
+        raw: None,
+        received: None,
+    }
+}
+
+fn audio_header(options: &GenerateOptions) -> AudioDataHeader {
+    AudioDataHeader {
+        sound_format: match options.audio {
+            AudioCodec::Mp3 => SoundFormat::MP3,
+            _ => SoundFormat::AAC,
+        },
+        sound_rate: SoundRate::R44kHz,
+        sound_size: SoundSize::S16Bit,
+        sound_type: SoundType::Stereo,
+    }
+}
+
+fn codec_id(options: &GenerateOptions) -> CodecId {
+    match options.video {
+        VideoCodec::H263 => CodecId::SorensonH263,
+        _ => CodecId::AVC,
+    }
+}
+
+fn metadata_tag(options: &GenerateOptions) -> Tag {
+    let mut properties = vec![(
+        "duration".to_owned(),
+        AmfValue::Number(options.duration as f64 / 1000.0),
+    )];
+    if options.video != VideoCodec::None {
+        properties.push(("framerate".into(), AmfValue::Number(options.frame_rate)));
+        properties.push((
+            "videocodecid".into(),
+            AmfValue::Number(codec_id(options) as u8 as f64),
+        ));
+    }
+    if options.audio != AudioCodec::None {
+        let header = audio_header(options);
+        properties.push(("audiosamplerate".into(), AmfValue::Number(SAMPLE_RATE)));
+        properties.push(("stereo".into(), AmfValue::Boolean(true)));
+        properties.push((
+            "audiocodecid".into(),
+            AmfValue::Number(header.sound_format as u8 as f64),
+        ));
+    }
+
+    let mut raw = BytesMut::new();
+    AmfValue::String("onMetaData".into()).encode(&mut raw);
+    AmfValue::EcmaArray(properties).encode(&mut raw);
+    tag(
+        TagType::Script,
+        0,
+        TagData::Script(ScriptData::new(raw.freeze())),
+    )
+}
+
+fn sequence_headers(options: &GenerateOptions) -> Vec<Tag> {
+    let mut tags = Vec::new();
+    if options.video == VideoCodec::Avc {
+        // AVCDecoderConfigurationRecord with a placeholder baseline SPS and PPS
+        let mut data = BytesMut::new();
+        data.put_slice(&[0, 0, 0, 0]);
+        data.put_slice(&[1, 66, 0, 30, 0xff, 0xe1]);
+        data.put_slice(&[0, 4, 0x67, 66, 0, 30]);
+        data.put_slice(&[1, 0, 2, 0x68, 0xce]);
+        tags.push(video_tag(
+            CodecId::AVC,
+            VideoFrameType::KeyFrame,
+            0,
+            data.freeze(),
+        ));
+    }
+    if options.audio == AudioCodec::Aac {
+        let mut data = vec![0];
+        data.extend_from_slice(&AAC_CONFIG);
+        tags.push(audio_tag(options, 0, data.into()));
+    }
+    tags
+}
+
+fn video_tag(codec_id: CodecId, frame_type: VideoFrameType, timestamp: i32, data: Bytes) -> Tag {
+    let header = VideoDataHeader {
+        frame_type,
+        codec_id,
+    };
+    tag(
+        TagType::Video,
+        timestamp,
+        TagData::Video(VideoData { header, data }),
+    )
+}
+
+fn audio_tag(options: &GenerateOptions, timestamp: i32, data: Bytes) -> Tag {
+    let header = audio_header(options);
+    tag(
+        TagType::Audio,
+        timestamp,
+        TagData::Audio(AudioData { header, data }),
+    )
+}
+
+fn video_tags(options: &GenerateOptions) -> Vec<Tag> {
+    if options.video == VideoCodec::None {
+        return vec![];
+    }
+    let frames = (options.duration as f64 * options.frame_rate / 1000.0).ceil() as u32;
+    (0..frames)
+        .map(|i| {
+            let key = i % options.gop.max(1) == 0;
+            let size = if key {
+                options.keyframe_size
+            } else {
+                options.video_tag_size
+            };
+            let mut data = BytesMut::new();
+            match options.video {
+                VideoCodec::Avc => {
+                    // NALU packet, composition time 0, one length-prefixed IDR or non-IDR slice
+                    data.put_slice(&[1, 0, 0, 0]);
+                    let nalu = size.saturating_sub(9).max(1);
+                    data.put_u32(nalu as u32);
+                    data.put_u8(if key { 0x65 } else { 0x41 });
+                    data.put_slice(&vec![0; nalu - 1]);
+                }
+                _ => data.put_slice(&vec![0; size.saturating_sub(1)]),
+            }
+            let frame_type = if key {
+                VideoFrameType::KeyFrame
+            } else {
+                VideoFrameType::InterFrame
+            };
+            let timestamp = (i as f64 * 1000.0 / options.frame_rate).round() as i32;
+            video_tag(codec_id(options), frame_type, timestamp, data.freeze())
+        })
+        .collect()
+}
+
+fn audio_tags(options: &GenerateOptions) -> Result<Vec<Tag>, Exception> {
+    let (samples, payload): (f64, Bytes) = match options.audio {
+        AudioCodec::None => return Ok(vec![]),
+        AudioCodec::Aac => {
+            let config = AudioSpecificConfig::try_from(&AAC_CONFIG[..])?;
+            let frame = match options.audio_tag_size {
+                Some(size) => vec![0; size.saturating_sub(2)].into(),
+                None => config.silent_frame()?,
+            };
+            let mut data = vec![1];
+            data.extend_from_slice(&frame);
+            (config.frame_length as f64, data.into())
+        }
+        AudioCodec::Mp3 => {
+            let mut data = vec![0; MP3_FRAME_LENGTH];
+            data[..4].copy_from_slice(&MP3_FRAME_HEADER);
+            (1152.0, data.into())
+        }
+    };
+
+    let frame_duration = samples * 1000.0 / SAMPLE_RATE;
+    let frames = (options.duration as f64 / frame_duration).ceil() as u32;
+    Ok((0..frames)
+        .map(|i| {
+            let timestamp = (i as f64 * frame_duration).round() as i32;
+            audio_tag(options, timestamp, payload.clone())
+        })
+        .collect())
+}
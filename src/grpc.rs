@@ -0,0 +1,250 @@
+use crate::findings::Rule;
+use crate::input::open_input;
+use crate::output::{stats_json, tag_json};
+use crate::reader::{read_flv, Field, Header, ParseMode, ParseOptions};
+use crate::stats::Stats;
+use crate::Exception;
+use bytes::Bytes;
+use proto::analyzer_server::{Analyzer, AnalyzerServer};
+use proto::{event, AnalyzeRequest, Chunk, Event, Options};
+use serde_json::{Map, Value};
+use std::io;
+use tokio::io::AsyncRead;
+use tokio::net::TcpListener;
+use tokio::sync::mpsc::{self, Sender};
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::StreamExt;
+use tokio_util::io::StreamReader;
+use tonic::transport::server::TcpIncoming;
+use tonic::transport::Server;
+use tonic::{Request, Response, Status, Streaming};
+
+// Tags make up most events, boxing them would only add an allocation.
+#[allow(clippy::large_enum_variant)]
+mod proto {
+    tonic::include_proto!("flv_dump.v1");
+}
+
+// Events parsed ahead of a slow client.
+const EVENTS_IN_FLIGHT: usize = 64;
+
+type Events = ReceiverStream<Result<Event, Status>>;
+
+struct Service;
+
+#[tonic::async_trait]
+impl Analyzer for Service {
+    type AnalyzeStream = Events;
+    type AnalyzeStreamStream = Events;
+
+    async fn analyze(&self, request: Request<AnalyzeRequest>) -> Result<Response<Events>, Status> {
+        let request = request.into_inner();
+        let (options, stats) = options(request.options.unwrap_or_default())
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let (size, input) = open_input(&request.source)
+            .await
+            .map_err(|e| Status::not_found(e.to_string()))?;
+        Ok(Response::new(spawn_analysis(
+            request.source,
+            size,
+            input,
+            options,
+            stats,
+        )))
+    }
+
+    async fn analyze_stream(
+        &self,
+        request: Request<Streaming<Chunk>>,
+    ) -> Result<Response<Events>, Status> {
+        let mut chunks = request.into_inner();
+        let first = chunks
+            .message()
+            .await?
+            .ok_or_else(|| Status::invalid_argument("No chunk was sent"))?;
+        let (options, stats) = options(first.options.clone().unwrap_or_default())
+            .map_err(|e| Status::invalid_argument(e.to_string()))?;
+        let data = tokio_stream::once(Ok(first))
+            .chain(chunks)
+            .map(|chunk| match chunk {
+                Ok(chunk) => Ok(Bytes::from(chunk.data)),
+                Err(status) => Err(io::Error::other(status.message().to_owned())),
+            });
+        let input = StreamReader::new(Box::pin(data));
+        Ok(Response::new(spawn_analysis(
+            "stream".into(),
+            None,
+            input,
+            options,
+            stats,
+        )))
+    }
+}
+
+/// Serves the parser over gRPC on `listen`, with the `Analyzer` service of
+/// proto/flv_dump.proto: `Analyze` parses a file, socket or URL the server can read,
+/// `AnalyzeStream` the bytes sent by the client. Both stream the header, tags and findings as
+/// they are parsed, and the statistics at the end if asked for.
+pub async fn grpc_serve(listen: &str) -> Result<(), Exception> {
+    let listener = TcpListener::bind(listen).await?;
+    eprintln!("Listening on http://{}", listener.local_addr()?);
+    let incoming = TcpIncoming::from_listener(listener, true, None)?;
+    Server::builder()
+        .add_service(AnalyzerServer::new(Service))
+        .serve_with_incoming(incoming)
+        .await?;
+    Ok(())
+}
+
+fn options(options: Options) -> Result<(ParseOptions, bool), Exception> {
+    let suppressed = options
+        .suppress
+        .iter()
+        .map(|id| id.parse::<Rule>())
+        .collect::<Result<_, _>>()?;
+    let mode = if options.strict {
+        ParseMode::Strict
+    } else {
+        ParseMode::Lenient
+    };
+    let parse_options = ParseOptions {
+        mode,
+        suppressed,
+        ..ParseOptions::default()
+    };
+    Ok((parse_options, options.stats))
+}
+
+// Parses on its own task, which stops when the client goes away.
+fn spawn_analysis<R>(
+    path: String,
+    size: Option<u64>,
+    input: R,
+    options: ParseOptions,
+    stats: bool,
+) -> Events
+where
+    R: AsyncRead + Send + Unpin + 'static,
+{
+    let (events, rx) = mpsc::channel(EVENTS_IN_FLIGHT);
+    tokio::spawn(async move {
+        let result = analyze(&path, size, input, options, stats, &events).await;
+        if let Err(e) = result {
+            let _ = events
+                .send(Err(Status::invalid_argument(e.to_string())))
+                .await;
+        }
+    });
+    ReceiverStream::new(rx)
+}
+
+async fn analyze<R: AsyncRead + Unpin>(
+    path: &str,
+    size: Option<u64>,
+    input: R,
+    options: ParseOptions,
+    stats: bool,
+    events: &Sender<Result<Event, Status>>,
+) -> Result<(), Exception> {
+    let (header, mut fields) = read_flv(input, options).await?;
+    send(
+        events,
+        event::Event::Header(header_event(path, size, &header)),
+    )
+    .await?;
+    let mut stats = if stats { Some(Stats::default()) } else { None };
+    let (mut index, mut previous_tag_size) = (1, None);
+    while let Some(field) = fields.next().await {
+        let event = match field? {
+            Field::PreTagSize(size) => {
+                previous_tag_size = Some(size);
+                continue;
+            }
+            Field::Tag(tag) => {
+                if let Some(stats) = &mut stats {
+                    stats.push(&tag);
+                }
+                let object = tag_json(index, previous_tag_size, &tag);
+                index += 1;
+                event::Event::Tag(tag_event(&object))
+            }
+            Field::Warning(finding) => event::Event::Finding(proto::Finding {
+                rule: finding.rule.id().to_owned(),
+                severity: finding.severity().name().to_owned(),
+                message: finding.message,
+            }),
+        };
+        send(events, event).await?;
+    }
+    if let Some(stats) = &stats {
+        send(
+            events,
+            event::Event::StatsJson(stats_json(stats).to_string()),
+        )
+        .await?;
+    }
+    Ok(())
+}
+
+async fn send(
+    events: &Sender<Result<Event, Status>>,
+    event: event::Event,
+) -> Result<(), Exception> {
+    let event = Event { event: Some(event) };
+    events
+        .send(Ok(event))
+        .await
+        .map_err(|_| "The client has gone away".into())
+}
+
+fn header_event(path: &str, size: Option<u64>, header: &Header) -> proto::Header {
+    proto::Header {
+        file: path.to_owned(),
+        file_size: size,
+        version: header.version as u32,
+        r#type: header.type_ as u32,
+        has_audio: header.has_audio(),
+        has_video: header.has_video(),
+        data_offset: header.offset,
+        extra_header_data: header.extra.to_vec(),
+    }
+}
+
+// The fields of the NDJSON tag record, so that both always agree on the values.
+fn tag_event(object: &Map<String, Value>) -> proto::Tag {
+    let number = |name: &str| object.get(name).and_then(Value::as_i64);
+    let json = |name: &str| object.get(name).map(Value::to_string).unwrap_or_default();
+    proto::Tag {
+        index: number("index").unwrap_or_default() as u64,
+        tag_type: string(object, "tag_type"),
+        tag_type_id: number("tag_type_id").map(|id| id as u32),
+        data_size: number("data_size").unwrap_or_default() as u32,
+        timestamp: number("timestamp").unwrap_or_default(),
+        previous_tag_size: number("previous_tag_size").map(|size| size as u32),
+        sound_format: string(object, "sound_format"),
+        sound_rate: string(object, "sound_rate"),
+        sound_size: string(object, "sound_size"),
+        sound_type: string(object, "sound_type"),
+        frame_type: string(object, "frame_type"),
+        codec_id: string(object, "codec_id"),
+        error: string(object, "error"),
+        script_json: json("script"),
+        aac_packet_type: string(object, "aac_packet_type"),
+        avc_packet_type: string(object, "avc_packet_type"),
+        composition_time: number("composition_time").map(|time| time as i32),
+        config_json: object
+            .get("aac_config")
+            .or_else(|| object.get("avc_config"))
+            .map(Value::to_string)
+            .unwrap_or_default(),
+        config_error: string(object, "config_error"),
+    }
+}
+
+fn string(object: &Map<String, Value>, name: &str) -> String {
+    object
+        .get(name)
+        .and_then(Value::as_str)
+        .unwrap_or_default()
+        .to_owned()
+}
@@ -0,0 +1,273 @@
+use crate::chunks::{ChunkLog, ChunkRecorder};
+use crate::remote::{Object, RangedReader, S3Object};
+use crate::Exception;
+use async_compression::tokio::bufread::{GzipDecoder, ZstdDecoder};
+use bytes::Bytes;
+use std::future::poll_fn;
+use std::io::{self, Read, Write};
+use std::pin::Pin;
+use std::process::Stdio;
+use std::task::{Context, Poll};
+use tokio::fs::File;
+use tokio::io::{AsyncBufRead, AsyncRead, BufReader, ReadBuf};
+use tokio::net::{TcpListener, TcpStream, UdpSocket};
+use tokio::process::{Child, ChildStdout, Command};
+use tokio::sync::mpsc;
+use tokio::task;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_util::io::StreamReader;
+
+pub type Input = Box<dyn AsyncRead + Unpin + Send>;
+
+// Chunk size and number of chunks in flight when adapting blocking readers.
+const BLOCKING_CHUNK_SIZE: usize = 64 * 1024;
+const BLOCKING_CHUNKS: usize = 4;
+// Datagrams buffered while the consumer is busy, anything beyond is dropped by the kernel.
+const UDP_DATAGRAMS: usize = 256;
+
+/// Opens an input source, which is one of
+///
+/// * a path to a regular file or named pipe,
+/// * a `unix://<path>` socket address,
+/// * a `http://` or `https://` URL (presigned URLs included), read with ranged requests,
+/// * a `s3://<bucket>/<key>` object, see `S3Object::from_env`,
+/// * a `srt://` URL, remuxed to FLV by ffmpeg (`$FFMPEG`, or `ffmpeg` from the `PATH`), which
+///   accepts both FLV and MPEG-TS payloads,
+/// * a `tcp://<host>:<port>` address to connect to, or to accept one connection on with
+///   `tcp://<host>:<port>?listen`,
+/// * a `udp://<host>:<port>` address to receive datagrams on, their payloads are concatenated.
+///
+/// The size is known for regular files and for objects whose server reports it. Gzip and zstd
+/// compressed sources are decompressed transparently, their size is then unknown.
+pub async fn open_input(source: &str) -> Result<(Option<u64>, Input), Exception> {
+    open_input_tee(source, vec![], None).await
+}
+
+/// Like `open_input`, but also writes the bytes to every writer in `tees` exactly as they are
+/// received, before any decompression. The chunks of HTTP(S) and S3 responses are recorded in
+/// `chunks` if given, other sources are then rejected.
+pub async fn open_input_tee(
+    source: &str,
+    tees: Vec<Box<dyn Write + Send>>,
+    chunks: Option<ChunkLog>,
+) -> Result<(Option<u64>, Input), Exception> {
+    let (size, mut input) = open_source(source, chunks).await?;
+    for output in tees {
+        input = Box::new(TeeInput { input, output });
+    }
+    let (compressed, input) = decompress(input).await?;
+    Ok((if compressed { None } else { size }, input))
+}
+
+async fn open_source(
+    source: &str,
+    chunks: Option<ChunkLog>,
+) -> Result<(Option<u64>, Input), Exception> {
+    if source.starts_with("http://") || source.starts_with("https://") {
+        return open_object(Object::Url(source.to_owned()), chunks).await;
+    }
+    if let Some(location) = source.strip_prefix("s3://") {
+        return open_object(Object::S3(S3Object::from_env(location)?), chunks).await;
+    }
+    if chunks.is_some() {
+        return Err("Chunk timing is only recorded for HTTP(S) and S3 inputs".into());
+    }
+    if let Some(path) = source.strip_prefix("unix://") {
+        return connect_unix(path).await;
+    }
+    if source.starts_with("srt://") {
+        return open_srt(source);
+    }
+    if let Some(address) = source.strip_prefix("tcp://") {
+        return open_tcp(address).await;
+    }
+    if let Some(address) = source.strip_prefix("udp://") {
+        return open_udp(address).await;
+    }
+
+    let file = File::open(source).await?;
+    let metadata = file.metadata().await?;
+    let size = if metadata.is_file() {
+        Some(metadata.len())
+    } else {
+        None
+    };
+    Ok((size, Box::new(file)))
+}
+
+/// Detects gzip and zstd streams by their magic bytes and wraps them in a decoder.
+async fn decompress(input: Input) -> Result<(bool, Input), Exception> {
+    let mut reader = BufReader::new(input);
+    let magic = poll_fn(|cx| {
+        Pin::new(&mut reader)
+            .poll_fill_buf(cx)
+            .map_ok(|buf| buf.iter().take(4).copied().collect::<Vec<_>>())
+    })
+    .await?;
+
+    if magic.starts_with(&[0x1f, 0x8b]) {
+        let mut decoder = GzipDecoder::new(reader);
+        decoder.multiple_members(true);
+        Ok((true, Box::new(decoder)))
+    } else if magic.starts_with(&[0x28, 0xb5, 0x2f, 0xfd]) {
+        Ok((true, Box::new(ZstdDecoder::new(reader))))
+    } else {
+        Ok((false, Box::new(reader)))
+    }
+}
+
+#[cfg(unix)]
+async fn connect_unix(path: &str) -> Result<(Option<u64>, Input), Exception> {
+    let stream = tokio::net::UnixStream::connect(path).await?;
+    Ok((None, Box::new(stream)))
+}
+
+#[cfg(not(unix))]
+async fn connect_unix(_path: &str) -> Result<(Option<u64>, Input), Exception> {
+    Err("unix:// inputs are only supported on unix platforms".into())
+}
+
+async fn open_tcp(address: &str) -> Result<(Option<u64>, Input), Exception> {
+    let stream = match address.strip_suffix("?listen") {
+        Some(address) => TcpListener::bind(address).await?.accept().await?.0,
+        None => TcpStream::connect(address).await?,
+    };
+    Ok((None, Box::new(stream)))
+}
+
+async fn open_udp(address: &str) -> Result<(Option<u64>, Input), Exception> {
+    let socket = UdpSocket::bind(address).await?;
+    let (tx, rx) = mpsc::channel::<io::Result<Bytes>>(UDP_DATAGRAMS);
+
+    tokio::spawn(async move {
+        let mut buf = vec![0; 65536];
+        loop {
+            let datagram = socket
+                .recv(&mut buf)
+                .await
+                .map(|n| Bytes::copy_from_slice(&buf[..n]));
+            let failed = datagram.is_err();
+            // An empty datagram would read as the end of the stream.
+            if datagram.as_ref().is_ok_and(|datagram| datagram.is_empty()) {
+                continue;
+            }
+            if tx.send(datagram).await.is_err() || failed {
+                break;
+            }
+        }
+    });
+
+    Ok((None, Box::new(StreamReader::new(ReceiverStream::new(rx)))))
+}
+
+fn open_srt(url: &str) -> Result<(Option<u64>, Input), Exception> {
+    let ffmpeg = std::env::var("FFMPEG").unwrap_or_else(|_| "ffmpeg".into());
+    let mut child = Command::new(&ffmpeg)
+        .args(["-hide_banner", "-loglevel", "error", "-i", url])
+        .args(["-c", "copy", "-f", "flv", "pipe:1"])
+        .stdin(Stdio::null())
+        .stdout(Stdio::piped())
+        .kill_on_drop(true)
+        .spawn()
+        .map_err(|e| format!("srt:// inputs need ffmpeg ({}): {}", ffmpeg, e))?;
+    let stdout = child.stdout.take().expect("stdout is piped");
+    Ok((
+        None,
+        Box::new(ChildInput {
+            _child: child,
+            stdout,
+        }),
+    ))
+}
+
+// Keeps the child process alive for as long as its output is read, it is killed when dropped.
+struct ChildInput {
+    _child: Child,
+    stdout: ChildStdout,
+}
+
+impl AsyncRead for ChildInput {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        Pin::new(&mut self.stdout).poll_read(cx, buf)
+    }
+}
+
+// Copies everything read from the input to a writer. The writes are synchronous like those of
+// the output and flushed right away, so whatever was read is passed on even when the reader
+// stops early.
+struct TeeInput {
+    input: Input,
+    output: Box<dyn Write + Send>,
+}
+
+impl AsyncRead for TeeInput {
+    fn poll_read(
+        mut self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let start = buf.filled().len();
+        match Pin::new(&mut self.input).poll_read(cx, buf) {
+            Poll::Ready(Ok(())) => {}
+            other => return other,
+        }
+        self.output.write_all(&buf.filled()[start..])?;
+        self.output.flush()?;
+        Poll::Ready(Ok(()))
+    }
+}
+
+async fn open_object(
+    object: Object,
+    chunks: Option<ChunkLog>,
+) -> Result<(Option<u64>, Input), Exception> {
+    let recorder = chunks.map(ChunkRecorder::new);
+    let reader = task::spawn_blocking(move || RangedReader::open(object, recorder)).await??;
+    Ok((reader.size(), blocking_input(reader)))
+}
+
+/// Adapts a blocking reader: chunks are read on the blocking thread pool and handed over through
+/// a bounded channel, so a slow consumer holds back the reader.
+pub fn blocking_input<R: Read + Send + 'static>(reader: R) -> Input {
+    let (tx, rx) = mpsc::channel::<io::Result<Bytes>>(BLOCKING_CHUNKS);
+
+    tokio::spawn(async move {
+        let mut reader = reader;
+        loop {
+            let read = task::spawn_blocking(move || {
+                let mut buf = vec![0; BLOCKING_CHUNK_SIZE];
+                let result = reader.read(&mut buf).map(|n| {
+                    buf.truncate(n);
+                    Bytes::from(buf)
+                });
+                (reader, result)
+            })
+            .await;
+
+            let result = match read {
+                Ok((returned, result)) => {
+                    reader = returned;
+                    result
+                }
+                Err(e) => {
+                    let _ = tx.send(Err(io::Error::other(e))).await;
+                    break;
+                }
+            };
+
+            let done = match &result {
+                Ok(chunk) => chunk.is_empty(),
+                Err(_) => true,
+            };
+            if tx.send(result).await.is_err() || done {
+                break;
+            }
+        }
+    });
+
+    Box::new(StreamReader::new(ReceiverStream::new(rx)))
+}
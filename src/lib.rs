@@ -0,0 +1,59 @@
+//! The FLV parser behind `flv-dump`, for reading FLV files and streams programmatically.
+//!
+//! `read_flv` reads the file header from any `AsyncRead` and returns a stream of `Field`s:
+//! every tag with its decoded header and body, the PreviousTagSize fields and the spec
+//! violations tolerated in lenient mode. `open_flv` does the same for a local file.
+//!
+//! ```no_run
+//! use flv_dump::{open_flv, Field};
+//! use tokio_stream::StreamExt;
+//!
+//! # async fn example() -> Result<(), flv_dump::Exception> {
+//! let (_, header, mut fields) = open_flv("test.flv").await?;
+//! println!("audio: {}, video: {}", header.has_audio(), header.has_video());
+//! while let Some(field) = fields.next().await {
+//!     if let Field::Tag(tag) = field? {
+//!         println!("{:?} at {} ms", tag.header.tag_type, tag.header.timestamp);
+//!     }
+//! }
+//! # Ok(())
+//! # }
+//! ```
+//!
+//! `writer::FlvWriter` writes tags back, and the codec modules parse the configurations and
+//! frames carried in the tag bodies.
+
+use std::error::Error;
+
+pub mod aac;
+pub mod amf;
+pub mod avc;
+mod bits;
+pub mod broadcast;
+mod changes;
+pub mod events;
+pub mod filters;
+pub mod findings;
+pub mod g711;
+mod gbk;
+pub mod mp3;
+pub mod nellymoser;
+pub mod order;
+pub mod pcm;
+pub mod reader;
+pub mod speex;
+#[cfg(feature = "proptest")]
+pub mod strategies;
+pub mod time;
+pub mod writer;
+
+pub use findings::{Finding, Rule, Severity};
+#[cfg(feature = "tokio-fs")]
+pub use reader::{open_flv, read_flv_at, read_flv_tail};
+pub use reader::{
+    read_flv, read_flv_compat, seek_flv, tail_flv, BodyDecoder, Field, Header, ParseMode,
+    ParseOptions, Tag, TagData, TagHeader, TagType,
+};
+pub use writer::FlvWriter;
+
+pub type Exception = Box<dyn Error + Send + Sync + 'static>;
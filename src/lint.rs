@@ -0,0 +1,331 @@
+use crate::amf::AmfValue;
+use crate::input::open_input;
+use crate::order::EarlyFrames;
+use crate::reader::{
+    read_flv, CodecId, Field, ParseOptions, SoundFormat, Tag, TagData, TagType, VideoFrameType,
+};
+use crate::Exception;
+use tokio_stream::StreamExt;
+
+// Thresholds of the low-latency profile.
+const MAX_GOP: i64 = 2_000;
+const MAX_AUDIO_INTERVAL: i64 = 200;
+const MAX_SCRIPT_SIZE: u32 = 4 * 1024;
+// Size of the buffer libavformat/flvdec.c reads AMF strings into, a string as long fails to parse.
+const FFMPEG_AMF_STRING_BUFFER: usize = 256;
+
+/// A set of rules for a use of FLV.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, clap::ValueEnum)]
+pub enum Profile {
+    /// HTTP-FLV played with little buffering: players start at a keyframe and can not wait for
+    /// reordered frames, long audio gaps or large script tags
+    LowLatency,
+    /// The FLV demuxer of FFmpeg (libavformat/flvdec.c): what it warns about or fails to read,
+    /// reported with its own terms
+    Ffmpeg,
+}
+
+struct Check {
+    rule: &'static str,
+    passed: bool,
+    detail: String,
+}
+
+/// What the low-latency rules are checked against, collected in one pass.
+#[derive(Default)]
+struct LowLatency {
+    video_frames: usize,
+    reordered_frames: usize,
+    last_keyframe: Option<i64>,
+    max_gop: Option<i64>,
+    last_audio: Option<i64>,
+    max_audio_interval: Option<i64>,
+    // index and DataSize of the largest script tag
+    largest_script: Option<(usize, u32)>,
+    early_frames: EarlyFrames,
+}
+
+impl LowLatency {
+    fn push(&mut self, index: usize, tag: &Tag) {
+        self.early_frames.push(tag);
+        let timestamp = tag.header.timestamp as i64;
+        match &tag.data {
+            _ if tag.data.is_sequence_header() => {}
+            TagData::Video(video) => {
+                self.video_frames += 1;
+                if video.composition_time().is_some_and(|cts| cts != 0) {
+                    self.reordered_frames += 1;
+                }
+                if video.header.frame_type == VideoFrameType::KeyFrame {
+                    if let Some(last) = self.last_keyframe.replace(timestamp) {
+                        self.max_gop = Some(self.max_gop.unwrap_or(0).max(timestamp - last));
+                    }
+                }
+            }
+            TagData::Audio(_) => {
+                if let Some(last) = self.last_audio.replace(timestamp) {
+                    let interval = timestamp - last;
+                    self.max_audio_interval =
+                        Some(self.max_audio_interval.unwrap_or(0).max(interval));
+                }
+            }
+            _ if tag.header.tag_type == TagType::Script => {
+                let size = tag.header.data_size;
+                if self
+                    .largest_script
+                    .is_none_or(|(_, largest)| size > largest)
+                {
+                    self.largest_script = Some((index, size));
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn checks(&self) -> Vec<Check> {
+        let mut checks = vec![Check {
+            rule: "no-b-frames",
+            passed: self.reordered_frames == 0,
+            detail: format!(
+                "{} of {} video frames have a CompositionTime",
+                self.reordered_frames, self.video_frames
+            ),
+        }];
+        checks.push(match self.max_gop {
+            Some(gop) => Check {
+                rule: "small-gop",
+                passed: gop <= MAX_GOP,
+                detail: format!("keyframes at most {} ms apart, limit {} ms", gop, MAX_GOP),
+            },
+            None => Check {
+                rule: "small-gop",
+                passed: self.video_frames == 0,
+                detail: "fewer than 2 keyframes".into(),
+            },
+        });
+        checks.push(Check {
+            rule: "audio-interval",
+            passed: self
+                .max_audio_interval
+                .is_none_or(|interval| interval <= MAX_AUDIO_INTERVAL),
+            detail: match self.max_audio_interval {
+                Some(interval) => format!(
+                    "audio tags at most {} ms apart, limit {} ms",
+                    interval, MAX_AUDIO_INTERVAL
+                ),
+                None => "no audio".into(),
+            },
+        });
+        checks.push(Check {
+            rule: "script-size",
+            passed: self
+                .largest_script
+                .is_none_or(|(_, size)| size <= MAX_SCRIPT_SIZE),
+            detail: match self.largest_script {
+                Some((index, size)) => format!(
+                    "largest script tag {} has {} bytes, limit {}",
+                    index, size, MAX_SCRIPT_SIZE
+                ),
+                None => "no script tags".into(),
+            },
+        });
+        checks.push(Check {
+            rule: "sequence-headers-first",
+            passed: self.early_frames.total() == 0,
+            detail: if self.early_frames.total() == 0 {
+                "no frame precedes its sequence header".into()
+            } else {
+                format!("{} precede their sequence header", self.early_frames)
+            },
+        });
+        checks
+    }
+}
+
+// The first tag a rule fails for, as index, timestamp and what FFmpeg would say, and how many.
+#[derive(Default)]
+struct Occurrences {
+    count: usize,
+    first: Option<(usize, i64, String)>,
+}
+
+impl Occurrences {
+    fn push(&mut self, index: usize, timestamp: i64, message: impl FnOnce() -> String) {
+        self.count += 1;
+        if self.first.is_none() {
+            self.first = Some((index, timestamp, message()));
+        }
+    }
+
+    fn check(&self, rule: &'static str, what: &str) -> Check {
+        Check {
+            rule,
+            passed: self.count == 0,
+            detail: match &self.first {
+                Some((index, timestamp, message)) => format!(
+                    "{} {}, the first tag {} at {} ms: {}",
+                    self.count, what, index, timestamp, message
+                ),
+                None => format!("no {}", what),
+            },
+        }
+    }
+}
+
+/// What the FFmpeg rules are checked against, collected in one pass.
+#[derive(Default)]
+struct Ffmpeg {
+    // index, DataSize and timestamp of the tag the next PreviousTagSize follows
+    last_tag: Option<(usize, u32, i64)>,
+    // sum_flv_tag_size, the size of all tags so far, which some muxers write instead
+    sum_tag_size: u64,
+    packet_mismatches: Occurrences,
+    aac_extradata: bool,
+    aac_before_extradata: Occurrences,
+    negative_cts: Occurrences,
+    long_metadata_strings: Occurrences,
+}
+
+impl Ffmpeg {
+    fn push(&mut self, index: usize, tag: &Tag) {
+        let timestamp = tag.header.timestamp as i64;
+        self.last_tag = Some((index, tag.header.data_size, timestamp));
+        self.sum_tag_size += tag.header.data_size as u64 + 11;
+        match &tag.data {
+            TagData::Audio(audio) if audio.header.sound_format == SoundFormat::AAC => {
+                if tag.data.is_sequence_header() {
+                    self.aac_extradata = true;
+                } else if !self.aac_extradata {
+                    self.aac_before_extradata.push(index, timestamp, || {
+                        "AAC raw data without extradata, the decoder can not be set up".into()
+                    });
+                }
+            }
+            TagData::Video(video) if video.header.codec_id == CodecId::AVC => {
+                if let Some(cts) = video.composition_time().filter(|cts| *cts < 0) {
+                    self.negative_cts.push(index, timestamp, || {
+                        format!(
+                            "CompositionTime {} ms, \"Negative cts, previous timestamps might be wrong.\"",
+                            cts
+                        )
+                    });
+                }
+            }
+            TagData::Script(script) => {
+                if let Some(metadata) = script.metadata() {
+                    if let Some(length) = longest_string(&metadata)
+                        .filter(|length| *length >= FFMPEG_AMF_STRING_BUFFER)
+                    {
+                        self.long_metadata_strings.push(index, timestamp, || {
+                            format!(
+                                "onMetaData has a string of {} bytes, metadata after it is not read",
+                                length
+                            )
+                        });
+                    }
+                }
+            }
+            _ => {}
+        }
+    }
+
+    fn pre_tag_size(&mut self, last: u32) {
+        let (index, size, timestamp) = match self.last_tag.take() {
+            Some(tag) => tag,
+            None => return,
+        };
+        // the sizes flvdec.c lets pass: with and without the tag header, one byte short of it,
+        // and the running total
+        let expected = size + 11;
+        if last != expected
+            && last != expected - 1
+            && (last != size || last == 0)
+            && last as u64 != self.sum_tag_size
+        {
+            let sum = self.sum_tag_size;
+            self.packet_mismatches.push(index, timestamp, || {
+                format!("\"Packet mismatch {} {} {}\"", last, expected, sum)
+            });
+        }
+    }
+
+    fn checks(&self) -> Vec<Check> {
+        vec![
+            self.packet_mismatches
+                .check("packet-mismatch", "PreviousTagSize mismatches"),
+            self.aac_before_extradata
+                .check("aac-before-extradata", "AAC frames before the extradata"),
+            self.negative_cts
+                .check("negative-cts", "frames with a negative cts"),
+            self.long_metadata_strings
+                .check("metadata-string-size", "oversized metadata strings"),
+        ]
+    }
+}
+
+// Bytes of the longest string or property name in an AMF value.
+fn longest_string(value: &AmfValue) -> Option<usize> {
+    let properties = |properties: &[(String, AmfValue)]| {
+        properties
+            .iter()
+            .flat_map(|(key, value)| [Some(key.len()), longest_string(value)])
+            .flatten()
+            .max()
+    };
+    match value {
+        AmfValue::String(string) | AmfValue::Xml(string) => Some(string.len()),
+        AmfValue::Object(items) | AmfValue::EcmaArray(items) => properties(items),
+        AmfValue::TypedObject(name, items) => properties(items).max(Some(name.len())),
+        AmfValue::StrictArray(items) => items.iter().filter_map(longest_string).max(),
+        _ => None,
+    }
+}
+
+/// Checks a FLV file against the rules of a profile and prints a pass/fail table. Fails if any
+/// rule does not pass.
+pub async fn lint(input: &str, profile: Profile) -> Result<(), Exception> {
+    let (_, source) = open_input(input).await?;
+    let (_, mut fields) = read_flv(source, ParseOptions::default()).await?;
+    let mut low_latency = LowLatency::default();
+    let mut ffmpeg = Ffmpeg::default();
+    let mut index = 1;
+    while let Some(field) = fields.next().await {
+        match field? {
+            Field::Tag(tag) => {
+                low_latency.push(index, &tag);
+                ffmpeg.push(index, &tag);
+                index += 1;
+            }
+            Field::Warning(finding) => eprintln!("{}", finding),
+            Field::PreTagSize(size) => ffmpeg.pre_tag_size(size),
+        }
+    }
+    let checks = match profile {
+        Profile::LowLatency => low_latency.checks(),
+        Profile::Ffmpeg => ffmpeg.checks(),
+    };
+
+    let width = checks
+        .iter()
+        .map(|check| check.rule.len())
+        .max()
+        .unwrap_or(0)
+        .max("Rule".len());
+    println!("{:width$}  Result  Detail", "Rule", width = width);
+    for check in &checks {
+        let result = if check.passed { "PASS" } else { "FAIL" };
+        println!(
+            "{:width$}  {:6}  {}",
+            check.rule,
+            result,
+            check.detail,
+            width = width
+        );
+    }
+
+    let failed = checks.iter().filter(|check| !check.passed).count();
+    if failed > 0 {
+        return Err(format!("{} of {} rules failed", failed, checks.len()).into());
+    }
+    Ok(())
+}
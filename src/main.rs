@@ -1,107 +1,626 @@
-use crate::reader::{
-    open_flv, AudioData, AudioDataHeader, Field, Header, Tag, TagData, TagHeader, VideoData,
-    VideoDataHeader,
+use crate::anonymize::anonymize;
+use crate::api::api_serve;
+use crate::archive::{is_archive, open_archive};
+use crate::audit::audit;
+use crate::chunks::ChunkTiming;
+use crate::cli::{Command, ManifestCommand, Opts};
+use crate::dvr::dvr;
+use crate::encrypt::{decrypt, encrypt};
+use crate::expect::Expectation;
+use crate::export::export;
+use crate::findings::{Finding, Rule};
+use crate::generate::{generate, GenerateOptions};
+use crate::input::{open_input_tee, Input};
+use crate::lint::lint;
+use crate::oneline::Oneline;
+use crate::output::{formatter, FileInfo, Format, Formatter, Ndjson, Output, Template, SCHEMA};
+use crate::pipeline::{spawn_stream, OutputThread, TimedFormatter};
+use crate::profile::{TimedInput, TimedStream};
+use crate::reader::{read_flv, seek_flv, tail_flv, Field, Header, TagData, TagType};
+use crate::rewrite::{rewrite, RewriteOptions};
+use crate::rolling::Rolling;
+use crate::rtmp::publish;
+use crate::sample::Sampler;
+use crate::stats::Stats;
+use crate::subtitles::inject_subs;
+use crate::timeline::Timeline;
+use crate::webhook::{Codecs, Webhook};
+use clap::Parser;
+use flv_dump::{
+    aac, amf, avc, events, findings, g711, mp3, nellymoser, order, pcm, reader, speex, time,
+    writer, Exception,
 };
-use std::error::Error;
-use tokio::stream::StreamExt;
+use std::fs::File;
+use std::io::{self, Write};
+use std::iter;
+use std::ops::AddAssign;
+use std::time::Duration;
+use tokio::time::{sleep_until, timeout};
+use tokio_stream::{Stream, StreamExt};
 
-mod reader;
-
-type Exception = Box<dyn Error + Send + Sync + 'static>;
+mod anonymize;
+mod api;
+mod archive;
+mod arrow;
+mod assertions;
+mod audit;
+mod cbor;
+mod chapters;
+mod chunks;
+mod cli;
+mod dvr;
+mod encrypt;
+mod expect;
+mod export;
+mod ffprobe;
+mod fingerprint;
+mod flvmeta;
+mod generate;
+#[cfg(feature = "grpc")]
+mod grpc;
+mod input;
+mod lint;
+mod manifest;
+mod oneline;
+mod output;
+mod pipeline;
+mod profile;
+mod remote;
+mod rewrite;
+mod rolling;
+mod rotate;
+mod rtmp;
+mod sample;
+mod score;
+mod stats;
+mod subtitles;
+mod timeline;
+mod webhook;
 
 #[tokio::main]
 async fn main() -> Result<(), Exception> {
-    let path = std::env::args()
-        .nth(1)
-        .unwrap_or("./resources/test.flv".into());
-
-    let (
-        file_size,
-        Header {
-            version,
-            type_,
+    let opts = Opts::parse();
+
+    match opts.command {
+        Some(Command::Rewrite(args)) => {
+            rewrite(&args.input, &args.output, &RewriteOptions::from(&args)).await
+        }
+        Some(Command::Publish { url, input }) => publish(&url, &input).await,
+        Some(Command::Audit { dir, policy }) => audit(&dir, &policy).await,
+        Some(Command::Generate(args)) => {
+            generate(&args.output, &GenerateOptions::from(&args)).await
+        }
+        Some(Command::Encrypt { input, output, key }) => {
+            encrypt(&input, &output, &key.key()?).await
+        }
+        Some(Command::Decrypt { input, output, key }) => {
+            decrypt(&input, &output, &key.key()?).await
+        }
+        Some(Command::Anonymize {
+            input,
+            output,
+            random,
+        }) => anonymize(&input, &output, random).await,
+        Some(Command::InjectSubs {
+            input,
+            output,
+            srt,
+            language,
             offset,
+        }) => inject_subs(&input, &output, &srt, &language, offset).await,
+        Some(Command::Export {
+            input,
+            output,
+            arrow,
+            chapters,
+        }) => export(&input, &output, arrow, chapters).await,
+        Some(Command::Lint { input, profile }) => lint(&input, profile).await,
+        Some(Command::Dvr {
+            input,
+            listen,
+            window,
+        }) => dvr(&input, &listen, window).await,
+        Some(Command::Manifest(ManifestCommand::Create { input, manifest })) => {
+            manifest::create(&input, manifest.as_deref()).await
+        }
+        Some(Command::Manifest(ManifestCommand::Verify { input, manifest })) => {
+            manifest::verify(&input, manifest.as_deref()).await
+        }
+        Some(Command::ApiServe {
+            listen,
+            max_files,
+            max_upload,
+        }) => api_serve(&listen, max_files, max_upload).await,
+        #[cfg(feature = "grpc")]
+        Some(Command::GrpcServe { listen }) => grpc::grpc_serve(&listen).await,
+        None if opts.schema => {
+            print!("{}", SCHEMA);
+            Ok(())
+        }
+        None if opts.profile => {
+            profile::enable();
+            let result = dump(&opts).await;
+            eprint!("{}", profile::report());
+            result
+        }
+        None => dump(&opts).await,
+    }
+}
+
+async fn dump(opts: &Opts) -> Result<(), Exception> {
+    if !opts.paths.is_empty() {
+        return dump_all(opts).await;
+    }
+    let rotation = opts.rotation();
+    if rotation.is_some() && !matches!(opts.format, Format::Text | Format::Ndjson) {
+        return Err("Rotated output needs --format text or ndjson".into());
+    }
+    let mut out = OutputThread::spawn(if opts.passthrough && opts.output.is_none() {
+        Output::stderr(opts.gzip_output)
+    } else if let (Some(path), Some(rotation)) = (&opts.output, rotation) {
+        Output::rotating(path, rotation)?
+    } else {
+        Output::open(opts.output.as_deref(), opts.gzip_output)?
+    });
+
+    if let Some(count) = opts.tail {
+        let (file_size, file) = open_file(&opts.path).await?;
+        let (header, fields) = tail_flv(file, file_size, count, opts.parse_options()).await?;
+        let failures = dump_fields(
+            &opts.path,
+            Some(file_size),
+            header,
+            fields,
+            None,
+            opts,
+            &mut out,
+        )
+        .await?;
+        out.finish()?;
+        return fail_on(opts, failures);
+    }
+
+    if let Some(time) = opts.seek_time {
+        let (file_size, file) = open_file(&opts.path).await?;
+        let (header, fields) = seek_flv(file, file_size, time, opts.parse_options()).await?;
+        let failures = dump_fields(
+            &opts.path,
+            Some(file_size),
+            header,
+            fields,
+            None,
+            opts,
+            &mut out,
+        )
+        .await?;
+        out.finish()?;
+        return fail_on(opts, failures);
+    }
+
+    if !is_archive(&opts.path) {
+        let mut tees: Vec<Box<dyn Write + Send>> = vec![];
+        if let Some(path) = &opts.tee {
+            let file = File::create(path)
+                .map_err(|e| format!("Failed to create {}: {}", path.display(), e))?;
+            tees.push(Box::new(file));
+        }
+        if opts.passthrough {
+            tees.push(Box::new(io::stdout()));
+        }
+        let mut timing = if opts.chunk_timing {
+            Some(ChunkTiming::default())
+        } else {
+            None
+        };
+        let chunks = timing.as_ref().map(ChunkTiming::log);
+        let (file_size, input) = open_input_tee(&opts.path, tees, chunks).await?;
+        let failures = dump_input(
+            &opts.path,
+            file_size,
+            input,
+            timing.as_mut(),
+            opts,
+            &mut out,
+        )
+        .await?;
+        // the report follows the dump once it has been written out
+        let result = out.finish();
+        if let Some(timing) = &timing {
+            eprint!("{}", timing.report());
+        }
+        result?;
+        return fail_on(opts, failures);
+    }
+
+    if opts.tee.is_some() || opts.passthrough || opts.chunk_timing {
+        return Err("--tee, --passthrough and --chunk-timing do not support archives".into());
+    }
+    let failures = dump_archive(&opts.path, opts, &mut out).await?;
+
+    out.finish()?;
+    fail_on(opts, failures)
+}
+
+// Several inputs, summarized one after the other by --oneline. Like the entries of an archive,
+// an input that cannot be read is reported and skipped.
+async fn dump_all(opts: &Opts) -> Result<(), Exception> {
+    if opts.tail.is_some()
+        || opts.seek_time.is_some()
+        || opts.tee.is_some()
+        || opts.passthrough
+        || opts.chunk_timing
+        || opts.rotation().is_some()
+    {
+        return Err(
+            "--tail, --seek, --tee, --passthrough, --chunk-timing and rotated output \
+                    take a single input"
+                .into(),
+        );
+    }
+    let mut out = OutputThread::spawn(Output::open(opts.output.as_deref(), opts.gzip_output)?);
+    let mut failures = Failures::default();
+    for path in iter::once(&opts.path).chain(&opts.paths) {
+        let result = if is_archive(path) {
+            dump_archive(path, opts, &mut out).await
+        } else {
+            match open_input_tee(path, vec![], None).await {
+                Ok((file_size, input)) => {
+                    dump_input(path, file_size, input, None, opts, &mut out).await
+                }
+                Err(e) => Err(e),
+            }
+        };
+        match result {
+            Ok(n) => failures += n,
+            Err(e) => eprintln!("{}: {}", path, e),
+        }
+    }
+
+    out.finish()?;
+    fail_on(opts, failures)
+}
+
+async fn dump_archive(path: &str, opts: &Opts, out: &mut dyn Write) -> Result<Failures, Exception> {
+    let mut entries = open_archive(path);
+    let mut failures = Failures::default();
+    while let Some(entry) = entries.recv().await {
+        let entry = entry?;
+        let path = format!("{}!{}", path, entry.name);
+        match dump_input(&path, Some(entry.size), entry.input, None, opts, out).await {
+            Ok(n) => failures += n,
+            Err(e) => eprintln!("{}: {}", path, e),
+        }
+    }
+    Ok(failures)
+}
+
+/// What makes a dump exit with an error.
+#[derive(Debug, Default, Clone, Copy)]
+struct Failures {
+    /// findings of the --fail-on severity or above
+    findings: usize,
+    /// failed --assert-* checks
+    assertions: usize,
+}
+
+impl AddAssign for Failures {
+    fn add_assign(&mut self, rhs: Self) {
+        self.findings += rhs.findings;
+        self.assertions += rhs.assertions;
+    }
+}
+
+fn fail_on(opts: &Opts, failures: Failures) -> Result<(), Exception> {
+    if failures.assertions > 0 {
+        let assertions = if failures.assertions == 1 {
+            "assertion"
+        } else {
+            "assertions"
+        };
+        return Err(format!("{} {} failed", failures.assertions, assertions).into());
+    }
+    match opts.fail_on {
+        Some(severity) if failures.findings > 0 => {
+            let findings = if failures.findings == 1 {
+                "finding"
+            } else {
+                "findings"
+            };
+            Err(format!(
+                "{} {} of severity {} or above",
+                failures.findings,
+                findings,
+                severity.name()
+            )
+            .into())
+        }
+        _ => Ok(()),
+    }
+}
+
+async fn open_file(path: &str) -> Result<(u64, tokio::fs::File), Exception> {
+    let file = tokio::fs::File::open(path).await?;
+    Ok((file.metadata().await?.len(), file))
+}
+
+async fn dump_input(
+    path: &str,
+    file_size: Option<u64>,
+    input: Input,
+    timing: Option<&mut ChunkTiming>,
+    opts: &Opts,
+    out: &mut dyn Write,
+) -> Result<Failures, Exception> {
+    let input: Input = if opts.profile {
+        Box::new(TimedInput::new(input))
+    } else {
+        input
+    };
+    let (header, fields) = read_flv(input, opts.parse_options()).await?;
+    dump_fields(path, file_size, header, fields, timing, opts, out).await
+}
+
+async fn dump_fields<S>(
+    path: &str,
+    file_size: Option<u64>,
+    header: Header,
+    fields: S,
+    mut timing: Option<&mut ChunkTiming>,
+    opts: &Opts,
+    out: &mut dyn Write,
+) -> Result<Failures, Exception>
+where
+    S: Stream<Item = Result<Field, Exception>> + Send + Unpin + 'static,
+{
+    // decoding runs ahead on its own task
+    let mut fields = spawn_stream(TimedStream(fields));
+
+    let json = opts.format == Format::Ndjson;
+    let mut rolling = match opts.stats_interval {
+        Some(_) if opts.format != Format::Text && !json => {
+            return Err("--stats-interval prints text or NDJSON".into())
+        }
+        Some(interval) => Some(Rolling::new(interval)),
+        None => None,
+    };
+    let mut formatter: Box<dyn Formatter> = match &opts.template {
+        Some(template) => Box::new(Template::parse(template)?),
+        None if opts.timeline => Box::new(Timeline::new(opts.format)?),
+        None if opts.oneline => Box::new(Oneline::default()),
+        // Records are flushed one by one where someone may be waiting for them: on the terminal
+        // or a pipe, and for inputs that keep coming. Compression and files are better off
+        // buffered.
+        None if json && !opts.gzip_output && (opts.output.is_none() || file_size.is_none()) => {
+            Box::new(Ndjson::flushing())
+        }
+        None => formatter(opts.format),
+    };
+    if opts.profile {
+        formatter = Box::new(TimedFormatter(formatter));
+    }
+    formatter.header(
+        out,
+        &FileInfo {
+            path,
+            size: file_size,
+            header: &header,
         },
-        mut decoder,
-    ) = open_flv(&path).await?;
+    )?;
+
+    // Findings of the decoder arrive without the suppressed ones.
+    let suppressed = opts.parse_options().suppressed;
+    let fails = |rule: Rule| {
+        !suppressed.contains(rule) && opts.fail_on.is_some_and(|level| rule.severity() >= level)
+    };
+    let mut failures = 0;
+    let webhook = opts.webhook.as_deref().map(|url| Webhook::spawn(url, path));
+    let alert = |finding: &Finding| match &webhook {
+        Some(webhook) if finding.severity() >= opts.webhook_severity => webhook.finding(finding),
+        _ => {}
+    };
+    let stall_timeout = Duration::from_secs(opts.stall_timeout);
+    let mut codecs = Codecs::default();
 
-    println!("=====================================");
-    println!("File: {}", path);
-    println!("FileSize: {}", file_size);
-    println!("Version: {}", version);
-    println!("Type: {}", type_);
-    println!("DataOffset: {}", offset);
+    if header.reserved_type_flags() != 0 && !suppressed.contains(Rule::ReservedTypeFlags) {
+        let message = format!(
+            "Reserved TypeFlags bits are set: {:#04x}",
+            header.reserved_type_flags()
+        );
+        let finding = Finding::new(Rule::ReservedTypeFlags, message);
+        failures += fails(Rule::ReservedTypeFlags) as usize;
+        alert(&finding);
+        formatter.warning(out, &finding)?;
+    }
 
-    let mut pre_tag_size_index = 0;
     let mut tag_index = 1;
+    let (mut has_audio, mut has_video) = (false, false);
+    let mut stats = if opts.stats {
+        Some(Stats::default())
+    } else {
+        None
+    };
 
-    while let Some(result) = decoder.next().await {
-        match result {
-            Ok(field) => match field {
-                Field::PreTagSize(size) => {
-                    println!("=====================================");
-                    println!("PreviousTagSize{}: {}", pre_tag_size_index, size);
-                    pre_tag_size_index += 1;
+    // --skip and --limit select tags by index, PreviousTagSize N goes along with tag N + 1.
+    // With --tail and --seek-time, tags are numbered from the first one shown.
+    let selected = |index: usize| {
+        index > opts.skip && opts.limit.is_none_or(|limit| index <= opts.skip + limit)
+    };
+    let mut limited = false;
+    // the SequenceOrder findings of the decoder, which only tracks the order from the start
+    let mut early_frames = vec![];
+    let mut sampler = opts.sample_every.map(Sampler::new);
+    let expectation = opts.expect.as_deref().map(Expectation::load).transpose()?;
+    let assertions = opts.assertions();
+    let mut checked_stats = if expectation.is_some() || !assertions.is_empty() {
+        Some(Stats::default())
+    } else {
+        None
+    };
+
+    loop {
+        if opts.limit.is_some() && tag_index > opts.skip && !selected(tag_index) {
+            limited = true;
+            break;
+        }
+        let next = async {
+            match &webhook {
+                Some(webhook) => match timeout(stall_timeout, fields.next()).await {
+                    Ok(next) => next,
+                    Err(_) => {
+                        webhook.stall(stall_timeout);
+                        fields.next().await
+                    }
+                },
+                None => fields.next().await,
+            }
+        };
+        // the statistics of live inputs are due whether tags arrive or not
+        let next = match &mut rolling {
+            Some(rolling) => {
+                tokio::pin!(next);
+                loop {
+                    tokio::select! {
+                        next = &mut next => break next,
+                        _ = sleep_until(rolling.deadline()) => rolling.flush(out, json)?,
+                    }
                 }
-                Field::Tag(Tag {
-                    header:
-                        TagHeader {
-                            tag_type,
-                            data_size,
-                            timestamp,
-                        },
-                    data,
-                }) => {
-                    println!("=====================================");
-                    println!("TagIndex: {}", tag_index);
-                    println!("TagType: {:?}", tag_type);
-                    println!("DataSize: {:?}", data_size);
-                    println!("Timestamp: {:?}", timestamp);
-                    match data {
-                        TagData::Audio(AudioData {
-                            header:
-                                AudioDataHeader {
-                                    sound_format,
-                                    sound_rate,
-                                    sound_size,
-                                    sound_type,
-                                },
-                            data,
-                        }) => {
-                            println!("SoundFormat: {:?}", sound_format);
-                            println!("SoundRate: {:?}", sound_rate);
-                            println!("SoundSize: {:?}", sound_size);
-                            println!("SoundType: {:?}", sound_type);
-                            println!("Data: {:?}", data);
-                        }
-                        TagData::Video(VideoData {
-                            header:
-                                VideoDataHeader {
-                                    frame_type,
-                                    codec_id,
-                                },
-                            data,
-                        }) => {
-                            println!("FrameType: {:?}", frame_type);
-                            println!("CodecId: {:?}", codec_id);
-                            println!("Data: {:?}", data);
-                        }
-                        TagData::Script(_) => {
-                            // TODO: parse the raw script data
-                            println!("RawScriptData: {:?}", data);
-                        }
-                        TagData::Reserved(data) => {
-                            println!("Data: {:?}", data);
+            }
+            None => next.await,
+        };
+        let result = match next {
+            Some(result) => result,
+            None => break,
+        };
+        match result? {
+            // PreviousTagSize N follows tag N
+            Field::PreTagSize(size) => {
+                if stats.is_none() && sampler.is_none() && rolling.is_none() && selected(tag_index)
+                {
+                    formatter.pre_tag_size(out, tag_index - 1, size)?;
+                }
+            }
+            Field::Tag(tag) => {
+                // Encrypted tags have the Filter bit 0x20 set on top of their type.
+                match tag.header.tag_type {
+                    TagType::Audio | TagType::Reserved(0x28) => has_audio = true,
+                    TagType::Video | TagType::Reserved(0x29) => has_video = true,
+                    _ => {}
+                }
+                if let (Some(webhook), Some(change)) = (&webhook, codecs.push(&tag)) {
+                    webhook.codec_change(tag_index, &tag, &change);
+                }
+                if let TagData::Invalid(_) = &tag.data {
+                    failures += fails(Rule::InvalidData) as usize;
+                }
+                if let Some(timing) = &mut timing {
+                    timing.push(&tag);
+                }
+                if selected(tag_index) {
+                    if let Some(checked_stats) = &mut checked_stats {
+                        checked_stats.push(&tag);
+                    }
+                    match (&mut stats, &mut rolling) {
+                        (Some(stats), _) => stats.push(&tag),
+                        (None, Some(rolling)) => rolling.push(&tag),
+                        (None, None) => {
+                            if sampler
+                                .as_mut()
+                                .is_none_or(|sampler| sampler.take(tag_index, &tag))
+                            {
+                                formatter.tag(out, tag_index, &tag)?
+                            }
                         }
                     }
-                    tag_index += 1;
                 }
-            },
-            Err(e) => return Err(e),
+                tag_index += 1;
+            }
+            Field::Warning(finding) => {
+                failures += fails(finding.rule) as usize;
+                alert(&finding);
+                if finding.rule == Rule::SequenceOrder {
+                    early_frames.push(finding.message.clone());
+                }
+                if let Some(rolling) = &mut rolling {
+                    rolling.finding(&finding);
+                }
+                formatter.warning(out, &finding)?
+            }
         }
     }
 
-    println!("=====================================");
+    // Frames before the sequence header make the start of the stream undecodable, which deserves
+    // more than a line among the tags.
+    for message in &early_frames {
+        eprintln!(
+            "Warning: {}: {}, most players can not decode them",
+            path, message
+        );
+    }
+
+    // Only a complete pass tells which streams the file contains.
+    let streams = if limited || opts.tail.is_some() || opts.seek_time.is_some() {
+        vec![]
+    } else {
+        vec![
+            ("audio", header.has_audio(), has_audio),
+            ("video", header.has_video(), has_video),
+        ]
+    };
+    for (name, flagged, present) in streams {
+        let message = if flagged && !present {
+            format!(
+                "Header TypeFlags announce {0}, but the file contains no {0} tags",
+                name
+            )
+        } else if !flagged && present {
+            format!(
+                "The file contains {0} tags, but header TypeFlags do not announce {0}",
+                name
+            )
+        } else {
+            continue;
+        };
+        if !suppressed.contains(Rule::TypeFlags) {
+            let finding = Finding::new(Rule::TypeFlags, message);
+            failures += fails(Rule::TypeFlags) as usize;
+            alert(&finding);
+            formatter.warning(out, &finding)?;
+        }
+    }
 
-    Ok(())
+    if let (Some(expectation), Some(stats)) = (&expectation, &checked_stats) {
+        if !suppressed.contains(Rule::Expectation) {
+            for deviation in expectation.deviations(stats) {
+                let finding = Finding::new(Rule::Expectation, deviation);
+                failures += fails(Rule::Expectation) as usize;
+                alert(&finding);
+                formatter.warning(out, &finding)?;
+            }
+        }
+    }
+
+    if let Some(rolling) = &mut rolling {
+        rolling.flush(out, json)?;
+    }
+
+    let mut assertion_failures = 0;
+    if let Some(stats) = &checked_stats {
+        for failure in assertions.failures(stats) {
+            eprintln!("Assertion failed: {}: {}", path, failure);
+            assertion_failures += 1;
+        }
+    }
+
+    if let Some(stats) = &stats {
+        formatter.stats(out, stats)?;
+    }
+
+    formatter.finish(out)?;
+    if let Some(webhook) = webhook {
+        webhook.finish();
+    }
+    Ok(Failures {
+        findings: failures,
+        assertions: assertion_failures,
+    })
 }
@@ -0,0 +1,192 @@
+use crate::reader::{read_flv, Field, ParseOptions, HEADER_SIZE};
+use crate::Exception;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::io::{self, Read, Seek, SeekFrom};
+use std::path::{Path, PathBuf};
+use tokio::fs::File;
+use tokio_stream::StreamExt;
+
+const VERSION: u32 = 1;
+
+/// Hashes of a FLV file as a whole and of every tag, written next to an archived recording so
+/// that it can later be shown to be unchanged, and if not, which tags were.
+#[derive(Debug, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Manifest {
+    pub version: u32,
+    /// bytes
+    pub size: u64,
+    /// SHA-256 of the whole file, hexadecimal
+    pub sha256: String,
+    /// SHA-256 of the file header up to DataOffset
+    pub header_sha256: String,
+    pub tags: Vec<TagDigest>,
+}
+
+#[derive(Debug, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct TagDigest {
+    /// Numbered from 1 like in the dump.
+    pub index: usize,
+    pub offset: u64,
+    /// TagType as in the file: 8 audio, 9 video, 18 script data
+    #[serde(rename = "type")]
+    pub tag_type: u8,
+    pub timestamp: i32,
+    pub data_size: u32,
+    /// SHA-256 of the tag header and body
+    pub sha256: String,
+}
+
+/// The sidecar of a FLV file when none is given: `<input>.manifest.json`.
+pub fn default_path(input: &Path) -> PathBuf {
+    let mut path = input.as_os_str().to_owned();
+    path.push(".manifest.json");
+    PathBuf::from(path)
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+impl Manifest {
+    /// Reads the file twice: once byte for byte for the file digest, once parsed for the tags.
+    pub async fn of(input: &Path) -> Result<Self, Exception> {
+        let mut file = std::fs::File::open(input)
+            .map_err(|e| format!("Failed to open {}: {}", input.display(), e))?;
+        let mut hasher = Sha256::new();
+        let size = io::copy(&mut file, &mut hasher)?;
+        let sha256 = hex(&hasher.finalize());
+
+        let options = ParseOptions {
+            keep_raw: true,
+            ..ParseOptions::default()
+        };
+        let (header, mut decoder) = read_flv(File::open(input).await?, options).await?;
+        // DataOffset comes from the file, it is hashed as it is read rather than buffered
+        let header_size = header.offset.max(HEADER_SIZE as u32) as u64;
+        if header_size > size {
+            return Err(format!(
+                "DataOffset {} is past the end of the file ({} bytes)",
+                header.offset, size
+            )
+            .into());
+        }
+        file.seek(SeekFrom::Start(0))?;
+        let mut hasher = Sha256::new();
+        io::copy(&mut (&mut file).take(header_size), &mut hasher)?;
+        let header_sha256 = hex(&hasher.finalize());
+
+        let mut tags = vec![];
+        while let Some(field) = decoder.next().await {
+            let tag = match field? {
+                Field::Tag(tag) => tag,
+                Field::Warning(finding) => {
+                    eprintln!("{}", finding);
+                    continue;
+                }
+                Field::PreTagSize(_) => continue,
+            };
+            let raw = tag.raw.as_deref().unwrap_or_default();
+            tags.push(TagDigest {
+                index: tags.len() + 1,
+                offset: tag.header.offset,
+                tag_type: raw.first().map_or(0, |b| b & 0x1f),
+                timestamp: tag.header.timestamp,
+                data_size: tag.header.data_size,
+                sha256: hex(&Sha256::digest(raw)),
+            });
+        }
+        Ok(Self {
+            version: VERSION,
+            size,
+            sha256,
+            header_sha256,
+            tags,
+        })
+    }
+
+    /// What differs in `actual`, the manifest of the file as it is now, with self as recorded.
+    pub fn differences(&self, actual: &Manifest) -> Vec<String> {
+        let mut differences = vec![];
+        if self.size != actual.size {
+            differences.push(format!(
+                "the file has {} bytes, the manifest {}",
+                actual.size, self.size
+            ));
+        }
+        if self.header_sha256 != actual.header_sha256 {
+            differences.push("the file header changed".into());
+        }
+        for (recorded, tag) in self.tags.iter().zip(&actual.tags) {
+            if recorded != tag {
+                differences.push(format!(
+                    "tag {} at offset {} changed",
+                    recorded.index, recorded.offset
+                ));
+            }
+        }
+        if self.tags.len() > actual.tags.len() {
+            differences.push(format!(
+                "{} tags are missing from tag {} on",
+                self.tags.len() - actual.tags.len(),
+                actual.tags.len() + 1
+            ));
+        } else if actual.tags.len() > self.tags.len() {
+            differences.push(format!(
+                "{} tags were added from tag {} on",
+                actual.tags.len() - self.tags.len(),
+                self.tags.len() + 1
+            ));
+        }
+        if self.sha256 != actual.sha256 && differences.is_empty() {
+            // every tag is the same, so it is in between them
+            differences
+                .push("the PreviousTagSize fields or the bytes after the last tag changed".into());
+        }
+        differences
+    }
+}
+
+/// Writes the manifest of a FLV file to `manifest`, or next to the file.
+pub async fn create(input: &Path, manifest: Option<&Path>) -> Result<(), Exception> {
+    let path = manifest.map_or_else(|| default_path(input), Path::to_owned);
+    let digest = Manifest::of(input).await?;
+    let mut json = serde_json::to_string_pretty(&digest)?;
+    json.push('\n');
+    std::fs::write(&path, json)
+        .map_err(|e| format!("Failed to write {}: {}", path.display(), e))?;
+
+    println!("Manifest: {}", path.display());
+    println!("Tags: {}", digest.tags.len());
+    println!("SHA256: {}", digest.sha256);
+    Ok(())
+}
+
+/// Parses a FLV file again and checks it against its manifest. Fails if anything changed.
+pub async fn verify(input: &Path, manifest: Option<&Path>) -> Result<(), Exception> {
+    let path = manifest.map_or_else(|| default_path(input), Path::to_owned);
+    let text = std::fs::read_to_string(&path)
+        .map_err(|e| format!("Failed to read {}: {}", path.display(), e))?;
+    let recorded: Manifest = serde_json::from_str(&text)
+        .map_err(|e| format!("Invalid manifest {}: {}", path.display(), e))?;
+    if recorded.version != VERSION {
+        return Err(format!("Unsupported manifest version {}", recorded.version).into());
+    }
+    let actual = Manifest::of(input).await?;
+
+    let differences = recorded.differences(&actual);
+    if !differences.is_empty() {
+        for difference in &differences {
+            println!("Changed: {}", difference);
+        }
+        return Err(format!("{} does not match {}", input.display(), path.display()).into());
+    }
+    println!(
+        "Verified: {} tags, SHA256 {}",
+        actual.tags.len(),
+        actual.sha256
+    );
+    Ok(())
+}
@@ -0,0 +1,130 @@
+use crate::Exception;
+use std::convert::TryFrom;
+
+// kbps, indexed by [MPEG-1][layer - 1] and [MPEG-2/2.5][layer - 1]
+const BITRATES: [[[u32; 15]; 3]; 2] = [
+    [
+        [
+            0, 32, 64, 96, 128, 160, 192, 224, 256, 288, 320, 352, 384, 416, 448,
+        ],
+        [
+            0, 32, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320, 384,
+        ],
+        [
+            0, 32, 40, 48, 56, 64, 80, 96, 112, 128, 160, 192, 224, 256, 320,
+        ],
+    ],
+    [
+        [
+            0, 32, 48, 56, 64, 80, 96, 112, 128, 144, 160, 176, 192, 224, 256,
+        ],
+        [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+        [0, 8, 16, 24, 32, 40, 48, 56, 64, 80, 96, 112, 128, 144, 160],
+    ],
+];
+
+const SAMPLE_RATES: [u32; 3] = [44100, 48000, 32000];
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum MpegVersion {
+    Mpeg1,
+    Mpeg2,
+    Mpeg25,
+}
+
+/// The 4 byte header in front of every MPEG audio frame.
+#[derive(Debug, Clone)]
+pub struct FrameHeader {
+    pub version: MpegVersion,
+    pub layer: u8,
+    /// kbps, 0 for free format
+    pub bitrate: u32,
+    pub sample_rate: u32,
+    pub padding: bool,
+}
+
+impl TryFrom<&[u8]> for FrameHeader {
+    type Error = Exception;
+
+    fn try_from(value: &[u8]) -> Result<Self, Self::Error> {
+        let header = match value {
+            [b0, b1, b2, b3, ..] => u32::from_be_bytes([*b0, *b1, *b2, *b3]),
+            _ => return Err("MPEG audio frame header is too short".into()),
+        };
+        if header >> 21 != 0x7ff {
+            return Err(format!("Invalid MPEG audio frame sync: {:08x}", header).into());
+        }
+
+        let version = match (header >> 19) & 3 {
+            0 => MpegVersion::Mpeg25,
+            2 => MpegVersion::Mpeg2,
+            3 => MpegVersion::Mpeg1,
+            _ => return Err("Reserved MPEG audio version".into()),
+        };
+        let layer = match (header >> 17) & 3 {
+            1 => 3,
+            2 => 2,
+            3 => 1,
+            _ => return Err("Reserved MPEG audio layer".into()),
+        };
+        let bitrate_index = ((header >> 12) & 0xf) as usize;
+        let sample_rate_index = ((header >> 10) & 3) as usize;
+        if bitrate_index == 15 || sample_rate_index == 3 {
+            return Err("Invalid MPEG audio bitrate or sample rate index".into());
+        }
+
+        let table = if version == MpegVersion::Mpeg1 { 0 } else { 1 };
+        let sample_rate = SAMPLE_RATES[sample_rate_index]
+            / match version {
+                MpegVersion::Mpeg1 => 1,
+                MpegVersion::Mpeg2 => 2,
+                MpegVersion::Mpeg25 => 4,
+            };
+
+        Ok(Self {
+            version,
+            layer,
+            bitrate: BITRATES[table][layer as usize - 1][bitrate_index],
+            sample_rate,
+            padding: (header >> 9) & 1 == 1,
+        })
+    }
+}
+
+impl FrameHeader {
+    pub fn samples_per_frame(&self) -> u32 {
+        match (self.layer, self.version) {
+            (1, _) => 384,
+            (3, MpegVersion::Mpeg2) | (3, MpegVersion::Mpeg25) => 576,
+            _ => 1152,
+        }
+    }
+
+    /// Frame size in bytes including the header, unknown for free format streams.
+    pub fn frame_length(&self) -> Option<usize> {
+        if self.bitrate == 0 {
+            return None;
+        }
+        let padding = self.padding as u32;
+        let length = if self.layer == 1 {
+            (12 * self.bitrate * 1000 / self.sample_rate + padding) * 4
+        } else {
+            self.samples_per_frame() / 8 * self.bitrate * 1000 / self.sample_rate + padding
+        };
+        Some(length as usize)
+    }
+}
+
+/// Counts the frames of an audio tag body and returns them with the header of the first one.
+pub fn count_frames(mut data: &[u8]) -> Result<(FrameHeader, u32), Exception> {
+    let first = FrameHeader::try_from(data)?;
+    let mut frames = 0;
+    while !data.is_empty() {
+        let length = FrameHeader::try_from(data)?
+            .frame_length()
+            .unwrap_or(data.len());
+        frames += 1;
+        data = &data[length.min(data.len())..];
+    }
+    Ok((first, frames))
+}
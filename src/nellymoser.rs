@@ -0,0 +1,55 @@
+use crate::reader::{AudioDataHeader, SoundFormat, SoundRate};
+use crate::Exception;
+
+/// Nellymoser packs 256 samples into every 64 byte frame, whatever the sample rate.
+pub const FRAME_SIZE: usize = 64;
+pub const SAMPLES_PER_FRAME: u32 = 256;
+
+pub fn is_nellymoser(format: SoundFormat) -> bool {
+    matches!(
+        format,
+        SoundFormat::Nellymoser16 | SoundFormat::Nellymoser8 | SoundFormat::Nellymoser
+    )
+}
+
+/// The sample rate the audio is decoded at: fixed by the 8 kHz and 16 kHz formats, given by
+/// SoundRate for the variable rate one.
+pub fn sample_rate(header: &AudioDataHeader) -> u32 {
+    match header.sound_format {
+        SoundFormat::Nellymoser8 => 8000,
+        SoundFormat::Nellymoser16 => 16000,
+        _ => header.sound_rate.hz(),
+    }
+}
+
+/// Checks that SoundRate agrees with the format. SoundRate can not express 8 or 16 kHz, so
+/// encoders write 5.5 kHz for the fixed rate formats.
+pub fn check_header(header: &AudioDataHeader) -> Result<(), Exception> {
+    let fixed = matches!(
+        header.sound_format,
+        SoundFormat::Nellymoser8 | SoundFormat::Nellymoser16
+    );
+    if fixed && header.sound_rate != SoundRate::R5p5kHz {
+        return Err(format!(
+            "SoundRate {:?} does not match {:?}, which implies {} Hz and SoundRate R5p5kHz",
+            header.sound_rate,
+            header.sound_format,
+            sample_rate(header)
+        )
+        .into());
+    }
+    Ok(())
+}
+
+/// Number of frames in a tag body, which has to hold whole frames.
+pub fn count_frames(data: &[u8]) -> Result<u32, Exception> {
+    if !data.len().is_multiple_of(FRAME_SIZE) {
+        return Err(format!(
+            "Nellymoser data of {} bytes is not a whole number of {} byte frames",
+            data.len(),
+            FRAME_SIZE
+        )
+        .into());
+    }
+    Ok((data.len() / FRAME_SIZE) as u32)
+}
@@ -0,0 +1,134 @@
+use crate::avc::{DecoderConfigurationRecord, Sps};
+use crate::findings::{Finding, Severity};
+use crate::output::{FileInfo, Formatter};
+use crate::profile::{self, Stage};
+use crate::reader::{Tag, TagData};
+use crate::stats::Stats;
+use crate::Exception;
+use std::convert::TryFrom;
+use std::io::Write;
+
+/// A single summary line per input file, e.g. for auditing thousands of files and grepping the
+/// results: `path duration=12.345s audio=AAC video=AVC resolution=1280x720 bitrate=2048kbps
+/// errors=0 warnings=1`. Unknown values are written as `-`, missing streams as `none`. The
+/// findings are only counted.
+#[derive(Default)]
+pub struct Oneline {
+    path: String,
+    first_timestamp: Option<i64>,
+    last_timestamp: i64,
+    // audio and video DataSize
+    media_bytes: u64,
+    audio: Option<String>,
+    video: Option<String>,
+    // from the AVC sequence header, or else onMetaData
+    sps_resolution: Option<(u32, u32)>,
+    metadata_resolution: Option<(f64, f64)>,
+    errors: usize,
+    warnings: usize,
+}
+
+impl Oneline {
+    fn resolution(&self) -> Option<String> {
+        match (self.sps_resolution, self.metadata_resolution) {
+            (Some((width, height)), _) => Some(format!("{}x{}", width, height)),
+            (None, Some((width, height))) => Some(format!("{}x{}", width, height)),
+            (None, None) => None,
+        }
+    }
+
+    /// kbps over the whole file
+    fn bitrate(&self) -> Option<f64> {
+        let duration = self.last_timestamp - self.first_timestamp?;
+        if duration <= 0 {
+            return None;
+        }
+        Some(self.media_bytes as f64 * 8.0 / duration as f64)
+    }
+}
+
+impl Formatter for Oneline {
+    fn header(&mut self, _: &mut dyn Write, file: &FileInfo) -> Result<(), Exception> {
+        *self = Oneline {
+            path: file.path.to_owned(),
+            ..Oneline::default()
+        };
+        Ok(())
+    }
+
+    fn pre_tag_size(&mut self, _: &mut dyn Write, _: usize, _: u32) -> Result<(), Exception> {
+        Ok(())
+    }
+
+    fn tag(&mut self, _: &mut dyn Write, _: usize, tag: &Tag) -> Result<(), Exception> {
+        let timestamp = tag.header.timestamp as i64;
+        self.first_timestamp.get_or_insert(timestamp);
+        self.last_timestamp = self.last_timestamp.max(timestamp);
+        match &tag.data {
+            TagData::Audio(audio) => {
+                self.media_bytes += tag.header.data_size as u64;
+                self.audio
+                    .get_or_insert_with(|| format!("{:?}", audio.header.sound_format));
+            }
+            TagData::Video(video) => {
+                self.media_bytes += tag.header.data_size as u64;
+                self.video
+                    .get_or_insert_with(|| format!("{:?}", video.header.codec_id));
+                if tag.data.is_sequence_header() {
+                    if let [_, _, _, _, record @ ..] = &video.data[..] {
+                        let sps = profile::measure(Stage::Codec, || {
+                            DecoderConfigurationRecord::try_from(record)
+                                .ok()
+                                .and_then(|record| Sps::try_from(&record.sps.first()?[..]).ok())
+                        });
+                        if let Some(sps) = sps {
+                            self.sps_resolution.get_or_insert((sps.width, sps.height));
+                        }
+                    }
+                }
+            }
+            TagData::Script(script) => {
+                if let Some(metadata) = script.as_metadata() {
+                    if let (Some(width), Some(height)) = (metadata.width, metadata.height) {
+                        self.metadata_resolution.get_or_insert((width, height));
+                    }
+                }
+            }
+            _ => {}
+        }
+        Ok(())
+    }
+
+    fn stats(&mut self, _: &mut dyn Write, _: &Stats) -> Result<(), Exception> {
+        Err("Statistics can not be printed with --oneline".into())
+    }
+
+    fn warning(&mut self, _: &mut dyn Write, finding: &Finding) -> Result<(), Exception> {
+        match finding.severity() {
+            Severity::Error => self.errors += 1,
+            Severity::Warning => self.warnings += 1,
+            Severity::Info => {}
+        }
+        Ok(())
+    }
+
+    fn finish(&mut self, out: &mut dyn Write) -> Result<(), Exception> {
+        let duration = self
+            .first_timestamp
+            .map(|first| format!("{:.3}s", (self.last_timestamp - first) as f64 / 1000.0));
+        writeln!(
+            out,
+            "{} duration={} audio={} video={} resolution={} bitrate={} errors={} warnings={}",
+            self.path,
+            duration.as_deref().unwrap_or("-"),
+            self.audio.as_deref().unwrap_or("none"),
+            self.video.as_deref().unwrap_or("none"),
+            self.resolution().as_deref().unwrap_or("-"),
+            self.bitrate()
+                .map_or("-".into(), |bitrate| format!("{:.0}kbps", bitrate)),
+            self.errors,
+            self.warnings
+        )?;
+        Ok(())
+    }
+}
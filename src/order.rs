@@ -0,0 +1,213 @@
+use crate::findings::{Finding, Rule};
+use crate::reader::{CodecId, ScriptData, SoundFormat, Tag, TagData};
+
+/// Checks that AAC frames and AVC NALUs come after the sequence header of their stream, and in
+/// strict mode that no audio or video frame comes before onMetaData. Tags are numbered from 1 like
+/// in the dump.
+#[derive(Debug)]
+pub struct SequenceOrder {
+    aac: StreamOrder,
+    avc: StreamOrder,
+    metadata: bool,
+    frames_before_metadata: Indexes,
+}
+
+impl Default for SequenceOrder {
+    fn default() -> Self {
+        Self {
+            aac: StreamOrder::new("AAC", "AAC frame", "AAC frames"),
+            avc: StreamOrder::new("AVC", "AVC NALU", "AVC NALUs"),
+            metadata: false,
+            frames_before_metadata: Indexes::default(),
+        }
+    }
+}
+
+#[derive(Debug)]
+struct StreamOrder {
+    codec: &'static str,
+    frame: &'static str,
+    frames: &'static str,
+    sequence_header: bool,
+    // frames before the first sequence header, not yet reported
+    early_frames: Indexes,
+    // all of them, reported or not
+    early_frame_count: usize,
+}
+
+impl StreamOrder {
+    fn new(codec: &'static str, frame: &'static str, frames: &'static str) -> Self {
+        Self {
+            codec,
+            frame,
+            frames,
+            sequence_header: false,
+            early_frames: Indexes::default(),
+            early_frame_count: 0,
+        }
+    }
+
+    fn push(&mut self, index: usize, sequence_header: bool, strict: bool) -> Option<Finding> {
+        if self.sequence_header {
+            return None;
+        }
+        if !sequence_header {
+            self.early_frame_count += 1;
+            if strict {
+                let message = format!(
+                    "Tag {} is an {} before the {} sequence header",
+                    index, self.frame, self.codec
+                );
+                return Some(Finding::new(Rule::SequenceOrder, message));
+            }
+            self.early_frames.push(index);
+            return None;
+        }
+        self.sequence_header = true;
+        if self.early_frames.count == 0 {
+            return None;
+        }
+        let early_frames = std::mem::take(&mut self.early_frames);
+        let message = format!(
+            "{} {} in tags {} precede the {} sequence header in tag {}",
+            early_frames.count, self.frames, early_frames, self.codec, index
+        );
+        Some(Finding::new(Rule::SequenceOrder, message))
+    }
+}
+
+impl SequenceOrder {
+    /// The violation the tag reveals, if any. Strict mode reports every misplaced frame as it
+    /// comes, lenient mode the misplaced frames of a stream at once, when its sequence header
+    /// arrives.
+    pub fn push(&mut self, index: usize, tag: &Tag, strict: bool) -> Option<Finding> {
+        let sequence_header = tag.data.is_sequence_header();
+        match &tag.data {
+            TagData::Script(script) => return self.script(index, script),
+            TagData::Audio(_) | TagData::Video(_)
+                if strict && !self.metadata && !sequence_header =>
+            {
+                self.frames_before_metadata.push(index)
+            }
+            _ => {}
+        }
+        match &tag.data {
+            TagData::Audio(audio) if audio.header.sound_format == SoundFormat::AAC => {
+                self.aac.push(index, sequence_header, strict)
+            }
+            TagData::Video(video) if video.header.codec_id == CodecId::AVC => {
+                self.avc.push(index, sequence_header, strict)
+            }
+            _ => None,
+        }
+    }
+
+    // Frames before onMetaData are only recorded in strict mode.
+    fn script(&mut self, index: usize, script: &ScriptData) -> Option<Finding> {
+        if self.metadata || script.as_metadata().is_none() {
+            return None;
+        }
+        self.metadata = true;
+        if self.frames_before_metadata.count == 0 {
+            return None;
+        }
+        let message = format!(
+            "{} audio or video frames in tags {} precede onMetaData in tag {}",
+            self.frames_before_metadata.count, self.frames_before_metadata, index
+        );
+        Some(Finding::new(Rule::MetadataOrder, message))
+    }
+
+    /// The frames of the streams whose sequence header never came, once the input has ended.
+    pub fn finish(&mut self) -> Vec<Finding> {
+        let mut findings = vec![];
+        for stream in [&mut self.aac, &mut self.avc].iter_mut() {
+            let early_frames = std::mem::take(&mut stream.early_frames);
+            if early_frames.count > 0 {
+                let message = format!(
+                    "{} {} in tags {} without any {} sequence header",
+                    early_frames.count, stream.frames, early_frames, stream.codec
+                );
+                findings.push(Finding::new(Rule::SequenceOrder, message));
+            }
+        }
+        findings
+    }
+}
+
+/// Increasing tag indexes, kept as ranges and shown like "1-3, 5".
+#[derive(Debug, Default)]
+struct Indexes {
+    ranges: Vec<(usize, usize)>,
+    count: usize,
+}
+
+impl Indexes {
+    fn push(&mut self, index: usize) {
+        self.count += 1;
+        match self.ranges.last_mut() {
+            Some((_, end)) if *end + 1 == index => *end = index,
+            _ => self.ranges.push((index, index)),
+        }
+    }
+}
+
+impl std::fmt::Display for Indexes {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        for (i, (start, end)) in self.ranges.iter().enumerate() {
+            if i > 0 {
+                write!(f, ", ")?;
+            }
+            if start == end {
+                write!(f, "{}", start)?;
+            } else {
+                write!(f, "{}-{}", start, end)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Counts the AAC frames and AVC NALUs that come before the sequence header of their stream,
+/// tracked by a lenient `SequenceOrder`. Most players can not decode them, they are the sign of
+/// a stream that started broken.
+#[derive(Debug, Default)]
+pub struct EarlyFrames {
+    order: SequenceOrder,
+    tags: usize,
+}
+
+impl EarlyFrames {
+    pub fn push(&mut self, tag: &Tag) {
+        self.tags += 1;
+        // the findings are those of the decoder, only the counts are wanted here
+        self.order.push(self.tags, tag, false);
+    }
+
+    pub fn aac(&self) -> usize {
+        self.order.aac.early_frame_count
+    }
+
+    pub fn avc(&self) -> usize {
+        self.order.avc.early_frame_count
+    }
+
+    pub fn total(&self) -> usize {
+        self.aac() + self.avc()
+    }
+}
+
+impl std::fmt::Display for EarlyFrames {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let mut counts = vec![];
+        if self.aac() > 0 {
+            let frames = if self.aac() == 1 { "frame" } else { "frames" };
+            counts.push(format!("{} AAC {}", self.aac(), frames));
+        }
+        if self.avc() > 0 {
+            let nalus = if self.avc() == 1 { "NALU" } else { "NALUs" };
+            counts.push(format!("{} AVC {}", self.avc(), nalus));
+        }
+        write!(f, "{}", counts.join(" and "))
+    }
+}
@@ -0,0 +1,1644 @@
+use crate::aac::{self, AacPacketType, AudioSpecificConfig};
+use crate::amf::AmfValue;
+use crate::avc::{self, AvcPacketType, DecoderConfigurationRecord, Sps};
+use crate::cbor;
+use crate::events::ScriptEvent;
+use crate::ffprobe::Ffprobe;
+use crate::findings::Finding;
+use crate::fingerprint::Confidence;
+use crate::flvmeta::Flvmeta;
+use crate::g711;
+use crate::nellymoser;
+use crate::pcm;
+use crate::profile::{self, Stage};
+use crate::reader::{AudioData, Header, SoundFormat, Tag, TagData, TagType, VideoData};
+use crate::rotate::{RotatingFile, Rotation};
+use crate::score::Score;
+use crate::speex;
+use crate::stats::{
+    AudioContinuity, AudioSummary, DuplicateCount, KeyframeMap, Stats, StreamStats,
+    StreamTimestamps, VideoCodecSummary, VideoSummary,
+};
+use crate::time;
+use crate::Exception;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde_json::{json, Map, Value};
+use std::convert::TryFrom;
+use std::fs::File;
+use std::io::{self, BufWriter, Write};
+use std::path::Path;
+
+const SEPARATOR: &str = "=====================================";
+/// JSON Schema of the JSON and NDJSON output. Fields may be added without a new version,
+/// renaming or removing one takes the next.
+pub const SCHEMA: &str = include_str!("../schema/flv-dump-v1.schema.json");
+// Number of the most common frame intervals listed in the text statistics.
+const FRAME_INTERVALS_SHOWN: usize = 5;
+// Frame drops listed in the text statistics, all of them are in the JSON.
+const FRAME_DROPS_SHOWN: usize = 10;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq, clap::ValueEnum)]
+pub enum Format {
+    /// The human readable dump
+    Text,
+    /// One JSON document per input file
+    Json,
+    /// One JSON object per line: the file header, then every tag
+    Ndjson,
+    /// One row per tag
+    Csv,
+    /// One CBOR item per input file with the same content as the JSON document, the tags being
+    /// streamed in an array of indefinite length
+    Cbor,
+    /// Streams and packets like `ffprobe -show_packets -show_streams -of json`
+    Ffprobe,
+    /// The onMetaData properties like `flvmeta --dump --raw`
+    FlvmetaDump,
+    /// Every tag like `flvmeta --full-dump --raw`
+    FlvmetaFullDump,
+}
+
+/// Where the report goes: stdout, stderr or a file, optionally gzip compressed.
+pub enum Output {
+    Plain(Box<dyn Write + Send>),
+    Gzip(GzEncoder<Box<dyn Write + Send>>),
+}
+
+impl Output {
+    pub fn open(path: Option<&Path>, gzip: bool) -> Result<Self, Exception> {
+        let inner: Box<dyn Write + Send> = match path {
+            Some(path) => Box::new(BufWriter::new(File::create(path)?)),
+            None => Box::new(BufWriter::new(io::stdout())),
+        };
+        Ok(Self::new(inner, gzip))
+    }
+
+    pub fn rotating(path: &Path, rotation: Rotation) -> Result<Self, Exception> {
+        Ok(Output::Plain(Box::new(RotatingFile::open(path, rotation)?)))
+    }
+
+    pub fn stderr(gzip: bool) -> Self {
+        Self::new(Box::new(BufWriter::new(io::stderr())), gzip)
+    }
+
+    fn new(inner: Box<dyn Write + Send>, gzip: bool) -> Self {
+        if gzip {
+            Output::Gzip(GzEncoder::new(inner, Compression::default()))
+        } else {
+            Output::Plain(inner)
+        }
+    }
+
+    pub fn finish(self) -> io::Result<()> {
+        match self {
+            Output::Plain(mut inner) => inner.flush(),
+            Output::Gzip(encoder) => encoder.finish()?.flush(),
+        }
+    }
+}
+
+impl Write for Output {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        match self {
+            Output::Plain(inner) => inner.write(buf),
+            Output::Gzip(encoder) => encoder.write(buf),
+        }
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        match self {
+            Output::Plain(inner) => inner.flush(),
+            Output::Gzip(encoder) => encoder.flush(),
+        }
+    }
+}
+
+pub struct FileInfo<'a> {
+    pub path: &'a str,
+    pub size: Option<u64>,
+    pub header: &'a Header,
+}
+
+/// Receives the decoded fields of one input file, in order.
+pub trait Formatter {
+    fn header(&mut self, out: &mut dyn Write, file: &FileInfo) -> Result<(), Exception>;
+    fn pre_tag_size(
+        &mut self,
+        out: &mut dyn Write,
+        index: usize,
+        size: u32,
+    ) -> Result<(), Exception>;
+    fn tag(&mut self, out: &mut dyn Write, index: usize, tag: &Tag) -> Result<(), Exception>;
+    fn stats(&mut self, out: &mut dyn Write, stats: &Stats) -> Result<(), Exception>;
+    fn warning(&mut self, out: &mut dyn Write, finding: &Finding) -> Result<(), Exception>;
+    fn finish(&mut self, out: &mut dyn Write) -> Result<(), Exception>;
+}
+
+pub fn formatter(format: Format) -> Box<dyn Formatter> {
+    match format {
+        Format::Text => Box::new(Text),
+        Format::Json => Box::new(Json::default()),
+        Format::Ndjson => Box::new(Ndjson::default()),
+        Format::Cbor => Box::new(Cbor::default()),
+        Format::Csv => Box::new(Csv::default()),
+        Format::Ffprobe => Box::new(Ffprobe::default()),
+        Format::FlvmetaDump => Box::new(Flvmeta::dump()),
+        Format::FlvmetaFullDump => Box::new(Flvmeta::full_dump()),
+    }
+}
+
+pub struct Text;
+
+impl Formatter for Text {
+    fn header(&mut self, out: &mut dyn Write, file: &FileInfo) -> Result<(), Exception> {
+        writeln!(out, "{}", SEPARATOR)?;
+        writeln!(out, "File: {}", file.path)?;
+        if let Some(size) = file.size {
+            writeln!(out, "FileSize: {}", size)?;
+        }
+        writeln!(out, "Version: {}", file.header.version)?;
+        writeln!(out, "Type: {}", file.header.type_)?;
+        writeln!(out, "TypeFlags: {}", file.header.type_flags())?;
+        writeln!(out, "DataOffset: {}", file.header.offset)?;
+        if !file.header.extra.is_empty() {
+            writeln!(out, "ExtraHeaderData: {:?}", file.header.extra)?;
+        }
+        Ok(())
+    }
+
+    fn pre_tag_size(
+        &mut self,
+        out: &mut dyn Write,
+        index: usize,
+        size: u32,
+    ) -> Result<(), Exception> {
+        writeln!(out, "{}", SEPARATOR)?;
+        writeln!(out, "PreviousTagSize{}: {}", index, size)?;
+        Ok(())
+    }
+
+    fn tag(&mut self, out: &mut dyn Write, index: usize, tag: &Tag) -> Result<(), Exception> {
+        writeln!(out, "{}", SEPARATOR)?;
+        writeln!(out, "TagIndex: {}", index)?;
+        writeln!(out, "TagType: {:?}", tag.header.tag_type)?;
+        writeln!(out, "DataSize: {:?}", tag.header.data_size)?;
+        writeln!(out, "Timestamp: {:?}", tag.header.timestamp)?;
+        if let Some(received) = tag.received {
+            writeln!(out, "Wallclock: {}", time::format_utc(received))?;
+        }
+        match &tag.data {
+            TagData::Audio(audio) => {
+                writeln!(out, "SoundFormat: {:?}", audio.header.sound_format)?;
+                writeln!(out, "SoundRate: {:?}", audio.header.sound_rate)?;
+                writeln!(out, "SoundSize: {:?}", audio.header.sound_size)?;
+                writeln!(out, "SoundType: {:?}", audio.header.sound_type)?;
+                if let Some(packet_type) = audio.aac_packet_type() {
+                    writeln!(out, "AACPacketType: {:?}", packet_type)?;
+                }
+                match aac_config(audio) {
+                    Some(Ok(config)) => {
+                        let object_type = object_type_text(config.object_type);
+                        writeln!(out, "AudioObjectType: {}", object_type)?;
+                        writeln!(out, "SamplingFrequency: {}", config.sampling_frequency)?;
+                        if let Some(extension) = config.extension_sampling_frequency {
+                            writeln!(out, "ExtensionSamplingFrequency: {}", extension)?;
+                        }
+                        writeln!(
+                            out,
+                            "ChannelConfiguration: {}",
+                            config.channel_configuration
+                        )?;
+                    }
+                    Some(Err(e)) => writeln!(out, "ConfigError: {}", e)?,
+                    None => {}
+                }
+                if let Some((sample_rate, frames)) = codec_frames(audio) {
+                    writeln!(out, "SampleRate: {}", sample_rate)?;
+                    match frames {
+                        Ok(frames) => writeln!(out, "Frames: {}", frames)?,
+                        Err(e) => writeln!(out, "FrameError: {}", e)?,
+                    }
+                }
+                if let Some((sample_rate, duration)) = sized_duration(audio) {
+                    writeln!(out, "SampleRate: {}", sample_rate)?;
+                    writeln!(out, "Duration: {:.3} ms", duration)?;
+                }
+                writeln!(out, "Data: {:?}", audio.data)?;
+            }
+            TagData::Video(video) => {
+                writeln!(out, "FrameType: {:?}", video.header.frame_type)?;
+                writeln!(out, "CodecId: {:?}", video.header.codec_id)?;
+                if let Some(packet_type) = video.avc_packet_type() {
+                    writeln!(out, "AVCPacketType: {:?}", packet_type)?;
+                }
+                if let Some(composition_time) = video.composition_time() {
+                    writeln!(out, "CompositionTime: {}", composition_time)?;
+                }
+                match avc_config(video) {
+                    Some(Ok(sps)) => {
+                        writeln!(out, "AVCProfile: {}", profile_text(sps.profile))?;
+                        writeln!(out, "AVCLevel: {}", avc::level_name(sps.level))?;
+                        writeln!(out, "Width: {}", sps.width)?;
+                        writeln!(out, "Height: {}", sps.height)?;
+                        writeln!(
+                            out,
+                            "ChromaFormat: {}",
+                            chroma_format_text(sps.chroma_format)
+                        )?;
+                        writeln!(out, "BitDepth: {}", sps.bit_depth)?;
+                    }
+                    Some(Err(e)) => writeln!(out, "ConfigError: {}", e)?,
+                    None => {}
+                }
+                writeln!(out, "Data: {:?}", video.data)?;
+            }
+            TagData::Script(script) => {
+                if let Some(event) = script.event() {
+                    writeln!(out, "ScriptEvent: {}", event)?;
+                }
+                let decoded = script.decode();
+                writeln!(out, "ScriptData:")?;
+                for value in &decoded.values {
+                    write_amf(out, 1, None, value)?;
+                }
+                if let Some(error) = decoded.error {
+                    writeln!(out, "ScriptDataError: {}", error)?;
+                    writeln!(out, "RawScriptData: {:?}", script.raw())?;
+                }
+            }
+            TagData::Reserved(data) => {
+                writeln!(out, "Data: {:?}", data)?;
+            }
+            TagData::Invalid(invalid) => {
+                writeln!(out, "Error: {}", invalid.error)?;
+                writeln!(out, "Data: {:?}", invalid.data)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn stats(&mut self, out: &mut dyn Write, stats: &Stats) -> Result<(), Exception> {
+        write_stream_stats(out, "Audio", &stats.audio)?;
+
+        let continuity = &stats.audio_continuity;
+        if let Some(frame_duration) = continuity.frame_duration {
+            writeln!(out, "AudioFrameDuration: {:.2} ms", frame_duration)?;
+            writeln!(out, "MissingAudioFrames: {}", continuity.missing_frames())?;
+            writeln!(
+                out,
+                "OverlappingAudioFrames: {}",
+                continuity.overlapping_frames()
+            )?;
+            writeln!(out, "AudioDrift: {:+.1} ms", continuity.drift())?;
+            for (minute, stats) in &continuity.minutes {
+                if !stats.is_continuous() {
+                    writeln!(
+                        out,
+                        "AudioMinute{}: MissingFrames {}, OverlappingFrames {}, Drift {:+.1} ms",
+                        minute, stats.missing_frames, stats.overlapping_frames, stats.drift
+                    )?;
+                }
+            }
+        }
+
+        let pcm = &stats.pcm_endianness;
+        if pcm.is_pcm() {
+            writeln!(
+                out,
+                "PcmTags: {} platform-endian, {} little-endian",
+                pcm.platform_endian_tags, pcm.little_endian_tags
+            )?;
+            writeln!(
+                out,
+                "PcmDataByteOrder: {} tags look little-endian, {} big-endian",
+                pcm.little_endian_data, pcm.big_endian_data
+            )?;
+            writeln!(out, "PcmEndiannessConsistent: {}", pcm.is_consistent())?;
+        }
+
+        write_audio_summary(out, &stats.audio_summary)?;
+
+        write_stream_stats(out, "Video", &stats.video)?;
+
+        let frame_rate = stats.frame_intervals.frame_rate();
+        if let Some(frame_rate) = &frame_rate {
+            writeln!(out, "FrameRate: {:.2} fps", frame_rate.average)?;
+            if let Some(fps) = frame_rate.mode() {
+                writeln!(out, "ModeFrameRate: {:.2} fps", fps)?;
+            }
+            writeln!(
+                out,
+                "FrameIntervalMode: {} ms ({:.1}%)",
+                frame_rate.mode_interval,
+                frame_rate.mode_share * 100.0
+            )?;
+            let mut intervals: Vec<_> = stats.frame_intervals.intervals.iter().collect();
+            intervals.sort_by_key(|(interval, count)| (std::cmp::Reverse(**count), **interval));
+            let total: usize = intervals.iter().map(|(_, count)| **count).sum();
+            let distribution: Vec<_> = intervals
+                .iter()
+                .take(FRAME_INTERVALS_SHOWN)
+                .map(|(interval, count)| {
+                    format!(
+                        "{} ms {:.1}%",
+                        interval,
+                        **count as f64 * 100.0 / total as f64
+                    )
+                })
+                .collect();
+            writeln!(out, "FrameIntervals: {}", distribution.join(", "))?;
+            if let Some(cadence) = stats.frame_intervals.cadence() {
+                let dominant: Vec<_> = cadence
+                    .dominant
+                    .iter()
+                    .map(|(interval, share)| format!("{} ms {:.1}%", interval, share * 100.0))
+                    .collect();
+                writeln!(
+                    out,
+                    "FrameRateType: {}, {:.1}% variable intervals, dominant {}",
+                    if cadence.constant { "CFR" } else { "VFR" },
+                    cadence.variable_share * 100.0,
+                    dominant.join(", ")
+                )?;
+            }
+        }
+        if let Some(fps) = stats.metadata_frame_rate {
+            writeln!(out, "MetadataFrameRate: {:.2} fps", fps)?;
+        }
+        write_composition_times(out, stats)?;
+        if let Some(matches) = stats.frame_rate_matches_metadata() {
+            writeln!(out, "FrameRateMatchesMetadata: {}", matches)?;
+        }
+        if let Some(report) = stats.frame_drops() {
+            writeln!(
+                out,
+                "DroppedFrames: {} (nominal frame interval {:.1} ms)",
+                report.total(),
+                report.nominal_interval
+            )?;
+            for drop in report.drops.iter().take(FRAME_DROPS_SHOWN) {
+                writeln!(
+                    out,
+                    "FrameDrop: {} frames after {} ms",
+                    drop.frames, drop.after
+                )?;
+            }
+            if report.drops.len() > FRAME_DROPS_SHOWN {
+                writeln!(
+                    out,
+                    "FrameDrop: {} more gaps, see the JSON output",
+                    report.drops.len() - FRAME_DROPS_SHOWN
+                )?;
+            }
+            for (minute, frames) in &report.minutes {
+                writeln!(out, "VideoMinute{}: DroppedFrames {}", minute, frames)?;
+            }
+        }
+
+        write_video_summary(out, &stats.video_summary)?;
+        write_timestamps(out, &stats.timestamps)?;
+        let early = &stats.early_frames;
+        if early.total() > 0 {
+            writeln!(
+                out,
+                "FramesBeforeSequenceHeader: AAC {}, AVC {}",
+                early.aac(),
+                early.avc()
+            )?;
+        }
+        let duplicates = &stats.duplicates;
+        let total = duplicates.total();
+        if total.tags > 0 {
+            writeln!(
+                out,
+                "DuplicateTags: {} (audio {}, video {}, script {}), {} bytes",
+                total.tags,
+                duplicates.audio.tags,
+                duplicates.video.tags,
+                duplicates.script.tags,
+                total.bytes
+            )?;
+        }
+        if let Some(start) = stats.keyframe_map.start() {
+            let map: String = stats
+                .keyframe_map
+                .seconds()
+                .iter()
+                .map(|keyframe| if keyframe.is_some() { '1' } else { '0' })
+                .collect();
+            writeln!(out, "KeyframeMap: {} (from second {})", map, start)?;
+        }
+        if !stats.script_events.is_empty() {
+            let events: Vec<String> = stats
+                .script_events
+                .iter()
+                .map(|(name, count)| format!("{} {}", name, count))
+                .collect();
+            writeln!(out, "ScriptEvents: {}", events.join(", "))?;
+        }
+        if let Some(identification) = stats.fingerprint.identify() {
+            writeln!(out, "Encoder: {}", identification)?;
+            if let Some(relay) = &identification.relay {
+                writeln!(out, "Relay: {}", relay)?;
+            }
+            for evidence in &identification.evidence {
+                writeln!(out, "EncoderEvidence: {}", evidence)?;
+            }
+        }
+        if let Some(score) = Score::new(stats) {
+            writeln!(out, "SeekabilityScore: {}/100", score.total)?;
+            for factor in &score.factors {
+                writeln!(
+                    out,
+                    "ScoreFactor: {} {}/100 (weight {}), {}",
+                    factor.name, factor.score, factor.weight, factor.detail
+                )?;
+            }
+        }
+        Ok(())
+    }
+
+    fn warning(&mut self, out: &mut dyn Write, finding: &Finding) -> Result<(), Exception> {
+        writeln!(out, "{}", SEPARATOR)?;
+        writeln!(out, "{}", finding)?;
+        Ok(())
+    }
+
+    fn finish(&mut self, out: &mut dyn Write) -> Result<(), Exception> {
+        writeln!(out, "{}", SEPARATOR)?;
+        Ok(())
+    }
+}
+
+fn write_stream_stats(
+    out: &mut dyn Write,
+    name: &str,
+    stream: &StreamStats,
+) -> Result<(), Exception> {
+    writeln!(out, "{}", SEPARATOR)?;
+    writeln!(out, "Stream: {}", name)?;
+    writeln!(out, "Tags: {}", stream.tags)?;
+    writeln!(out, "Bytes: {}", stream.bytes)?;
+    match stream.bitrate() {
+        Some(bitrate) => {
+            writeln!(out, "AverageBitrate: {:.1} kbps", bitrate.average)?;
+            writeln!(out, "BitrateStdDev: {:.1} kbps", bitrate.std_dev)?;
+            writeln!(out, "BitrateVariation: {:.1}%", bitrate.variation() * 100.0)?;
+            writeln!(out, "PeakBitrate: {:.1} kbps", bitrate.peak)?;
+            writeln!(out, "PeakToAverage: {:.2}", bitrate.peak_to_average())?;
+            writeln!(out, "BitrateMode: {:?}", bitrate.mode())?;
+        }
+        None => writeln!(out, "BitrateMode: Unknown")?,
+    }
+    Ok(())
+}
+
+// Sample rate and frame count of the codecs whose frames are not described anywhere else.
+fn codec_frames(audio: &AudioData) -> Option<(u32, Result<u32, Exception>)> {
+    profile::measure(Stage::Codec, || match audio.header.sound_format {
+        SoundFormat::Speex => Some((speex::SAMPLE_RATE, speex::count_frames(&audio.data))),
+        format if nellymoser::is_nellymoser(format) => Some((
+            nellymoser::sample_rate(&audio.header),
+            nellymoser::count_frames(&audio.data),
+        )),
+        _ => None,
+    })
+}
+
+// The AudioSpecificConfig of an AAC sequence header.
+fn aac_config(audio: &AudioData) -> Option<Result<AudioSpecificConfig, Exception>> {
+    if audio.aac_packet_type()? != AacPacketType::SequenceHeader {
+        return None;
+    }
+    // after the AACPacketType
+    Some(profile::measure(Stage::Codec, || {
+        AudioSpecificConfig::try_from(&audio.data[1..])
+    }))
+}
+
+fn object_type_text(object_type: u8) -> String {
+    aac::object_type_name(object_type).map_or_else(|| object_type.to_string(), str::to_owned)
+}
+
+// The first SPS of the AVCDecoderConfigurationRecord of an AVC sequence header.
+fn avc_config(video: &VideoData) -> Option<Result<Sps, Exception>> {
+    if video.avc_packet_type()? != AvcPacketType::SequenceHeader {
+        return None;
+    }
+    Some(profile::measure(Stage::Codec, || {
+        // after the AVCPacketType and CompositionTime
+        let record = video.data.get(4..).unwrap_or_default();
+        let record = DecoderConfigurationRecord::try_from(record)?;
+        let sps = record
+            .sps
+            .first()
+            .ok_or("AVCDecoderConfigurationRecord has no SPS")?;
+        Sps::try_from(&sps[..])
+    }))
+}
+
+fn profile_text(profile: u8) -> String {
+    avc::profile_name(profile).map_or_else(|| profile.to_string(), str::to_owned)
+}
+
+fn chroma_format_text(chroma_format: u32) -> String {
+    avc::chroma_format_name(chroma_format).map_or_else(|| chroma_format.to_string(), str::to_owned)
+}
+
+// Sample rate and duration in milliseconds of the codecs whose tags last as long as their size.
+fn sized_duration(audio: &AudioData) -> Option<(u32, f64)> {
+    let header = &audio.header;
+    match header.sound_format {
+        format if g711::is_g711(format) => {
+            Some((g711::SAMPLE_RATE, g711::duration(header, &audio.data)))
+        }
+        format if pcm::is_pcm(format) => {
+            Some((pcm::sample_rate(header), pcm::duration(header, &audio.data)))
+        }
+        _ => None,
+    }
+}
+
+// One line per AMF value, indented by two spaces per level, with the values of objects and
+// arrays below their key.
+fn write_amf(
+    out: &mut dyn Write,
+    indent: usize,
+    key: Option<&str>,
+    value: &AmfValue,
+) -> io::Result<()> {
+    let prefix = format!(
+        "{:indent$}{}",
+        "",
+        key.map_or_else(String::new, |key| format!("{}: ", key)),
+        indent = indent * 2
+    );
+    let properties = match value {
+        AmfValue::Number(n) => return writeln!(out, "{}{}", prefix, n),
+        AmfValue::Boolean(b) => return writeln!(out, "{}{}", prefix, b),
+        AmfValue::String(s) => return writeln!(out, "{}{:?}", prefix, s),
+        AmfValue::Null => return writeln!(out, "{}null", prefix),
+        AmfValue::Undefined => return writeln!(out, "{}undefined", prefix),
+        AmfValue::Date { millis, timezone } if millis.is_finite() => {
+            return writeln!(
+                out,
+                "{}Date {} (time zone {})",
+                prefix,
+                time::format_millis_utc(*millis as i64),
+                timezone
+            )
+        }
+        AmfValue::Date { millis, .. } => return writeln!(out, "{}Date {}", prefix, millis),
+        AmfValue::Xml(xml) => return writeln!(out, "{}XML {:?}", prefix, xml),
+        AmfValue::Reference(index) => return writeln!(out, "{}Reference {}", prefix, index),
+        AmfValue::StrictArray(items) => {
+            writeln!(out, "{}StrictArray ({})", prefix, items.len())?;
+            for (index, item) in items.iter().enumerate() {
+                write_amf(out, indent + 1, Some(&index.to_string()), item)?;
+            }
+            return Ok(());
+        }
+        AmfValue::Object(properties) => {
+            writeln!(out, "{}Object", prefix)?;
+            properties
+        }
+        AmfValue::EcmaArray(properties) => {
+            writeln!(out, "{}EcmaArray ({})", prefix, properties.len())?;
+            properties
+        }
+        AmfValue::TypedObject(class, properties) => {
+            writeln!(out, "{}TypedObject {:?}", prefix, class)?;
+            properties
+        }
+    };
+    for (key, value) in properties {
+        write_amf(out, indent + 1, Some(key), value)?;
+    }
+    Ok(())
+}
+
+// Left-aligned columns separated by two spaces, "-" standing for unknown values.
+fn write_table(out: &mut dyn Write, columns: &[&str], rows: &[Vec<String>]) -> io::Result<()> {
+    let widths: Vec<usize> = columns
+        .iter()
+        .enumerate()
+        .map(|(i, column)| {
+            rows.iter()
+                .map(|row| row[i].len())
+                .fold(column.len(), usize::max)
+        })
+        .collect();
+    let line = |cells: Vec<&str>| {
+        let cells: Vec<String> = cells
+            .iter()
+            .zip(&widths)
+            .map(|(cell, width)| format!("{:width$}", cell, width = width))
+            .collect();
+        cells.join("  ").trim_end().to_owned()
+    };
+    writeln!(out, "{}", line(columns.to_vec()))?;
+    for row in rows {
+        writeln!(out, "{}", line(row.iter().map(String::as_str).collect()))?;
+    }
+    Ok(())
+}
+
+fn or_dash<T: ToString>(value: Option<T>) -> String {
+    value.map_or_else(|| "-".to_owned(), |value| value.to_string())
+}
+
+fn write_audio_summary(out: &mut dyn Write, summary: &AudioSummary) -> Result<(), Exception> {
+    if summary.codecs.is_empty() {
+        return Ok(());
+    }
+    let rows: Vec<Vec<String>> = summary
+        .codecs
+        .iter()
+        .map(|codec| {
+            vec![
+                format!("{:?}", codec.sound_format),
+                codec.header_sample_rate.to_string(),
+                or_dash(codec.codec_sample_rate),
+                codec.header_channels.to_string(),
+                or_dash(codec.codec_channels),
+                codec.tags.to_string(),
+                codec.bytes.to_string(),
+                or_dash(codec.average_bitrate().map(|kbps| format!("{:.1}", kbps))),
+                codec.first_timestamp.to_string(),
+                codec.last_timestamp.to_string(),
+            ]
+        })
+        .collect();
+    writeln!(out, "{}", SEPARATOR)?;
+    writeln!(out, "AudioSummary:")?;
+    write_table(
+        out,
+        &[
+            "Codec",
+            "HeaderRate",
+            "CodecRate",
+            "HeaderChannels",
+            "CodecChannels",
+            "Tags",
+            "Bytes",
+            "Kbps",
+            "FirstTimestamp",
+            "LastTimestamp",
+        ],
+        &rows,
+    )?;
+    Ok(())
+}
+
+fn frame_types(codec: &VideoCodecSummary) -> String {
+    let counts: Vec<String> = codec
+        .frame_types
+        .iter()
+        .map(|(frame_type, count)| format!("{:?} {}", frame_type, count))
+        .collect();
+    counts.join(", ")
+}
+
+fn write_video_summary(out: &mut dyn Write, summary: &VideoSummary) -> Result<(), Exception> {
+    if summary.codecs.is_empty() {
+        return Ok(());
+    }
+    let rows: Vec<Vec<String>> = summary
+        .codecs
+        .iter()
+        .map(|codec| {
+            let sps = codec.sps.as_ref();
+            let bitrate = codec.stream.bitrate();
+            vec![
+                format!("{:?}", codec.codec_id),
+                or_dash(sps.map(|sps| format!("{}x{}", sps.width, sps.height))),
+                or_dash(sps.map(|sps| profile_text(sps.profile))),
+                or_dash(sps.map(|sps| avc::level_name(sps.level))),
+                codec.keyframes.to_string(),
+                or_dash(bitrate.as_ref().map(|b| format!("{:.1}", b.average))),
+                or_dash(bitrate.as_ref().map(|b| format!("{:.1}", b.peak))),
+                or_dash(
+                    codec
+                        .frame_intervals
+                        .frame_rate()
+                        .map(|frame_rate| format!("{:.2}", frame_rate.average)),
+                ),
+                frame_types(codec),
+            ]
+        })
+        .collect();
+    writeln!(out, "{}", SEPARATOR)?;
+    writeln!(out, "VideoSummary:")?;
+    write_table(
+        out,
+        &[
+            "Codec",
+            "Resolution",
+            "Profile",
+            "Level",
+            "Keyframes",
+            "Kbps",
+            "PeakKbps",
+            "Fps",
+            "Frames",
+        ],
+        &rows,
+    )?;
+    Ok(())
+}
+
+fn write_composition_times(out: &mut dyn Write, stats: &Stats) -> Result<(), Exception> {
+    let composition = &stats.composition_times;
+    let (min, max, mean) = match (composition.min, composition.max, composition.mean()) {
+        (Some(min), Some(max), Some(mean)) => (min, max, mean),
+        _ => return Ok(()),
+    };
+    writeln!(
+        out,
+        "CompositionTime: min {} ms, max {} ms, mean {:.1} ms",
+        min, max, mean
+    )?;
+    let histogram: Vec<_> = composition
+        .histogram
+        .iter()
+        .map(|(offset, count)| format!("{} ms x{}", offset, count))
+        .collect();
+    writeln!(out, "CompositionTimes: {}", histogram.join(", "))?;
+    if let Some(delay) = composition.reorder_delay() {
+        let frames = stats
+            .frame_intervals
+            .frame_rate()
+            .and_then(|frame_rate| composition.reorder_frames(frame_rate.mode_interval));
+        match frames {
+            Some(frames) => writeln!(out, "ReorderDelay: {} ms ({:.1} frames)", delay, frames)?,
+            None => writeln!(out, "ReorderDelay: {} ms", delay)?,
+        }
+    }
+    Ok(())
+}
+
+fn write_timestamps(out: &mut dyn Write, timestamps: &StreamTimestamps) -> Result<(), Exception> {
+    let streams = timestamps.streams();
+    if streams.is_empty() {
+        return Ok(());
+    }
+    let rows: Vec<Vec<String>> = streams
+        .iter()
+        .map(|(name, range)| {
+            vec![
+                name.to_string(),
+                range.first.to_string(),
+                range.last.to_string(),
+                format!("{:+}", timestamps.offset(range)),
+            ]
+        })
+        .collect();
+    writeln!(out, "{}", SEPARATOR)?;
+    writeln!(out, "StreamTimestamps:")?;
+    write_table(out, &["Stream", "First", "Last", "Offset"], &rows)?;
+    if let Some(offset) = timestamps.audio_video_offset() {
+        writeln!(out, "AudioVideoOffset: {:+} ms", offset)?;
+    }
+    Ok(())
+}
+
+fn tag_type_name(tag_type: TagType) -> &'static str {
+    match tag_type {
+        TagType::Audio => "Audio",
+        TagType::Video => "Video",
+        TagType::Script => "Script",
+        TagType::Reserved(_) => "Reserved",
+    }
+}
+
+pub fn header_json(file: &FileInfo) -> Map<String, Value> {
+    let mut object = Map::new();
+    object.insert("file".into(), json!(file.path));
+    object.insert("file_size".into(), json!(file.size));
+    object.insert("version".into(), json!(file.header.version));
+    object.insert("type".into(), json!(file.header.type_));
+    object.insert("has_audio".into(), json!(file.header.has_audio()));
+    object.insert("has_video".into(), json!(file.header.has_video()));
+    object.insert("data_offset".into(), json!(file.header.offset));
+    if !file.header.extra.is_empty() {
+        let hex: String = file
+            .header
+            .extra
+            .iter()
+            .map(|b| format!("{:02x}", b))
+            .collect();
+        object.insert("extra_header_data".into(), json!(hex));
+    }
+    object
+}
+
+pub fn tag_json(index: usize, previous_tag_size: Option<u32>, tag: &Tag) -> Map<String, Value> {
+    let mut object = Map::new();
+    object.insert("index".into(), json!(index));
+    object.insert("tag_type".into(), json!(tag_type_name(tag.header.tag_type)));
+    if let TagType::Reserved(n) = tag.header.tag_type {
+        object.insert("tag_type_id".into(), json!(n));
+    }
+    object.insert("data_size".into(), json!(tag.header.data_size));
+    object.insert("timestamp".into(), json!(tag.header.timestamp));
+    if let Some(received) = tag.received {
+        object.insert("wallclock".into(), json!(time::format_utc(received)));
+    }
+    object.insert("previous_tag_size".into(), json!(previous_tag_size));
+    match &tag.data {
+        TagData::Audio(audio) => {
+            let header = &audio.header;
+            object.insert(
+                "sound_format".into(),
+                json!(format!("{:?}", header.sound_format)),
+            );
+            object.insert(
+                "sound_rate".into(),
+                json!(format!("{:?}", header.sound_rate)),
+            );
+            object.insert(
+                "sound_size".into(),
+                json!(format!("{:?}", header.sound_size)),
+            );
+            object.insert(
+                "sound_type".into(),
+                json!(format!("{:?}", header.sound_type)),
+            );
+            if let Some(packet_type) = audio.aac_packet_type() {
+                object.insert(
+                    "aac_packet_type".into(),
+                    json!(format!("{:?}", packet_type)),
+                );
+            }
+            match aac_config(audio) {
+                Some(Ok(config)) => {
+                    let config = json!({
+                        "object_type": config.object_type,
+                        "object_type_name": aac::object_type_name(config.object_type),
+                        "sampling_frequency": config.sampling_frequency,
+                        "extension_sampling_frequency": config.extension_sampling_frequency,
+                        "channel_configuration": config.channel_configuration,
+                    });
+                    object.insert("aac_config".into(), config);
+                }
+                Some(Err(e)) => {
+                    object.insert("config_error".into(), json!(e.to_string()));
+                }
+                None => {}
+            }
+            if let Some((sample_rate, frames)) = codec_frames(audio) {
+                object.insert("sample_rate".into(), json!(sample_rate));
+                match frames {
+                    Ok(frames) => object.insert("frames".into(), json!(frames)),
+                    Err(e) => object.insert("frame_error".into(), json!(e.to_string())),
+                };
+            }
+            if let Some((sample_rate, duration)) = sized_duration(audio) {
+                object.insert("sample_rate".into(), json!(sample_rate));
+                object.insert("duration".into(), json!(duration));
+            }
+        }
+        TagData::Video(video) => {
+            let header = &video.header;
+            object.insert(
+                "frame_type".into(),
+                json!(format!("{:?}", header.frame_type)),
+            );
+            object.insert("codec_id".into(), json!(format!("{:?}", header.codec_id)));
+            if let Some(packet_type) = video.avc_packet_type() {
+                object.insert(
+                    "avc_packet_type".into(),
+                    json!(format!("{:?}", packet_type)),
+                );
+            }
+            if let Some(composition_time) = video.composition_time() {
+                object.insert("composition_time".into(), json!(composition_time));
+            }
+            match avc_config(video) {
+                Some(Ok(sps)) => {
+                    let config = json!({
+                        "profile": sps.profile,
+                        "profile_name": avc::profile_name(sps.profile),
+                        "level": avc::level_name(sps.level),
+                        "width": sps.width,
+                        "height": sps.height,
+                        "chroma_format": chroma_format_text(sps.chroma_format),
+                        "bit_depth": sps.bit_depth,
+                    });
+                    object.insert("avc_config".into(), config);
+                }
+                Some(Err(e)) => {
+                    object.insert("config_error".into(), json!(e.to_string()));
+                }
+                None => {}
+            }
+        }
+        TagData::Invalid(invalid) => {
+            object.insert("error".into(), json!(invalid.error));
+        }
+        TagData::Script(script) => {
+            let values = script.values();
+            if let Some(event) = ScriptEvent::from_values(&values) {
+                let mut fields = Map::new();
+                fields.insert("name".into(), json!(event.name()));
+                fields.extend(
+                    event
+                        .fields()
+                        .into_iter()
+                        .map(|(name, value)| (name.to_owned(), value)),
+                );
+                object.insert("event".into(), Value::Object(fields));
+            }
+            let values: Vec<Value> = values.into_iter().map(Value::from).collect();
+            object.insert("script".into(), Value::Array(values));
+        }
+        TagData::Reserved(_) => {}
+    }
+    object
+}
+
+fn stream_stats_json(stream: &StreamStats) -> Value {
+    let mut object = Map::new();
+    object.insert("tags".into(), json!(stream.tags));
+    object.insert("bytes".into(), json!(stream.bytes));
+    if let Some(bitrate) = stream.bitrate() {
+        object.insert("average_bitrate".into(), json!(bitrate.average));
+        object.insert("bitrate_std_dev".into(), json!(bitrate.std_dev));
+        object.insert("bitrate_variation".into(), json!(bitrate.variation()));
+        object.insert("peak_bitrate".into(), json!(bitrate.peak));
+        object.insert("peak_to_average".into(), json!(bitrate.peak_to_average()));
+        object.insert(
+            "bitrate_mode".into(),
+            json!(format!("{:?}", bitrate.mode())),
+        );
+    }
+    Value::Object(object)
+}
+
+fn frame_rate_json(stats: &Stats) -> Value {
+    let mut object = Map::new();
+    if let Some(frame_rate) = stats.frame_intervals.frame_rate() {
+        object.insert("average".into(), json!(frame_rate.average));
+        object.insert("mode".into(), json!(frame_rate.mode()));
+        object.insert("mode_interval".into(), json!(frame_rate.mode_interval));
+        object.insert("mode_share".into(), json!(frame_rate.mode_share));
+    }
+    if let Some(cadence) = stats.frame_intervals.cadence() {
+        let dominant: Vec<Value> = cadence
+            .dominant
+            .iter()
+            .map(|(interval, share)| json!({ "interval": interval, "share": share }))
+            .collect();
+        object.insert(
+            "type".into(),
+            json!(if cadence.constant { "cfr" } else { "vfr" }),
+        );
+        object.insert("variable_share".into(), json!(cadence.variable_share));
+        object.insert("dominant_intervals".into(), Value::Array(dominant));
+    }
+    let intervals: Map<String, Value> = stats
+        .frame_intervals
+        .intervals
+        .iter()
+        .map(|(interval, count)| (interval.to_string(), json!(count)))
+        .collect();
+    object.insert("intervals".into(), Value::Object(intervals));
+    object.insert("metadata".into(), json!(stats.metadata_frame_rate));
+    object.insert(
+        "matches_metadata".into(),
+        json!(stats.frame_rate_matches_metadata()),
+    );
+    Value::Object(object)
+}
+
+fn audio_continuity_json(continuity: &AudioContinuity) -> Value {
+    let minutes: Vec<Value> = continuity
+        .minutes
+        .iter()
+        .map(|(minute, stats)| {
+            json!({
+                "minute": minute,
+                "missing_frames": stats.missing_frames,
+                "overlapping_frames": stats.overlapping_frames,
+                "drift": stats.drift,
+            })
+        })
+        .collect();
+    json!({
+        "frame_duration": continuity.frame_duration,
+        "missing_frames": continuity.missing_frames(),
+        "overlapping_frames": continuity.overlapping_frames(),
+        "drift": continuity.drift(),
+        "minutes": minutes,
+    })
+}
+
+pub fn finding_json(finding: &Finding) -> Value {
+    json!({
+        "rule": finding.rule.id(),
+        "severity": finding.severity().name(),
+        "message": finding.message,
+    })
+}
+
+fn duplicate_json(count: DuplicateCount) -> Value {
+    json!({ "tags": count.tags, "bytes": count.bytes })
+}
+
+pub fn stats_json(stats: &Stats) -> Value {
+    let mut audio = stream_stats_json(&stats.audio);
+    audio["continuity"] = audio_continuity_json(&stats.audio_continuity);
+    let codecs: Vec<Value> = stats
+        .audio_summary
+        .codecs
+        .iter()
+        .map(|codec| {
+            json!({
+                "sound_format": format!("{:?}", codec.sound_format),
+                "header_sample_rate": codec.header_sample_rate,
+                "codec_sample_rate": codec.codec_sample_rate,
+                "header_channels": codec.header_channels,
+                "codec_channels": codec.codec_channels,
+                "tags": codec.tags,
+                "bytes": codec.bytes,
+                "average_bitrate": codec.average_bitrate(),
+                "first_timestamp": codec.first_timestamp,
+                "last_timestamp": codec.last_timestamp,
+            })
+        })
+        .collect();
+    audio["codecs"] = Value::Array(codecs);
+    let pcm = &stats.pcm_endianness;
+    if pcm.is_pcm() {
+        audio["pcm"] = json!({
+            "platform_endian_tags": pcm.platform_endian_tags,
+            "little_endian_tags": pcm.little_endian_tags,
+            "little_endian_data": pcm.little_endian_data,
+            "big_endian_data": pcm.big_endian_data,
+            "consistent": pcm.is_consistent(),
+        });
+    }
+    let mut video = stream_stats_json(&stats.video);
+    video["frame_rate"] = frame_rate_json(stats);
+    video["frame_drops"] = json!(stats.frame_drops().map(|report| {
+        let drops: Vec<Value> = report
+            .drops
+            .iter()
+            .map(|drop| json!({ "after": drop.after, "frames": drop.frames }))
+            .collect();
+        let minutes: Vec<Value> = report
+            .minutes
+            .iter()
+            .map(|(minute, frames)| json!({ "minute": minute, "dropped_frames": frames }))
+            .collect();
+        json!({
+            "nominal_interval": report.nominal_interval,
+            "dropped_frames": report.total(),
+            "drops": drops,
+            "minutes": minutes,
+        })
+    }));
+    let composition = &stats.composition_times;
+    let histogram: Map<String, Value> = composition
+        .histogram
+        .iter()
+        .map(|(offset, count)| (offset.to_string(), json!(count)))
+        .collect();
+    let mode_interval = stats
+        .frame_intervals
+        .frame_rate()
+        .map(|frame_rate| frame_rate.mode_interval);
+    video["composition_time"] = json!({
+        "min": composition.min,
+        "max": composition.max,
+        "mean": composition.mean(),
+        "histogram": histogram,
+        "reorder_delay": composition.reorder_delay(),
+        "reorder_frames": mode_interval.and_then(|interval| composition.reorder_frames(interval)),
+    });
+    let codecs: Vec<Value> = stats
+        .video_summary
+        .codecs
+        .iter()
+        .map(|codec| {
+            let mut object = Map::new();
+            object.insert("codec_id".into(), json!(format!("{:?}", codec.codec_id)));
+            if let Some(sps) = &codec.sps {
+                object.insert("width".into(), json!(sps.width));
+                object.insert("height".into(), json!(sps.height));
+                object.insert("profile".into(), json!(sps.profile));
+                object.insert("profile_name".into(), json!(avc::profile_name(sps.profile)));
+                object.insert("level".into(), json!(avc::level_name(sps.level)));
+            }
+            let frame_types: Map<String, Value> = codec
+                .frame_types
+                .iter()
+                .map(|(frame_type, count)| (format!("{:?}", frame_type), json!(count)))
+                .collect();
+            object.insert("frames".into(), Value::Object(frame_types));
+            object.insert("keyframes".into(), json!(codec.keyframes));
+            let bitrate = codec.stream.bitrate();
+            object.insert(
+                "average_bitrate".into(),
+                json!(bitrate.as_ref().map(|b| b.average)),
+            );
+            object.insert("peak_bitrate".into(), json!(bitrate.map(|b| b.peak)));
+            object.insert(
+                "frame_rate".into(),
+                json!(codec.frame_intervals.frame_rate().map(|f| f.average)),
+            );
+            Value::Object(object)
+        })
+        .collect();
+    video["codecs"] = Value::Array(codecs);
+    let timestamps: Map<String, Value> = stats
+        .timestamps
+        .streams()
+        .iter()
+        .map(|(name, range)| {
+            let range = json!({
+                "first": range.first,
+                "last": range.last,
+                "offset": stats.timestamps.offset(range),
+            });
+            (name.to_lowercase(), range)
+        })
+        .collect();
+    json!({
+        "audio": audio,
+        "video": video,
+        "timestamps": timestamps,
+        "audio_video_offset": stats.timestamps.audio_video_offset(),
+        "frames_before_sequence_header": {
+            "aac": stats.early_frames.aac(),
+            "avc": stats.early_frames.avc(),
+        },
+        "duplicates": {
+            "audio": duplicate_json(stats.duplicates.audio),
+            "video": duplicate_json(stats.duplicates.video),
+            "script": duplicate_json(stats.duplicates.script),
+        },
+        "keyframe_map": keyframe_map_json(&stats.keyframe_map),
+        "script_events": stats.script_events,
+        "seekability": Score::new(stats).map(|score| score_json(&score)),
+        "encoder": stats.fingerprint.identify().map(|identification| json!({
+            "software": identification.software,
+            "confidence": identification.confidence.map(Confidence::name),
+            "relay": identification.relay,
+            "evidence": identification.evidence,
+        })),
+    })
+}
+
+fn score_json(score: &Score) -> Value {
+    let factors: Vec<Value> = score
+        .factors
+        .iter()
+        .map(|factor| {
+            json!({
+                "name": factor.name,
+                "score": factor.score,
+                "weight": factor.weight,
+                "detail": factor.detail,
+            })
+        })
+        .collect();
+    json!({ "score": score.total, "factors": factors })
+}
+
+fn keyframe_map_json(map: &KeyframeMap) -> Value {
+    let seconds: Vec<Value> = map
+        .seconds()
+        .into_iter()
+        .map(|keyframe| match keyframe {
+            Some((timestamp, offset)) => json!({ "timestamp": timestamp, "offset": offset }),
+            None => Value::Null,
+        })
+        .collect();
+    json!({ "start": map.start(), "seconds": seconds })
+}
+
+/// Streams a single JSON object per file, `tags` being written as they are decoded.
+#[derive(Default)]
+pub struct Json {
+    previous_tag_size: Option<u32>,
+    tags: usize,
+    stats: Option<Value>,
+    warnings: Vec<Value>,
+}
+
+impl Formatter for Json {
+    fn header(&mut self, out: &mut dyn Write, file: &FileInfo) -> Result<(), Exception> {
+        let header = serde_json::to_string(&header_json(file))?;
+        // leave the object open for the tags
+        write!(out, "{}", &header[..header.len() - 1])?;
+        Ok(())
+    }
+
+    fn pre_tag_size(&mut self, _: &mut dyn Write, _: usize, size: u32) -> Result<(), Exception> {
+        self.previous_tag_size = Some(size);
+        Ok(())
+    }
+
+    fn tag(&mut self, out: &mut dyn Write, index: usize, tag: &Tag) -> Result<(), Exception> {
+        out.write_all(if self.tags == 0 { b",\"tags\":[" } else { b"," })?;
+        serde_json::to_writer(&mut *out, &tag_json(index, self.previous_tag_size, tag))?;
+        self.tags += 1;
+        Ok(())
+    }
+
+    fn stats(&mut self, _: &mut dyn Write, stats: &Stats) -> Result<(), Exception> {
+        self.stats = Some(stats_json(stats));
+        Ok(())
+    }
+
+    fn warning(&mut self, _: &mut dyn Write, finding: &Finding) -> Result<(), Exception> {
+        self.warnings.push(finding_json(finding));
+        Ok(())
+    }
+
+    fn finish(&mut self, out: &mut dyn Write) -> Result<(), Exception> {
+        if self.tags > 0 {
+            out.write_all(b"]")?;
+        }
+        if let Some(stats) = &self.stats {
+            write!(out, ",\"stats\":{}", stats)?;
+        }
+        if !self.warnings.is_empty() {
+            write!(out, ",\"warnings\":{}", json!(self.warnings))?;
+        }
+        writeln!(out, "}}")?;
+        Ok(())
+    }
+}
+
+/// The JSON document in CBOR: a self-described map of indefinite length per file.
+#[derive(Default)]
+pub struct Cbor {
+    previous_tag_size: Option<u32>,
+    tags: usize,
+    stats: Option<Value>,
+    warnings: Vec<Value>,
+}
+
+impl Formatter for Cbor {
+    fn header(&mut self, out: &mut dyn Write, file: &FileInfo) -> Result<(), Exception> {
+        out.write_all(&cbor::SELF_DESCRIBED)?;
+        out.write_all(&[cbor::MAP_START])?;
+        for (key, value) in header_json(file) {
+            cbor::write_str(out, &key)?;
+            cbor::write_value(out, &value)?;
+        }
+        Ok(())
+    }
+
+    fn pre_tag_size(&mut self, _: &mut dyn Write, _: usize, size: u32) -> Result<(), Exception> {
+        self.previous_tag_size = Some(size);
+        Ok(())
+    }
+
+    fn tag(&mut self, out: &mut dyn Write, index: usize, tag: &Tag) -> Result<(), Exception> {
+        if self.tags == 0 {
+            cbor::write_str(out, "tags")?;
+            out.write_all(&[cbor::ARRAY_START])?;
+        }
+        let object = Value::Object(tag_json(index, self.previous_tag_size, tag));
+        cbor::write_value(out, &object)?;
+        self.tags += 1;
+        Ok(())
+    }
+
+    fn stats(&mut self, _: &mut dyn Write, stats: &Stats) -> Result<(), Exception> {
+        self.stats = Some(stats_json(stats));
+        Ok(())
+    }
+
+    fn warning(&mut self, _: &mut dyn Write, finding: &Finding) -> Result<(), Exception> {
+        self.warnings.push(finding_json(finding));
+        Ok(())
+    }
+
+    fn finish(&mut self, out: &mut dyn Write) -> Result<(), Exception> {
+        if self.tags > 0 {
+            out.write_all(&[cbor::BREAK])?;
+        }
+        if let Some(stats) = &self.stats {
+            cbor::write_str(out, "stats")?;
+            cbor::write_value(out, stats)?;
+        }
+        if !self.warnings.is_empty() {
+            cbor::write_str(out, "warnings")?;
+            cbor::write_value(out, &json!(self.warnings))?;
+        }
+        out.write_all(&[cbor::BREAK])?;
+        Ok(())
+    }
+}
+
+#[derive(Default)]
+pub struct Ndjson {
+    previous_tag_size: Option<u32>,
+    flush: bool,
+}
+
+impl Ndjson {
+    /// Flushes the output after every record, for consumers that process a live stream as it is
+    /// decoded.
+    pub fn flushing() -> Self {
+        Self {
+            flush: true,
+            ..Self::default()
+        }
+    }
+
+    fn line(
+        &self,
+        out: &mut dyn Write,
+        record: &str,
+        object: Map<String, Value>,
+    ) -> Result<(), Exception> {
+        let mut line = Map::new();
+        line.insert("record".into(), json!(record));
+        line.extend(object);
+        serde_json::to_writer(&mut *out, &line)?;
+        writeln!(out)?;
+        if self.flush {
+            out.flush()?;
+        }
+        Ok(())
+    }
+}
+
+impl Formatter for Ndjson {
+    fn header(&mut self, out: &mut dyn Write, file: &FileInfo) -> Result<(), Exception> {
+        self.line(out, "header", header_json(file))
+    }
+
+    fn pre_tag_size(&mut self, _: &mut dyn Write, _: usize, size: u32) -> Result<(), Exception> {
+        self.previous_tag_size = Some(size);
+        Ok(())
+    }
+
+    fn tag(&mut self, out: &mut dyn Write, index: usize, tag: &Tag) -> Result<(), Exception> {
+        self.line(out, "tag", tag_json(index, self.previous_tag_size, tag))
+    }
+
+    fn stats(&mut self, out: &mut dyn Write, stats: &Stats) -> Result<(), Exception> {
+        let mut object = Map::new();
+        object.insert("stats".into(), stats_json(stats));
+        self.line(out, "stats", object)
+    }
+
+    fn warning(&mut self, out: &mut dyn Write, finding: &Finding) -> Result<(), Exception> {
+        let mut object = Map::new();
+        object.insert("rule".into(), json!(finding.rule.id()));
+        object.insert("severity".into(), json!(finding.severity().name()));
+        object.insert("message".into(), json!(finding.message));
+        self.line(out, "warning", object)
+    }
+
+    fn finish(&mut self, _: &mut dyn Write) -> Result<(), Exception> {
+        Ok(())
+    }
+}
+
+pub const CSV_COLUMNS: [&str; 15] = [
+    "index",
+    "tag_type",
+    "data_size",
+    "timestamp",
+    "previous_tag_size",
+    "sound_format",
+    "sound_rate",
+    "sound_size",
+    "sound_type",
+    "aac_packet_type",
+    "frame_type",
+    "codec_id",
+    "avc_packet_type",
+    "composition_time",
+    "error",
+];
+
+// Quotes a value if it contains a separator, a quote or a line break (RFC 4180).
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_owned()
+    }
+}
+
+#[derive(Default)]
+pub struct Csv {
+    previous_tag_size: Option<u32>,
+}
+
+impl Formatter for Csv {
+    fn header(&mut self, out: &mut dyn Write, _: &FileInfo) -> Result<(), Exception> {
+        writeln!(out, "{}", CSV_COLUMNS.join(","))?;
+        Ok(())
+    }
+
+    fn pre_tag_size(&mut self, _: &mut dyn Write, _: usize, size: u32) -> Result<(), Exception> {
+        self.previous_tag_size = Some(size);
+        Ok(())
+    }
+
+    fn tag(&mut self, out: &mut dyn Write, index: usize, tag: &Tag) -> Result<(), Exception> {
+        // Reuse the JSON view so both exports always agree on the values.
+        let object = tag_json(index, self.previous_tag_size, tag);
+        let row: Vec<String> = CSV_COLUMNS
+            .iter()
+            .map(|column| match object.get(*column) {
+                Some(Value::String(s)) => csv_field(s),
+                Some(Value::Null) | None => String::new(),
+                Some(value) => value.to_string(),
+            })
+            .collect();
+        writeln!(out, "{}", row.join(","))?;
+        Ok(())
+    }
+
+    fn stats(&mut self, _: &mut dyn Write, _: &Stats) -> Result<(), Exception> {
+        Err("Statistics can not be exported as CSV".into())
+    }
+
+    // Rows all share the same columns, warnings go to stderr instead.
+    fn warning(&mut self, _: &mut dyn Write, finding: &Finding) -> Result<(), Exception> {
+        eprintln!("{}", finding);
+        Ok(())
+    }
+
+    fn finish(&mut self, _: &mut dyn Write) -> Result<(), Exception> {
+        Ok(())
+    }
+}
+
+// Short placeholder names accepted by templates besides the CSV column names.
+const TEMPLATE_ALIASES: [(&str, &str); 4] = [
+    ("type", "tag_type"),
+    ("ts", "timestamp"),
+    ("size", "data_size"),
+    ("cts", "composition_time"),
+];
+
+enum Piece {
+    Literal(String),
+    Field(&'static str),
+}
+
+/// One line per tag, rendered from a template such as `{index}\t{type}\t{ts}\t{size}`.
+/// Placeholders are the CSV column names and their short aliases, `{{` and `}}` are literal
+/// braces, and `\t`, `\n` and `\\` are unescaped.
+pub struct Template {
+    pieces: Vec<Piece>,
+    previous_tag_size: Option<u32>,
+}
+
+impl Template {
+    pub fn parse(template: &str) -> Result<Self, Exception> {
+        let mut pieces = Vec::new();
+        let mut literal = String::new();
+        let mut chars = template.chars();
+        while let Some(c) = chars.next() {
+            match c {
+                '\\' => match chars.next() {
+                    Some('t') => literal.push('\t'),
+                    Some('n') => literal.push('\n'),
+                    Some('\\') => literal.push('\\'),
+                    Some(c) => {
+                        literal.push('\\');
+                        literal.push(c);
+                    }
+                    None => literal.push('\\'),
+                },
+                '{' if chars.as_str().starts_with('{') => {
+                    chars.next();
+                    literal.push('{');
+                }
+                '}' if chars.as_str().starts_with('}') => {
+                    chars.next();
+                    literal.push('}');
+                }
+                '{' => {
+                    let rest = chars.as_str();
+                    let end = rest
+                        .find('}')
+                        .ok_or_else(|| format!("Unclosed placeholder in template: {}", template))?;
+                    let name = &rest[..end];
+                    let field = TEMPLATE_ALIASES
+                        .iter()
+                        .find(|(alias, _)| *alias == name)
+                        .map(|(_, column)| *column)
+                        .or_else(|| {
+                            CSV_COLUMNS
+                                .iter()
+                                .chain(&["tag_type_id"])
+                                .find(|column| **column == name)
+                                .copied()
+                        })
+                        .ok_or_else(|| format!("Unknown template placeholder: {{{}}}", name))?;
+                    if !literal.is_empty() {
+                        pieces.push(Piece::Literal(std::mem::take(&mut literal)));
+                    }
+                    pieces.push(Piece::Field(field));
+                    chars = rest[end + 1..].chars();
+                }
+                c => literal.push(c),
+            }
+        }
+        if !literal.is_empty() {
+            pieces.push(Piece::Literal(literal));
+        }
+
+        Ok(Self {
+            pieces,
+            previous_tag_size: None,
+        })
+    }
+}
+
+impl Formatter for Template {
+    fn header(&mut self, _: &mut dyn Write, _: &FileInfo) -> Result<(), Exception> {
+        Ok(())
+    }
+
+    fn pre_tag_size(&mut self, _: &mut dyn Write, _: usize, size: u32) -> Result<(), Exception> {
+        self.previous_tag_size = Some(size);
+        Ok(())
+    }
+
+    fn tag(&mut self, out: &mut dyn Write, index: usize, tag: &Tag) -> Result<(), Exception> {
+        let object = tag_json(index, self.previous_tag_size, tag);
+        for piece in &self.pieces {
+            match piece {
+                Piece::Literal(literal) => out.write_all(literal.as_bytes())?,
+                Piece::Field(field) => match object.get(*field) {
+                    Some(Value::String(s)) => out.write_all(s.as_bytes())?,
+                    Some(Value::Null) | None => {}
+                    Some(value) => write!(out, "{}", value)?,
+                },
+            }
+        }
+        writeln!(out)?;
+        Ok(())
+    }
+
+    fn stats(&mut self, _: &mut dyn Write, _: &Stats) -> Result<(), Exception> {
+        Err("Statistics can not be printed with a template".into())
+    }
+
+    fn warning(&mut self, _: &mut dyn Write, finding: &Finding) -> Result<(), Exception> {
+        eprintln!("{}", finding);
+        Ok(())
+    }
+
+    fn finish(&mut self, _: &mut dyn Write) -> Result<(), Exception> {
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn amf_text() {
+        let value = AmfValue::EcmaArray(vec![
+            ("title".into(), AmfValue::String("a \"b\"".into())),
+            (
+                "keyframes".into(),
+                AmfValue::Object(vec![(
+                    "times".into(),
+                    AmfValue::StrictArray(vec![AmfValue::Number(0.0), AmfValue::Number(2.5)]),
+                )]),
+            ),
+            (
+                "created".into(),
+                AmfValue::Date {
+                    millis: 0.0,
+                    timezone: 0,
+                },
+            ),
+            ("self".into(), AmfValue::Reference(0)),
+            ("empty".into(), AmfValue::Null),
+        ]);
+        let mut out = Vec::new();
+        write_amf(&mut out, 1, None, &value).unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "  EcmaArray (5)
+    title: \"a \\\"b\\\"\"
+    keyframes: Object
+      times: StrictArray (2)
+        0: 0
+        1: 2.5
+    created: Date 1970-01-01T00:00:00.000Z (time zone 0)
+    self: Reference 0
+    empty: null
+"
+        );
+    }
+}
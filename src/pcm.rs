@@ -0,0 +1,73 @@
+use crate::reader::{AudioDataHeader, SoundFormat, SoundSize};
+
+// Sample data has to differ this many times more between neighbours in one byte order than in the
+// other before a byte order is guessed.
+const ENDIANNESS_RATIO: f64 = 2.0;
+// Fewest samples per channel to guess a byte order from.
+const ENDIANNESS_MIN_SAMPLES: usize = 32;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Endianness {
+    Little,
+    Big,
+}
+
+pub fn is_pcm(format: SoundFormat) -> bool {
+    matches!(
+        format,
+        SoundFormat::LinearPCMPlatformEndian | SoundFormat::LinearPCMLittleEndian
+    )
+}
+
+pub fn sample_rate(header: &AudioDataHeader) -> u32 {
+    header.sound_rate.hz()
+}
+
+fn channels(header: &AudioDataHeader) -> usize {
+    header.sound_type.channels() as usize
+}
+
+fn sample_size(header: &AudioDataHeader) -> usize {
+    match header.sound_size {
+        SoundSize::S8Bit => 1,
+        SoundSize::S16Bit => 2,
+    }
+}
+
+/// Duration in milliseconds of a tag body, from its size, SoundRate, SoundSize and SoundType.
+pub fn duration(header: &AudioDataHeader, data: &[u8]) -> f64 {
+    let frames = data.len() / (sample_size(header) * channels(header));
+    frames as f64 * 1000.0 / sample_rate(header) as f64
+}
+
+/// Guesses the byte order of 16 bit samples: audio changes little from one sample to the next, so
+/// the byte order that gives the smoother signal is the right one. None for 8 bit samples, short
+/// tags and data that is not clear either way, such as silence.
+pub fn guess_endianness(header: &AudioDataHeader, data: &[u8]) -> Option<Endianness> {
+    if header.sound_size != SoundSize::S16Bit {
+        return None;
+    }
+    let channels = channels(header);
+    let samples: Vec<[u8; 2]> = data.chunks_exact(2).map(|s| [s[0], s[1]]).collect();
+    if samples.len() / channels < ENDIANNESS_MIN_SAMPLES {
+        return None;
+    }
+
+    // neighbours are `channels` samples apart in interleaved data
+    let variation = |decode: fn([u8; 2]) -> i16| -> f64 {
+        samples
+            .iter()
+            .zip(&samples[channels..])
+            .map(|(a, b)| (decode(*b) as f64 - decode(*a) as f64).abs())
+            .sum()
+    };
+    let little = variation(i16::from_le_bytes);
+    let big = variation(i16::from_be_bytes);
+    if big >= little * ENDIANNESS_RATIO {
+        Some(Endianness::Little)
+    } else if little >= big * ENDIANNESS_RATIO {
+        Some(Endianness::Big)
+    } else {
+        None
+    }
+}
@@ -0,0 +1,184 @@
+//! Stages of the dump that run concurrently: reading and decoding the input on one task,
+//! parsing and formatting the fields on another, compressing and writing the output on a thread
+//! of its own. They are connected by bounded channels, so a slow stage holds back the others.
+
+use crate::findings::Finding;
+use crate::output::{FileInfo, Formatter, Output};
+use crate::profile::{self, measure, Stage};
+use crate::reader::Tag;
+use crate::stats::Stats;
+use crate::Exception;
+use std::io::{self, Write};
+use std::sync::mpsc::{sync_channel, Receiver, SyncSender};
+use std::thread::{self, JoinHandle};
+use tokio::sync::mpsc;
+use tokio_stream::wrappers::ReceiverStream;
+use tokio_stream::{Stream, StreamExt};
+
+// Fields decoded ahead of the formatter.
+const FIELDS_IN_FLIGHT: usize = 256;
+// Formatted output is handed over in chunks of this size, and this many chunks are in flight.
+const OUTPUT_CHUNK_SIZE: usize = 64 * 1024;
+const OUTPUT_CHUNKS: usize = 4;
+
+/// Polls the stream on a task of its own and passes the items on. The task stops when the
+/// receiver is dropped, having decoded at most `FIELDS_IN_FLIGHT` items too many.
+pub fn spawn_stream<S>(stream: S) -> ReceiverStream<S::Item>
+where
+    S: Stream + Send + 'static,
+    S::Item: Send,
+{
+    let (tx, rx) = mpsc::channel(FIELDS_IN_FLIGHT);
+    tokio::spawn(async move {
+        tokio::pin!(stream);
+        while let Some(item) = stream.next().await {
+            if tx.send(item).await.is_err() {
+                break;
+            }
+        }
+    });
+    ReceiverStream::new(rx)
+}
+
+/// Counts the time of a formatter for the format stage.
+pub struct TimedFormatter(pub Box<dyn Formatter>);
+
+impl Formatter for TimedFormatter {
+    fn header(&mut self, out: &mut dyn Write, file: &FileInfo) -> Result<(), Exception> {
+        measure(Stage::Format, || self.0.header(out, file))
+    }
+
+    fn pre_tag_size(
+        &mut self,
+        out: &mut dyn Write,
+        index: usize,
+        size: u32,
+    ) -> Result<(), Exception> {
+        measure(Stage::Format, || self.0.pre_tag_size(out, index, size))
+    }
+
+    fn tag(&mut self, out: &mut dyn Write, index: usize, tag: &Tag) -> Result<(), Exception> {
+        measure(Stage::Format, || self.0.tag(out, index, tag))
+    }
+
+    fn stats(&mut self, out: &mut dyn Write, stats: &Stats) -> Result<(), Exception> {
+        measure(Stage::Format, || self.0.stats(out, stats))
+    }
+
+    fn warning(&mut self, out: &mut dyn Write, finding: &Finding) -> Result<(), Exception> {
+        measure(Stage::Format, || self.0.warning(out, finding))
+    }
+
+    fn finish(&mut self, out: &mut dyn Write) -> Result<(), Exception> {
+        measure(Stage::Format, || self.0.finish(out))
+    }
+}
+
+enum Chunk {
+    Data(Vec<u8>),
+    Flush,
+}
+
+/// Writes to an `Output` on a separate thread. Writes are buffered and flushing passes the
+/// buffer on without waiting for it to be written. Errors of the thread are returned by the
+/// next write or flush, or by `finish`.
+pub struct OutputThread {
+    buffer: Vec<u8>,
+    chunks: Option<SyncSender<Chunk>>,
+    /// buffers the thread has written out, to be filled again
+    spare: Receiver<Vec<u8>>,
+    thread: Option<JoinHandle<io::Result<()>>>,
+}
+
+impl OutputThread {
+    pub fn spawn(mut output: Output) -> Self {
+        let (tx, rx) = sync_channel(OUTPUT_CHUNKS);
+        let (spare_tx, spare) = sync_channel(OUTPUT_CHUNKS + 1);
+        let thread = thread::spawn(move || {
+            for chunk in rx {
+                match chunk {
+                    Chunk::Data(mut data) => {
+                        profile::measure(Stage::Write, || output.write_all(&data))?;
+                        data.clear();
+                        let _ = spare_tx.try_send(data);
+                    }
+                    Chunk::Flush => profile::measure(Stage::Write, || output.flush())?,
+                }
+            }
+            profile::measure(Stage::Write, || output.finish())
+        });
+        Self {
+            buffer: Vec::with_capacity(OUTPUT_CHUNK_SIZE),
+            chunks: Some(tx),
+            spare,
+            thread: Some(thread),
+        }
+    }
+
+    /// Writes what is left and waits for the output to be finished.
+    pub fn finish(mut self) -> io::Result<()> {
+        self.send_buffer()?;
+        self.join()
+    }
+
+    fn send_buffer(&mut self) -> io::Result<()> {
+        if self.buffer.is_empty() {
+            return Ok(());
+        }
+        let next = self
+            .spare
+            .try_recv()
+            .unwrap_or_else(|_| Vec::with_capacity(OUTPUT_CHUNK_SIZE));
+        let data = std::mem::replace(&mut self.buffer, next);
+        self.send(Chunk::Data(data))
+    }
+
+    fn send(&mut self, chunk: Chunk) -> io::Result<()> {
+        let sent = match &self.chunks {
+            Some(chunks) => chunks.send(chunk).is_ok(),
+            None => false,
+        };
+        if sent {
+            Ok(())
+        } else {
+            // the thread only hangs up on an error
+            self.join()
+                .and(Err(io::Error::other("Output thread has stopped")))
+        }
+    }
+
+    fn join(&mut self) -> io::Result<()> {
+        self.chunks = None;
+        match self.thread.take() {
+            Some(thread) => thread
+                .join()
+                .map_err(|_| io::Error::other("Output thread panicked"))?,
+            None => Err(io::Error::other("Output thread has stopped")),
+        }
+    }
+}
+
+// Whatever was written gets out even when the dump stops with an error, like with a BufWriter.
+impl Drop for OutputThread {
+    fn drop(&mut self) {
+        if self.thread.is_some() {
+            let _ = self.send_buffer();
+            let _ = self.join();
+        }
+    }
+}
+
+impl Write for OutputThread {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        self.buffer.extend_from_slice(buf);
+        if self.buffer.len() >= OUTPUT_CHUNK_SIZE {
+            self.send_buffer()?;
+        }
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.send_buffer()?;
+        self.send(Chunk::Flush)
+    }
+}
@@ -0,0 +1,188 @@
+//! `--profile`: where the time of a dump goes. The stages run concurrently on the input task,
+//! the formatting task and the output thread, so their times may add up to more than the wall
+//! time. Codec-level parsing is not counted again in the stage it is called from.
+
+use std::cell::Cell;
+use std::fmt;
+use std::io;
+use std::pin::Pin;
+use std::sync::atomic::{AtomicBool, AtomicU64, Ordering};
+use std::sync::OnceLock;
+use std::task::{Context, Poll};
+use std::time::{Duration, Instant};
+use tokio::io::{AsyncRead, ReadBuf};
+use tokio_stream::Stream;
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Stage {
+    /// Waiting for and reading the input.
+    Read,
+    /// Splitting the input into tags and decoding their headers and script data, along with the
+    /// checks of the decoder.
+    Decode,
+    /// Parsing audio and video payloads for the statistics and the output: AVC and AAC
+    /// configurations, MP3, Speex and other frames.
+    Codec,
+    /// Printing the fields in the output format.
+    Format,
+    /// Compressing and writing the output.
+    Write,
+}
+
+impl Stage {
+    const ALL: [Stage; 5] = [
+        Stage::Read,
+        Stage::Decode,
+        Stage::Codec,
+        Stage::Format,
+        Stage::Write,
+    ];
+}
+
+static ENABLED: AtomicBool = AtomicBool::new(false);
+static STARTED: OnceLock<Instant> = OnceLock::new();
+// ns per stage, in the order of Stage::ALL
+static NANOS: [AtomicU64; 5] = [
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+    AtomicU64::new(0),
+];
+
+thread_local! {
+    // ns of the measurements nested in the current one
+    static NESTED: Cell<u64> = const { Cell::new(0) };
+}
+
+/// Starts profiling, until then `measure` only runs its function.
+pub fn enable() {
+    STARTED.get_or_init(Instant::now);
+    ENABLED.store(true, Ordering::Relaxed);
+}
+
+fn add(stage: Stage, nanos: u64) {
+    NANOS[stage as usize].fetch_add(nanos, Ordering::Relaxed);
+}
+
+/// Runs the function and counts its time for the stage, except for the time of the measurements
+/// nested in it, which count for their own stage.
+pub fn measure<T>(stage: Stage, f: impl FnOnce() -> T) -> T {
+    if !ENABLED.load(Ordering::Relaxed) {
+        return f();
+    }
+    let outer = NESTED.with(|nested| nested.replace(0));
+    let start = Instant::now();
+    let result = f();
+    let elapsed = start.elapsed().as_nanos() as u64;
+    let nested = NESTED.with(|nested| nested.replace(outer + elapsed));
+    add(stage, elapsed.saturating_sub(nested));
+    result
+}
+
+/// Counts the time from asking the input for data until it arrives for the read stage.
+pub struct TimedInput<R> {
+    input: R,
+    waiting: Option<Instant>,
+    // spent in polls of the read so far, which `measure` has counted already
+    polling: Duration,
+}
+
+impl<R> TimedInput<R> {
+    pub fn new(input: R) -> Self {
+        Self {
+            input,
+            waiting: None,
+            polling: Duration::ZERO,
+        }
+    }
+}
+
+impl<R: AsyncRead + Unpin> AsyncRead for TimedInput<R> {
+    fn poll_read(
+        self: Pin<&mut Self>,
+        cx: &mut Context<'_>,
+        buf: &mut ReadBuf<'_>,
+    ) -> Poll<io::Result<()>> {
+        let this = self.get_mut();
+        let start = *this.waiting.get_or_insert_with(Instant::now);
+        // measured on its own so it does not count for the decode stage polling the input
+        let polled = Instant::now();
+        let poll = measure(Stage::Read, || Pin::new(&mut this.input).poll_read(cx, buf));
+        this.polling += polled.elapsed();
+        if poll.is_ready() {
+            let waited = start
+                .elapsed()
+                .saturating_sub(std::mem::take(&mut this.polling));
+            this.waiting = None;
+            add(Stage::Read, waited.as_nanos() as u64);
+        }
+        poll
+    }
+}
+
+/// Counts the time of polling the decoded fields for the decode stage.
+pub struct TimedStream<S>(pub S);
+
+impl<S: Stream + Unpin> Stream for TimedStream<S> {
+    type Item = S::Item;
+
+    fn poll_next(self: Pin<&mut Self>, cx: &mut Context<'_>) -> Poll<Option<S::Item>> {
+        let stream = &mut self.get_mut().0;
+        measure(Stage::Decode, || Pin::new(stream).poll_next(cx))
+    }
+}
+
+pub struct Report {
+    wall: Duration,
+    stages: Vec<(Stage, Duration)>,
+    /// bytes
+    peak_memory: Option<u64>,
+}
+
+/// The times measured since profiling was enabled.
+pub fn report() -> Report {
+    Report {
+        wall: STARTED.get().map_or(Duration::ZERO, Instant::elapsed),
+        stages: Stage::ALL
+            .iter()
+            .map(|stage| {
+                let nanos = NANOS[*stage as usize].load(Ordering::Relaxed);
+                (*stage, Duration::from_nanos(nanos))
+            })
+            .collect(),
+        peak_memory: peak_memory(),
+    }
+}
+
+// The peak resident set size, only known on Linux.
+fn peak_memory() -> Option<u64> {
+    let status = std::fs::read_to_string("/proc/self/status").ok()?;
+    let line = status.lines().find(|line| line.starts_with("VmHWM:"))?;
+    let kb: u64 = line["VmHWM:".len()..]
+        .trim()
+        .strip_suffix("kB")?
+        .trim()
+        .parse()
+        .ok()?;
+    Some(kb * 1024)
+}
+
+impl fmt::Display for Report {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let wall = self.wall.as_secs_f64();
+        writeln!(f, "WallTime: {:.3} s", wall)?;
+        for (stage, time) in &self.stages {
+            let seconds = time.as_secs_f64();
+            write!(f, "{:?}Time: {:.3} s", stage, seconds)?;
+            if wall > 0.0 {
+                write!(f, " ({:.1}%)", seconds * 100.0 / wall)?;
+            }
+            writeln!(f)?;
+        }
+        match self.peak_memory {
+            Some(bytes) => writeln!(f, "PeakMemory: {:.1} MiB", bytes as f64 / 1024.0 / 1024.0),
+            None => writeln!(f, "PeakMemory: unknown"),
+        }
+    }
+}
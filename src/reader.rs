@@ -1,20 +1,78 @@
+#![allow(clippy::upper_case_acronyms)]
+
+use crate::aac::AacPacketType;
+use crate::amf::LegacyEncoding;
+use crate::avc::AvcPacketType;
+use crate::changes::ParameterChanges;
+use crate::findings::{Finding, Rule, RuleSet};
+use crate::nellymoser;
+use crate::order::SequenceOrder;
+use crate::speex;
 use crate::Exception;
 use bytes::{Buf, Bytes, BytesMut};
+use std::collections::VecDeque;
 use std::convert::TryFrom;
+use std::fmt;
+use std::io::SeekFrom;
+#[cfg(feature = "tokio-fs")]
 use std::path::Path;
+use std::time::SystemTime;
+#[cfg(feature = "tokio-fs")]
 use tokio::fs::File;
 use tokio::io::BufReader;
-use tokio::prelude::*;
+use tokio::io::{AsyncRead, AsyncReadExt, AsyncSeek, AsyncSeekExt};
 use tokio_util::codec::{Decoder, FramedRead};
+use tokio_util::compat::{Compat, FuturesAsyncReadCompatExt};
 
 #[derive(Debug)]
 pub struct Header {
     pub version: u8,
     pub type_: u8,
     pub offset: u32,
+    /// The bytes between the 9 byte header and DataOffset, if it is larger than 9.
+    pub extra: Bytes,
 }
 
-#[derive(Debug, Copy, Clone)]
+pub const HEADER_SIZE: usize = 9;
+// A larger DataOffset is taken for a corrupted header rather than read into memory.
+const MAX_DATA_OFFSET: u32 = 1024 * 1024;
+
+// TypeFlags of the file header
+pub const TYPE_FLAGS_AUDIO: u8 = 0x04;
+pub const TYPE_FLAGS_VIDEO: u8 = 0x01;
+
+impl Header {
+    pub fn has_audio(&self) -> bool {
+        self.type_ & TYPE_FLAGS_AUDIO != 0
+    }
+
+    pub fn has_video(&self) -> bool {
+        self.type_ & TYPE_FLAGS_VIDEO != 0
+    }
+
+    /// Bits of TypeFlags other than TypeFlagsAudio and TypeFlagsVideo, which must be 0.
+    pub fn reserved_type_flags(&self) -> u8 {
+        self.type_ & !(TYPE_FLAGS_AUDIO | TYPE_FLAGS_VIDEO)
+    }
+
+    /// TypeFlags spelled out, e.g. `Audio | Video`.
+    pub fn type_flags(&self) -> String {
+        let mut flags = Vec::new();
+        if self.has_audio() {
+            flags.push("Audio");
+        }
+        if self.has_video() {
+            flags.push("Video");
+        }
+        if flags.is_empty() {
+            "None".to_owned()
+        } else {
+            flags.join(" | ")
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum TagType {
     Audio,        // 8
     Video,        // 9
@@ -27,31 +85,85 @@ pub struct TagHeader {
     pub tag_type: TagType,
     pub data_size: u32,
     pub timestamp: i32, // UI24 + UI8 => SI32
-                        // stream_id: u32, // UI24 always 0
+    // stream_id: u32, // UI24 always 0
+    /// Position of the tag in the input, in bytes.
+    pub offset: u64,
+}
+
+impl TagHeader {
+    /// Parses the 11 bytes of a tag header found at `offset`, returning it with the StreamID.
+    pub fn parse(bytes: &[u8], offset: u64) -> Result<(Self, u32), Exception> {
+        let (tt, s1, s2, s3, t1, t2, t3, t0, stream_id) = match bytes {
+            [tt, s1, s2, s3, t1, t2, t3, t0, i1, i2, i3] => (
+                *tt,
+                *s1,
+                *s2,
+                *s3,
+                *t1,
+                *t2,
+                *t3,
+                *t0,
+                u32::from_be_bytes([0, *i1, *i2, *i3]),
+            ),
+            n => return Err(format!("Invalid tag header: {:?}", n).into()),
+        };
+
+        let tag_type = match tt {
+            8 => TagType::Audio,
+            9 => TagType::Video,
+            18 => TagType::Script,
+            n => TagType::Reserved(n),
+        };
+
+        // UI24 big endian
+        let data_size = u32::from_be_bytes([0, s1, s2, s3]);
+
+        // t0: Extension of the timestamp field to form a SI32 value.
+        // This field represents the upper 8 bits, while the previous timestamp
+        // field represents the lower 24 bits of the time in milliseconds.
+        //
+        // t1~t3: time in milliseconds which the data in this tag applies.
+        // This value is relative to the first tag in the FLV file, which always
+        // has a timestamp of 0.
+        let timestamp = i32::from_be_bytes([t0, t1, t2, t3]);
+
+        let header = TagHeader {
+            tag_type,
+            data_size,
+            timestamp,
+            offset,
+        };
+        Ok((header, stream_id))
+    }
 }
 
 #[derive(Debug)]
 pub struct Tag {
     pub header: TagHeader,
     pub data: TagData,
+    /// The tag header and body as read, if `ParseOptions::keep_raw` is set. It is not updated
+    /// when the tag is modified.
+    pub raw: Option<Bytes>,
+    /// When the tag was read, if `ParseOptions::wallclock` is set.
+    pub received: Option<SystemTime>,
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum SoundFormat {
-    LinearPCMPlatformEndian,
-    ADPCM,
-    MP3,
-    LinearPCMLittleEndian,
-    Nellymoser16,
-    Nellymoser8,
-    Nellymoser,
-    G711ALaw,
-    G711MuLaw,
-    Reserved,
-    AAC,
-    Speex,
-    MP38kHz,
-    DeviceSpecific,
+    LinearPCMPlatformEndian = 0,
+    ADPCM = 1,
+    MP3 = 2,
+    LinearPCMLittleEndian = 3,
+    Nellymoser16 = 4,
+    Nellymoser8 = 5,
+    Nellymoser = 6,
+    G711ALaw = 7,
+    G711MuLaw = 8,
+    Reserved = 9,
+    AAC = 10,
+    Speex = 11,
+    MP38kHz = 14,
+    DeviceSpecific = 15,
 }
 
 impl TryFrom<u8> for SoundFormat {
@@ -79,7 +191,8 @@ impl TryFrom<u8> for SoundFormat {
     }
 }
 
-#[derive(Debug)]
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum SoundRate {
     R5p5kHz = 0,
     R11kHz = 1,
@@ -102,7 +215,18 @@ impl TryFrom<u8> for SoundRate {
     }
 }
 
-#[derive(Debug)]
+impl SoundRate {
+    pub fn hz(self) -> u32 {
+        match self {
+            SoundRate::R5p5kHz => 5512,
+            SoundRate::R11kHz => 11025,
+            SoundRate::R22kHz => 22050,
+            SoundRate::R44kHz => 44100,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum SoundSize {
     S8Bit = 0,
     S16Bit = 1,
@@ -121,7 +245,7 @@ impl TryFrom<u8> for SoundSize {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum SoundType {
     Mono = 0,
     Stereo = 1,
@@ -140,7 +264,16 @@ impl TryFrom<u8> for SoundType {
     }
 }
 
-#[derive(Debug)]
+impl SoundType {
+    pub fn channels(self) -> u8 {
+        match self {
+            SoundType::Mono => 1,
+            SoundType::Stereo => 2,
+        }
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
 pub struct AudioDataHeader {
     pub sound_format: SoundFormat,
     pub sound_rate: SoundRate,
@@ -166,19 +299,39 @@ impl TryFrom<u8> for AudioDataHeader {
     }
 }
 
+impl From<AudioDataHeader> for u8 {
+    fn from(header: AudioDataHeader) -> Self {
+        (header.sound_format as u8) << 4
+            | (header.sound_rate as u8) << 2
+            | (header.sound_size as u8) << 1
+            | header.sound_type as u8
+    }
+}
+
 #[derive(Debug)]
 pub struct AudioData {
     pub header: AudioDataHeader,
     pub data: Bytes,
 }
 
-#[derive(Debug)]
+impl AudioData {
+    /// AACPacketType of an AAC audio tag, None for other formats or an unknown packet type.
+    pub fn aac_packet_type(&self) -> Option<AacPacketType> {
+        if self.header.sound_format != SoundFormat::AAC {
+            return None;
+        }
+        AacPacketType::try_from(*self.data.first()?).ok()
+    }
+}
+
+#[allow(clippy::enum_variant_names)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum VideoFrameType {
-    KeyFrame,
-    InterFrame,
-    DisposableInterFrame,
-    GeneratedKeyFrame,
-    VideoInfoOrCommandFrame,
+    KeyFrame = 1,
+    InterFrame = 2,
+    DisposableInterFrame = 3,
+    GeneratedKeyFrame = 4,
+    VideoInfoOrCommandFrame = 5,
 }
 
 impl TryFrom<u8> for VideoFrameType {
@@ -197,15 +350,15 @@ impl TryFrom<u8> for VideoFrameType {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
 pub enum CodecId {
-    JPEG,
-    SorensonH263,
-    ScreenVideo,
-    On2VP6,
-    On2VP6WithAlpha,
-    ScreenVideoVersion2,
-    AVC,
+    JPEG = 1,
+    SorensonH263 = 2,
+    ScreenVideo = 3,
+    On2VP6 = 4,
+    On2VP6WithAlpha = 5,
+    ScreenVideoVersion2 = 6,
+    AVC = 7,
 }
 
 impl TryFrom<u8> for CodecId {
@@ -226,7 +379,7 @@ impl TryFrom<u8> for CodecId {
     }
 }
 
-#[derive(Debug)]
+#[derive(Debug, Copy, Clone)]
 pub struct VideoDataHeader {
     pub frame_type: VideoFrameType,
     pub codec_id: CodecId,
@@ -246,15 +399,75 @@ impl TryFrom<u8> for VideoDataHeader {
     }
 }
 
+impl From<VideoDataHeader> for u8 {
+    fn from(header: VideoDataHeader) -> Self {
+        (header.frame_type as u8) << 4 | header.codec_id as u8
+    }
+}
+
 #[derive(Debug)]
 pub struct VideoData {
     pub header: VideoDataHeader,
     pub data: Bytes,
 }
 
-#[derive(Debug)]
+impl VideoData {
+    /// AVCPacketType of an AVC video tag, None for other codecs or an unknown packet type.
+    pub fn avc_packet_type(&self) -> Option<AvcPacketType> {
+        if self.header.codec_id != CodecId::AVC {
+            return None;
+        }
+        AvcPacketType::try_from(*self.data.first()?).ok()
+    }
+
+    /// CompositionTime of an AVC NALU: the offset in ms of the presentation time from the tag
+    /// timestamp, which is the decoding time.
+    pub fn composition_time(&self) -> Option<i32> {
+        match &self.data[..] {
+            [1, c1, c2, c3, ..] if self.header.codec_id == CodecId::AVC => {
+                // signed 24 bit
+                Some(i32::from_be_bytes([*c1, *c2, *c3, 0]) >> 8)
+            }
+            _ => None,
+        }
+    }
+}
+
 pub struct ScriptData {
-    raw: Bytes,
+    pub(crate) raw: Bytes,
+    /// how `values()` decodes strings that are not UTF-8
+    pub(crate) legacy_encoding: Option<LegacyEncoding>,
+}
+
+impl ScriptData {
+    /// Script data of the AMF0 values encoded in `raw`, such as a name and an ECMA array.
+    pub fn new(raw: Bytes) -> Self {
+        Self {
+            raw,
+            legacy_encoding: None,
+        }
+    }
+
+    /// The script data as it is in the file.
+    pub fn raw(&self) -> &Bytes {
+        &self.raw
+    }
+}
+
+// The text dump shows the script data as it is in the file.
+impl fmt::Debug for ScriptData {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("ScriptData")
+            .field("raw", &self.raw)
+            .finish()
+    }
+}
+
+/// A tag body that failed to parse, kept as is.
+#[derive(Debug)]
+pub struct InvalidData {
+    pub data: Bytes,
+    pub error: String,
 }
 
 #[derive(Debug)]
@@ -263,133 +476,640 @@ pub enum TagData {
     Video(VideoData),
     Script(ScriptData),
     Reserved(Bytes),
+    Invalid(InvalidData),
+}
+
+impl TagData {
+    // AAC and AVC carry their decoder configuration in a packet of type 0.
+    pub fn is_sequence_header(&self) -> bool {
+        match self {
+            TagData::Audio(audio) => {
+                audio.header.sound_format == SoundFormat::AAC && audio.data.first() == Some(&0)
+            }
+            TagData::Video(video) => {
+                video.header.codec_id == CodecId::AVC && video.data.first() == Some(&0)
+            }
+            _ => false,
+        }
+    }
 }
 
 #[derive(Debug)]
 pub enum Field {
     PreTagSize(u32),
     Tag(Tag),
+    /// A spec violation that was tolerated in lenient mode, reported before the field it
+    /// concerns. Tags whose body fails to parse are reported as `TagData::Invalid` instead.
+    Warning(Finding),
 }
 
-#[derive(Debug)]
+/// How spec violations (a PreviousTagSize that does not match the previous tag, an invalid
+/// audio or video data header, a non-zero StreamID) are handled.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+pub enum ParseMode {
+    /// Abort parsing.
+    Strict,
+    /// Record a warning and carry on.
+    #[default]
+    Lenient,
+}
+
+// Tags larger than this are assumed to be corrupted, unless configured otherwise. DataSize being
+// a UI24, the limit has to stay below 16 MiB to have any effect.
+pub const DEFAULT_MAX_TAG_SIZE: u32 = 8 * 1024 * 1024;
+
+// The capacity tokio uses for BufReader and FramedRead.
+pub const DEFAULT_BUFFER_SIZE: usize = 8 * 1024;
+
+/// How the buffer of a decoder grows while a tag is incomplete.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum BufferGrowth {
+    /// A little at a time as data arrives, for small tags such as live chunks.
+    #[default]
+    Incremental,
+    /// Room for the whole tag at once as soon as its header is known, which saves reallocating
+    /// and copying large keyframes several times.
+    WholeTag,
+}
+
+/// What happens to a tag that is larger than `ParseOptions::max_buffered`.
+#[derive(Debug, Copy, Clone, PartialEq, Eq, Default)]
+#[cfg_attr(feature = "cli", derive(clap::ValueEnum))]
+pub enum Overflow {
+    /// Stop with an error.
+    #[default]
+    Error,
+    /// Discard the tag as it arrives, without buffering it, and report it as a warning.
+    Skip,
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct ParseOptions {
+    pub mode: ParseMode,
+    /// Largest DataSize that is trusted. Larger tags are a violation: lenient mode skips ahead to
+    /// the next plausible tag header instead of buffering the tag.
+    pub max_tag_size: u32,
+    /// Keep the original bytes of every tag in `Tag::raw`, for copying tags without encoding
+    /// them again. They share the buffer the tag was parsed from.
+    pub keep_raw: bool,
+    /// Capacity of the read buffer and the initial capacity of the decoder buffer.
+    pub buffer_size: usize,
+    pub buffer_growth: BufferGrowth,
+    /// Most bytes held in the buffer while a tag is incomplete, whatever its DataSize. Reading
+    /// always waits for the consumer, so this bounds the memory taken by the input.
+    pub max_buffered: Option<usize>,
+    pub overflow: Overflow,
+    /// Record in `Tag::received` when every tag was read, e.g. to correlate a live stream with
+    /// other logs. The decoder runs ahead of the consumer, so this is when the tag arrived.
+    pub wallclock: bool,
+    /// Rules whose findings are dropped, violations of them are not reported even in strict
+    /// mode.
+    pub suppressed: RuleSet,
+    /// Decode AMF strings that are not UTF-8 with this encoding instead of replacing the
+    /// invalid bytes.
+    pub legacy_encoding: Option<LegacyEncoding>,
+}
+
+impl Default for ParseOptions {
+    fn default() -> Self {
+        Self {
+            mode: ParseMode::default(),
+            max_tag_size: DEFAULT_MAX_TAG_SIZE,
+            keep_raw: false,
+            buffer_size: DEFAULT_BUFFER_SIZE,
+            buffer_growth: BufferGrowth::default(),
+            max_buffered: None,
+            overflow: Overflow::default(),
+            wallclock: false,
+            suppressed: RuleSet::default(),
+            legacy_encoding: None,
+        }
+    }
+}
+
+#[derive(Debug, Default)]
 pub enum CodecStatus {
+    #[default]
     PreTagSize,
     Tag,
+    // Looking for the next tag header after corrupted data.
+    Resync {
+        skipped: usize,
+    },
+    // Discarding a tag that is too large to buffer.
+    Skip {
+        remaining: usize,
+    },
 }
 
-impl Default for CodecStatus {
-    fn default() -> Self {
-        Self::PreTagSize
-    }
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum TruncatedPart {
+    /// The PreviousTagSize after tag `Truncation::tag`, PreviousTagSize0 being the first.
+    PreTagSize,
+    TagHeader,
+    TagBody,
 }
 
-#[derive(Debug)]
-pub struct BodyDecoder {
-    status: CodecStatus,
+/// An input that ends in the middle of a field.
+#[derive(Debug, Clone)]
+pub struct Truncation {
+    /// Where the incomplete field starts.
+    pub offset: u64,
+    /// Index of the tag, counted from 1 like in the dump.
+    pub tag: usize,
+    pub part: TruncatedPart,
+    pub present: usize,
+    /// Bytes of the tag (header and body) or of the PreviousTagSize.
+    pub expected: usize,
 }
 
-impl Default for BodyDecoder {
-    fn default() -> Self {
-        Self {
-            status: CodecStatus::default(),
+impl fmt::Display for Truncation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self.part {
+            TruncatedPart::PreTagSize => write!(
+                f,
+                "File truncated at offset {} in PreviousTagSize{}, {} of {} bytes present",
+                self.offset, self.tag, self.present, self.expected
+            ),
+            TruncatedPart::TagHeader => write!(
+                f,
+                "File truncated at offset {} in the header of tag {}, {} of {} bytes present",
+                self.offset, self.tag, self.present, self.expected
+            ),
+            TruncatedPart::TagBody => write!(
+                f,
+                "File truncated at offset {} inside tag {}, {} of {} bytes present",
+                self.offset, self.tag, self.present, self.expected
+            ),
         }
     }
 }
 
+// Outcome of looking for a tag header in corrupted data.
+enum Candidate {
+    // A tag header that is confirmed by the PreviousTagSize following the tag.
+    Found(usize),
+    // A possible tag header that can only be confirmed once more data has arrived.
+    Pending(usize),
+}
+
+#[derive(Debug, Default)]
+pub struct BodyDecoder {
+    status: CodecStatus,
+    options: ParseOptions,
+    // Size of the last tag, which the next PreviousTagSize has to match.
+    last_tag_size: u32,
+    // Position in the input of the next byte to decode.
+    position: u64,
+    // No more data is coming, what is buffered is all there is.
+    eof: bool,
+    // Number of tags decoded.
+    tags: usize,
+    truncation: Option<Truncation>,
+    // None when decoding starts in the middle of the stream, where sequence headers and
+    // onMetaData may lie behind.
+    order: Option<SequenceOrder>,
+    changes: ParameterChanges,
+    pending: VecDeque<Field>,
+}
+
 impl Decoder for BodyDecoder {
     type Item = Field;
     type Error = Exception;
 
     fn decode(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        self.decode_field(src)
+    }
+
+    fn decode_eof(&mut self, src: &mut BytesMut) -> Result<Option<Self::Item>, Self::Error> {
+        self.eof = true;
+        if let Some(field) = self.decode(src)? {
+            return Ok(Some(field));
+        }
+        match self.status {
+            CodecStatus::Resync { skipped } => {
+                let skipped = skipped + src.len();
+                self.position += src.len() as u64;
+                src.clear();
+                self.status = CodecStatus::PreTagSize;
+                let message = format!(
+                    "Skipped {} bytes at the end of the file without finding another tag",
+                    skipped
+                );
+                self.report(Finding::new(Rule::Resync, message));
+                Ok(self.pending.pop_front())
+            }
+            CodecStatus::Skip { remaining } => Err(format!(
+                "{} bytes of a skipped tag missing at the end of the file",
+                remaining
+            )
+            .into()),
+            _ if src.is_empty() => {
+                let findings = self.order.as_mut().map(SequenceOrder::finish);
+                for finding in findings.unwrap_or_default() {
+                    self.violation(finding)?;
+                }
+                Ok(self.pending.pop_front())
+            }
+            _ => {
+                let truncation = self.truncated(src);
+                src.clear();
+                if self.options.mode == ParseMode::Strict {
+                    return Err(truncation.to_string().into());
+                }
+                let message = truncation.to_string();
+                self.truncation = Some(truncation);
+                self.report(Finding::new(Rule::Truncated, message));
+                Ok(self.pending.pop_front())
+            }
+        }
+    }
+}
+
+impl BodyDecoder {
+    pub const PRE_TAG_SIZE_SIZE: usize = 32 / 8;
+    pub const TAG_HEADER_SIZE: usize = (8 + 24 + 24 + 8 + 24) / 8;
+
+    pub fn new(options: ParseOptions) -> Self {
+        Self {
+            options,
+            order: Some(SequenceOrder::default()),
+            ..Self::default()
+        }
+    }
+
+    fn decode_field(&mut self, src: &mut BytesMut) -> Result<Option<Field>, Exception> {
+        if let Some(field) = self.pending.pop_front() {
+            return Ok(Some(field));
+        }
+
         match &self.status {
             CodecStatus::PreTagSize => {
                 if src.len() >= Self::PRE_TAG_SIZE_SIZE {
+                    let pre_tag_size = u32::from_be_bytes([src[0], src[1], src[2], src[3]]);
+                    // A wrong PreviousTagSize followed by something that is no tag header is
+                    // taken for junk, e.g. padding or a partial write, which is skipped.
+                    if pre_tag_size != self.last_tag_size && self.options.mode == ParseMode::Lenient
+                    {
+                        match self.tag_follows(&src[Self::PRE_TAG_SIZE_SIZE..]) {
+                            None => return Ok(None),
+                            Some(false) => {
+                                self.status = CodecStatus::Resync { skipped: 0 };
+                                return self.decode_field(src);
+                            }
+                            Some(true) => {}
+                        }
+                    }
                     self.status = CodecStatus::Tag;
-                    let pre_tag_size = src.get_u32();
-                    Ok(Some(Field::PreTagSize(pre_tag_size)))
+                    src.advance(Self::PRE_TAG_SIZE_SIZE);
+                    self.position += Self::PRE_TAG_SIZE_SIZE as u64;
+                    if pre_tag_size != self.last_tag_size {
+                        self.violation(Finding::new(
+                            Rule::PreTagSize,
+                            format!(
+                                "PreviousTagSize {} does not match the size of the previous tag {}",
+                                pre_tag_size, self.last_tag_size
+                            ),
+                        ))?;
+                    }
+                    self.emit(Field::PreTagSize(pre_tag_size))
                 } else {
                     Ok(None)
                 }
             }
             CodecStatus::Tag => {
-                if src.len() >= Self::TAG_HEADER_SIZE {
-                    match &src[..Self::TAG_HEADER_SIZE] {
-                        [tt, s1, s2, s3, t1, t2, t3, t0, 0, 0, 0] => {
-                            let tag_type = match tt {
-                                8 => TagType::Audio,
-                                9 => TagType::Video,
-                                18 => TagType::Script,
-                                n => TagType::Reserved(*n),
-                            };
-
-                            // UI24 big endian
-                            let data_size = u32::from_be_bytes([0, *s1, *s2, *s3]);
-
-                            // t0: Extension of the timestamp field to form a SI32 value.
-                            // This field represents the upper 8 bits, while the previous timestamp
-                            // field represents the lower 24 bits of the time in milliseconds.
-                            //
-                            // t1~t3: time in milliseconds which the data in this tag applies.
-                            // This value is relative to the first tag in the FLV file, which always
-                            // has a timestamp of 0.
-                            let timestamp = i32::from_be_bytes([*t0, *t1, *t2, *t3]);
-
-                            let header = TagHeader {
-                                tag_type,
-                                data_size,
-                                timestamp,
-                            };
-
-                            if src.len() >= data_size as usize + Self::TAG_HEADER_SIZE {
-                                src.advance(Self::TAG_HEADER_SIZE);
-                                let mut data_bytes = src.split_to(data_size as usize);
-
-                                self.status = CodecStatus::PreTagSize;
-                                match header.tag_type {
-                                    TagType::Audio => Ok(Some(Field::Tag(Tag {
-                                        header,
-                                        data: TagData::Audio(AudioData {
-                                            header: AudioDataHeader::try_from(data_bytes.get_u8())?,
-                                            data: data_bytes.freeze(),
-                                        }),
-                                    }))),
-                                    TagType::Video => Ok(Some(Field::Tag(Tag {
-                                        header,
-                                        data: TagData::Video(VideoData {
-                                            header: VideoDataHeader::try_from(data_bytes.get_u8())?,
-                                            data: data_bytes.freeze(),
-                                        }),
-                                    }))),
-                                    TagType::Script => Ok(Some(Field::Tag(Tag {
-                                        header,
-                                        data: TagData::Script(ScriptData {
-                                            raw: data_bytes.freeze(),
-                                        }),
-                                    }))),
-                                    TagType::Reserved(_) => Ok(Some(Field::Tag(Tag {
-                                        header,
-                                        data: TagData::Reserved(data_bytes.freeze()),
-                                    }))),
-                                }
-                            } else {
-                                Ok(None)
-                            }
+                if src.len() < Self::TAG_HEADER_SIZE {
+                    return Ok(None);
+                }
+
+                let (header, stream_id) =
+                    TagHeader::parse(&src[..Self::TAG_HEADER_SIZE], self.position)?;
+                let data_size = header.data_size;
+
+                if data_size > self.options.max_tag_size {
+                    self.violation(Finding::new(
+                        Rule::TagSize,
+                        format!(
+                            "DataSize {} exceeds the limit of {} bytes",
+                            data_size, self.options.max_tag_size
+                        ),
+                    ))?;
+                    src.advance(1);
+                    self.position += 1;
+                    self.status = CodecStatus::Resync { skipped: 1 };
+                    return Ok(self.pending.pop_front());
+                }
+
+                let tag_size = Self::TAG_HEADER_SIZE + data_size as usize;
+                // An unknown tag type or a non-zero StreamID may as well be junk, the tag is only
+                // accepted if the PreviousTagSize after it matches.
+                if self.options.mode == ParseMode::Lenient
+                    && !self.plausible_header(&src[..Self::TAG_HEADER_SIZE])
+                {
+                    match self.confirmed(src, tag_size) {
+                        None => return Ok(None),
+                        Some(false) => {
+                            self.status = CodecStatus::Resync { skipped: 0 };
+                            return self.decode_field(src);
                         }
-                        n => Err(format!("Invalid tag header: {:?}", n).into()),
+                        Some(true) => {}
                     }
+                }
+                if let Some(max) = self.options.max_buffered.filter(|max| tag_size > *max) {
+                    let message = format!(
+                        "Tag at offset {} is {} bytes, more than the {} bytes that may be buffered",
+                        header.offset, tag_size, max
+                    );
+                    if self.options.overflow == Overflow::Error {
+                        return Err(message.into());
+                    }
+                    self.status = CodecStatus::Skip {
+                        remaining: tag_size,
+                    };
+                    self.last_tag_size = tag_size as u32;
+                    self.report(Finding::new(
+                        Rule::Overflow,
+                        format!("{}, skipped", message),
+                    ));
+                    return self.decode_field(src);
+                }
+                if src.len() < tag_size {
+                    if self.options.buffer_growth == BufferGrowth::WholeTag {
+                        src.reserve(tag_size - src.len());
+                    }
+                    return Ok(None);
+                }
+
+                if stream_id != 0 {
+                    self.violation(Finding::new(
+                        Rule::StreamId,
+                        format!("StreamID is {}, it must be 0", stream_id),
+                    ))?;
+                }
+
+                let raw = src.split_to(tag_size).freeze();
+                let data_bytes = raw.slice(Self::TAG_HEADER_SIZE..);
+                self.position += Self::TAG_HEADER_SIZE as u64 + data_size as u64;
+                self.status = CodecStatus::PreTagSize;
+                self.last_tag_size = Self::TAG_HEADER_SIZE as u32 + data_size;
+
+                let data = match header.tag_type {
+                    TagType::Audio => self.media_data(data_bytes, "audio", |header, data| {
+                        AudioDataHeader::try_from(header)
+                            .map(|header| TagData::Audio(AudioData { header, data }))
+                    })?,
+                    TagType::Video => self.media_data(data_bytes, "video", |header, data| {
+                        VideoDataHeader::try_from(header)
+                            .map(|header| TagData::Video(VideoData { header, data }))
+                    })?,
+                    TagType::Script => TagData::Script(ScriptData {
+                        raw: data_bytes,
+                        legacy_encoding: self.options.legacy_encoding,
+                    }),
+                    TagType::Reserved(_) => TagData::Reserved(data_bytes),
+                };
+
+                if let TagData::Script(script) = &data {
+                    let decoded = script.decode();
+                    for invalid in decoded.invalid_strings {
+                        let message =
+                            format!("Script tag at offset {}: {}", header.offset, invalid);
+                        self.report(Finding::new(Rule::AmfString, message));
+                    }
+                    if let Some(error) = decoded.error {
+                        let message = format!(
+                            "Script tag at offset {}: {}, kept {} values up to there",
+                            header.offset,
+                            error,
+                            decoded.values.len()
+                        );
+                        self.report(Finding::new(Rule::AmfData, message));
+                    }
+                }
+
+                if let TagData::Audio(audio) = &data {
+                    let check = match audio.header.sound_format {
+                        SoundFormat::Speex => speex::check_header(&audio.header),
+                        format if nellymoser::is_nellymoser(format) => {
+                            nellymoser::check_header(&audio.header)
+                        }
+                        _ => Ok(()),
+                    };
+                    if let Err(e) = check {
+                        self.violation(Finding::new(Rule::AudioHeader, e.to_string()))?;
+                    }
+                }
+
+                let raw = if self.options.keep_raw {
+                    Some(raw)
                 } else {
-                    Ok(None)
+                    None
+                };
+                let received = if self.options.wallclock {
+                    Some(SystemTime::now())
+                } else {
+                    None
+                };
+                let tag = Tag {
+                    header,
+                    data,
+                    raw,
+                    received,
+                };
+                self.tags += 1;
+                let strict = self.options.mode == ParseMode::Strict;
+                let index = self.tags;
+                if let Some(finding) = self
+                    .order
+                    .as_mut()
+                    .and_then(|o| o.push(index, &tag, strict))
+                {
+                    self.violation(finding)?;
                 }
+                if let Some(finding) = self.changes.video(index, &tag) {
+                    self.report(finding);
+                }
+                if let Some(finding) = self.changes.audio(index, &tag) {
+                    self.report(finding);
+                }
+                self.emit(Field::Tag(tag))
+            }
+            CodecStatus::Resync { skipped } => {
+                let (position, found) = match self.find_tag(src) {
+                    Candidate::Found(position) => (position, true),
+                    Candidate::Pending(position) => (position, false),
+                };
+                src.advance(position);
+                self.position += position as u64;
+                let skipped = skipped + position;
+                if !found {
+                    self.status = CodecStatus::Resync { skipped };
+                    return Ok(None);
+                }
+                self.status = CodecStatus::Tag;
+                let message = format!(
+                    "Resynchronized after skipping {} bytes at offset {}",
+                    skipped,
+                    self.position - skipped as u64
+                );
+                self.report(Finding::new(Rule::Resync, message));
+                self.decode_field(src)
+            }
+            CodecStatus::Skip { remaining } => {
+                let n = (*remaining).min(src.len());
+                src.advance(n);
+                self.position += n as u64;
+                let remaining = remaining - n;
+                if remaining > 0 {
+                    self.status = CodecStatus::Skip { remaining };
+                    return Ok(None);
+                }
+                self.status = CodecStatus::PreTagSize;
+                self.decode_field(src)
             }
         }
     }
-}
 
-impl BodyDecoder {
-    const PRE_TAG_SIZE_SIZE: usize = 32 / 8;
-    const TAG_HEADER_SIZE: usize = (8 + 24 + 24 + 8 + 24) / 8;
+    // A tag header is an audio, video or script tag type, a size within the limits and a zero
+    // StreamID, followed after DataSize bytes by a PreviousTagSize that matches.
+    fn find_tag(&self, src: &[u8]) -> Candidate {
+        for position in 0..src.len() {
+            let candidate = &src[position..];
+            if candidate.len() < Self::TAG_HEADER_SIZE {
+                return Candidate::Pending(position);
+            }
+            if !self.plausible_header(candidate) {
+                continue;
+            }
+            let data_size = u32::from_be_bytes([0, candidate[1], candidate[2], candidate[3]]);
+            let tag_size = Self::TAG_HEADER_SIZE + data_size as usize;
+            if self.options.max_buffered.is_some_and(|max| tag_size > max) {
+                continue;
+            }
+            match candidate.get(tag_size..tag_size + Self::PRE_TAG_SIZE_SIZE) {
+                Some(pre_tag_size) if pre_tag_size == (tag_size as u32).to_be_bytes() => {
+                    return Candidate::Found(position)
+                }
+                Some(_) => continue,
+                None => return Candidate::Pending(position),
+            }
+        }
+        Candidate::Pending(src.len())
+    }
+
+    // An audio, video or script tag type, a DataSize within the limit and a zero StreamID.
+    fn plausible_header(&self, header: &[u8]) -> bool {
+        let data_size = u32::from_be_bytes([0, header[1], header[2], header[3]]);
+        matches!(header[0], 8 | 9 | 18)
+            && header[8..11] == [0, 0, 0]
+            && data_size <= self.options.max_tag_size
+    }
+
+    // Whether the data starts with a plausible tag header, None until enough data has arrived.
+    // The end of the file counts as a tag boundary.
+    fn tag_follows(&self, src: &[u8]) -> Option<bool> {
+        if src.len() >= Self::TAG_HEADER_SIZE {
+            Some(self.plausible_header(src))
+        } else if self.eof {
+            Some(true)
+        } else {
+            None
+        }
+    }
+
+    // Whether the tag at the start of the data is followed by a matching PreviousTagSize, None
+    // until enough data has arrived. A tag that ends the file or can not be buffered whole is
+    // given the benefit of the doubt.
+    fn confirmed(&self, src: &[u8], tag_size: usize) -> Option<bool> {
+        if self.options.max_buffered.is_some_and(|max| tag_size > max) {
+            return Some(true);
+        }
+        match src.get(tag_size..tag_size + Self::PRE_TAG_SIZE_SIZE) {
+            Some(pre_tag_size) => Some(pre_tag_size == (tag_size as u32).to_be_bytes()),
+            None if self.eof => Some(true),
+            None => None,
+        }
+    }
+
+    fn violation(&mut self, finding: Finding) -> Result<(), Exception> {
+        if self.options.suppressed.contains(finding.rule) {
+            return Ok(());
+        }
+        match self.options.mode {
+            ParseMode::Strict => Err(finding.message.into()),
+            ParseMode::Lenient => {
+                self.report(finding);
+                Ok(())
+            }
+        }
+    }
+
+    // Queues a finding that does not stop parsing even in strict mode, unless it is suppressed.
+    fn report(&mut self, finding: Finding) {
+        if !self.options.suppressed.contains(finding.rule) {
+            self.pending.push_back(Field::Warning(finding));
+        }
+    }
+
+    /// Where the input ended in the middle of a tag, once it has been read to the end. Strict
+    /// mode reports this as an error, lenient mode as a warning.
+    pub fn truncation(&self) -> Option<&Truncation> {
+        self.truncation.as_ref()
+    }
+
+    // Describes the incomplete field left in `src` at the end of the input.
+    fn truncated(&self, src: &[u8]) -> Truncation {
+        let (part, expected) = match self.status {
+            CodecStatus::PreTagSize => (TruncatedPart::PreTagSize, Self::PRE_TAG_SIZE_SIZE),
+            _ if src.len() < Self::TAG_HEADER_SIZE => {
+                (TruncatedPart::TagHeader, Self::TAG_HEADER_SIZE)
+            }
+            _ => (
+                TruncatedPart::TagBody,
+                Self::TAG_HEADER_SIZE + u32::from_be_bytes([0, src[1], src[2], src[3]]) as usize,
+            ),
+        };
+        Truncation {
+            offset: self.position,
+            tag: match part {
+                TruncatedPart::PreTagSize => self.tags,
+                _ => self.tags + 1,
+            },
+            part,
+            present: src.len(),
+            expected,
+        }
+    }
+
+    // Returns the warnings queued while decoding the field first, then the field itself.
+    fn emit(&mut self, field: Field) -> Result<Option<Field>, Exception> {
+        self.pending.push_back(field);
+        Ok(self.pending.pop_front())
+    }
+
+    // Audio and video bodies start with a one byte header. If it can not be parsed, lenient mode
+    // keeps the tag with its error and moves on to the next one.
+    fn media_data<F>(&mut self, data: Bytes, kind: &str, parse: F) -> Result<TagData, Exception>
+    where
+        F: FnOnce(u8, Bytes) -> Result<TagData, Exception>,
+    {
+        let result = match data.first() {
+            Some(header) => parse(*header, data.slice(1..)),
+            None => Err(format!("Empty {} tag", kind).into()),
+        };
+        match (result, self.options.mode) {
+            (Ok(tag_data), _) => Ok(tag_data),
+            (Err(e), ParseMode::Strict) => Err(format!("Invalid {} data: {}", kind, e).into()),
+            (Err(e), ParseMode::Lenient) => Ok(TagData::Invalid(InvalidData {
+                data,
+                error: format!("Invalid {} data: {}", kind, e),
+            })),
+        }
+    }
 }
 
+#[cfg(feature = "tokio-fs")]
 pub async fn open_flv<P: AsRef<Path>>(
     path: P,
 ) -> Result<(u64, Header, FramedRead<BufReader<File>, BodyDecoder>), Exception> {
@@ -397,26 +1117,679 @@ pub async fn open_flv<P: AsRef<Path>>(
 
     let file_size = file.metadata().await?.len();
 
-    let reader = BufReader::new(file);
+    let (header, reader) = read_flv(file, ParseOptions::default()).await?;
+    Ok((file_size, header, reader))
+}
 
-    let mut reader = reader;
-    let mut buf = [0u8; 9];
-    let _len = reader.read_exact(&mut buf).await?;
+pub async fn read_flv<R: AsyncRead + Unpin>(
+    reader: R,
+    options: ParseOptions,
+) -> Result<(Header, FramedRead<BufReader<R>, BodyDecoder>), Exception> {
+    let capacity = options.buffer_size.max(1);
+    let mut reader = BufReader::with_capacity(capacity, reader);
+    let header = read_header(&mut reader).await?;
+    let mut decoder = BodyDecoder::new(options);
+    decoder.position = header.offset.max(HEADER_SIZE as u32) as u64;
+    Ok((header, FramedRead::with_capacity(reader, decoder, capacity)))
+}
 
-    assert_eq!(_len, 9);
+/// Like `read_flv`, for readers implementing `futures::io::AsyncRead`, e.g. under async-std or
+/// smol. The decoder only relies on tokio's I/O traits, not on its runtime.
+pub async fn read_flv_compat<R: futures::io::AsyncRead + Unpin>(
+    reader: R,
+    options: ParseOptions,
+) -> Result<(Header, FramedRead<BufReader<Compat<R>>, BodyDecoder>), Exception> {
+    read_flv(reader.compat(), options).await
+}
 
-    let header = match buf {
-        [b'F', b'L', b'V', version, type_, o1, o2, o3, o4] => {
-            let offset = u32::from_be_bytes([o1, o2, o3, o4]);
-            Header {
-                version,
-                type_,
-                offset,
+/// Opens a local FLV file positioned on its last `count` tags, see `tail_flv`.
+#[cfg(feature = "tokio-fs")]
+pub async fn read_flv_tail<P: AsRef<Path>>(
+    path: P,
+    count: usize,
+    options: ParseOptions,
+) -> Result<(u64, Header, FramedRead<BufReader<File>, BodyDecoder>), Exception> {
+    let file = File::open(path).await?;
+    let file_size = file.metadata().await?.len();
+    let (header, fields) = tail_flv(file, file_size, count, options).await?;
+    Ok((file_size, header, fields))
+}
+
+/// Positions a seekable reader of `file_size` bytes on its last `count` tags, which are located
+/// by walking the PreviousTagSize fields backwards from the end. The decoder starts with a tag.
+pub async fn tail_flv<R: AsyncRead + AsyncSeek + Unpin>(
+    mut file: R,
+    file_size: u64,
+    count: usize,
+    options: ParseOptions,
+) -> Result<(Header, FramedRead<BufReader<R>, BodyDecoder>), Exception> {
+    let header = read_header(&mut file).await?;
+
+    let tag_header_size = BodyDecoder::TAG_HEADER_SIZE as u64;
+    let pre_tag_size_size = BodyDecoder::PRE_TAG_SIZE_SIZE as u64;
+    let first_tag = header.offset.max(HEADER_SIZE as u32) as u64 + pre_tag_size_size;
+
+    // `position` is the start of the earliest tag found so far.
+    let mut position = file_size;
+    let mut found = 0;
+    while found < count && position >= first_tag + tag_header_size + pre_tag_size_size {
+        let pre_tag_size_offset = position - pre_tag_size_size;
+        file.seek(SeekFrom::Start(pre_tag_size_offset)).await?;
+        let tag_size = file.read_u32().await? as u64;
+
+        let start = match pre_tag_size_offset.checked_sub(tag_size) {
+            Some(start) if tag_size >= tag_header_size && start >= first_tag => start,
+            _ => {
+                return Err(format!(
+                    "PreviousTagSize {} at offset {} does not point to a tag",
+                    tag_size, pre_tag_size_offset
+                )
+                .into())
             }
+        };
+
+        let mut tag_header = [0u8; BodyDecoder::TAG_HEADER_SIZE];
+        file.seek(SeekFrom::Start(start)).await?;
+        file.read_exact(&mut tag_header).await?;
+        let data_size = u32::from_be_bytes([0, tag_header[1], tag_header[2], tag_header[3]]);
+        if data_size as u64 + tag_header_size != tag_size {
+            return Err(format!(
+                "PreviousTagSize {} at offset {} does not match the tag at offset {} (DataSize {})",
+                tag_size, pre_tag_size_offset, start, data_size
+            )
+            .into());
+        }
+
+        position = start;
+        found += 1;
+    }
+
+    file.seek(SeekFrom::Start(position)).await?;
+    let mut decoder = BodyDecoder::new(options);
+    decoder.status = CodecStatus::Tag;
+    decoder.position = position;
+    decoder.order = None;
+    Ok((header, framed(file, decoder)))
+}
+
+// Once the bisection has narrowed the position down to this many bytes, tags are walked one by one.
+const SEEK_WINDOW: u64 = 256 * 1024;
+const SEEK_READ_SIZE: usize = 64 * 1024;
+
+/// Opens a local FLV file positioned on the first tag at `time` milliseconds, see `seek_flv`.
+#[cfg(feature = "tokio-fs")]
+pub async fn read_flv_at<P: AsRef<Path>>(
+    path: P,
+    time: i32,
+    options: ParseOptions,
+) -> Result<(u64, Header, FramedRead<BufReader<File>, BodyDecoder>), Exception> {
+    let file = File::open(path).await?;
+    let file_size = file.metadata().await?.len();
+    let (header, fields) = seek_flv(file, file_size, time, options).await?;
+    Ok((file_size, header, fields))
+}
+
+/// Positions a seekable reader of `file_size` bytes on the first tag whose timestamp is at least
+/// `time` milliseconds. The position is found by bisection: jump into the file, resynchronize on
+/// the next tag boundary, read its timestamp and narrow down, so only a few reads are needed even
+/// for very large files. Timestamps are assumed to be increasing. The decoder starts with a tag.
+pub async fn seek_flv<R: AsyncRead + AsyncSeek + Unpin>(
+    mut file: R,
+    file_size: u64,
+    time: i32,
+    options: ParseOptions,
+) -> Result<(Header, FramedRead<BufReader<R>, BodyDecoder>), Exception> {
+    let header = read_header(&mut file).await?;
+    let decoder = BodyDecoder::new(options);
+
+    // `low` is always a tag boundary before the wanted tag, `high` is after it.
+    let mut low =
+        header.offset.max(HEADER_SIZE as u32) as u64 + BodyDecoder::PRE_TAG_SIZE_SIZE as u64;
+    let mut high = file_size;
+    while high.saturating_sub(low) > SEEK_WINDOW {
+        let middle = low + (high - low) / 2;
+        match next_tag(&mut file, middle, file_size, &decoder).await? {
+            Some((offset, timestamp)) if offset < high => {
+                if timestamp < time {
+                    low = offset;
+                } else {
+                    high = offset;
+                }
+            }
+            _ => high = middle,
+        }
+    }
+
+    // Walk the remaining tags up to the first one that is due.
+    let mut tag_header = [0u8; BodyDecoder::TAG_HEADER_SIZE];
+    while low + BodyDecoder::TAG_HEADER_SIZE as u64 <= file_size {
+        file.seek(SeekFrom::Start(low)).await?;
+        file.read_exact(&mut tag_header).await?;
+        if tag_timestamp(&tag_header) >= time {
+            break;
+        }
+        let data_size = u32::from_be_bytes([0, tag_header[1], tag_header[2], tag_header[3]]);
+        low += (BodyDecoder::TAG_HEADER_SIZE + BodyDecoder::PRE_TAG_SIZE_SIZE) as u64
+            + data_size as u64;
+    }
+
+    let position = low.min(file_size);
+    file.seek(SeekFrom::Start(position)).await?;
+    let mut decoder = decoder;
+    decoder.status = CodecStatus::Tag;
+    decoder.position = position;
+    decoder.order = None;
+    Ok((header, framed(file, decoder)))
+}
+
+// Offset and timestamp of the first tag starting at or after `offset`.
+async fn next_tag<R: AsyncRead + AsyncSeek + Unpin>(
+    file: &mut R,
+    mut offset: u64,
+    file_size: u64,
+    decoder: &BodyDecoder,
+) -> Result<Option<(u64, i32)>, Exception> {
+    file.seek(SeekFrom::Start(offset)).await?;
+    let mut buf = Vec::new();
+    loop {
+        let remaining = file_size - offset - buf.len() as u64;
+        if remaining == 0 {
+            return Ok(None);
+        }
+        let start = buf.len();
+        buf.resize(start + (SEEK_READ_SIZE as u64).min(remaining) as usize, 0);
+        file.read_exact(&mut buf[start..]).await?;
+
+        match decoder.find_tag(&buf) {
+            Candidate::Found(position) => {
+                return Ok(Some((
+                    offset + position as u64,
+                    tag_timestamp(&buf[position..]),
+                )))
+            }
+            Candidate::Pending(position) => {
+                buf.drain(..position);
+                offset += position as u64;
+            }
+        }
+    }
+}
+
+fn tag_timestamp(tag_header: &[u8]) -> i32 {
+    i32::from_be_bytes([tag_header[7], tag_header[4], tag_header[5], tag_header[6]])
+}
+
+// The file has to be positioned where the decoder starts.
+fn framed<R: AsyncRead>(file: R, decoder: BodyDecoder) -> FramedRead<BufReader<R>, BodyDecoder> {
+    let capacity = decoder.options.buffer_size.max(1);
+    FramedRead::with_capacity(BufReader::with_capacity(capacity, file), decoder, capacity)
+}
+
+/// Reads the file header, including the bytes up to DataOffset.
+pub async fn read_header<R: AsyncRead + Unpin>(reader: &mut R) -> Result<Header, Exception> {
+    let mut buf = [0u8; HEADER_SIZE];
+    reader.read_exact(&mut buf).await?;
+
+    let (version, type_, offset) = match buf {
+        [b'F', b'L', b'V', version, type_, o1, o2, o3, o4] => {
+            (version, type_, u32::from_be_bytes([o1, o2, o3, o4]))
         }
         _ => return Err("invalid flv file".into()),
     };
+    if offset > MAX_DATA_OFFSET {
+        return Err(format!("DataOffset {} is too large", offset).into());
+    }
 
-    let reader = FramedRead::new(reader, BodyDecoder::default());
-    Ok((file_size, header, reader))
+    let mut extra = vec![0u8; (offset as usize).saturating_sub(HEADER_SIZE)];
+    reader.read_exact(&mut extra).await?;
+    Ok(Header {
+        version,
+        type_,
+        offset,
+        extra: extra.into(),
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use futures::executor::block_on;
+    use std::io::Cursor;
+    use tokio_stream::StreamExt;
+
+    // test.flv with a DataOffset of 0, which `read_flv` treats as 9.
+    fn zero_data_offset() -> Vec<u8> {
+        let mut bytes = std::fs::read("resources/test.flv").unwrap();
+        bytes[5..9].copy_from_slice(&0u32.to_be_bytes());
+        bytes
+    }
+
+    async fn offsets<R: AsyncRead + Unpin>(
+        mut fields: FramedRead<BufReader<R>, BodyDecoder>,
+    ) -> Vec<u64> {
+        let mut offsets = vec![];
+        while let Some(field) = fields.next().await {
+            match field.unwrap() {
+                Field::Tag(tag) => offsets.push(tag.header.offset),
+                Field::Warning(finding) => panic!("{}", finding),
+                Field::PreTagSize(_) => {}
+            }
+        }
+        offsets
+    }
+
+    // An MP3 audio tag, header and body without the PreviousTagSize after it.
+    fn audio_tag(timestamp: i32, body: &[u8]) -> Vec<u8> {
+        let [_, s1, s2, s3] = (body.len() as u32 + 1).to_be_bytes();
+        let [t0, t1, t2, t3] = timestamp.to_be_bytes();
+        let mut tag = vec![8, s1, s2, s3, t1, t2, t3, t0, 0, 0, 0, 0x2f];
+        tag.extend_from_slice(body);
+        tag
+    }
+
+    // A file header and PreviousTagSize0, followed by the parts as they are.
+    fn flv(parts: &[&[u8]]) -> Vec<u8> {
+        let mut bytes = b"FLV\x01\x04\x00\x00\x00\x09\x00\x00\x00\x00".to_vec();
+        for part in parts {
+            bytes.extend_from_slice(part);
+        }
+        bytes
+    }
+
+    fn pre_tag_size(tag: &[u8]) -> [u8; 4] {
+        (tag.len() as u32).to_be_bytes()
+    }
+
+    // The fields decoded from `bytes` in short form, and the error that ended decoding if any.
+    // The input is fed both a byte at a time and in one go, which has to make no difference.
+    fn decode(bytes: &[u8], options: ParseOptions) -> (Vec<String>, Option<String>) {
+        let results: Vec<_> = [1, DEFAULT_BUFFER_SIZE]
+            .iter()
+            .map(|&buffer_size| {
+                let options = ParseOptions {
+                    buffer_size,
+                    ..options
+                };
+                block_on(async {
+                    let (_, mut fields) = read_flv(bytes, options).await.unwrap();
+                    let mut decoded = vec![];
+                    while let Some(field) = fields.next().await {
+                        decoded.push(match field {
+                            Ok(Field::PreTagSize(size)) => format!("PreTagSize {}", size),
+                            Ok(Field::Tag(tag)) => format!("Tag {}", tag.header.offset),
+                            Ok(Field::Warning(finding)) => {
+                                format!("{:?}: {}", finding.rule, finding.message)
+                            }
+                            Err(e) => return (decoded, Some(e.to_string())),
+                        });
+                    }
+                    (decoded, None)
+                })
+            })
+            .collect();
+        assert_eq!(results[0], results[1]);
+        results[0].clone()
+    }
+
+    fn strings(strs: &[&str]) -> Vec<String> {
+        strs.iter().map(|s| s.to_string()).collect()
+    }
+
+    fn avc(packet: &[u8]) -> VideoData {
+        VideoData {
+            header: VideoDataHeader {
+                frame_type: VideoFrameType::InterFrame,
+                codec_id: CodecId::AVC,
+            },
+            data: Bytes::copy_from_slice(packet),
+        }
+    }
+
+    #[test]
+    fn composition_time() {
+        assert_eq!(
+            avc(&[1, 0x00, 0x00, 0x21, 0x65]).composition_time(),
+            Some(33)
+        );
+        assert_eq!(
+            avc(&[1, 0x7f, 0xff, 0xff]).composition_time(),
+            Some(0x7f_ffff)
+        );
+        // negative values are sign extended from 24 bits
+        assert_eq!(avc(&[1, 0xff, 0xff, 0xdf]).composition_time(), Some(-33));
+        assert_eq!(avc(&[1, 0xff, 0xff, 0xff]).composition_time(), Some(-1));
+        assert_eq!(
+            avc(&[1, 0x80, 0x00, 0x00]).composition_time(),
+            Some(-0x80_0000)
+        );
+
+        // only NALUs have one
+        assert_eq!(avc(&[0, 0, 0, 0, 1]).composition_time(), None);
+        assert_eq!(avc(&[1, 0, 0]).composition_time(), None);
+        let mut h263 = avc(&[1, 0, 0, 0x21]);
+        h263.header.codec_id = CodecId::SorensonH263;
+        assert_eq!(h263.composition_time(), None);
+    }
+
+    #[test]
+    fn data_offset_below_header_size() {
+        let bytes = zero_data_offset();
+        let size = bytes.len() as u64;
+        block_on(async {
+            let (_, fields) = read_flv(&bytes[..], ParseOptions::default()).await.unwrap();
+            let all = offsets(fields).await;
+            assert_eq!(all[0], 13);
+
+            let (_, fields) = tail_flv(Cursor::new(&bytes), size, usize::MAX, Default::default())
+                .await
+                .unwrap();
+            assert_eq!(offsets(fields).await, all);
+
+            let (_, fields) = seek_flv(Cursor::new(&bytes), size, 0, Default::default())
+                .await
+                .unwrap();
+            assert_eq!(offsets(fields).await, all);
+        });
+    }
+
+    #[test]
+    fn max_tag_size() {
+        let (a, b) = (audio_tag(0, &[1, 2, 3]), audio_tag(52, &[4, 5, 6]));
+        let large = audio_tag(26, &[0; 40]);
+        let bytes = flv(&[
+            &a,
+            &pre_tag_size(&a),
+            &large,
+            &pre_tag_size(&large),
+            &b,
+            &pre_tag_size(&b),
+        ]);
+        let message = "DataSize 41 exceeds the limit of 20 bytes";
+
+        // the large tag is taken for corrupted data and skipped up to the next tag
+        let options = ParseOptions {
+            max_tag_size: 20,
+            ..ParseOptions::default()
+        };
+        let expected = [
+            "PreTagSize 0",
+            "Tag 13",
+            "PreTagSize 15",
+            &format!("TagSize: {}", message),
+            "Resync: Resynchronized after skipping 56 bytes at offset 32",
+            "Tag 88",
+            "PreTagSize 15",
+        ];
+        assert_eq!(decode(&bytes, options), (strings(&expected), None));
+
+        let strict = ParseOptions {
+            mode: ParseMode::Strict,
+            ..options
+        };
+        assert_eq!(
+            decode(&bytes, strict),
+            (strings(&expected[..3]), Some(message.to_owned()))
+        );
+    }
+
+    #[test]
+    fn junk_after_pre_tag_size() {
+        let (a, b) = (audio_tag(0, &[1, 2, 3]), audio_tag(26, &[4, 5, 6]));
+        let bytes = flv(&[&a, &pre_tag_size(&a), &[0; 5], &b, &pre_tag_size(&b)]);
+        let expected = [
+            "PreTagSize 0",
+            "Tag 13",
+            "PreTagSize 15",
+            "Resync: Resynchronized after skipping 5 bytes at offset 32",
+            "Tag 37",
+            "PreTagSize 15",
+        ];
+        assert_eq!(
+            decode(&bytes, ParseOptions::default()),
+            (strings(&expected), None)
+        );
+    }
+
+    #[test]
+    fn junk_before_pre_tag_size() {
+        // The junk is read as the PreviousTagSize of the first tag, which does not match and is
+        // not followed by a tag header, so it is skipped along with the real PreviousTagSize.
+        let (a, b) = (audio_tag(0, &[1, 2, 3]), audio_tag(26, &[4, 5, 6]));
+        let bytes = flv(&[&a, &[0; 5], &pre_tag_size(&a), &b, &pre_tag_size(&b)]);
+        let expected = [
+            "PreTagSize 0",
+            "Tag 13",
+            "Resync: Resynchronized after skipping 9 bytes at offset 28",
+            "Tag 37",
+            "PreTagSize 15",
+        ];
+        assert_eq!(
+            decode(&bytes, ParseOptions::default()),
+            (strings(&expected), None)
+        );
+    }
+
+    #[test]
+    fn bogus_pre_tag_size() {
+        // followed by a tag header, so the PreviousTagSize is wrong rather than junk
+        let (a, b) = (audio_tag(0, &[1, 2, 3]), audio_tag(26, &[4, 5, 6]));
+        let bytes = flv(&[&a, &99u32.to_be_bytes(), &b, &pre_tag_size(&b)]);
+        let message = "PreviousTagSize 99 does not match the size of the previous tag 15";
+        let expected = [
+            "PreTagSize 0",
+            "Tag 13",
+            &format!("PreTagSize: {}", message),
+            "PreTagSize 99",
+            "Tag 32",
+            "PreTagSize 15",
+        ];
+        assert_eq!(
+            decode(&bytes, ParseOptions::default()),
+            (strings(&expected), None)
+        );
+
+        let strict = ParseOptions {
+            mode: ParseMode::Strict,
+            ..ParseOptions::default()
+        };
+        assert_eq!(
+            decode(&bytes, strict),
+            (strings(&expected[..2]), Some(message.to_owned()))
+        );
+    }
+
+    #[test]
+    fn resync_past_implausible_candidates() {
+        // Junk that is no tag header, then a plausible tag header whose PreviousTagSize does
+        // not match, both are skipped to reach the next real tag.
+        let (a, b) = (audio_tag(0, &[1, 2, 3]), audio_tag(26, &[4, 5, 6]));
+        let mut junk = vec![0xff, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0, 0];
+        junk.extend_from_slice(&[8, 0, 0, 2, 0, 0, 0, 0, 0, 0, 0, 0xaa, 0xbb, 0, 0, 0, 0]);
+        let bytes = flv(&[&a, &pre_tag_size(&a), &junk, &b, &pre_tag_size(&b)]);
+        let expected = [
+            "PreTagSize 0",
+            "Tag 13",
+            "PreTagSize 15",
+            "Resync: Resynchronized after skipping 29 bytes at offset 32",
+            "Tag 61",
+            "PreTagSize 15",
+        ];
+        assert_eq!(
+            decode(&bytes, ParseOptions::default()),
+            (strings(&expected), None)
+        );
+    }
+
+    #[test]
+    fn junk_at_end() {
+        let a = audio_tag(0, &[1, 2, 3]);
+        let mut junk = vec![1];
+        junk.extend_from_slice(&[0; 19]);
+        let bytes = flv(&[&a, &pre_tag_size(&a), &junk]);
+        let expected = [
+            "PreTagSize 0",
+            "Tag 13",
+            "PreTagSize 15",
+            "Resync: Skipped 20 bytes at the end of the file without finding another tag",
+        ];
+        assert_eq!(
+            decode(&bytes, ParseOptions::default()),
+            (strings(&expected), None)
+        );
+    }
+
+    #[test]
+    fn truncated() {
+        let a = audio_tag(0, &[1, 2, 3]);
+        let cases: [(&[u8], &str); 3] = [
+            (
+                &a[..5],
+                "File truncated at offset 13 in the header of tag 1, 5 of 11 bytes present",
+            ),
+            (
+                &a[..13],
+                "File truncated at offset 13 inside tag 1, 13 of 15 bytes present",
+            ),
+            (
+                &[&a[..], &[0, 0]].concat(),
+                "File truncated at offset 28 in PreviousTagSize1, 2 of 4 bytes present",
+            ),
+        ];
+        for (part, message) in cases {
+            let bytes = flv(&[part]);
+            let mut expected = strings(&["PreTagSize 0"]);
+            if part.len() > a.len() {
+                expected.push("Tag 13".to_owned());
+            }
+            let (fields, error) = decode(&bytes, ParseOptions::default());
+            assert_eq!(fields[..fields.len() - 1], expected[..]);
+            assert_eq!(fields.last().unwrap(), &format!("Truncated: {}", message));
+            assert_eq!(error, None);
+
+            let strict = ParseOptions {
+                mode: ParseMode::Strict,
+                ..ParseOptions::default()
+            };
+            assert_eq!(decode(&bytes, strict), (expected, Some(message.to_owned())));
+        }
+    }
+
+    #[test]
+    fn truncation() {
+        let a = audio_tag(0, &[1, 2, 3]);
+        let bytes = flv(&[&a[..13]]);
+        block_on(async {
+            let (_, mut fields) = read_flv(&bytes[..], ParseOptions::default()).await.unwrap();
+            while fields.next().await.is_some() {}
+            let truncation = fields.decoder().truncation().unwrap();
+            assert_eq!(truncation.offset, 13);
+            assert_eq!(truncation.tag, 1);
+            assert_eq!(truncation.part, TruncatedPart::TagBody);
+            assert_eq!((truncation.present, truncation.expected), (13, 15));
+        });
+    }
+
+    #[test]
+    fn empty_and_header_only() {
+        block_on(async {
+            assert!(read_flv(&[][..], ParseOptions::default()).await.is_err());
+            assert!(read_flv(&b"FLV\x01\x05"[..], ParseOptions::default())
+                .await
+                .is_err());
+        });
+
+        // without PreviousTagSize0 there is no field to be cut off
+        let header = flv(&[]);
+        assert_eq!(
+            decode(&header[..9], ParseOptions::default()),
+            (vec![], None)
+        );
+        assert_eq!(
+            decode(&header, ParseOptions::default()),
+            (strings(&["PreTagSize 0"]), None)
+        );
+        assert_eq!(
+            decode(&header[..11], ParseOptions::default()),
+            (
+                strings(&[
+                    "Truncated: File truncated at offset 9 in PreviousTagSize0, 2 of 4 bytes present"
+                ]),
+                None
+            )
+        );
+    }
+
+    #[test]
+    fn max_buffered() {
+        let (a, b) = (audio_tag(0, &[1, 2, 3]), audio_tag(52, &[4, 5, 6]));
+        let large = audio_tag(26, &[0; 40]);
+        let bytes = flv(&[
+            &a,
+            &pre_tag_size(&a),
+            &large,
+            &pre_tag_size(&large),
+            &b,
+            &pre_tag_size(&b),
+        ]);
+        let message = "Tag at offset 32 is 52 bytes, more than the 32 bytes that may be buffered";
+
+        let skip = ParseOptions {
+            max_buffered: Some(32),
+            overflow: Overflow::Skip,
+            ..ParseOptions::default()
+        };
+        let expected = [
+            "PreTagSize 0",
+            "Tag 13",
+            "PreTagSize 15",
+            &format!("Overflow: {}, skipped", message),
+            "PreTagSize 52",
+            "Tag 88",
+            "PreTagSize 15",
+        ];
+        assert_eq!(decode(&bytes, skip), (strings(&expected), None));
+
+        // the skipped tag has to be complete
+        let (fields, error) = decode(&bytes[..60], skip);
+        assert_eq!(fields, strings(&expected[..4]));
+        assert_eq!(
+            error.as_deref(),
+            Some("24 bytes of a skipped tag missing at the end of the file")
+        );
+
+        let error = ParseOptions {
+            max_buffered: Some(32),
+            overflow: Overflow::Error,
+            ..ParseOptions::default()
+        };
+        assert_eq!(
+            decode(&bytes, error),
+            (strings(&expected[..3]), Some(message.to_owned()))
+        );
+    }
+
+    #[test]
+    fn audio_change() {
+        // legal FLV, reported even in strict mode but not an error
+        let a = audio_tag(0, &[1, 2, 3]);
+        let mut b = audio_tag(26, &[4, 5, 6]);
+        b[11] = 0x2b;
+        let bytes = flv(&[&a, &pre_tag_size(&a), &b, &pre_tag_size(&b)]);
+        let expected = [
+            "PreTagSize 0",
+            "Tag 13",
+            "PreTagSize 15",
+            "AudioChange: Audio data header in tag 2 at 26 ms changes the sample rate from 44100 \
+             Hz to 22050 Hz",
+            "Tag 32",
+            "PreTagSize 15",
+        ];
+        for mode in [ParseMode::Lenient, ParseMode::Strict] {
+            let options = ParseOptions {
+                mode,
+                ..ParseOptions::default()
+            };
+            assert_eq!(decode(&bytes, options), (strings(&expected), None));
+        }
+    }
 }
@@ -0,0 +1,315 @@
+use crate::chunks::ChunkRecorder;
+use crate::time::civil_from_days;
+use crate::Exception;
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::io::{self, Read};
+use std::time::{SystemTime, UNIX_EPOCH};
+
+// Objects are fetched in ranges of this size, so a dropped connection only costs one range.
+const RANGE_SIZE: u64 = 8 * 1024 * 1024;
+const MAX_RETRIES: usize = 3;
+
+/// An object reachable over HTTP(S): a plain or presigned URL, or an S3 object that is signed
+/// with the credentials from the environment.
+#[derive(Debug)]
+pub enum Object {
+    Url(String),
+    S3(S3Object),
+}
+
+#[derive(Debug)]
+pub struct S3Object {
+    url: String,
+    host: String,
+    path: String,
+    region: String,
+    credentials: Option<Credentials>,
+}
+
+#[derive(Debug)]
+struct Credentials {
+    access_key_id: String,
+    secret_access_key: String,
+    session_token: Option<String>,
+}
+
+impl S3Object {
+    /// Resolves `s3://bucket/key` using `AWS_REGION`/`AWS_DEFAULT_REGION`, the optional
+    /// `AWS_ENDPOINT_URL` (path-style, for S3 compatible storage) and, if present,
+    /// `AWS_ACCESS_KEY_ID`/`AWS_SECRET_ACCESS_KEY`/`AWS_SESSION_TOKEN`.
+    pub fn from_env(location: &str) -> Result<Self, Exception> {
+        let (bucket, key) = match location.split_once('/') {
+            Some((bucket, key)) if !bucket.is_empty() && !key.is_empty() => (bucket, key),
+            _ => return Err(format!("Invalid S3 location: s3://{}", location).into()),
+        };
+
+        let region = std::env::var("AWS_REGION")
+            .or_else(|_| std::env::var("AWS_DEFAULT_REGION"))
+            .unwrap_or_else(|_| "us-east-1".into());
+
+        let key = uri_encode(key);
+        let (scheme, host, path) = match std::env::var("AWS_ENDPOINT_URL") {
+            Ok(endpoint) => {
+                let (scheme, host) = endpoint
+                    .trim_end_matches('/')
+                    .split_once("://")
+                    .map(|(scheme, host)| (scheme.to_owned(), host.to_owned()))
+                    .ok_or_else(|| format!("Invalid AWS_ENDPOINT_URL: {}", endpoint))?;
+                (scheme, host, format!("/{}/{}", bucket, key))
+            }
+            Err(_) => (
+                "https".to_owned(),
+                format!("{}.s3.{}.amazonaws.com", bucket, region),
+                format!("/{}", key),
+            ),
+        };
+
+        let credentials = match (
+            std::env::var("AWS_ACCESS_KEY_ID"),
+            std::env::var("AWS_SECRET_ACCESS_KEY"),
+        ) {
+            (Ok(access_key_id), Ok(secret_access_key)) => Some(Credentials {
+                access_key_id,
+                secret_access_key,
+                session_token: std::env::var("AWS_SESSION_TOKEN").ok(),
+            }),
+            _ => None,
+        };
+
+        Ok(Self {
+            url: format!("{}://{}{}", scheme, host, path),
+            host,
+            path,
+            region,
+            credentials,
+        })
+    }
+
+    // AWS Signature Version 4 for a GET with a Range header and an unsigned payload.
+    fn sign(&self, request: ureq::Request, range: &str) -> ureq::Request {
+        let credentials = match &self.credentials {
+            Some(credentials) => credentials,
+            None => return request,
+        };
+
+        let (date, time) = utc_now();
+        let amz_date = format!("{}T{}Z", date, time);
+        let scope = format!("{}/{}/s3/aws4_request", date, self.region);
+
+        let mut headers = vec![
+            ("host", self.host.as_str()),
+            ("range", range),
+            ("x-amz-content-sha256", "UNSIGNED-PAYLOAD"),
+            ("x-amz-date", amz_date.as_str()),
+        ];
+        if let Some(token) = &credentials.session_token {
+            headers.push(("x-amz-security-token", token));
+        }
+
+        let canonical_headers: String = headers
+            .iter()
+            .map(|(name, value)| format!("{}:{}\n", name, value.trim()))
+            .collect();
+        let signed_headers = headers
+            .iter()
+            .map(|(name, _)| *name)
+            .collect::<Vec<_>>()
+            .join(";");
+        let canonical_request = format!(
+            "GET\n{}\n\n{}\n{}\nUNSIGNED-PAYLOAD",
+            self.path, canonical_headers, signed_headers
+        );
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256\n{}\n{}\n{}",
+            amz_date,
+            scope,
+            hex(&Sha256::digest(canonical_request.as_bytes()))
+        );
+
+        let secret = format!("AWS4{}", credentials.secret_access_key);
+        let key = hmac(secret.as_bytes(), date.as_bytes());
+        let key = hmac(&key, self.region.as_bytes());
+        let key = hmac(&key, b"s3");
+        let key = hmac(&key, b"aws4_request");
+        let signature = hex(&hmac(&key, string_to_sign.as_bytes()));
+
+        let authorization = format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+            credentials.access_key_id, scope, signed_headers, signature
+        );
+
+        headers
+            .iter()
+            .filter(|(name, _)| *name != "range")
+            .fold(request, |request, (name, value)| request.set(name, value))
+            .set("authorization", &authorization)
+    }
+}
+
+impl Object {
+    // Returns None once the range starts beyond the end of the object.
+    fn get(&self, agent: &ureq::Agent, start: u64) -> Result<Option<ureq::Response>, Exception> {
+        let range = format!("bytes={}-{}", start, start + RANGE_SIZE - 1);
+        let request = match self {
+            Object::Url(url) => agent.get(url),
+            Object::S3(object) => object.sign(agent.get(&object.url), &range),
+        };
+        match request.set("range", &range).call() {
+            Ok(response) => Ok(Some(response)),
+            Err(ureq::Error::Status(416, _)) => Ok(None),
+            Err(e) => Err(e.into()),
+        }
+    }
+}
+
+/// Reads an object sequentially through ranged GET requests. Servers that ignore the Range
+/// header are read in a single response.
+pub struct RangedReader {
+    agent: ureq::Agent,
+    object: Object,
+    position: u64,
+    size: Option<u64>,
+    body: Option<Box<dyn Read + Send + Sync>>,
+    ranged: bool,
+    chunks: Option<ChunkRecorder>,
+}
+
+impl RangedReader {
+    /// Issues the first request, which also tells the object size. Every read of the response
+    /// bodies is recorded in `chunks`, if given.
+    pub fn open(object: Object, chunks: Option<ChunkRecorder>) -> Result<Self, Exception> {
+        let mut reader = Self {
+            agent: ureq::Agent::new(),
+            object,
+            position: 0,
+            size: None,
+            body: None,
+            ranged: true,
+            chunks,
+        };
+        if !reader.request()? {
+            reader.size = Some(0);
+        }
+        Ok(reader)
+    }
+
+    pub fn size(&self) -> Option<u64> {
+        self.size
+    }
+
+    // Returns false if there is nothing left to read.
+    fn request(&mut self) -> Result<bool, Exception> {
+        let response = match self.object.get(&self.agent, self.position)? {
+            Some(response) => response,
+            None => return Ok(false),
+        };
+
+        if response.status() == 206 {
+            // Content-Range: bytes <start>-<end>/<size>
+            self.size = response
+                .header("content-range")
+                .and_then(|range| range.rsplit('/').next())
+                .and_then(|size| size.parse().ok());
+        } else {
+            if self.position > 0 {
+                return Err("Server stopped honoring range requests".into());
+            }
+            self.ranged = false;
+            self.size = response
+                .header("content-length")
+                .and_then(|size| size.parse().ok());
+        }
+
+        self.body = Some(response.into_reader());
+        Ok(true)
+    }
+}
+
+impl Read for RangedReader {
+    fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+        let mut retries = 0;
+        loop {
+            if self.body.is_none() {
+                if !self.ranged || self.size.is_some_and(|size| self.position >= size) {
+                    return Ok(0);
+                }
+                match self.request() {
+                    Ok(true) => {}
+                    Ok(false) => return Ok(0),
+                    Err(e) if retries < MAX_RETRIES => {
+                        retries += 1;
+                        eprintln!("Retrying range at {}: {}", self.position, e);
+                        continue;
+                    }
+                    Err(e) => return Err(io::Error::other(e)),
+                }
+            }
+
+            let body = self.body.as_mut().expect("body is present");
+            match body.read(buf) {
+                Ok(0) => {
+                    self.body = None;
+                    if !self.ranged {
+                        return Ok(0);
+                    }
+                }
+                Ok(n) => {
+                    if let Some(chunks) = &self.chunks {
+                        chunks.record(self.position, n);
+                    }
+                    self.position += n as u64;
+                    return Ok(n);
+                }
+                // A broken connection is resumed with a new range request from where it stopped.
+                Err(e) if self.ranged && retries < MAX_RETRIES => {
+                    retries += 1;
+                    eprintln!("Retrying range at {}: {}", self.position, e);
+                    self.body = None;
+                }
+                Err(e) => return Err(e),
+            }
+        }
+    }
+}
+
+fn hmac(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts keys of any size");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{:02x}", b)).collect()
+}
+
+fn uri_encode(path: &str) -> String {
+    path.bytes()
+        .map(|b| match b {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'.' | b'_' | b'~' | b'/' => {
+                (b as char).to_string()
+            }
+            _ => format!("%{:02X}", b),
+        })
+        .collect()
+}
+
+// (YYYYMMDD, HHMMSS) in UTC.
+fn utc_now() -> (String, String) {
+    let seconds = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_secs() as i64)
+        .unwrap_or(0);
+    let (days, seconds) = (seconds.div_euclid(86400), seconds.rem_euclid(86400));
+    let (year, month, day) = civil_from_days(days);
+
+    (
+        format!("{:04}{:02}{:02}", year, month, day),
+        format!(
+            "{:02}{:02}{:02}",
+            seconds / 3600,
+            seconds % 3600 / 60,
+            seconds % 60
+        ),
+    )
+}
@@ -0,0 +1,672 @@
+use crate::aac::{AacPacketType, AudioSpecificConfig};
+use crate::input::open_input;
+use crate::reader::{
+    read_flv, AudioData, Field, ParseOptions, SoundFormat, Tag, TagData, TagHeader, TagType,
+    VideoFrameType,
+};
+use crate::writer::FlvWriter;
+use crate::Exception;
+use bytes::{BufMut, Bytes, BytesMut};
+use std::collections::{BTreeMap, VecDeque};
+use std::convert::TryFrom;
+use std::path::Path;
+use tokio::fs::File;
+use tokio::io::BufWriter;
+use tokio_stream::StreamExt;
+
+#[derive(Debug, Default)]
+pub struct RewriteOptions {
+    /// Trim the leading stream so audio and video start within this many milliseconds.
+    pub align_start: Option<u32>,
+    /// Fill audio gaps longer than this many milliseconds (beyond one frame) with silence.
+    pub fill_audio_gaps: Option<u32>,
+    /// Reorder tags so that no stream runs ahead of another by more than this many milliseconds.
+    pub interleave: Option<u32>,
+    /// Remove DisposableInterFrame video tags.
+    pub drop_disposable: bool,
+    /// Sort tags by timestamp, moving back tags that arrive up to this many milliseconds late.
+    pub reorder: Option<u32>,
+}
+
+/// Copies the FLV file or stream `input` to `output` with the repairs selected in `options`
+/// applied in a fixed order. Reordering and filling gaps move tags backwards, so every tag is
+/// read into memory before any is written: this needs about as much memory as the input is
+/// large.
+pub async fn rewrite<P: AsRef<Path>>(
+    input: &str,
+    output: P,
+    options: &RewriteOptions,
+) -> Result<(), Exception> {
+    let (_, input) = open_input(input).await?;
+    let (header, mut decoder) = read_flv(input, ParseOptions::default()).await?;
+
+    let mut tags = Vec::new();
+    while let Some(field) = decoder.next().await {
+        match field? {
+            Field::Tag(tag) => tags.push(tag),
+            Field::Warning(finding) => eprintln!("{}", finding),
+            Field::PreTagSize(_) => {}
+        }
+    }
+
+    if let Some(window) = options.reorder {
+        let (sorted, out_of_order, late) = reorder(tags, window);
+        println!("OutOfOrderTags: {}", out_of_order);
+        println!("LateTags: {}", late);
+        tags = sorted;
+    }
+
+    if options.drop_disposable {
+        let (kept, dropped, bytes) = drop_disposable(tags);
+        println!("DroppedDisposableFrames: {}", dropped);
+        println!("DroppedBytes: {}", bytes);
+        tags = kept;
+    }
+
+    if let Some(tolerance) = options.align_start {
+        let (aligned, trimmed) = align_start(tags, tolerance);
+        println!("TrimmedLeadingTags: {}", trimmed);
+        tags = aligned;
+    }
+
+    if let Some(threshold) = options.fill_audio_gaps {
+        let (filled, gaps, frames) = fill_audio_gaps(tags, threshold);
+        println!("FilledAudioGaps: {}", gaps);
+        println!("SilentFrames: {}", frames);
+        tags = filled;
+    }
+
+    if let Some(window) = options.interleave {
+        let (interleaved, moved) = interleave(tags, window);
+        println!("InterleavedTags: {}", moved);
+        tags = interleaved;
+    }
+
+    let file = File::create(output).await?;
+    let mut writer = FlvWriter::new(BufWriter::new(file), &header).await?;
+    for tag in &tags {
+        writer.write_tag(tag).await?;
+    }
+    writer.finish().await?;
+
+    println!("Tags: {}", tags.len());
+
+    Ok(())
+}
+
+/// Sorts the tags by timestamp within a sliding window: a tag is held back until one more than
+/// `window` milliseconds later has been read, so tags up to `window` milliseconds late move back
+/// into place, e.g. after two recordings were naively concatenated. Tags with equal timestamps
+/// keep their order, sequence headers stay ahead of their frames.
+///
+/// Returns the new tag list, the number of tags that came after a later one in the input and the
+/// number of those that were too late to be put in place.
+fn reorder(tags: Vec<Tag>, window: u32) -> (Vec<Tag>, usize, usize) {
+    let mut pending = BTreeMap::new();
+    let mut sorted = Vec::with_capacity(tags.len());
+    let (mut out_of_order, mut late) = (0, 0);
+    // the latest timestamps read and written
+    let (mut latest, mut written): (Option<i32>, Option<i32>) = (None, None);
+    for (index, tag) in tags.into_iter().enumerate() {
+        let timestamp = tag.header.timestamp;
+        if latest.is_some_and(|latest| timestamp < latest) {
+            out_of_order += 1;
+        }
+        if written.is_some_and(|written| timestamp < written) {
+            late += 1;
+        }
+        let newest = latest.map_or(timestamp, |latest| latest.max(timestamp));
+        latest = Some(newest);
+        pending.insert((timestamp, index), tag);
+
+        while let Some(entry) = pending.first_entry() {
+            if entry.key().0 as i64 + window as i64 >= newest as i64 {
+                break;
+            }
+            let tag = entry.remove();
+            written = written.max(Some(tag.header.timestamp));
+            sorted.push(tag);
+        }
+    }
+    sorted.extend(pending.into_values());
+    (sorted, out_of_order, late)
+}
+
+/// Removes the video tags marked as DisposableInterFrame, which no other frame refers to, e.g. the
+/// non-reference B-frames of H.263. The PreviousTagSize fields are written anew.
+///
+/// Returns the remaining tags, the number of dropped tags and the bytes they took, headers and
+/// PreviousTagSize included.
+fn drop_disposable(tags: Vec<Tag>) -> (Vec<Tag>, usize, u64) {
+    let (mut dropped, mut bytes) = (0, 0);
+    let kept = tags
+        .into_iter()
+        .filter(|tag| match &tag.data {
+            TagData::Video(video)
+                if video.header.frame_type == VideoFrameType::DisposableInterFrame =>
+            {
+                dropped += 1;
+                bytes += 11 + tag.header.data_size as u64 + 4;
+                false
+            }
+            _ => true,
+        })
+        .collect();
+    (kept, dropped, bytes)
+}
+
+/// Drops the leading frames of whichever stream starts first until both start within `tolerance`
+/// milliseconds of each other. Video is only cut at a keyframe. Sequence headers and script tags
+/// are kept (superseded sequence headers of the trimmed stream excepted) and all timestamps are
+/// rebased so that the first remaining frame starts at 0.
+///
+/// Returns the new tag list and the number of dropped tags.
+fn align_start(tags: Vec<Tag>, tolerance: u32) -> (Vec<Tag>, usize) {
+    let is_frame = |tag: &Tag, tag_type: TagType| {
+        tag.header.tag_type == tag_type && !tag.data.is_sequence_header()
+    };
+    let first_timestamp = |tag_type: TagType| {
+        tags.iter()
+            .find(|tag| is_frame(tag, tag_type))
+            .map(|tag| tag.header.timestamp)
+    };
+
+    let (audio_start, video_start) = match (
+        first_timestamp(TagType::Audio),
+        first_timestamp(TagType::Video),
+    ) {
+        (Some(audio), Some(video)) => (audio, video),
+        _ => return (tags, 0),
+    };
+    if (audio_start - video_start).abs() <= tolerance as i32 {
+        return (tags, 0);
+    }
+
+    // Index of the first frame of the leading stream that is kept.
+    let (leading, cut) = if audio_start < video_start {
+        let target = video_start - tolerance as i32;
+        let cut = tags
+            .iter()
+            .position(|tag| is_frame(tag, TagType::Audio) && tag.header.timestamp >= target);
+        (TagType::Audio, cut)
+    } else {
+        let target = audio_start - tolerance as i32;
+        let cut = tags.iter().position(|tag| match &tag.data {
+            TagData::Video(video) => {
+                is_frame(tag, TagType::Video)
+                    && video.header.frame_type == VideoFrameType::KeyFrame
+                    && tag.header.timestamp >= target
+            }
+            _ => false,
+        });
+        (TagType::Video, cut)
+    };
+    let cut = match cut {
+        Some(cut) => cut,
+        None => return (tags, 0),
+    };
+
+    let last_sequence_header = tags[..cut]
+        .iter()
+        .rposition(|tag| tag.header.tag_type == leading && tag.data.is_sequence_header());
+
+    let total = tags.len();
+    let mut aligned: Vec<Tag> = tags
+        .into_iter()
+        .enumerate()
+        .filter(|(index, tag)| {
+            *index >= cut || tag.header.tag_type != leading || Some(*index) == last_sequence_header
+        })
+        .map(|(_, tag)| tag)
+        .collect();
+    let trimmed = total - aligned.len();
+
+    let start = aligned
+        .iter()
+        .filter(|tag| is_frame(tag, TagType::Audio) || is_frame(tag, TagType::Video))
+        .map(|tag| tag.header.timestamp)
+        .min()
+        .unwrap_or(0);
+    for tag in &mut aligned {
+        tag.header.timestamp = (tag.header.timestamp - start).max(0);
+    }
+
+    (aligned, trimmed)
+}
+
+/// Inserts silent AAC frames wherever two consecutive raw AAC frames are further apart than one
+/// frame duration plus `threshold` milliseconds. The frames are built from the most recent AAC
+/// sequence header and placed among the other tags by timestamp. After a sequence header that
+/// can not be parsed or has no silent frame, gaps are left as they are up to the next one.
+///
+/// Returns the new tag list, the number of gaps and the number of inserted frames.
+fn fill_audio_gaps(tags: Vec<Tag>, threshold: u32) -> (Vec<Tag>, usize, usize) {
+    // the configuration of the most recent sequence header and its silent frame
+    let mut config: Option<(AudioSpecificConfig, Bytes)> = None;
+    let mut last_frame: Option<(usize, i32)> = None;
+    let mut insertions: Vec<(usize, Tag)> = Vec::new();
+    let mut gaps = 0;
+
+    for (index, tag) in tags.iter().enumerate() {
+        let audio = match &tag.data {
+            TagData::Audio(audio) if audio.header.sound_format == SoundFormat::AAC => audio,
+            _ => continue,
+        };
+
+        match audio.data.first().map(|b| AacPacketType::try_from(*b)) {
+            Some(Ok(AacPacketType::SequenceHeader)) => {
+                let silent = AudioSpecificConfig::try_from(&audio.data[1..])
+                    .and_then(|config| Ok((config.silent_frame()?, config)));
+                config = match silent {
+                    Ok((silence, config)) => Some((config, silence)),
+                    Err(e) => {
+                        eprintln!(
+                            "Warning: AAC sequence header at offset {}: {}, audio gaps are not \
+                             filled up to the next one",
+                            tag.header.offset, e
+                        );
+                        None
+                    }
+                };
+                last_frame = None;
+            }
+            Some(Ok(AacPacketType::Raw)) => {
+                if let (Some((config, silence)), Some((last_index, last_timestamp))) =
+                    (&config, last_frame)
+                {
+                    let frame_duration = config.frame_duration();
+                    let delta = (tag.header.timestamp - last_timestamp) as f64;
+                    let missing = if delta > frame_duration + threshold as f64 {
+                        (delta / frame_duration).round() as usize - 1
+                    } else {
+                        0
+                    };
+                    // a gap just over the threshold can round to no missing frame at all
+                    if missing > 0 {
+                        let mut position = last_index + 1;
+                        for n in 1..=missing {
+                            let timestamp =
+                                last_timestamp + (n as f64 * frame_duration).round() as i32;
+                            while position < index && tags[position].header.timestamp <= timestamp {
+                                position += 1;
+                            }
+
+                            let mut data = BytesMut::with_capacity(1 + silence.len());
+                            data.put_u8(AacPacketType::Raw as u8);
+                            data.put_slice(silence);
+                            insertions.push((
+                                position,
+                                Tag {
+                                    header: TagHeader {
+                                        data_size: data.len() as u32 + 1,
+                                        timestamp,
+                                        ..tag.header.clone()
+                                    },
+                                    data: TagData::Audio(AudioData {
+                                        header: audio.header,
+                                        data: data.freeze(),
+                                    }),
+                                    raw: None,
+                                    received: None,
+                                },
+                            ));
+                        }
+                        gaps += 1;
+                    }
+                }
+                last_frame = Some((index, tag.header.timestamp));
+            }
+            _ => {}
+        }
+    }
+
+    let frames = insertions.len();
+    let mut insertions = insertions.into_iter().peekable();
+    let mut filled = Vec::with_capacity(tags.len() + frames);
+    for (index, tag) in tags.into_iter().enumerate() {
+        while let Some((_, silent)) = insertions.next_if(|(position, _)| *position == index) {
+            filled.push(silent);
+        }
+        filled.push(tag);
+    }
+
+    (filled, gaps, frames)
+}
+
+/// Re-interleaves the audio, video and script streams. Tags keep their original order unless the
+/// next one is more than `window` milliseconds ahead of the earliest pending tag of another stream,
+/// in which case that stream catches up first. The order within each stream is never changed.
+///
+/// Returns the new tag list and the number of tags that were emitted out of their original order.
+fn interleave(tags: Vec<Tag>, window: u32) -> (Vec<Tag>, usize) {
+    let mut streams: [VecDeque<(usize, Tag)>; 3] = Default::default();
+    let total = tags.len();
+    for (index, tag) in tags.into_iter().enumerate() {
+        let stream = match tag.header.tag_type {
+            TagType::Audio => 0,
+            TagType::Video => 1,
+            TagType::Script | TagType::Reserved(_) => 2,
+        };
+        streams[stream].push_back((index, tag));
+    }
+
+    let mut interleaved = Vec::with_capacity(total);
+    let mut moved = 0;
+    loop {
+        let heads = streams
+            .iter()
+            .enumerate()
+            .filter_map(|(stream, queue)| queue.front().map(|(index, tag)| (stream, *index, tag)));
+
+        let next = match heads.clone().min_by_key(|(_, index, _)| *index) {
+            Some(next) => next,
+            None => break,
+        };
+        let earliest = heads
+            .min_by_key(|(_, index, tag)| (tag.header.timestamp, *index))
+            .unwrap_or(next);
+
+        let stream = if next.2.header.timestamp as i64 - earliest.2.header.timestamp as i64
+            > window as i64
+        {
+            moved += 1;
+            earliest.0
+        } else {
+            next.0
+        };
+
+        if let Some((_, tag)) = streams[stream].pop_front() {
+            interleaved.push(tag);
+        }
+    }
+
+    (interleaved, moved)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::{
+        AudioDataHeader, CodecId, ScriptData, SoundRate, SoundSize, SoundType, VideoData,
+        VideoDataHeader,
+    };
+
+    fn tag(tag_type: TagType, timestamp: i32, data: TagData) -> Tag {
+        Tag {
+            header: TagHeader {
+                tag_type,
+                data_size: 0,
+                timestamp,
+                offset: 0,
+            },
+            data,
+            raw: None,
+            received: None,
+        }
+    }
+
+    // AAC LC, 44.1 kHz stereo
+    const ASC: [u8; 2] = [0x12, 0x10];
+
+    fn aac(timestamp: i32, packet: &[u8]) -> Tag {
+        let header = AudioDataHeader {
+            sound_format: SoundFormat::AAC,
+            sound_rate: SoundRate::R44kHz,
+            sound_size: SoundSize::S16Bit,
+            sound_type: SoundType::Stereo,
+        };
+        let data = TagData::Audio(AudioData {
+            header,
+            data: Bytes::copy_from_slice(packet),
+        });
+        tag(TagType::Audio, timestamp, data)
+    }
+
+    fn aac_sequence_header(timestamp: i32, config: &[u8]) -> Tag {
+        aac(timestamp, &[&[0], config].concat())
+    }
+
+    fn aac_frame(timestamp: i32) -> Tag {
+        aac(timestamp, &[1, 0x21, 0x10])
+    }
+
+    fn avc(timestamp: i32, frame_type: VideoFrameType, packet: &[u8]) -> Tag {
+        let header = VideoDataHeader {
+            frame_type,
+            codec_id: CodecId::AVC,
+        };
+        let data = TagData::Video(VideoData {
+            header,
+            data: Bytes::copy_from_slice(packet),
+        });
+        tag(TagType::Video, timestamp, data)
+    }
+
+    fn avc_sequence_header(timestamp: i32) -> Tag {
+        avc(timestamp, VideoFrameType::KeyFrame, &[0, 0, 0, 0, 1])
+    }
+
+    fn keyframe(timestamp: i32) -> Tag {
+        avc(timestamp, VideoFrameType::KeyFrame, &[1, 0, 0, 0, 0x65])
+    }
+
+    fn inter_frame(timestamp: i32) -> Tag {
+        avc(timestamp, VideoFrameType::InterFrame, &[1, 0, 0, 0, 0x41])
+    }
+
+    fn timestamps(tags: &[Tag]) -> Vec<i32> {
+        tags.iter().map(|tag| tag.header.timestamp).collect()
+    }
+
+    #[test]
+    fn fill_audio_gaps_after_invalid_config() {
+        let tags = vec![
+            aac_sequence_header(0, &ASC),
+            aac_frame(0),
+            aac_frame(100),
+            // an escaped object type that breaks off
+            aac_sequence_header(200, &[0xf8]),
+            aac_frame(200),
+            aac_frame(300),
+            // TwinVQ, parsable but without a silent frame
+            aac_sequence_header(400, &[0x3a, 0x10]),
+            aac_frame(400),
+            aac_frame(500),
+            aac_sequence_header(600, &ASC),
+            aac_frame(600),
+            aac_frame(700),
+        ];
+        let (filled, gaps, frames) = fill_audio_gaps(tags, 10);
+        assert_eq!((gaps, frames), (2, 6));
+        assert_eq!(
+            timestamps(&filled),
+            [0, 0, 23, 46, 70, 100, 200, 200, 300, 400, 400, 500, 600, 600, 623, 646, 670, 700]
+        );
+    }
+
+    #[test]
+    fn fill_audio_gaps_by_timestamp() {
+        let tags = vec![
+            aac_sequence_header(0, &ASC),
+            aac_frame(0),
+            aac_frame(23),
+            inter_frame(50),
+            inter_frame(90),
+            aac_frame(116),
+            // over one frame and the threshold, but short of a whole frame missing
+            aac_frame(146),
+        ];
+        let (filled, gaps, frames) = fill_audio_gaps(tags, 0);
+        assert_eq!((gaps, frames), (1, 3));
+        assert_eq!(
+            timestamps(&filled),
+            [0, 0, 23, 46, 50, 69, 90, 93, 116, 146]
+        );
+
+        let silence = AudioSpecificConfig::try_from(&ASC[..])
+            .unwrap()
+            .silent_frame()
+            .unwrap();
+        let expected = [&[AacPacketType::Raw as u8], &silence[..]].concat();
+        for index in [3, 5, 7] {
+            match &filled[index].data {
+                TagData::Audio(audio) => assert_eq!(audio.data, expected),
+                data => panic!("{:?}", data),
+            }
+            assert_eq!(filled[index].header.data_size as usize, expected.len() + 1);
+        }
+
+        // gaps within the threshold are left alone
+        let tags = vec![aac_sequence_header(0, &ASC), aac_frame(0), aac_frame(116)];
+        let (filled, gaps, frames) = fill_audio_gaps(tags, 100);
+        assert_eq!((filled.len(), gaps, frames), (3, 0, 0));
+    }
+
+    #[test]
+    fn interleave_within_window() {
+        use TagType::{Audio, Video};
+
+        // a burst of video ahead of the audio
+        let tags = vec![
+            inter_frame(0),
+            inter_frame(40),
+            inter_frame(80),
+            inter_frame(120),
+            aac_frame(0),
+            aac_frame(23),
+            aac_frame(46),
+        ];
+        let (interleaved, moved) = interleave(tags, 50);
+        assert_eq!(moved, 3);
+        assert_eq!(timestamps(&interleaved), [0, 40, 0, 23, 80, 46, 120]);
+        let types: Vec<_> = interleaved.iter().map(|tag| tag.header.tag_type).collect();
+        assert_eq!(types, [Video, Video, Audio, Audio, Video, Audio, Video]);
+
+        // a window wide enough keeps the order
+        let tags = vec![inter_frame(0), inter_frame(40), aac_frame(0)];
+        let (interleaved, moved) = interleave(tags, 50);
+        assert_eq!((timestamps(&interleaved), moved), (vec![0, 40, 0], 0));
+    }
+
+    #[test]
+    fn align_start_trims_leading_audio() {
+        use TagType::{Audio, Script, Video};
+
+        // AAC LC, 48 kHz stereo
+        const ASC_48K: [u8; 2] = [0x11, 0x90];
+        let script = TagData::Script(ScriptData::new(Bytes::new()));
+        let tags = vec![
+            tag(TagType::Script, 0, script),
+            avc_sequence_header(0),
+            aac_sequence_header(0, &ASC),
+            aac_frame(0),
+            aac_frame(100),
+            aac_sequence_header(150, &ASC_48K),
+            aac_frame(200),
+            aac_frame(300),
+            keyframe(400),
+            aac_frame(400),
+            inter_frame(440),
+            aac_frame(500),
+        ];
+        let (aligned, trimmed) = align_start(tags, 100);
+        assert_eq!(trimmed, 4);
+        let kept: Vec<_> = aligned
+            .iter()
+            .map(|tag| (tag.header.tag_type, tag.header.timestamp))
+            .collect();
+        assert_eq!(
+            kept,
+            [
+                (Script, 0),
+                (Video, 0),
+                (Audio, 0),
+                (Audio, 0),
+                (Video, 100),
+                (Audio, 100),
+                (Video, 140),
+                (Audio, 200),
+            ]
+        );
+        // the sequence header in force at the cut is kept
+        match &aligned[2].data {
+            TagData::Audio(audio) => assert_eq!(audio.data[1..], ASC_48K),
+            data => panic!("{:?}", data),
+        }
+    }
+
+    #[test]
+    fn align_start_cuts_video_at_keyframe() {
+        let tags = vec![
+            avc_sequence_header(0),
+            keyframe(0),
+            inter_frame(40),
+            keyframe(100),
+            aac_frame(100),
+            inter_frame(140),
+            aac_frame(123),
+        ];
+        let (aligned, trimmed) = align_start(tags, 10);
+        assert_eq!(trimmed, 2);
+        assert_eq!(timestamps(&aligned), [0, 0, 0, 40, 23]);
+        assert!(aligned[0].data.is_sequence_header());
+
+        // within the tolerance, or without a keyframe to cut at, nothing changes
+        let tags = vec![keyframe(0), inter_frame(40), aac_frame(100)];
+        let (aligned, trimmed) = align_start(tags, 100);
+        assert_eq!((timestamps(&aligned), trimmed), (vec![0, 40, 100], 0));
+        let tags = vec![keyframe(0), inter_frame(40), aac_frame(100)];
+        let (aligned, trimmed) = align_start(tags, 10);
+        assert_eq!((timestamps(&aligned), trimmed), (vec![0, 40, 100], 0));
+    }
+
+    #[test]
+    fn drop_disposable_frames() {
+        let disposable = |timestamp, data_size| {
+            let mut tag = avc(
+                timestamp,
+                VideoFrameType::DisposableInterFrame,
+                &[1, 0, 0, 0],
+            );
+            tag.header.data_size = data_size;
+            tag
+        };
+        let tags = vec![
+            keyframe(0),
+            disposable(40, 10),
+            inter_frame(80),
+            aac_frame(80),
+            disposable(120, 20),
+        ];
+        let (kept, dropped, bytes) = drop_disposable(tags);
+        assert_eq!(timestamps(&kept), [0, 80, 80]);
+        assert_eq!(dropped, 2);
+        assert_eq!(bytes, (11 + 10 + 4) + (11 + 20 + 4));
+    }
+
+    #[test]
+    fn reorder_within_window() {
+        let tags = vec![
+            inter_frame(0),
+            inter_frame(40),
+            inter_frame(80),
+            inter_frame(20),
+            inter_frame(120),
+            inter_frame(160),
+            // too late to go before 80, which was written already
+            avc_sequence_header(60),
+            keyframe(60),
+            inter_frame(200),
+            inter_frame(400),
+        ];
+        let (sorted, out_of_order, late) = reorder(tags, 50);
+        assert_eq!(
+            timestamps(&sorted),
+            [0, 20, 40, 80, 60, 60, 120, 160, 200, 400]
+        );
+        assert_eq!((out_of_order, late), (3, 2));
+        // equal timestamps keep their order
+        assert!(sorted[4].data.is_sequence_header());
+        assert!(!sorted[5].data.is_sequence_header());
+    }
+}
@@ -0,0 +1,155 @@
+use crate::findings::{Finding, Severity};
+use crate::reader::{Tag, TagType};
+use crate::time;
+use crate::Exception;
+use serde_json::json;
+use std::io::Write;
+use std::time::{Duration, SystemTime};
+use tokio::time::Instant;
+
+/// What happened in one interval.
+#[derive(Default)]
+struct Window {
+    tags: usize,
+    audio_bytes: u64,
+    video_bytes: u64,
+    // ms, the largest step between consecutive timestamps of the audio or of the video
+    max_gap: Option<i64>,
+    errors: usize,
+    warnings: usize,
+}
+
+/// Statistics of a live input over the last `--stats-interval`, printed at the end of every
+/// interval whether tags arrived or not, as a text line or an NDJSON `interval` record.
+pub struct Rolling {
+    interval: Duration,
+    started: Instant,
+    window: Window,
+    tags: usize,
+    errors: usize,
+    warnings: usize,
+    // when the first tag arrived and its timestamp
+    origin: Option<(Instant, i64)>,
+    // ms the last tag arrived after real time, counted from the first tag, negative when the
+    // input runs ahead of real time
+    latency: Option<i64>,
+    last_audio: Option<i64>,
+    last_video: Option<i64>,
+}
+
+impl Rolling {
+    pub fn new(interval: Duration) -> Self {
+        Self {
+            interval,
+            started: Instant::now(),
+            window: Window::default(),
+            tags: 0,
+            errors: 0,
+            warnings: 0,
+            origin: None,
+            latency: None,
+            last_audio: None,
+            last_video: None,
+        }
+    }
+
+    /// When the current interval ends.
+    pub fn deadline(&self) -> Instant {
+        self.started + self.interval
+    }
+
+    pub fn push(&mut self, tag: &Tag) {
+        let now = Instant::now();
+        let timestamp = tag.header.timestamp as i64;
+        let (origin, first) = *self.origin.get_or_insert((now, timestamp));
+        let elapsed = now.duration_since(origin).as_millis() as i64;
+        self.latency = Some(elapsed - (timestamp - first));
+
+        self.tags += 1;
+        self.window.tags += 1;
+        let last = match tag.header.tag_type {
+            TagType::Audio => {
+                self.window.audio_bytes += tag.header.data_size as u64;
+                &mut self.last_audio
+            }
+            TagType::Video => {
+                self.window.video_bytes += tag.header.data_size as u64;
+                &mut self.last_video
+            }
+            _ => return,
+        };
+        if let Some(last) = last.replace(timestamp) {
+            let gap = timestamp - last;
+            self.window.max_gap = Some(self.window.max_gap.map_or(gap, |max| max.max(gap)));
+        }
+    }
+
+    pub fn finding(&mut self, finding: &Finding) {
+        match finding.severity() {
+            Severity::Error => {
+                self.errors += 1;
+                self.window.errors += 1;
+            }
+            Severity::Warning => {
+                self.warnings += 1;
+                self.window.warnings += 1;
+            }
+            Severity::Info => {}
+        }
+    }
+
+    /// Prints the statistics of the interval so far and starts the next one.
+    pub fn flush(&mut self, out: &mut dyn Write, json: bool) -> Result<(), Exception> {
+        let now = Instant::now();
+        let seconds = now.duration_since(self.started).as_secs_f64();
+        let kbps = |bytes: u64| {
+            if seconds > 0.0 {
+                bytes as f64 * 8.0 / 1000.0 / seconds
+            } else {
+                0.0
+            }
+        };
+        let window = std::mem::take(&mut self.window);
+        let time = time::format_utc(SystemTime::now());
+        if json {
+            let record = json!({
+                "record": "interval",
+                "time": time,
+                "seconds": seconds,
+                "tags": window.tags,
+                "audio_bitrate": kbps(window.audio_bytes),
+                "video_bitrate": kbps(window.video_bytes),
+                "max_gap": window.max_gap,
+                "latency": self.latency,
+                "errors": window.errors,
+                "warnings": window.warnings,
+                "total_tags": self.tags,
+                "total_errors": self.errors,
+                "total_warnings": self.warnings,
+            });
+            serde_json::to_writer(&mut *out, &record)?;
+            writeln!(out)?;
+        } else {
+            let or_dash = |ms: Option<i64>| ms.map_or("-".into(), |ms| format!("{} ms", ms));
+            writeln!(
+                out,
+                "Interval: {}, {:.1} s, {} tags, audio {:.1} kbps, video {:.1} kbps, max gap {}, \
+                 latency {}, {} errors ({} in total), {} warnings ({} in total)",
+                time,
+                seconds,
+                window.tags,
+                kbps(window.audio_bytes),
+                kbps(window.video_bytes),
+                or_dash(window.max_gap),
+                or_dash(self.latency),
+                window.errors,
+                self.errors,
+                window.warnings,
+                self.warnings
+            )?;
+        }
+        out.flush()?;
+        self.started = now;
+        Ok(())
+    }
+}
@@ -0,0 +1,135 @@
+use crate::time;
+use crate::Exception;
+use std::fs::{self, File};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, Instant, SystemTime};
+
+/// When to start a new log file and how many old ones to keep.
+#[derive(Debug, Clone, Copy)]
+pub struct Rotation {
+    /// bytes
+    pub max_size: Option<u64>,
+    pub interval: Option<Duration>,
+    /// rotated files, the oldest beyond these are deleted
+    pub keep: usize,
+}
+
+/// A log file that is rotated by size or age, e.g. for the NDJSON events of a probe that runs
+/// unattended for days. The current file always has the given path, rotated ones get the UTC time
+/// of their rotation appended, e.g. `probe.ndjson.20240501T120000123Z`. Files are only rotated
+/// after a line break, so that every file holds whole lines.
+pub struct RotatingFile {
+    path: PathBuf,
+    rotation: Rotation,
+    file: File,
+    size: u64,
+    opened: Instant,
+}
+
+impl RotatingFile {
+    pub fn open(path: &Path, rotation: Rotation) -> Result<Self, Exception> {
+        if path.file_name().is_none() {
+            return Err(format!("Invalid log file {}", path.display()).into());
+        }
+        let file = File::options()
+            .create(true)
+            .append(true)
+            .open(path)
+            .map_err(|e| format!("Failed to open {}: {}", path.display(), e))?;
+        let size = file.metadata()?.len();
+        Ok(Self {
+            path: path.to_owned(),
+            rotation,
+            file,
+            size,
+            opened: Instant::now(),
+        })
+    }
+
+    fn is_due(&self) -> bool {
+        self.rotation.max_size.is_some_and(|max| self.size >= max)
+            || self
+                .rotation
+                .interval
+                .is_some_and(|interval| self.opened.elapsed() >= interval)
+    }
+
+    fn rotate(&mut self) -> io::Result<()> {
+        self.file.flush()?;
+        let stamp: String = time::format_utc(SystemTime::now())
+            .chars()
+            .filter(|c| !matches!(c, '-' | ':' | '.'))
+            .collect();
+        let name = self.file_name();
+        let mut rotated = self.path.with_file_name(format!("{}.{}", name, stamp));
+        let mut n = 1;
+        while rotated.exists() {
+            rotated = self
+                .path
+                .with_file_name(format!("{}.{}-{}", name, stamp, n));
+            n += 1;
+        }
+        fs::rename(&self.path, &rotated)?;
+        self.file = File::create(&self.path)?;
+        self.size = 0;
+        self.opened = Instant::now();
+        self.remove_old()
+    }
+
+    fn file_name(&self) -> String {
+        self.path
+            .file_name()
+            .map(|name| name.to_string_lossy().into_owned())
+            .unwrap_or_default()
+    }
+
+    // Deletes the oldest rotated files beyond the number to keep.
+    fn remove_old(&self) -> io::Result<()> {
+        let prefix = format!("{}.", self.file_name());
+        let dir = match self.path.parent() {
+            Some(dir) if !dir.as_os_str().is_empty() => dir,
+            _ => Path::new("."),
+        };
+        let mut rotated: Vec<String> = fs::read_dir(dir)?
+            .filter_map(|entry| entry.ok()?.file_name().into_string().ok())
+            .filter(|name| {
+                name.strip_prefix(&prefix).is_some_and(|stamp| {
+                    stamp.contains('Z')
+                        && stamp
+                            .chars()
+                            .all(|c| c.is_ascii_digit() || matches!(c, 'T' | 'Z' | '-'))
+                })
+            })
+            .collect();
+        // the stamps sort by time
+        rotated.sort();
+        let excess = rotated.len().saturating_sub(self.rotation.keep);
+        for name in &rotated[..excess] {
+            fs::remove_file(dir.join(name))?;
+        }
+        Ok(())
+    }
+}
+
+impl Write for RotatingFile {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        // up to the last line break, then the start of the next line
+        let split = buf.iter().rposition(|b| *b == b'\n').map_or(0, |i| i + 1);
+        let (lines, rest) = buf.split_at(split);
+        if !lines.is_empty() {
+            self.file.write_all(lines)?;
+            self.size += lines.len() as u64;
+            if self.is_due() {
+                self.rotate()?;
+            }
+        }
+        self.file.write_all(rest)?;
+        self.size += rest.len() as u64;
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        self.file.flush()
+    }
+}
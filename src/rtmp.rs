@@ -0,0 +1,541 @@
+use crate::amf::AmfValue;
+use crate::input::open_input;
+use crate::reader::{read_flv, Field, ParseOptions, TagType};
+use crate::Exception;
+use bytes::{Buf, BufMut, Bytes, BytesMut};
+use std::collections::HashMap;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+use tokio::io::{AsyncReadExt, AsyncWriteExt};
+use tokio::net::TcpStream;
+use tokio::time::{sleep_until, Instant};
+use tokio_stream::StreamExt;
+
+const DEFAULT_PORT: u16 = 1935;
+const HANDSHAKE_SIZE: usize = 1536;
+const DEFAULT_CHUNK_SIZE: usize = 128;
+const CHUNK_SIZE: usize = 4096;
+
+// Chunk stream ids
+const CONTROL_CSID: u8 = 2;
+const COMMAND_CSID: u8 = 3;
+const AUDIO_CSID: u8 = 4;
+const VIDEO_CSID: u8 = 6;
+const DATA_CSID: u8 = 8;
+
+// Message type ids
+const SET_CHUNK_SIZE: u8 = 1;
+const ACKNOWLEDGEMENT: u8 = 3;
+const USER_CONTROL: u8 = 4;
+const WINDOW_ACK_SIZE: u8 = 5;
+const AUDIO: u8 = 8;
+const VIDEO: u8 = 9;
+const AMF3_COMMAND: u8 = 17;
+const AMF0_DATA: u8 = 18;
+const AMF0_COMMAND: u8 = 20;
+
+// User control events
+const PING_REQUEST: u16 = 6;
+const PING_RESPONSE: u16 = 7;
+
+/// `rtmp://host[:port]/app[/instance]/stream_key`
+#[derive(Debug)]
+pub struct RtmpUrl {
+    host: String,
+    port: u16,
+    app: String,
+    stream_key: String,
+}
+
+impl RtmpUrl {
+    pub fn parse(url: &str) -> Result<Self, Exception> {
+        let invalid = || format!("Invalid RTMP URL: {}", url);
+        let rest = url.strip_prefix("rtmp://").ok_or_else(invalid)?;
+        let (authority, path) = rest.split_once('/').ok_or_else(invalid)?;
+        let (app, stream_key) = path.rsplit_once('/').ok_or_else(invalid)?;
+        if authority.is_empty() || app.is_empty() || stream_key.is_empty() {
+            return Err(invalid().into());
+        }
+
+        let (host, port) = match authority.rsplit_once(':') {
+            Some((host, port)) => (host, port.parse().map_err(|_| invalid())?),
+            None => (authority, DEFAULT_PORT),
+        };
+
+        Ok(Self {
+            host: host.to_owned(),
+            port,
+            app: app.to_owned(),
+            stream_key: stream_key.to_owned(),
+        })
+    }
+
+    fn tc_url(&self) -> String {
+        format!("rtmp://{}:{}/{}", self.host, self.port, self.app)
+    }
+}
+
+struct Message {
+    type_id: u8,
+    payload: Bytes,
+}
+
+// State of an incoming chunk stream: the fields of the last message header plus the payload
+// of the message being assembled.
+#[derive(Default)]
+struct ChunkStream {
+    length: usize,
+    type_id: u8,
+    extended: bool,
+    payload: BytesMut,
+}
+
+struct Connection {
+    stream: TcpStream,
+    read_buf: BytesMut,
+    // Control messages produced while reading, sent with the next write.
+    pending: BytesMut,
+    in_chunk_size: usize,
+    out_chunk_size: usize,
+    chunk_streams: HashMap<u32, ChunkStream>,
+    received: u64,
+    acknowledged: u64,
+    ack_window: u64,
+}
+
+impl Connection {
+    async fn connect(url: &RtmpUrl) -> Result<Self, Exception> {
+        let mut stream = TcpStream::connect((url.host.as_str(), url.port)).await?;
+        stream.set_nodelay(true)?;
+
+        // Simple handshake: C0 + C1, then S0 + S1 + S2, then C2 echoing S1.
+        let mut c0c1 = BytesMut::with_capacity(1 + HANDSHAKE_SIZE);
+        c0c1.put_u8(3);
+        c0c1.put_u32(0);
+        c0c1.put_u32(0);
+        let mut seed = SystemTime::now()
+            .duration_since(UNIX_EPOCH)
+            .map(|duration| duration.as_nanos() as u32)
+            .unwrap_or(0)
+            | 1;
+        while c0c1.len() < 1 + HANDSHAKE_SIZE {
+            // xorshift32, the random block only has to be hard to mistake for anything else
+            seed ^= seed << 13;
+            seed ^= seed >> 17;
+            seed ^= seed << 5;
+            c0c1.put_u8(seed as u8);
+        }
+        stream.write_all(&c0c1).await?;
+
+        let mut s0s1s2 = vec![0; 1 + 2 * HANDSHAKE_SIZE];
+        stream.read_exact(&mut s0s1s2).await?;
+        if s0s1s2[0] != 3 {
+            return Err(format!("Unsupported RTMP version: {}", s0s1s2[0]).into());
+        }
+        stream.write_all(&s0s1s2[1..=HANDSHAKE_SIZE]).await?;
+
+        Ok(Self {
+            stream,
+            read_buf: BytesMut::new(),
+            pending: BytesMut::new(),
+            in_chunk_size: DEFAULT_CHUNK_SIZE,
+            out_chunk_size: DEFAULT_CHUNK_SIZE,
+            chunk_streams: HashMap::new(),
+            received: 0,
+            acknowledged: 0,
+            ack_window: 0,
+        })
+    }
+
+    /// Reads the next message that is not a protocol control message. Cancelling this is safe,
+    /// partially received chunks stay buffered.
+    async fn read_message(&mut self) -> Result<Message, Exception> {
+        loop {
+            while let Some(message) = self.parse_chunk()? {
+                if let Some(message) = self.handle_control(message)? {
+                    return Ok(message);
+                }
+            }
+
+            let n = self.stream.read_buf(&mut self.read_buf).await?;
+            if n == 0 {
+                return Err("RTMP server closed the connection".into());
+            }
+            self.received += n as u64;
+            if self.ack_window > 0 && self.received - self.acknowledged >= self.ack_window {
+                self.acknowledged = self.received;
+                let sequence = (self.received as u32).to_be_bytes();
+                self.queue_message(CONTROL_CSID, ACKNOWLEDGEMENT, 0, 0, &sequence);
+            }
+        }
+    }
+
+    // Consumes one chunk from the read buffer, returning the message it completes, if any.
+    // Nothing is consumed until the whole chunk has been received.
+    fn parse_chunk(&mut self) -> Result<Option<Message>, Exception> {
+        let mut src = &self.read_buf[..];
+        if src.is_empty() {
+            return Ok(None);
+        }
+
+        let first = src.get_u8();
+        let fmt = first >> 6;
+        let csid = match first & 0x3f {
+            0 if src.remaining() >= 1 => 64 + src.get_u8() as u32,
+            1 if src.remaining() >= 2 => 64 + src.get_u16_le() as u32,
+            0 | 1 => return Ok(None),
+            csid => csid as u32,
+        };
+
+        let header_size = [11, 7, 3, 0][fmt as usize];
+        if src.remaining() < header_size {
+            return Ok(None);
+        }
+
+        let mut state = self.chunk_streams.remove(&csid).unwrap_or_default();
+        if fmt > 0 && state.type_id == 0 {
+            return Err(format!("RTMP chunk stream {} starts without a full header", csid).into());
+        }
+
+        // Timestamps of incoming messages are of no interest, only the framing is tracked.
+        let (mut length, mut type_id, mut extended) = (state.length, state.type_id, state.extended);
+        if fmt < 3 {
+            extended = read_u24(&mut src) == 0xFF_FFFF;
+        }
+        if fmt < 2 {
+            length = read_u24(&mut src) as usize;
+            type_id = src.get_u8();
+        }
+        if fmt == 0 {
+            src.advance(4); // message stream id
+        }
+
+        let extended_size = if extended { 4 } else { 0 };
+        let size = self
+            .in_chunk_size
+            .min(length.saturating_sub(state.payload.len()));
+        if src.remaining() < extended_size + size {
+            self.chunk_streams.insert(csid, state);
+            return Ok(None);
+        }
+        src.advance(extended_size);
+        state.length = length;
+        state.type_id = type_id;
+        state.extended = extended;
+
+        state.payload.extend_from_slice(&src[..size]);
+        let consumed = self.read_buf.len() - src.remaining() + size;
+        self.read_buf.advance(consumed);
+
+        let message = if state.payload.len() == state.length {
+            Some(Message {
+                type_id: state.type_id,
+                payload: state.payload.split().freeze(),
+            })
+        } else {
+            None
+        };
+        self.chunk_streams.insert(csid, state);
+        Ok(message)
+    }
+
+    fn handle_control(&mut self, message: Message) -> Result<Option<Message>, Exception> {
+        let mut payload = &message.payload[..];
+        match message.type_id {
+            SET_CHUNK_SIZE if payload.len() >= 4 => {
+                self.in_chunk_size = (payload.get_u32() & 0x7fff_ffff).max(1) as usize;
+            }
+            WINDOW_ACK_SIZE if payload.len() >= 4 => self.ack_window = payload.get_u32() as u64,
+            USER_CONTROL if payload.len() >= 6 => {
+                if payload.get_u16() == PING_REQUEST {
+                    let mut response = BytesMut::with_capacity(6);
+                    response.put_u16(PING_RESPONSE);
+                    response.put_slice(&payload[..4]);
+                    self.queue_message(CONTROL_CSID, USER_CONTROL, 0, 0, &response);
+                }
+            }
+            SET_CHUNK_SIZE..=6 => {}
+            _ => return Ok(Some(message)),
+        }
+        Ok(None)
+    }
+
+    fn queue_message(
+        &mut self,
+        csid: u8,
+        type_id: u8,
+        stream_id: u32,
+        timestamp: u32,
+        payload: &[u8],
+    ) {
+        let dst = &mut self.pending;
+        let extended = timestamp >= 0xFF_FFFF;
+        let [_, l1, l2, l3] = (payload.len() as u32).to_be_bytes();
+
+        dst.put_u8(csid);
+        put_u24(dst, timestamp.min(0xFF_FFFF));
+        dst.put_slice(&[l1, l2, l3, type_id]);
+        dst.put_u32_le(stream_id);
+        if extended {
+            dst.put_u32(timestamp);
+        }
+
+        for (index, chunk) in payload.chunks(self.out_chunk_size).enumerate() {
+            if index > 0 {
+                dst.put_u8(0xc0 | csid);
+                if extended {
+                    dst.put_u32(timestamp);
+                }
+            }
+            dst.put_slice(chunk);
+        }
+    }
+
+    async fn flush(&mut self) -> Result<(), Exception> {
+        if !self.pending.is_empty() {
+            self.stream.write_all(&self.pending).await?;
+            self.pending.clear();
+        }
+        Ok(())
+    }
+
+    async fn write_message(
+        &mut self,
+        csid: u8,
+        type_id: u8,
+        stream_id: u32,
+        timestamp: u32,
+        payload: &[u8],
+    ) -> Result<(), Exception> {
+        self.queue_message(csid, type_id, stream_id, timestamp, payload);
+        self.flush().await
+    }
+
+    async fn set_chunk_size(&mut self, size: usize) -> Result<(), Exception> {
+        self.write_message(
+            CONTROL_CSID,
+            SET_CHUNK_SIZE,
+            0,
+            0,
+            &(size as u32).to_be_bytes(),
+        )
+        .await?;
+        self.out_chunk_size = size;
+        Ok(())
+    }
+
+    async fn command(
+        &mut self,
+        stream_id: u32,
+        name: &str,
+        transaction_id: f64,
+        arguments: Vec<AmfValue>,
+    ) -> Result<(), Exception> {
+        let mut payload = BytesMut::new();
+        AmfValue::String(name.to_owned()).encode(&mut payload);
+        AmfValue::Number(transaction_id).encode(&mut payload);
+        for argument in arguments {
+            argument.encode(&mut payload);
+        }
+        self.write_message(COMMAND_CSID, AMF0_COMMAND, stream_id, 0, &payload)
+            .await
+    }
+
+    /// Waits for the `_result` of a command, skipping any other messages.
+    async fn result(&mut self, transaction_id: f64) -> Result<Vec<AmfValue>, Exception> {
+        loop {
+            let message = self.read_message().await?;
+            self.flush().await?;
+            let values = match check_command(&message)? {
+                Some(values) => values,
+                None => continue,
+            };
+            if values.get(1).and_then(AmfValue::as_number) != Some(transaction_id) {
+                continue;
+            }
+            match values[0].as_str() {
+                Some("_result") => return Ok(values),
+                Some("_error") => {
+                    return Err(format!("RTMP command failed: {}", describe(&values)).into())
+                }
+                _ => {}
+            }
+        }
+    }
+
+    async fn publish_started(&mut self) -> Result<(), Exception> {
+        loop {
+            let message = self.read_message().await?;
+            self.flush().await?;
+            if let Some(values) = check_command(&message)? {
+                let code = values
+                    .get(3)
+                    .and_then(|info| info.property("code"))
+                    .and_then(AmfValue::as_str);
+                if code == Some("NetStream.Publish.Start") {
+                    return Ok(());
+                }
+            }
+        }
+    }
+}
+
+// Decodes a command message. onStatus errors are turned into errors.
+fn check_command(message: &Message) -> Result<Option<Vec<AmfValue>>, Exception> {
+    let payload = match message.type_id {
+        AMF0_COMMAND => &message.payload[..],
+        // AMF3 commands start with a format byte and are AMF0 encoded otherwise.
+        AMF3_COMMAND if !message.payload.is_empty() => &message.payload[1..],
+        _ => return Ok(None),
+    };
+
+    let values = AmfValue::decode_all(payload)?;
+    if values.first().and_then(AmfValue::as_str) == Some("onStatus") {
+        let level = values
+            .get(3)
+            .and_then(|info| info.property("level"))
+            .and_then(AmfValue::as_str);
+        if level == Some("error") {
+            return Err(format!("RTMP publish failed: {}", describe(&values)).into());
+        }
+    }
+    Ok(Some(values))
+}
+
+// The code and description of a _result/_error/onStatus info object.
+fn describe(values: &[AmfValue]) -> String {
+    let info = values.get(3);
+    let field = |name| {
+        info.and_then(|info| info.property(name))
+            .and_then(AmfValue::as_str)
+            .unwrap_or_default()
+    };
+    format!("{} {}", field("code"), field("description"))
+        .trim()
+        .to_owned()
+}
+
+fn read_u24(src: &mut &[u8]) -> u32 {
+    let [b0, b1, b2] = [src.get_u8(), src.get_u8(), src.get_u8()];
+    u32::from_be_bytes([0, b0, b1, b2])
+}
+
+fn put_u24(dst: &mut BytesMut, value: u32) {
+    dst.put_slice(&value.to_be_bytes()[1..]);
+}
+
+/// Publishes a FLV file to an RTMP server in real time, pacing tags by their timestamps.
+pub async fn publish(url: &str, input: &str) -> Result<(), Exception> {
+    let url = RtmpUrl::parse(url)?;
+    let (_, input) = open_input(input).await?;
+    let (_, mut decoder) = read_flv(input, ParseOptions::default()).await?;
+
+    let mut conn = Connection::connect(&url).await?;
+    conn.set_chunk_size(CHUNK_SIZE).await?;
+
+    let tc_url = url.tc_url();
+    let properties = vec![
+        ("app".to_owned(), AmfValue::String(url.app.clone())),
+        ("type".to_owned(), AmfValue::String("nonprivate".to_owned())),
+        (
+            "flashVer".to_owned(),
+            AmfValue::String("FMLE/3.0 (compatible; flv-dump)".to_owned()),
+        ),
+        ("tcUrl".to_owned(), AmfValue::String(tc_url)),
+    ];
+    conn.command(0, "connect", 1.0, vec![AmfValue::Object(properties)])
+        .await?;
+    conn.result(1.0).await?;
+
+    let key = AmfValue::String(url.stream_key.clone());
+    conn.command(0, "releaseStream", 2.0, vec![AmfValue::Null, key.clone()])
+        .await?;
+    conn.command(0, "FCPublish", 3.0, vec![AmfValue::Null, key.clone()])
+        .await?;
+    conn.command(0, "createStream", 4.0, vec![AmfValue::Null])
+        .await?;
+    let stream_id = conn
+        .result(4.0)
+        .await?
+        .get(3)
+        .and_then(AmfValue::as_number)
+        .ok_or("createStream returned no stream id")? as u32;
+
+    conn.command(
+        stream_id,
+        "publish",
+        5.0,
+        vec![
+            AmfValue::Null,
+            key.clone(),
+            AmfValue::String("live".to_owned()),
+        ],
+    )
+    .await?;
+    conn.publish_started().await?;
+
+    let mut set_data_frame = BytesMut::new();
+    AmfValue::String("@setDataFrame".to_owned()).encode(&mut set_data_frame);
+
+    let mut start = None;
+    let mut tags = 0;
+    let mut last_timestamp = 0;
+    while let Some(field) = decoder.next().await {
+        let tag = match field? {
+            Field::Tag(tag) => tag,
+            Field::PreTagSize(_) => continue,
+            Field::Warning(finding) => {
+                eprintln!("{}", finding);
+                continue;
+            }
+        };
+
+        let (csid, type_id) = match tag.header.tag_type {
+            TagType::Audio => (AUDIO_CSID, AUDIO),
+            TagType::Video => (VIDEO_CSID, VIDEO),
+            TagType::Script => (DATA_CSID, AMF0_DATA),
+            TagType::Reserved(_) => continue,
+        };
+
+        // Wait until the tag is due, answering the server in the meantime.
+        let timestamp = tag.header.timestamp as u32;
+        let (start_time, first_timestamp) = *start.get_or_insert((Instant::now(), timestamp));
+        let due =
+            start_time + Duration::from_millis(timestamp.saturating_sub(first_timestamp) as u64);
+        loop {
+            tokio::select! {
+                _ = sleep_until(due) => break,
+                message = conn.read_message() => {
+                    check_command(&message?)?;
+                }
+            }
+        }
+
+        let mut payload = BytesMut::new();
+        if type_id == AMF0_DATA {
+            payload.extend_from_slice(&set_data_frame);
+        }
+        tag.data.encode(&mut payload);
+        conn.write_message(csid, type_id, stream_id, timestamp, &payload)
+            .await?;
+
+        tags += 1;
+        last_timestamp = timestamp;
+    }
+
+    conn.command(0, "FCUnpublish", 6.0, vec![AmfValue::Null, key])
+        .await?;
+    conn.command(
+        0,
+        "deleteStream",
+        7.0,
+        vec![AmfValue::Null, AmfValue::Number(stream_id as f64)],
+    )
+    .await?;
+
+    println!("PublishedTags: {}", tags);
+    println!(
+        "Duration: {}",
+        last_timestamp.saturating_sub(start.map_or(0, |(_, first)| first))
+    );
+    Ok(())
+}
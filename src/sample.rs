@@ -0,0 +1,73 @@
+use crate::reader::{Tag, TagType};
+use crate::time;
+use crate::Exception;
+use std::str::FromStr;
+
+/// Which tags a sampled dump shows: a few per interval of the timeline, or every Nth tag.
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum Sampling {
+    /// milliseconds
+    Interval(i64),
+    Tags(usize),
+}
+
+/// `10s`, `500ms`, `2m` or `1h` for an interval, a plain number N for every Nth tag.
+impl FromStr for Sampling {
+    type Err = Exception;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let sampling = match s.parse::<usize>() {
+            Ok(every) => Sampling::Tags(every),
+            Err(_) => Sampling::Interval(time::parse_duration(s)?.as_millis() as i64),
+        };
+        if let Sampling::Tags(0) | Sampling::Interval(0) = sampling {
+            return Err(format!("Invalid sampling {}, expected more than 0", s).into());
+        }
+        Ok(sampling)
+    }
+}
+
+/// Picks the tags of a sampled dump. With an interval, the timeline is cut into windows of that
+/// length from the first tag, and the first tag of each type (audio, video, script) in each
+/// window is shown, so that every stream stays visible. With a tag count, tags 1, N + 1, 2N + 1
+/// and so on are shown.
+pub struct Sampler {
+    sampling: Sampling,
+    start: Option<i64>,
+    window: Option<i64>,
+    // tag types already shown in the current window
+    shown: Vec<TagType>,
+}
+
+impl Sampler {
+    pub fn new(sampling: Sampling) -> Self {
+        Self {
+            sampling,
+            start: None,
+            window: None,
+            shown: vec![],
+        }
+    }
+
+    /// Whether to show the tag with this index, counted from 1.
+    pub fn take(&mut self, index: usize, tag: &Tag) -> bool {
+        match self.sampling {
+            Sampling::Tags(every) => (index - 1).is_multiple_of(every),
+            Sampling::Interval(interval) => {
+                let timestamp = tag.header.timestamp as i64;
+                let start = *self.start.get_or_insert(timestamp);
+                let window = (timestamp - start).div_euclid(interval);
+                if self.window != Some(window) {
+                    self.window = Some(window);
+                    self.shown.clear();
+                }
+                let tag_type = tag.header.tag_type;
+                if self.shown.contains(&tag_type) {
+                    return false;
+                }
+                self.shown.push(tag_type);
+                true
+            }
+        }
+    }
+}
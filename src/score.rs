@@ -0,0 +1,135 @@
+use crate::stats::Stats;
+
+// Keyframes further apart make seeking slow however regular they are.
+const MAX_KEYFRAME_INTERVAL: f64 = 10_000.0;
+// ms between audio and video, full score up to the first value and none from the second on
+const INTERLEAVING: (f64, f64) = (500.0, 5_000.0);
+const START_ALIGNMENT: (f64, f64) = (100.0, 2_000.0);
+
+/// One aspect of how well a file seeks and streams.
+#[derive(Debug)]
+pub struct Factor {
+    pub name: &'static str,
+    pub weight: u32,
+    /// 0 to 100
+    pub score: u32,
+    pub detail: String,
+}
+
+/// The weighted average of the factors that apply to the file, e.g. interleaving only with
+/// both audio and video.
+#[derive(Debug)]
+pub struct Score {
+    /// 0 to 100
+    pub total: u32,
+    pub factors: Vec<Factor>,
+}
+
+// 1 up to `good`, 0 from `bad` on, linear in between.
+fn falloff(value: f64, (good, bad): (f64, f64)) -> f64 {
+    ((bad - value) / (bad - good)).clamp(0.0, 1.0)
+}
+
+fn factor(name: &'static str, weight: u32, value: f64, detail: String) -> (Factor, f64) {
+    let factor = Factor {
+        name,
+        weight,
+        score: (value * 100.0).round() as u32,
+        detail,
+    };
+    (factor, value)
+}
+
+impl Score {
+    /// None for files without audio and video.
+    pub fn new(stats: &Stats) -> Option<Self> {
+        let has_video = stats.timestamps.video.is_some();
+        let has_audio = stats.timestamps.audio.is_some();
+        if !has_audio && !has_video {
+            return None;
+        }
+        let mut factors = vec![];
+
+        if has_video {
+            let intervals = &stats.keyframe_map.intervals;
+            factors.push(if intervals.is_empty() {
+                factor(
+                    "keyframe_regularity",
+                    30,
+                    0.0,
+                    "fewer than 2 keyframes".into(),
+                )
+            } else {
+                let n = intervals.len() as f64;
+                let mean = intervals.iter().sum::<i64>() as f64 / n;
+                let variance = intervals
+                    .iter()
+                    .map(|interval| (*interval as f64 - mean).powi(2))
+                    .sum::<f64>()
+                    / n;
+                let variation = if mean > 0.0 {
+                    variance.sqrt() / mean
+                } else {
+                    1.0
+                };
+                let max = *intervals.iter().max().unwrap_or(&0) as f64;
+                let value = (1.0 - variation).clamp(0.0, 1.0)
+                    * (MAX_KEYFRAME_INTERVAL / max.max(1.0)).min(1.0);
+                factor(
+                    "keyframe_regularity",
+                    30,
+                    value,
+                    format!(
+                        "keyframe interval {:.0} ms ± {:.0}%, at most {:.0} ms",
+                        mean,
+                        variation * 100.0,
+                        max
+                    ),
+                )
+            });
+        }
+
+        factors.push(match stats.keyframe_index {
+            Some(true) => factor(
+                "metadata_index",
+                20,
+                1.0,
+                "onMetaData has a keyframes index".into(),
+            ),
+            Some(false) => factor(
+                "metadata_index",
+                20,
+                0.0,
+                "onMetaData has no keyframes index".into(),
+            ),
+            None => factor("metadata_index", 20, 0.0, "no onMetaData".into()),
+        });
+
+        if has_audio && has_video {
+            let distance = stats.interleaving.max_distance;
+            factors.push(factor(
+                "interleaving",
+                30,
+                falloff(distance as f64, INTERLEAVING),
+                format!("audio and video at most {} ms apart", distance),
+            ));
+            let offset = stats.timestamps.audio_video_offset().unwrap_or(0);
+            factors.push(factor(
+                "start_alignment",
+                20,
+                falloff(offset.abs() as f64, START_ALIGNMENT),
+                format!("audio starts {:+} ms after video", offset),
+            ));
+        }
+
+        let weights: u32 = factors.iter().map(|(factor, _)| factor.weight).sum();
+        let weighted: f64 = factors
+            .iter()
+            .map(|(factor, value)| factor.weight as f64 * value)
+            .sum();
+        Some(Score {
+            total: (weighted * 100.0 / weights as f64).round() as u32,
+            factors: factors.into_iter().map(|(factor, _)| factor).collect(),
+        })
+    }
+}
@@ -0,0 +1,112 @@
+use crate::bits::BitReader;
+use crate::reader::{AudioDataHeader, SoundRate, SoundSize, SoundType};
+use crate::Exception;
+
+/// FLV only allows wideband Speex: 16 kHz mono, 320 samples per 20 ms frame.
+pub const SAMPLE_RATE: u32 = 16000;
+pub const SAMPLES_PER_FRAME: u32 = 320;
+
+// Bits per frame of each narrowband and wideband submode, including the wideband bit and the
+// submode number in front of it.
+const NB_FRAME_BITS: [usize; 9] = [5, 43, 119, 160, 220, 300, 364, 492, 79];
+const WB_FRAME_BITS: [usize; 5] = [4, 36, 112, 192, 352];
+const NB_SUBMODE_BITS: u32 = 4;
+const WB_SUBMODE_BITS: u32 = 3;
+// Narrowband "submodes" that are not frames.
+const USER_INBAND: u32 = 13;
+const SPEEX_INBAND: u32 = 14;
+const TERMINATOR: u32 = 15;
+
+/// Checks the header of a Speex tag: the audio must be 16 kHz mono, with SoundRate 0 (5.5 kHz,
+/// since SoundRate can not express 16 kHz), SoundSize 16 bit and SoundType mono.
+pub fn check_header(header: &AudioDataHeader) -> Result<(), Exception> {
+    let mut errors = vec![];
+    if header.sound_rate != SoundRate::R5p5kHz {
+        errors.push(format!("SoundRate {:?}", header.sound_rate));
+    }
+    if header.sound_size != SoundSize::S16Bit {
+        errors.push(format!("SoundSize {:?}", header.sound_size));
+    }
+    if header.sound_type != SoundType::Mono {
+        errors.push(format!("SoundType {:?}", header.sound_type));
+    }
+    if errors.is_empty() {
+        return Ok(());
+    }
+    Err(format!(
+        "Invalid Speex configuration: {}, expected R5p5kHz, S16Bit and Mono",
+        errors.join(", ")
+    )
+    .into())
+}
+
+/// Counts the frames of a tag body by walking the Speex bitstream. Every frame has to carry a
+/// wideband layer in front of the narrowband one.
+pub fn count_frames(data: &[u8]) -> Result<u32, Exception> {
+    let mut reader = BitReader::new(data);
+    let mut frames = 0;
+    // whether the current frame has a wideband layer
+    let mut wideband = false;
+    // the shortest frame is the wideband bit and a silent narrowband submode, anything less is
+    // padding
+    while reader.remaining() >= NB_FRAME_BITS[0] {
+        if reader.read_bit()? {
+            if wideband {
+                return Err(format!(
+                    "Speex frame {} has more than one wideband layer",
+                    frames + 1
+                )
+                .into());
+            }
+            let submode = reader.read_bits(WB_SUBMODE_BITS)? as usize;
+            let bits = WB_FRAME_BITS.get(submode).ok_or_else(|| {
+                format!(
+                    "Invalid Speex wideband submode {} in frame {}",
+                    submode,
+                    frames + 1
+                )
+            })?;
+            reader.skip_bits(bits - 1 - WB_SUBMODE_BITS as usize)?;
+            wideband = true;
+            continue;
+        }
+
+        match reader.read_bits(NB_SUBMODE_BITS)? {
+            TERMINATOR => break,
+            SPEEX_INBAND => {
+                let bits = match reader.read_bits(4)? {
+                    0..=1 => 1,
+                    2..=7 => 4,
+                    8..=9 => 8,
+                    10..=11 => 16,
+                    12..=13 => 32,
+                    _ => 64,
+                };
+                reader.skip_bits(bits)?;
+            }
+            USER_INBAND => {
+                let size = reader.read_bits(4)? as usize;
+                reader.skip_bits(5 + 8 * size)?;
+            }
+            submode => {
+                let bits = NB_FRAME_BITS.get(submode as usize).ok_or_else(|| {
+                    format!("Invalid Speex submode {} in frame {}", submode, frames + 1)
+                })?;
+                if !wideband {
+                    return Err(format!(
+                        "Speex frame {} is narrowband (8 kHz), FLV requires 16 kHz wideband",
+                        frames + 1
+                    )
+                    .into());
+                }
+                reader.skip_bits(bits - 1 - NB_SUBMODE_BITS as usize)?;
+                frames += 1;
+                wideband = false;
+            }
+        }
+    }
+    if frames == 0 {
+        return Err("No Speex frame".into());
+    }
+    Ok(frames)
+}
@@ -0,0 +1,1347 @@
+use crate::aac::{AacPacketType, AudioSpecificConfig};
+use crate::avc::{DecoderConfigurationRecord, Sps};
+use crate::fingerprint::Fingerprint;
+use crate::g711;
+use crate::mp3;
+use crate::nellymoser;
+use crate::order::EarlyFrames;
+use crate::pcm::{self, Endianness};
+use crate::profile::{self, Stage};
+use crate::reader::{
+    AudioData, AudioDataHeader, CodecId, SoundFormat, Tag, TagData, TagType, VideoData,
+    VideoFrameType,
+};
+use crate::speex;
+use std::collections::hash_map::DefaultHasher;
+use std::collections::{BTreeMap, HashSet};
+use std::convert::TryFrom;
+use std::hash::Hasher;
+
+// A stream whose per-second bitrate varies less than this is considered constant bitrate.
+const CBR_MAX_VARIATION: f64 = 0.1;
+// The measured frame rate may deviate this much from onMetaData's framerate.
+const FRAME_RATE_TOLERANCE: f64 = 0.05;
+// Video with more frame intervals off its cadence has a variable frame rate.
+const MAX_VFR_SHARE: f64 = 0.05;
+
+#[derive(Debug, Default)]
+pub struct StreamStats {
+    pub tags: usize,
+    pub bytes: u64,
+    // payload bytes per second of timestamp
+    seconds: BTreeMap<i64, u64>,
+}
+
+#[derive(Debug)]
+pub struct Bitrate {
+    /// kbps
+    pub average: f64,
+    /// kbps
+    pub std_dev: f64,
+    /// kbps
+    pub peak: f64,
+}
+
+#[allow(clippy::upper_case_acronyms)]
+#[derive(Debug, Copy, Clone, PartialEq, Eq)]
+pub enum BitrateMode {
+    CBR,
+    VBR,
+}
+
+impl Bitrate {
+    pub fn variation(&self) -> f64 {
+        self.std_dev / self.average
+    }
+
+    pub fn peak_to_average(&self) -> f64 {
+        self.peak / self.average
+    }
+
+    pub fn mode(&self) -> BitrateMode {
+        if self.variation() < CBR_MAX_VARIATION {
+            BitrateMode::CBR
+        } else {
+            BitrateMode::VBR
+        }
+    }
+}
+
+impl StreamStats {
+    pub fn push(&mut self, tag: &Tag) {
+        self.tags += 1;
+        self.bytes += tag.header.data_size as u64;
+        *self
+            .seconds
+            .entry((tag.header.timestamp as i64).div_euclid(1000))
+            .or_default() += tag.header.data_size as u64;
+    }
+
+    /// Per-second bitrate statistics. The last second is usually incomplete and is left out, so
+    /// at least two seconds of data are needed.
+    pub fn bitrate(&self) -> Option<Bitrate> {
+        let first = *self.seconds.keys().next()?;
+        let last = *self.seconds.keys().next_back()?;
+        if last - first < 2 {
+            return None;
+        }
+
+        let samples: Vec<f64> = (first..last)
+            .map(|second| self.seconds.get(&second).copied().unwrap_or(0) as f64 * 8.0 / 1000.0)
+            .collect();
+        let average = samples.iter().sum::<f64>() / samples.len() as f64;
+        if average == 0.0 {
+            return None;
+        }
+        let variance = samples
+            .iter()
+            .map(|sample| (sample - average).powi(2))
+            .sum::<f64>()
+            / samples.len() as f64;
+        let peak = samples.iter().copied().fold(0.0, f64::max);
+
+        Some(Bitrate {
+            average,
+            std_dev: variance.sqrt(),
+            peak,
+        })
+    }
+}
+
+/// Distribution of the timestamp deltas between consecutive video frames.
+#[derive(Debug, Default)]
+pub struct FrameIntervals {
+    frames: usize,
+    first: Option<i32>,
+    last: Option<i32>,
+    /// number of frames per interval in milliseconds
+    pub intervals: BTreeMap<i64, usize>,
+}
+
+#[derive(Debug)]
+pub struct FrameRate {
+    /// fps over the whole stream
+    pub average: f64,
+    /// ms, the most common frame interval
+    pub mode_interval: i64,
+    /// share of the frame intervals equal to the mode
+    pub mode_share: f64,
+}
+
+impl FrameRate {
+    /// fps according to the most common frame interval
+    pub fn mode(&self) -> Option<f64> {
+        if self.mode_interval > 0 {
+            Some(1000.0 / self.mode_interval as f64)
+        } else {
+            None
+        }
+    }
+}
+
+/// Whether the video has a constant or variable frame rate.
+#[derive(Debug)]
+pub struct Cadence {
+    /// less than `MAX_VFR_SHARE` of the intervals off the cadence
+    pub constant: bool,
+    /// ms and share of the intervals, the most common one and its neighbours by a millisecond,
+    /// which millisecond timestamps alternate between at e.g. 30 fps
+    pub dominant: Vec<(i64, f64)>,
+    /// share of the intervals that are neither dominant nor a multiple of the most common one,
+    /// as after dropped frames
+    pub variable_share: f64,
+}
+
+impl FrameIntervals {
+    pub fn cadence(&self) -> Option<Cadence> {
+        let total: usize = self.intervals.values().sum();
+        let mode = self.frame_rate()?.mode_interval;
+        if mode <= 0 {
+            return None;
+        }
+        let share = |count: usize| count as f64 / total as f64;
+        let mut dominant: Vec<(i64, usize)> = (mode - 1..=mode + 1)
+            .filter_map(|interval| Some((interval, *self.intervals.get(&interval)?)))
+            .collect();
+        dominant.sort_by_key(|(interval, count)| (std::cmp::Reverse(*count), *interval));
+        let variable: usize = self
+            .intervals
+            .iter()
+            .filter(|(interval, _)| {
+                let multiple = (**interval as f64 / mode as f64).round() as i64;
+                multiple < 1 || (**interval - multiple * mode).abs() > multiple
+            })
+            .map(|(_, count)| count)
+            .sum();
+        let variable_share = share(variable);
+        Some(Cadence {
+            constant: variable_share < MAX_VFR_SHARE,
+            dominant: dominant
+                .into_iter()
+                .map(|(interval, count)| (interval, share(count)))
+                .collect(),
+            variable_share,
+        })
+    }
+
+    pub fn push(&mut self, tag: &Tag) {
+        // Sequence headers are decoder configuration, not frames.
+        if tag.data.is_sequence_header() {
+            return;
+        }
+        let timestamp = tag.header.timestamp;
+        if let Some(last) = self.last {
+            *self
+                .intervals
+                .entry(timestamp as i64 - last as i64)
+                .or_default() += 1;
+        }
+        self.frames += 1;
+        self.first.get_or_insert(timestamp);
+        self.last = Some(timestamp);
+    }
+
+    pub fn frame_rate(&self) -> Option<FrameRate> {
+        let duration = (self.last? as i64 - self.first? as i64) as f64;
+        if duration <= 0.0 {
+            return None;
+        }
+        let (&mode_interval, &count) = self
+            .intervals
+            .iter()
+            .max_by_key(|(interval, count)| (**count, -**interval))?;
+
+        Some(FrameRate {
+            average: (self.frames - 1) as f64 * 1000.0 / duration,
+            mode_interval,
+            mode_share: count as f64 / (self.frames - 1) as f64,
+        })
+    }
+}
+
+/// Gaps between video frames that are too long for the frame rate, taken for dropped frames.
+/// Which gaps count is only known at the end, with the nominal frame interval, so the timestamps
+/// of all frames are kept.
+#[derive(Debug, Default)]
+pub struct FrameDrops {
+    timestamps: Vec<i32>,
+}
+
+/// Frames that went missing where the timestamps leap.
+#[derive(Debug)]
+pub struct FrameDrop {
+    /// ms, of the last frame before the gap
+    pub after: i32,
+    pub frames: u64,
+}
+
+#[derive(Debug)]
+pub struct DropReport {
+    /// ms
+    pub nominal_interval: f64,
+    pub drops: Vec<FrameDrop>,
+    /// dropped frames per minute of the timestamps, minutes without drops left out
+    pub minutes: BTreeMap<i64, u64>,
+}
+
+impl DropReport {
+    pub fn total(&self) -> u64 {
+        self.drops.iter().map(|drop| drop.frames).sum()
+    }
+}
+
+impl FrameDrops {
+    pub fn push(&mut self, tag: &Tag) {
+        if tag.data.is_sequence_header() {
+            return;
+        }
+        self.timestamps.push(tag.header.timestamp);
+    }
+
+    /// The frames missing in the gaps of more than 1.5 nominal frame intervals.
+    pub fn report(&self, nominal_interval: f64) -> DropReport {
+        let mut report = DropReport {
+            nominal_interval,
+            drops: vec![],
+            minutes: BTreeMap::new(),
+        };
+        for pair in self.timestamps.windows(2) {
+            let (after, gap) = (pair[0], (pair[1] as i64 - pair[0] as i64) as f64);
+            if gap <= nominal_interval * 1.5 {
+                continue;
+            }
+            let frames = (gap / nominal_interval).round() as u64 - 1;
+            report.drops.push(FrameDrop { after, frames });
+            *report
+                .minutes
+                .entry((after as i64).div_euclid(60000))
+                .or_default() += frames;
+        }
+        report
+    }
+}
+
+/// Distribution of the CompositionTime of AVC NALUs, the offset of the presentation time from the
+/// decoding time. Keyframes are presented in decoding order, so their offset is the delay the
+/// encoder added to make room for reordering B-frames.
+#[derive(Debug, Default)]
+pub struct CompositionTimes {
+    pub frames: usize,
+    /// ms
+    pub min: Option<i32>,
+    pub max: Option<i32>,
+    sum: i64,
+    keyframe_max: Option<i32>,
+    /// number of frames per offset in milliseconds
+    pub histogram: BTreeMap<i32, usize>,
+}
+
+impl CompositionTimes {
+    pub fn push(&mut self, tag: &Tag) {
+        let (composition_time, keyframe) = match &tag.data {
+            TagData::Video(video) => match video.composition_time() {
+                Some(composition_time) => (
+                    composition_time,
+                    video.header.frame_type == VideoFrameType::KeyFrame,
+                ),
+                None => return,
+            },
+            _ => return,
+        };
+        if keyframe {
+            self.keyframe_max = Some(
+                self.keyframe_max
+                    .map_or(composition_time, |max| max.max(composition_time)),
+            );
+        }
+        self.frames += 1;
+        self.min = Some(
+            self.min
+                .map_or(composition_time, |min| min.min(composition_time)),
+        );
+        self.max = Some(
+            self.max
+                .map_or(composition_time, |max| max.max(composition_time)),
+        );
+        self.sum += composition_time as i64;
+        *self.histogram.entry(composition_time).or_default() += 1;
+    }
+
+    /// ms
+    pub fn mean(&self) -> Option<f64> {
+        if self.frames == 0 {
+            return None;
+        }
+        Some(self.sum as f64 / self.frames as f64)
+    }
+
+    /// ms, the delay reordering adds before a frame can be presented
+    pub fn reorder_delay(&self) -> Option<i32> {
+        self.keyframe_max.map(|max| max.max(0))
+    }
+
+    /// The reorder delay in frames at the given frame interval in ms.
+    pub fn reorder_frames(&self, frame_interval: i64) -> Option<f64> {
+        if frame_interval <= 0 {
+            return None;
+        }
+        Some(self.reorder_delay()? as f64 / frame_interval as f64)
+    }
+}
+
+/// Checks that audio timestamps advance by the duration of the audio the previous tag carried,
+/// which is derived from the samples per frame (AAC 1024 or 960, MP3 1152 or 576, Nellymoser
+/// 256, Speex 320) and the sample rate of the codec. G.711 tags last as long as their size at
+/// 8000 bytes per second and channel, Linear PCM tags as long as their size at the SoundRate,
+/// SoundSize and SoundType of their header.
+#[derive(Debug, Default)]
+pub struct AudioContinuity {
+    aac: Option<AudioSpecificConfig>,
+    // timestamp and duration in milliseconds of the previous tag
+    previous: Option<(i32, f64)>,
+    /// ms
+    pub frame_duration: Option<f64>,
+    pub minutes: BTreeMap<i64, AudioMinute>,
+}
+
+#[derive(Debug, Default)]
+pub struct AudioMinute {
+    pub missing_frames: u64,
+    pub overlapping_frames: u64,
+    /// ms, sum of the deviations smaller than half a frame
+    pub drift: f64,
+}
+
+impl AudioMinute {
+    pub fn is_continuous(&self) -> bool {
+        self.missing_frames == 0 && self.overlapping_frames == 0
+    }
+}
+
+impl AudioContinuity {
+    pub fn push(&mut self, tag: &Tag) {
+        let audio = match &tag.data {
+            TagData::Audio(audio) => audio,
+            _ => return,
+        };
+
+        // (duration of the tag, duration of one frame)
+        let (duration, frame_duration) = match audio.header.sound_format {
+            SoundFormat::AAC => {
+                let packet_type = audio.data.first().copied().map(AacPacketType::try_from);
+                match (packet_type, &self.aac) {
+                    (Some(Ok(AacPacketType::SequenceHeader)), _) => {
+                        self.aac = AudioSpecificConfig::try_from(&audio.data[1..]).ok();
+                        return;
+                    }
+                    (Some(Ok(AacPacketType::Raw)), Some(config)) => {
+                        (config.frame_duration(), config.frame_duration())
+                    }
+                    _ => return,
+                }
+            }
+            SoundFormat::MP3 | SoundFormat::MP38kHz => match mp3::count_frames(&audio.data) {
+                Ok((header, frames)) => {
+                    let frame_duration =
+                        header.samples_per_frame() as f64 * 1000.0 / header.sample_rate as f64;
+                    (frame_duration * frames as f64, frame_duration)
+                }
+                Err(_) => return,
+            },
+            // tags hold any number of samples, so the tag itself is the unit
+            format if g711::is_g711(format) || pcm::is_pcm(format) => {
+                let duration = if pcm::is_pcm(format) {
+                    pcm::duration(&audio.header, &audio.data)
+                } else {
+                    g711::duration(&audio.header, &audio.data)
+                };
+                if duration == 0.0 {
+                    return;
+                }
+                (duration, duration)
+            }
+            SoundFormat::Speex => match speex::count_frames(&audio.data) {
+                Ok(frames) => {
+                    let frame_duration =
+                        speex::SAMPLES_PER_FRAME as f64 * 1000.0 / speex::SAMPLE_RATE as f64;
+                    (frame_duration * frames as f64, frame_duration)
+                }
+                Err(_) => return,
+            },
+            format if nellymoser::is_nellymoser(format) => {
+                match nellymoser::count_frames(&audio.data) {
+                    Ok(frames) => {
+                        let frame_duration = nellymoser::SAMPLES_PER_FRAME as f64 * 1000.0
+                            / nellymoser::sample_rate(&audio.header) as f64;
+                        (frame_duration * frames as f64, frame_duration)
+                    }
+                    Err(_) => return,
+                }
+            }
+            _ => return,
+        };
+
+        let timestamp = tag.header.timestamp;
+        if let Some((previous, previous_duration)) = self.previous {
+            let deviation = (timestamp as i64 - previous as i64) as f64 - previous_duration;
+            let minute = self
+                .minutes
+                .entry((timestamp as i64).div_euclid(60000))
+                .or_default();
+            if deviation >= frame_duration / 2.0 {
+                minute.missing_frames += (deviation / frame_duration).round() as u64;
+            } else if deviation <= -frame_duration / 2.0 {
+                minute.overlapping_frames += (-deviation / frame_duration).round() as u64;
+            } else {
+                minute.drift += deviation;
+            }
+        }
+        self.previous = Some((timestamp, duration));
+        self.frame_duration = Some(frame_duration);
+    }
+
+    pub fn missing_frames(&self) -> u64 {
+        self.minutes
+            .values()
+            .map(|minute| minute.missing_frames)
+            .sum()
+    }
+
+    pub fn overlapping_frames(&self) -> u64 {
+        self.minutes
+            .values()
+            .map(|minute| minute.overlapping_frames)
+            .sum()
+    }
+
+    /// ms
+    pub fn drift(&self) -> f64 {
+        self.minutes.values().map(|minute| minute.drift).sum()
+    }
+}
+
+/// The byte order of the Linear PCM tags: SoundFormat 0 is in the byte order of the platform that
+/// wrote the file, 3 is always little-endian. The order of 16 bit samples is also guessed from
+/// the data.
+#[derive(Debug, Default)]
+pub struct PcmEndianness {
+    pub platform_endian_tags: usize,
+    pub little_endian_tags: usize,
+    pub little_endian_data: usize,
+    pub big_endian_data: usize,
+}
+
+impl PcmEndianness {
+    pub fn push(&mut self, tag: &Tag) {
+        let audio = match &tag.data {
+            TagData::Audio(audio) => audio,
+            _ => return,
+        };
+        match audio.header.sound_format {
+            SoundFormat::LinearPCMPlatformEndian => self.platform_endian_tags += 1,
+            SoundFormat::LinearPCMLittleEndian => self.little_endian_tags += 1,
+            _ => return,
+        }
+        match pcm::guess_endianness(&audio.header, &audio.data) {
+            Some(Endianness::Little) => self.little_endian_data += 1,
+            Some(Endianness::Big) => self.big_endian_data += 1,
+            None => {}
+        }
+    }
+
+    pub fn is_pcm(&self) -> bool {
+        self.platform_endian_tags + self.little_endian_tags > 0
+    }
+
+    /// Inconsistent if both SoundFormats are used, if the data looks big-endian in places and
+    /// little-endian in others, or if little-endian tags look big-endian.
+    pub fn is_consistent(&self) -> bool {
+        let mixed_formats = self.platform_endian_tags > 0 && self.little_endian_tags > 0;
+        let mixed_data = self.little_endian_data > 0 && self.big_endian_data > 0;
+        let big_little_endian = self.little_endian_tags > 0 && self.big_endian_data > 0;
+        !(mixed_formats || mixed_data || big_little_endian)
+    }
+}
+
+/// One line of the audio summary: the tags of one SoundFormat.
+#[derive(Debug)]
+pub struct AudioCodecSummary {
+    pub sound_format: SoundFormat,
+    /// Hz, according to the SoundRate of the first tag
+    pub header_sample_rate: u32,
+    /// Hz, according to the AudioSpecificConfig, the MP3 frame header or the rate the codec
+    /// implies
+    pub codec_sample_rate: Option<u32>,
+    /// according to the SoundType of the first tag
+    pub header_channels: u8,
+    /// according to the AudioSpecificConfig
+    pub codec_channels: Option<u8>,
+    pub tags: usize,
+    pub bytes: u64,
+    pub first_timestamp: i32,
+    pub last_timestamp: i32,
+}
+
+impl AudioCodecSummary {
+    fn new(header: &AudioDataHeader, timestamp: i32) -> Self {
+        let codec_sample_rate = match header.sound_format {
+            SoundFormat::G711ALaw | SoundFormat::G711MuLaw => Some(g711::SAMPLE_RATE),
+            SoundFormat::Speex => Some(speex::SAMPLE_RATE),
+            format if nellymoser::is_nellymoser(format) => Some(nellymoser::sample_rate(header)),
+            format if pcm::is_pcm(format) => Some(pcm::sample_rate(header)),
+            _ => None,
+        };
+        Self {
+            sound_format: header.sound_format,
+            header_sample_rate: header.sound_rate.hz(),
+            codec_sample_rate,
+            header_channels: header.sound_type.channels(),
+            codec_channels: None,
+            tags: 0,
+            bytes: 0,
+            first_timestamp: timestamp,
+            last_timestamp: timestamp,
+        }
+    }
+
+    fn push(&mut self, tag: &Tag, audio: &AudioData) {
+        self.tags += 1;
+        self.bytes += tag.header.data_size as u64;
+        self.last_timestamp = tag.header.timestamp;
+        match audio.header.sound_format {
+            SoundFormat::AAC => {
+                if let [0, config @ ..] = &audio.data[..] {
+                    if let Ok(config) = AudioSpecificConfig::try_from(config) {
+                        self.codec_sample_rate = Some(config.sampling_frequency);
+                        self.codec_channels = Some(config.channel_configuration);
+                    }
+                }
+            }
+            SoundFormat::MP3 | SoundFormat::MP38kHz if self.codec_sample_rate.is_none() => {
+                self.codec_sample_rate = mp3::FrameHeader::try_from(&audio.data[..])
+                    .ok()
+                    .map(|frame| frame.sample_rate);
+            }
+            _ => {}
+        }
+    }
+
+    /// kbps over the time from the first to the last tag.
+    pub fn average_bitrate(&self) -> Option<f64> {
+        let duration = self.last_timestamp as i64 - self.first_timestamp as i64;
+        if duration <= 0 {
+            return None;
+        }
+        Some(self.bytes as f64 * 8.0 / duration as f64)
+    }
+}
+
+/// The audio tags grouped by SoundFormat, in the order the formats first appear.
+#[derive(Debug, Default)]
+pub struct AudioSummary {
+    pub codecs: Vec<AudioCodecSummary>,
+}
+
+impl AudioSummary {
+    pub fn push(&mut self, tag: &Tag) {
+        let audio = match &tag.data {
+            TagData::Audio(audio) => audio,
+            _ => return,
+        };
+        let format = audio.header.sound_format;
+        let index = match self.codecs.iter().position(|c| c.sound_format == format) {
+            Some(index) => index,
+            None => {
+                let summary = AudioCodecSummary::new(&audio.header, tag.header.timestamp);
+                self.codecs.push(summary);
+                self.codecs.len() - 1
+            }
+        };
+        self.codecs[index].push(tag, audio);
+    }
+}
+
+/// One line of the video summary: the tags of one CodecId.
+#[derive(Debug)]
+pub struct VideoCodecSummary {
+    pub codec_id: CodecId,
+    /// the SPS of the last AVC sequence header
+    pub sps: Option<Sps>,
+    /// frames per FrameType in the order the types first appear, sequence headers not counted
+    pub frame_types: Vec<(VideoFrameType, usize)>,
+    /// keyframes, not counting sequence headers
+    pub keyframes: usize,
+    pub stream: StreamStats,
+    pub frame_intervals: FrameIntervals,
+}
+
+impl VideoCodecSummary {
+    fn new(codec_id: CodecId) -> Self {
+        Self {
+            codec_id,
+            sps: None,
+            frame_types: vec![],
+            keyframes: 0,
+            stream: StreamStats::default(),
+            frame_intervals: FrameIntervals::default(),
+        }
+    }
+
+    fn push(&mut self, tag: &Tag, video: &VideoData) {
+        self.stream.push(tag);
+        self.frame_intervals.push(tag);
+        if tag.data.is_sequence_header() {
+            if let [_, _, _, _, record @ ..] = &video.data[..] {
+                let sps = DecoderConfigurationRecord::try_from(record)
+                    .ok()
+                    .and_then(|record| Sps::try_from(&record.sps.first()?[..]).ok());
+                self.sps = sps.or_else(|| self.sps.take());
+            }
+            return;
+        }
+
+        let frame_type = video.header.frame_type;
+        match self.frame_types.iter_mut().find(|(t, _)| *t == frame_type) {
+            Some((_, count)) => *count += 1,
+            None => self.frame_types.push((frame_type, 1)),
+        }
+        if frame_type == VideoFrameType::KeyFrame {
+            self.keyframes += 1;
+        }
+    }
+}
+
+/// The video tags grouped by CodecId, in the order the codecs first appear.
+#[derive(Debug, Default)]
+pub struct VideoSummary {
+    pub codecs: Vec<VideoCodecSummary>,
+}
+
+impl VideoSummary {
+    pub fn push(&mut self, tag: &Tag) {
+        let video = match &tag.data {
+            TagData::Video(video) => video,
+            _ => return,
+        };
+        let codec_id = video.header.codec_id;
+        let index = match self.codecs.iter().position(|c| c.codec_id == codec_id) {
+            Some(index) => index,
+            None => {
+                self.codecs.push(VideoCodecSummary::new(codec_id));
+                self.codecs.len() - 1
+            }
+        };
+        self.codecs[index].push(tag, video);
+    }
+}
+
+#[derive(Debug, Copy, Clone)]
+pub struct TimestampRange {
+    pub first: i32,
+    pub last: i32,
+}
+
+/// First and last timestamps of the audio frames, video frames and script tags. Sequence headers
+/// are left out: a player shows nothing before the first frame, however early its sequence
+/// header comes.
+#[derive(Debug, Default)]
+pub struct StreamTimestamps {
+    pub audio: Option<TimestampRange>,
+    pub video: Option<TimestampRange>,
+    pub script: Option<TimestampRange>,
+}
+
+impl StreamTimestamps {
+    pub fn push(&mut self, tag: &Tag) {
+        let range = match tag.header.tag_type {
+            _ if tag.data.is_sequence_header() => return,
+            TagType::Audio => &mut self.audio,
+            TagType::Video => &mut self.video,
+            TagType::Script => &mut self.script,
+            TagType::Reserved(_) => return,
+        };
+        let timestamp = tag.header.timestamp;
+        range
+            .get_or_insert(TimestampRange {
+                first: timestamp,
+                last: timestamp,
+            })
+            .last = timestamp;
+    }
+
+    /// The streams that occur with their ranges.
+    pub fn streams(&self) -> Vec<(&'static str, TimestampRange)> {
+        vec![
+            ("Audio", self.audio),
+            ("Video", self.video),
+            ("Script", self.script),
+        ]
+        .into_iter()
+        .filter_map(|(name, range)| Some((name, range?)))
+        .collect()
+    }
+
+    /// ms from the start of the earliest stream to the start of the given one.
+    pub fn offset(&self, range: &TimestampRange) -> i64 {
+        let earliest = self
+            .streams()
+            .iter()
+            .map(|(_, range)| range.first)
+            .min()
+            .unwrap_or(range.first);
+        range.first as i64 - earliest as i64
+    }
+
+    /// ms by which the first audio frame follows the first video frame, negative if it comes
+    /// first.
+    pub fn audio_video_offset(&self) -> Option<i64> {
+        Some(self.audio?.first as i64 - self.video?.first as i64)
+    }
+
+    /// ms from the first to the last audio or video frame.
+    pub fn media_duration(&self) -> Option<i64> {
+        let ranges = [self.audio, self.video];
+        let first = ranges.iter().flatten().map(|range| range.first).min()?;
+        let last = ranges.iter().flatten().map(|range| range.last).max()?;
+        Some(last as i64 - first as i64)
+    }
+}
+
+#[derive(Debug, Default, Clone, Copy)]
+pub struct DuplicateCount {
+    pub tags: usize,
+    /// whole tags with their PreviousTagSize
+    pub bytes: u64,
+}
+
+/// Tags that repeat the type, timestamp and body of an earlier tag, e.g. a GOP sent again by a
+/// relay after reconnecting. Bodies are compared by hash.
+#[derive(Debug, Default)]
+pub struct Duplicates {
+    seen: HashSet<(u8, i32, u64)>,
+    pub audio: DuplicateCount,
+    pub video: DuplicateCount,
+    pub script: DuplicateCount,
+}
+
+impl Duplicates {
+    pub fn push(&mut self, tag: &Tag) {
+        let mut hasher = DefaultHasher::new();
+        let (kind, count) = match &tag.data {
+            TagData::Audio(audio) => {
+                hasher.write_u8(audio.header.into());
+                hasher.write(&audio.data);
+                (8, &mut self.audio)
+            }
+            TagData::Video(video) => {
+                hasher.write_u8(video.header.into());
+                hasher.write(&video.data);
+                (9, &mut self.video)
+            }
+            TagData::Script(script) => {
+                hasher.write(script.raw());
+                (18, &mut self.script)
+            }
+            _ => return,
+        };
+        if !self
+            .seen
+            .insert((kind, tag.header.timestamp, hasher.finish()))
+        {
+            count.tags += 1;
+            count.bytes += 11 + tag.header.data_size as u64 + 4;
+        }
+    }
+
+    pub fn total(&self) -> DuplicateCount {
+        let counts = [self.audio, self.video, self.script];
+        DuplicateCount {
+            tags: counts.iter().map(|count| count.tags).sum(),
+            bytes: counts.iter().map(|count| count.bytes).sum(),
+        }
+    }
+}
+
+/// Whether each second of video has a keyframe, and where the first one is, as needed to seek
+/// or to take a thumbnail per second.
+#[derive(Debug, Default)]
+pub struct KeyframeMap {
+    /// timestamp and file offset of the first keyframe, by second of timestamp
+    keyframes: BTreeMap<i64, (i32, u64)>,
+    first: Option<i64>,
+    last: Option<i64>,
+    last_keyframe: Option<i32>,
+    /// ms between consecutive keyframes
+    pub intervals: Vec<i64>,
+}
+
+impl KeyframeMap {
+    pub fn push(&mut self, tag: &Tag) {
+        let video = match &tag.data {
+            TagData::Video(video) if !tag.data.is_sequence_header() => video,
+            _ => return,
+        };
+        let second = (tag.header.timestamp as i64).div_euclid(1000);
+        self.first = Some(self.first.map_or(second, |first| first.min(second)));
+        self.last = Some(self.last.map_or(second, |last| last.max(second)));
+        if video.header.frame_type == VideoFrameType::KeyFrame {
+            if let Some(last) = self.last_keyframe.replace(tag.header.timestamp) {
+                self.intervals
+                    .push(tag.header.timestamp as i64 - last as i64);
+            }
+            self.keyframes
+                .entry(second)
+                .or_insert((tag.header.timestamp, tag.header.offset));
+        }
+    }
+
+    /// The first second with video.
+    pub fn start(&self) -> Option<i64> {
+        self.first
+    }
+
+    /// Timestamp and file offset of the first keyframe of every second from the first to the
+    /// last one with video.
+    pub fn seconds(&self) -> Vec<Option<(i32, u64)>> {
+        match (self.first, self.last) {
+            (Some(first), Some(last)) => (first..=last)
+                .map(|second| self.keyframes.get(&second).copied())
+                .collect(),
+            _ => vec![],
+        }
+    }
+}
+
+/// How far apart the audio and video timestamps get while the file is read in order. A player
+/// has to buffer the difference, as does a server remuxing the stream.
+#[derive(Debug, Default)]
+pub struct Interleaving {
+    audio: Option<i64>,
+    video: Option<i64>,
+    /// ms
+    pub max_distance: i64,
+}
+
+impl Interleaving {
+    pub fn push(&mut self, tag: &Tag) {
+        let timestamp = tag.header.timestamp as i64;
+        match tag.header.tag_type {
+            _ if tag.data.is_sequence_header() => return,
+            TagType::Audio => self.audio = Some(timestamp),
+            TagType::Video => self.video = Some(timestamp),
+            _ => return,
+        }
+        if let (Some(audio), Some(video)) = (self.audio, self.video) {
+            self.max_distance = self.max_distance.max((audio - video).abs());
+        }
+    }
+}
+
+#[derive(Debug, Default)]
+pub struct Stats {
+    pub audio: StreamStats,
+    pub video: StreamStats,
+    pub audio_continuity: AudioContinuity,
+    pub pcm_endianness: PcmEndianness,
+    pub audio_summary: AudioSummary,
+    pub frame_intervals: FrameIntervals,
+    pub frame_drops: FrameDrops,
+    pub composition_times: CompositionTimes,
+    pub video_summary: VideoSummary,
+    pub timestamps: StreamTimestamps,
+    pub early_frames: EarlyFrames,
+    pub duplicates: Duplicates,
+    pub keyframe_map: KeyframeMap,
+    pub interleaving: Interleaving,
+    pub fingerprint: Fingerprint,
+    /// framerate claimed by onMetaData
+    pub metadata_frame_rate: Option<f64>,
+    /// seconds, as claimed by onMetaData
+    pub metadata_duration: Option<f64>,
+    /// whether onMetaData has a keyframes index, None without onMetaData
+    pub keyframe_index: Option<bool>,
+    /// the number of tags of each known script event, see `ScriptEvent`
+    pub script_events: BTreeMap<&'static str, usize>,
+}
+
+impl Stats {
+    pub fn push(&mut self, tag: &Tag) {
+        self.timestamps.push(tag);
+        self.early_frames.push(tag);
+        self.duplicates.push(tag);
+        self.interleaving.push(tag);
+        self.fingerprint.push(tag);
+        match (&tag.header.tag_type, &tag.data) {
+            (TagType::Audio, _) => {
+                self.audio.push(tag);
+                profile::measure(Stage::Codec, || {
+                    self.audio_continuity.push(tag);
+                    self.pcm_endianness.push(tag);
+                    self.audio_summary.push(tag);
+                });
+            }
+            (TagType::Video, _) => {
+                self.video.push(tag);
+                self.frame_intervals.push(tag);
+                self.frame_drops.push(tag);
+                self.composition_times.push(tag);
+                profile::measure(Stage::Codec, || self.video_summary.push(tag));
+                self.keyframe_map.push(tag);
+            }
+            (TagType::Script, TagData::Script(script)) => {
+                if let Some(metadata) = script.as_metadata() {
+                    self.metadata_frame_rate = self.metadata_frame_rate.or(metadata.frame_rate);
+                    self.metadata_duration = self.metadata_duration.or(metadata.duration);
+                    self.keyframe_index =
+                        Some(self.keyframe_index.unwrap_or(false) || metadata.keyframe_index);
+                }
+                if let Some(event) = script.event() {
+                    *self.script_events.entry(event.name()).or_default() += 1;
+                }
+            }
+            _ => {}
+        }
+    }
+
+    /// Dropped video frames, against the frame interval of onMetaData's framerate, or else the
+    /// most common one. None without enough video frames to tell.
+    pub fn frame_drops(&self) -> Option<DropReport> {
+        let frame_rate = self.frame_intervals.frame_rate()?;
+        let nominal_interval = match self.metadata_frame_rate.filter(|fps| *fps > 0.0) {
+            Some(fps) => 1000.0 / fps,
+            None => frame_rate.mode_interval as f64,
+        };
+        if nominal_interval <= 0.0 {
+            return None;
+        }
+        Some(self.frame_drops.report(nominal_interval))
+    }
+
+    /// Whether the measured frame rate agrees with the one claimed in onMetaData.
+    pub fn frame_rate_matches_metadata(&self) -> Option<bool> {
+        let claimed = self.metadata_frame_rate.filter(|fps| *fps > 0.0)?;
+        let average = self.frame_intervals.frame_rate()?.average;
+        Some((average - claimed).abs() / claimed <= FRAME_RATE_TOLERANCE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::{SoundRate, SoundSize, SoundType, TagHeader, VideoDataHeader};
+    use bytes::Bytes;
+
+    fn video(timestamp: i32) -> Tag {
+        Tag {
+            header: TagHeader {
+                tag_type: TagType::Video,
+                data_size: 2,
+                timestamp,
+                offset: 0,
+            },
+            data: TagData::Video(VideoData {
+                header: VideoDataHeader {
+                    frame_type: VideoFrameType::InterFrame,
+                    codec_id: CodecId::SorensonH263,
+                },
+                data: Bytes::from_static(&[0]),
+            }),
+            raw: None,
+            received: None,
+        }
+    }
+
+    fn audio(timestamp: i32, data_size: u32) -> Tag {
+        Tag {
+            header: TagHeader {
+                tag_type: TagType::Audio,
+                data_size,
+                timestamp,
+                offset: 0,
+            },
+            data: TagData::Audio(AudioData {
+                header: AudioDataHeader {
+                    sound_format: SoundFormat::MP3,
+                    sound_rate: SoundRate::R44kHz,
+                    sound_size: SoundSize::S16Bit,
+                    sound_type: SoundType::Stereo,
+                },
+                data: Bytes::new(),
+            }),
+            raw: None,
+            received: None,
+        }
+    }
+
+    fn frame_drops(timestamps: &[i32], nominal_interval: f64) -> Vec<(i32, u64)> {
+        let mut drops = FrameDrops::default();
+        for &timestamp in timestamps {
+            drops.push(&video(timestamp));
+        }
+        drops
+            .report(nominal_interval)
+            .drops
+            .iter()
+            .map(|drop| (drop.after, drop.frames))
+            .collect()
+    }
+
+    #[test]
+    fn frame_drop_in_first_interval() {
+        assert_eq!(frame_drops(&[0, 200, 240, 280, 320], 40.0), vec![(0, 4)]);
+    }
+
+    #[test]
+    fn frame_drops_below_observed_interval() {
+        // 25 fps of frames against a nominal 50 fps, every other frame is missing
+        assert_eq!(
+            frame_drops(&[0, 40, 80, 120], 20.0),
+            vec![(0, 1), (40, 1), (80, 1)]
+        );
+        assert_eq!(frame_drops(&[0, 40, 80, 120], 40.0), vec![]);
+    }
+
+    #[test]
+    fn frame_drops_against_metadata() {
+        let mut stats = Stats::default();
+        for timestamp in (0..10).map(|frame| frame * 40) {
+            stats.push(&video(timestamp));
+        }
+        assert_eq!(stats.frame_drops().unwrap().total(), 0);
+        stats.metadata_frame_rate = Some(50.0);
+        let report = stats.frame_drops().unwrap();
+        assert_eq!(report.nominal_interval, 20.0);
+        assert_eq!(report.total(), 9);
+        assert_eq!(report.minutes, BTreeMap::from([(0, 9)]));
+    }
+
+    #[test]
+    fn bitrate_of_negative_timestamps() {
+        // seconds -2 to 1 with 1000 bytes each, like the keyframe map counts them
+        let mut stream = StreamStats::default();
+        for timestamp in [-1500, -500, 500, 1500] {
+            stream.push(&audio(timestamp, 1000));
+        }
+        let bitrate = stream.bitrate().unwrap();
+        assert_eq!(
+            (bitrate.average, bitrate.peak, bitrate.std_dev),
+            (8.0, 8.0, 0.0)
+        );
+    }
+
+    fn bitrate(bytes_per_second: &[u32]) -> Option<Bitrate> {
+        let mut stream = StreamStats::default();
+        for (second, &bytes) in bytes_per_second.iter().enumerate() {
+            stream.push(&audio(second as i32 * 1000, bytes));
+        }
+        stream.bitrate()
+    }
+
+    #[test]
+    fn bitrate_mode() {
+        // the last second is left out
+        let cbr = bitrate(&[1000, 1000, 1040, 1000, 5000]).unwrap();
+        assert_eq!(cbr.mode(), BitrateMode::CBR);
+        assert_eq!((cbr.average, cbr.peak), (8.08, 8.32));
+
+        let vbr = bitrate(&[1000, 3000, 1000, 3000, 0]).unwrap();
+        assert_eq!(vbr.mode(), BitrateMode::VBR);
+        assert_eq!((vbr.average, vbr.std_dev, vbr.peak), (16.0, 8.0, 24.0));
+        assert_eq!(vbr.variation(), 0.5);
+        assert_eq!(vbr.peak_to_average(), 1.5);
+
+        // seconds without data count as 0 kbps
+        let gap = bitrate(&[1000, 0, 1000, 0]).unwrap();
+        assert_eq!((gap.average, gap.mode()), (16.0 / 3.0, BitrateMode::VBR));
+
+        assert!(bitrate(&[1000, 1000]).is_none());
+        assert!(bitrate(&[0, 0, 0]).is_none());
+    }
+
+    fn cadence(intervals: &[i32]) -> Cadence {
+        let mut frame_intervals = FrameIntervals::default();
+        let mut timestamp = 0;
+        frame_intervals.push(&video(timestamp));
+        for interval in intervals {
+            timestamp += interval;
+            frame_intervals.push(&video(timestamp));
+        }
+        frame_intervals.cadence().unwrap()
+    }
+
+    #[test]
+    fn cadence_of_30_fps() {
+        // millisecond timestamps at 30 fps, with a dropped frame that doubles an interval
+        let cadence = cadence(&[33, 33, 34, 33, 33, 34, 66, 34, 33]);
+        assert!(cadence.constant);
+        assert_eq!(cadence.variable_share, 0.0);
+        assert_eq!(cadence.dominant, vec![(33, 5.0 / 9.0), (34, 3.0 / 9.0)]);
+    }
+
+    #[test]
+    fn cadence_of_vfr() {
+        let mut intervals = vec![40; 10];
+        intervals.extend_from_slice(&[25, 55]);
+        let vfr = cadence(&intervals);
+        assert!(!vfr.constant);
+        assert_eq!(vfr.variable_share, 2.0 / 12.0);
+        assert_eq!(vfr.dominant, vec![(40, 10.0 / 12.0)]);
+
+        // up to 5% of the intervals may be off the cadence
+        let mut intervals = vec![40; 19];
+        intervals.push(25);
+        assert!(!cadence(&intervals).constant);
+        intervals.push(40);
+        assert!(cadence(&intervals).constant);
+    }
+
+    #[test]
+    fn duplicates() {
+        let mut other_frame = video(0);
+        if let TagData::Video(video) = &mut other_frame.data {
+            video.data = Bytes::from_static(&[1]);
+        }
+        let mut duplicates = Duplicates::default();
+        for tag in &[
+            audio(0, 10),
+            video(0),
+            other_frame,
+            audio(0, 10),
+            video(0),
+            video(40),
+            audio(23, 10),
+            video(0),
+        ] {
+            duplicates.push(tag);
+        }
+        assert_eq!(
+            (duplicates.audio.tags, duplicates.audio.bytes),
+            (1, 11 + 10 + 4)
+        );
+        assert_eq!(
+            (duplicates.video.tags, duplicates.video.bytes),
+            (2, 2 * (11 + 2 + 4))
+        );
+        assert_eq!(duplicates.script.tags, 0);
+        let total = duplicates.total();
+        assert_eq!((total.tags, total.bytes), (3, 25 + 34));
+    }
+
+    #[test]
+    fn interleaving() {
+        let mut interleaving = Interleaving::default();
+        // audio alone has nothing to be interleaved with
+        interleaving.push(&audio(0, 10));
+        interleaving.push(&audio(500, 10));
+        assert_eq!(interleaving.max_distance, 0);
+        // the distance is between the latest tag of each stream
+        interleaving.push(&video(0));
+        assert_eq!(interleaving.max_distance, 500);
+        interleaving.push(&video(600));
+        interleaving.push(&audio(1000, 10));
+        assert_eq!(interleaving.max_distance, 500);
+        interleaving.push(&audio(1300, 10));
+        assert_eq!(interleaving.max_distance, 700);
+    }
+
+    #[test]
+    fn frame_rate_matches_metadata() {
+        let mut stats = Stats::default();
+        assert_eq!(stats.frame_rate_matches_metadata(), None);
+        // 25 fps with one dropped frame
+        for timestamp in (0..=40).map(|frame| frame * 40).filter(|ts| *ts != 400) {
+            stats.push(&video(timestamp));
+        }
+        assert_eq!(stats.frame_rate_matches_metadata(), None);
+        stats.metadata_frame_rate = Some(25.0);
+        assert_eq!(stats.frame_rate_matches_metadata(), Some(true));
+        stats.metadata_frame_rate = Some(30.0);
+        assert_eq!(stats.frame_rate_matches_metadata(), Some(false));
+        stats.metadata_frame_rate = Some(0.0);
+        assert_eq!(stats.frame_rate_matches_metadata(), None);
+    }
+
+    fn aac(timestamp: i32, data: &'static [u8]) -> Tag {
+        Tag {
+            header: TagHeader {
+                tag_type: TagType::Audio,
+                data_size: 1 + data.len() as u32,
+                timestamp,
+                offset: 0,
+            },
+            data: TagData::Audio(AudioData {
+                header: AudioDataHeader {
+                    sound_format: SoundFormat::AAC,
+                    sound_rate: SoundRate::R44kHz,
+                    sound_size: SoundSize::S16Bit,
+                    sound_type: SoundType::Stereo,
+                },
+                data: Bytes::from_static(data),
+            }),
+            raw: None,
+            received: None,
+        }
+    }
+
+    #[test]
+    fn audio_continuity() {
+        // AAC LC at 44.1 kHz, 1024 samples per frame
+        let frame_duration = 1024.0 * 1000.0 / 44100.0;
+        let mut continuity = AudioContinuity::default();
+        // frames before the sequence header have no known duration
+        continuity.push(&aac(-100, &[1, 0x21]));
+        continuity.push(&aac(0, &[0, 0x12, 0x10]));
+        for timestamp in [0, 23, 46, 70, 116, 120, 60_000, 60_023] {
+            continuity.push(&aac(timestamp, &[1, 0x21]));
+        }
+        assert_eq!(continuity.frame_duration, Some(frame_duration));
+        // 116 is a frame late, 120 a frame early and 60000 thousands of frames late
+        let minute = &continuity.minutes[&0];
+        assert_eq!((minute.missing_frames, minute.overlapping_frames), (1, 1));
+        assert!((minute.drift - (70.0 - 3.0 * frame_duration)).abs() < 1e-9);
+        let minute = &continuity.minutes[&1];
+        assert_eq!(
+            minute.missing_frames,
+            ((60_000.0 - 120.0 - frame_duration) / frame_duration).round() as u64
+        );
+        assert_eq!(minute.overlapping_frames, 0);
+        assert!(!minute.is_continuous());
+        assert_eq!(continuity.missing_frames(), 1 + minute.missing_frames);
+        assert_eq!(continuity.overlapping_frames(), 1);
+    }
+
+    fn avc(timestamp: i32, frame_type: VideoFrameType, data: &'static [u8]) -> Tag {
+        Tag {
+            header: TagHeader {
+                tag_type: TagType::Video,
+                data_size: 1 + data.len() as u32,
+                timestamp,
+                offset: 0,
+            },
+            data: TagData::Video(VideoData {
+                header: VideoDataHeader {
+                    frame_type,
+                    codec_id: CodecId::AVC,
+                },
+                data: Bytes::from_static(data),
+            }),
+            raw: None,
+            received: None,
+        }
+    }
+
+    #[test]
+    fn composition_times() {
+        let mut composition_times = CompositionTimes::default();
+        assert_eq!(composition_times.mean(), None);
+        assert_eq!(composition_times.reorder_delay(), None);
+        // the sequence header has no CompositionTime
+        composition_times.push(&avc(0, VideoFrameType::KeyFrame, &[0, 0, 0, 0]));
+        // I P B B, with B-frames presented before the P-frame they reference
+        composition_times.push(&avc(0, VideoFrameType::KeyFrame, &[1, 0, 0, 80]));
+        composition_times.push(&avc(40, VideoFrameType::InterFrame, &[1, 0, 0, 120]));
+        composition_times.push(&avc(80, VideoFrameType::InterFrame, &[1, 0, 0, 0]));
+        composition_times.push(&avc(
+            120,
+            VideoFrameType::InterFrame,
+            &[1, 0xff, 0xff, 0xd8],
+        ));
+        assert_eq!(composition_times.frames, 4);
+        assert_eq!(
+            (composition_times.min, composition_times.max),
+            (Some(-40), Some(120))
+        );
+        assert_eq!(composition_times.mean(), Some(40.0));
+        assert_eq!(
+            composition_times.histogram,
+            BTreeMap::from([(-40, 1), (0, 1), (80, 1), (120, 1)])
+        );
+        // the delay is that of the keyframes
+        assert_eq!(composition_times.reorder_delay(), Some(80));
+        assert_eq!(composition_times.reorder_frames(40), Some(2.0));
+        assert_eq!(composition_times.reorder_frames(0), None);
+    }
+
+    #[test]
+    fn keyframe_map() {
+        let keyframe = |timestamp, offset| {
+            let mut tag = avc(timestamp, VideoFrameType::KeyFrame, &[1, 0, 0, 0]);
+            tag.header.offset = offset;
+            tag
+        };
+        let mut map = KeyframeMap::default();
+        assert_eq!(map.seconds(), vec![]);
+        // the sequence header is no keyframe
+        map.push(&avc(-2000, VideoFrameType::KeyFrame, &[0, 0, 0, 0]));
+        map.push(&keyframe(-500, 13));
+        map.push(&keyframe(1200, 100));
+        map.push(&keyframe(1800, 200));
+        map.push(&avc(3500, VideoFrameType::InterFrame, &[1, 0, 0, 0]));
+        assert_eq!(map.start(), Some(-1));
+        assert_eq!(
+            map.seconds(),
+            vec![Some((-500, 13)), None, Some((1200, 100)), None, None]
+        );
+        assert_eq!(map.intervals, vec![1700, 600]);
+    }
+}
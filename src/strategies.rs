@@ -0,0 +1,229 @@
+//! proptest strategies for FLV structures, enabled by the `proptest` feature, and the round-trip
+//! property they are meant for: decoding, encoding and decoding again must not change anything.
+
+use crate::reader::{
+    read_flv, AudioData, AudioDataHeader, CodecId, Field, Header, ParseOptions, ScriptData,
+    SoundFormat, SoundRate, SoundSize, SoundType, Tag, TagData, TagHeader, TagType, VideoData,
+    VideoDataHeader, VideoFrameType, TYPE_FLAGS_AUDIO, TYPE_FLAGS_VIDEO,
+};
+use crate::Exception;
+use bytes::{BufMut, Bytes, BytesMut};
+use proptest::collection::vec;
+use proptest::prelude::*;
+use tokio_stream::StreamExt;
+
+// Payloads are kept small, the structure is what is being tested.
+const MAX_PAYLOAD: usize = 256;
+const MAX_TAGS: usize = 16;
+
+pub fn audio_data_header() -> impl Strategy<Value = AudioDataHeader> {
+    use SoundFormat::*;
+    let sound_format = prop_oneof![
+        Just(LinearPCMPlatformEndian),
+        Just(ADPCM),
+        Just(MP3),
+        Just(LinearPCMLittleEndian),
+        Just(Nellymoser16),
+        Just(Nellymoser8),
+        Just(Nellymoser),
+        Just(G711ALaw),
+        Just(G711MuLaw),
+        Just(Reserved),
+        Just(AAC),
+        Just(Speex),
+        Just(MP38kHz),
+        Just(DeviceSpecific),
+    ];
+    let sound_rate = prop_oneof![
+        Just(SoundRate::R5p5kHz),
+        Just(SoundRate::R11kHz),
+        Just(SoundRate::R22kHz),
+        Just(SoundRate::R44kHz),
+    ];
+    let sound_size = prop_oneof![Just(SoundSize::S8Bit), Just(SoundSize::S16Bit)];
+    let sound_type = prop_oneof![Just(SoundType::Mono), Just(SoundType::Stereo)];
+    (sound_format, sound_rate, sound_size, sound_type).prop_map(
+        |(sound_format, sound_rate, sound_size, sound_type)| AudioDataHeader {
+            sound_format,
+            sound_rate,
+            sound_size,
+            sound_type,
+        },
+    )
+}
+
+pub fn video_data_header() -> impl Strategy<Value = VideoDataHeader> {
+    use VideoFrameType::*;
+    let frame_type = prop_oneof![
+        Just(KeyFrame),
+        Just(InterFrame),
+        Just(DisposableInterFrame),
+        Just(GeneratedKeyFrame),
+        Just(VideoInfoOrCommandFrame),
+    ];
+    let codec_id = prop_oneof![
+        Just(CodecId::JPEG),
+        Just(CodecId::SorensonH263),
+        Just(CodecId::ScreenVideo),
+        Just(CodecId::On2VP6),
+        Just(CodecId::On2VP6WithAlpha),
+        Just(CodecId::ScreenVideoVersion2),
+        Just(CodecId::AVC),
+    ];
+    (frame_type, codec_id).prop_map(|(frame_type, codec_id)| VideoDataHeader {
+        frame_type,
+        codec_id,
+    })
+}
+
+fn payload() -> impl Strategy<Value = Bytes> {
+    vec(any::<u8>(), 0..MAX_PAYLOAD).prop_map(Bytes::from)
+}
+
+/// A tag body of the given type. Script data is arbitrary bytes, it is not decoded while reading.
+pub fn tag_data() -> impl Strategy<Value = TagData> {
+    prop_oneof![
+        (audio_data_header(), payload())
+            .prop_map(|(header, data)| TagData::Audio(AudioData { header, data })),
+        (video_data_header(), payload())
+            .prop_map(|(header, data)| TagData::Video(VideoData { header, data })),
+        payload().prop_map(|raw| TagData::Script(ScriptData {
+            raw,
+            legacy_encoding: None,
+        })),
+    ]
+}
+
+pub fn tag() -> impl Strategy<Value = Tag> {
+    (tag_data(), 0..=i32::MAX).prop_map(|(data, timestamp)| Tag {
+        header: TagHeader {
+            tag_type: match data {
+                TagData::Audio(_) => TagType::Audio,
+                TagData::Video(_) => TagType::Video,
+                _ => TagType::Script,
+            },
+            data_size: data.encoded_len() as u32,
+            timestamp,
+            offset: 0,
+        },
+        data,
+        raw: None,
+        received: None,
+    })
+}
+
+pub fn header() -> impl Strategy<Value = Header> {
+    (any::<u8>(), 0..=(TYPE_FLAGS_AUDIO | TYPE_FLAGS_VIDEO)).prop_map(|(version, type_)| Header {
+        version,
+        type_,
+        offset: 9,
+        extra: Bytes::new(),
+    })
+}
+
+/// A complete, valid FLV file.
+pub fn flv_bytes() -> impl Strategy<Value = Vec<u8>> {
+    (header(), vec(tag(), 0..MAX_TAGS)).prop_map(|(header, tags)| encode(&header, &tags))
+}
+
+/// A valid FLV file with a few bytes past the file header overwritten and possibly truncated,
+/// to exercise the error paths of a parser.
+pub fn near_valid_flv_bytes() -> impl Strategy<Value = Vec<u8>> {
+    (
+        flv_bytes(),
+        vec((any::<prop::sample::Index>(), any::<u8>()), 0..4),
+        any::<prop::sample::Index>(),
+        any::<bool>(),
+    )
+        .prop_map(|(mut bytes, changes, cut, truncate)| {
+            let body = bytes.len() - 9;
+            if body > 0 {
+                for (index, value) in changes {
+                    bytes[9 + index.index(body)] = value;
+                }
+                if truncate {
+                    bytes.truncate(9 + cut.index(body));
+                }
+            }
+            bytes
+        })
+}
+
+/// Encodes a header and tags with correct PreviousTagSize fields.
+pub fn encode(header: &Header, tags: &[Tag]) -> Vec<u8> {
+    let mut buf = BytesMut::new();
+    header.encode(&mut buf);
+    let mut pre_tag_size = 0u32;
+    for tag in tags {
+        buf.put_u32(pre_tag_size);
+        // decoded tags and those of the strategies always fit in a DataSize
+        pre_tag_size = tag.encode(&mut buf).expect("tag body too large");
+    }
+    buf.put_u32(pre_tag_size);
+    buf.to_vec()
+}
+
+/// Decodes the tags of a FLV file in lenient mode.
+pub fn decode(bytes: &[u8]) -> Result<(Header, Vec<Tag>), Exception> {
+    futures::executor::block_on(async {
+        let (header, mut fields) = read_flv(bytes, ParseOptions::default()).await?;
+        let mut tags = Vec::new();
+        while let Some(field) = fields.next().await {
+            if let Field::Tag(tag) = field? {
+                tags.push(tag);
+            }
+        }
+        Ok((header, tags))
+    })
+}
+
+/// The round-trip property: whatever decodes, encodes to bytes that decode to the same tags.
+/// Tags are compared by their encoding, which covers every decoded field.
+pub fn check_round_trip(bytes: &[u8]) -> Result<(), TestCaseError> {
+    let (header, tags) = match decode(bytes) {
+        Ok(decoded) => decoded,
+        // near-valid input may be rejected, that is not what is tested here
+        Err(_) => return Ok(()),
+    };
+    let encoded = encode(&header, &tags);
+    let (header2, tags2) =
+        decode(&encoded).map_err(|e| TestCaseError::fail(format!("re-decoding failed: {}", e)))?;
+
+    prop_assert_eq!(header.version, header2.version);
+    prop_assert_eq!(header.type_, header2.type_);
+    prop_assert_eq!(encoded, encode(&header2, &tags2));
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::writer::FlvWriter;
+
+    // Writes the tags with `FlvWriter`, which is what the strategies stand in for.
+    fn write(header: &Header, tags: &[Tag]) -> Result<Vec<u8>, Exception> {
+        futures::executor::block_on(async {
+            let mut writer = FlvWriter::new(Vec::new(), header).await?;
+            for tag in tags {
+                writer.write_tag(tag).await?;
+            }
+            writer.finish().await
+        })
+    }
+
+    proptest! {
+        #[test]
+        fn round_trip(header in header(), tags in vec(tag(), 0..MAX_TAGS)) {
+            let bytes = write(&header, &tags).unwrap();
+            prop_assert_eq!(&bytes, &encode(&header, &tags));
+            let (_, decoded) = decode(&bytes).unwrap();
+            prop_assert_eq!(&encode(&header, &decoded), &bytes);
+            check_round_trip(&bytes)?;
+        }
+
+        #[test]
+        fn round_trip_near_valid(bytes in near_valid_flv_bytes()) {
+            check_round_trip(&bytes)?;
+        }
+    }
+}
@@ -0,0 +1,148 @@
+//! Timed text for testing caption rendering in players: SRT cues muxed as `onTextData` script
+//! tags, as Flash Media Server sends the text tracks of MP4 files.
+
+use crate::amf::AmfValue;
+use crate::input::open_input;
+use crate::reader::{read_flv, Field, ParseOptions, ScriptData, Tag, TagData, TagHeader, TagType};
+use crate::writer::FlvWriter;
+use crate::Exception;
+use bytes::BytesMut;
+use std::path::Path;
+use tokio::fs::File;
+use tokio::io::BufWriter;
+use tokio_stream::StreamExt;
+
+const TEXT_DATA: &str = "onTextData";
+
+/// A subtitle of an SRT file, shown from `start` until `end`, in ms.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cue {
+    pub start: i64,
+    pub end: i64,
+    pub text: String,
+}
+
+// HH:MM:SS,mmm, also with a '.' as some editors write it.
+fn parse_time(s: &str) -> Option<i64> {
+    let (hms, ms) = s.trim().split_once([',', '.'])?;
+    let mut parts = hms.split(':');
+    let (h, m, s) = (parts.next()?, parts.next()?, parts.next()?);
+    if parts.next().is_some() {
+        return None;
+    }
+    let field = |s: &str| s.parse::<i64>().ok();
+    Some(((field(h)? * 60 + field(m)?) * 60 + field(s)?) * 1000 + field(ms)?)
+}
+
+/// Parses the cues of an SRT file: a number, `start --> end` and the lines of text, with blank
+/// lines in between. Coordinates after the end time are ignored, the text is kept as written,
+/// formatting tags included.
+pub fn parse_srt(text: &str) -> Result<Vec<Cue>, Exception> {
+    let text = text.trim_start_matches('\u{feff}').replace("\r\n", "\n");
+    let mut cues = vec![];
+    for block in text.split("\n\n") {
+        let mut lines = block.lines().skip_while(|line| line.trim().is_empty());
+        let number = match lines.next() {
+            Some(number) => number.trim(),
+            None => continue,
+        };
+        let timing = lines.next().unwrap_or_default();
+        let times = timing.split_once("-->").and_then(|(start, end)| {
+            let end = end.split_whitespace().next()?;
+            Some((parse_time(start)?, parse_time(end)?))
+        });
+        let (start, end) = match times {
+            Some(times) => times,
+            None => return Err(format!("Invalid timing of SRT cue {}: {}", number, timing).into()),
+        };
+        let text = lines.collect::<Vec<_>>().join("\n");
+        cues.push(Cue { start, end, text });
+    }
+    cues.sort_by_key(|cue| cue.start);
+    Ok(cues)
+}
+
+/// The `onTextData` tags showing the cues, and clearing them at their end unless the next cue
+/// starts by then, in the order of their timestamps.
+fn text_tags(cues: &[Cue], language: &str, offset: i64) -> Vec<Tag> {
+    let mut tags = vec![];
+    for (i, cue) in cues.iter().enumerate() {
+        tags.push(text_tag(cue.start + offset, &cue.text, language));
+        if cues.get(i + 1).is_none_or(|next| next.start > cue.end) {
+            tags.push(text_tag(cue.end + offset, "", language));
+        }
+    }
+    tags.sort_by_key(|tag| tag.header.timestamp);
+    tags
+}
+
+fn text_tag(timestamp: i64, text: &str, language: &str) -> Tag {
+    let mut raw = BytesMut::new();
+    AmfValue::String(TEXT_DATA.into()).encode(&mut raw);
+    AmfValue::EcmaArray(vec![
+        ("text".into(), AmfValue::String(text.into())),
+        ("language".into(), AmfValue::String(language.into())),
+        ("trackid".into(), AmfValue::Number(1.0)),
+    ])
+    .encode(&mut raw);
+    let raw = raw.freeze();
+    Tag {
+        header: TagHeader {
+            tag_type: TagType::Script,
+            data_size: raw.len() as u32,
+            timestamp: timestamp.clamp(0, i32::MAX as i64) as i32,
+            offset: 0,
+        },
+        data: TagData::Script(ScriptData::new(raw)),
+        raw: None,
+        received: None,
+    }
+}
+
+/// Copies a FLV file with the cues of an SRT file added as `onTextData` script tags, each in
+/// front of the first tag at or after its time. The SRT times are moved by `offset` ms; cues
+/// beyond the last tag are written at the end.
+pub async fn inject_subs<P: AsRef<Path>>(
+    input: &str,
+    output: P,
+    srt: &Path,
+    language: &str,
+    offset: i64,
+) -> Result<(), Exception> {
+    let text = std::fs::read_to_string(srt)
+        .map_err(|e| format!("Failed to read {}: {}", srt.display(), e))?;
+    let cues = parse_srt(&text)?;
+    let mut texts = text_tags(&cues, language, offset).into_iter().peekable();
+
+    let (_, input) = open_input(input).await?;
+    let (header, mut decoder) = read_flv(input, ParseOptions::default()).await?;
+    let file = File::create(output).await?;
+    let mut writer = FlvWriter::new(BufWriter::new(file), &header).await?;
+    let (mut tags, mut injected) = (0, 0);
+    while let Some(field) = decoder.next().await {
+        let tag = match field? {
+            Field::Tag(tag) => tag,
+            Field::Warning(finding) => {
+                eprintln!("{}", finding);
+                continue;
+            }
+            Field::PreTagSize(_) => continue,
+        };
+        while let Some(text) = texts.next_if(|text| text.header.timestamp <= tag.header.timestamp) {
+            writer.write_tag(&text).await?;
+            injected += 1;
+        }
+        writer.write_tag(&tag).await?;
+        tags += 1;
+    }
+    for text in texts {
+        writer.write_tag(&text).await?;
+        injected += 1;
+    }
+    writer.finish().await?;
+
+    println!("Cues: {}", cues.len());
+    println!("InjectedTags: {}", injected);
+    println!("CopiedTags: {}", tags);
+    Ok(())
+}
@@ -0,0 +1,160 @@
+//! Tag timestamps as durations. FLV timestamps are signed milliseconds and so are the differences
+//! between them, `std::time::Duration` covers the non-negative ones.
+
+use crate::reader::TagHeader;
+use crate::Exception;
+use std::convert::TryFrom;
+use std::fmt;
+use std::ops::{Add, Neg, Sub};
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// A duration that may be negative, with millisecond precision.
+#[derive(Debug, Copy, Clone, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct SignedDuration {
+    millis: i64,
+}
+
+impl SignedDuration {
+    pub const ZERO: SignedDuration = SignedDuration { millis: 0 };
+
+    pub fn from_millis(millis: i64) -> Self {
+        Self { millis }
+    }
+
+    pub fn as_millis(self) -> i64 {
+        self.millis
+    }
+
+    pub fn as_secs_f64(self) -> f64 {
+        self.millis as f64 / 1000.0
+    }
+
+    pub fn is_negative(self) -> bool {
+        self.millis < 0
+    }
+
+    pub fn abs(self) -> Duration {
+        Duration::from_millis(self.millis.unsigned_abs())
+    }
+
+    /// The duration, or None if it is negative.
+    pub fn to_duration(self) -> Option<Duration> {
+        if self.is_negative() {
+            None
+        } else {
+            Some(self.abs())
+        }
+    }
+}
+
+impl From<Duration> for SignedDuration {
+    /// Truncates to milliseconds, saturating at `i64::MAX`.
+    fn from(duration: Duration) -> Self {
+        Self::from_millis(i64::try_from(duration.as_millis()).unwrap_or(i64::MAX))
+    }
+}
+
+impl Add for SignedDuration {
+    type Output = Self;
+
+    fn add(self, rhs: Self) -> Self {
+        Self::from_millis(self.millis + rhs.millis)
+    }
+}
+
+impl Sub for SignedDuration {
+    type Output = Self;
+
+    fn sub(self, rhs: Self) -> Self {
+        Self::from_millis(self.millis - rhs.millis)
+    }
+}
+
+impl Neg for SignedDuration {
+    type Output = Self;
+
+    fn neg(self) -> Self {
+        Self::from_millis(-self.millis)
+    }
+}
+
+/// `[-]H:MM:SS.mmm`, e.g. `0:01:02.345`.
+impl fmt::Display for SignedDuration {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let sign = if self.is_negative() { "-" } else { "" };
+        let millis = self.millis.unsigned_abs();
+        write!(
+            f,
+            "{}{}:{:02}:{:02}.{:03}",
+            sign,
+            millis / 3_600_000,
+            millis / 60_000 % 60,
+            millis / 1000 % 60,
+            millis % 1000
+        )
+    }
+}
+
+impl TagHeader {
+    /// The timestamp, or None if it is negative.
+    pub fn time(&self) -> Option<Duration> {
+        self.signed_time().to_duration()
+    }
+
+    pub fn signed_time(&self) -> SignedDuration {
+        SignedDuration::from_millis(self.timestamp as i64)
+    }
+}
+
+/// `(year, month, day)` of the day that is `days` days after 1970-01-01, after Howard Hinnant's
+/// civil_from_days.
+pub fn civil_from_days(days: i64) -> (i64, i64, i64) {
+    let z = days + 719468;
+    let era = z.div_euclid(146097);
+    let doe = z - era * 146097;
+    let yoe = (doe - doe / 1460 + doe / 36524 - doe / 146096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + if month <= 2 { 1 } else { 0 };
+    (year, month, day)
+}
+
+/// Parses a duration with a unit such as `10s`, `500ms`, `2m` or `1h`.
+pub fn parse_duration(s: &str) -> Result<Duration, Exception> {
+    let units = [("ms", 1), ("s", 1000), ("m", 60_000), ("h", 3_600_000)];
+    units
+        .iter()
+        .find_map(|(suffix, unit)| {
+            let number: u64 = s.strip_suffix(suffix)?.parse().ok()?;
+            Some(Duration::from_millis(number.checked_mul(*unit)?))
+        })
+        .ok_or_else(|| format!("Invalid duration {}, expected e.g. 10s or 500ms", s).into())
+}
+
+/// RFC 3339 in UTC with milliseconds, e.g. `2024-05-01T12:00:00.250Z`. Times before the epoch
+/// are shown as the epoch.
+pub fn format_utc(time: SystemTime) -> String {
+    let millis = time
+        .duration_since(UNIX_EPOCH)
+        .map(|duration| duration.as_millis() as i64)
+        .unwrap_or(0);
+    format_millis_utc(millis)
+}
+
+/// Formats milliseconds since the Unix epoch like `format_utc`, including times before it.
+pub fn format_millis_utc(millis: i64) -> String {
+    let (days, millis) = (millis.div_euclid(86_400_000), millis.rem_euclid(86_400_000));
+    let (year, month, day) = civil_from_days(days);
+    format!(
+        "{:04}-{:02}-{:02}T{:02}:{:02}:{:02}.{:03}Z",
+        year,
+        month,
+        day,
+        millis / 3_600_000,
+        millis / 60_000 % 60,
+        millis / 1000 % 60,
+        millis % 1000
+    )
+}
@@ -0,0 +1,109 @@
+use crate::findings::Finding;
+use crate::output::{FileInfo, Format, Formatter};
+use crate::reader::{Tag, TagData, VideoFrameType};
+use crate::stats::Stats;
+use crate::Exception;
+use serde_json::{json, Value};
+use std::io::Write;
+
+const COLUMNS: [&str; 7] = ["index", "stream", "dts", "cts", "pts", "keyframe", "size"];
+
+/// The decoding and presentation time of every audio and video frame, as CSV rows or a JSON
+/// document per input file, e.g. for external A/V sync and latency tools. The tag timestamp is
+/// the DTS, AVC NALUs add their CompositionTime for the PTS. Sequence headers are left out.
+pub struct Timeline {
+    json: bool,
+    frames: usize,
+}
+
+impl Timeline {
+    pub fn new(format: Format) -> Result<Self, Exception> {
+        let json = match format {
+            Format::Csv => false,
+            Format::Json => true,
+            _ => return Err("The timeline can only be exported as CSV or JSON".into()),
+        };
+        Ok(Self { json, frames: 0 })
+    }
+}
+
+fn frame_json(index: usize, tag: &Tag) -> Option<Value> {
+    let (stream, cts, keyframe) = match &tag.data {
+        _ if tag.data.is_sequence_header() => return None,
+        TagData::Audio(_) => ("audio", 0, true),
+        TagData::Video(video) => (
+            "video",
+            video.composition_time().unwrap_or(0),
+            video.header.frame_type == VideoFrameType::KeyFrame,
+        ),
+        _ => return None,
+    };
+    let dts = tag.header.timestamp as i64;
+    Some(json!({
+        "index": index,
+        "stream": stream,
+        "dts": dts,
+        "cts": cts,
+        "pts": dts + cts as i64,
+        "keyframe": keyframe,
+        "size": tag.header.data_size,
+    }))
+}
+
+impl Formatter for Timeline {
+    fn header(&mut self, out: &mut dyn Write, file: &FileInfo) -> Result<(), Exception> {
+        self.frames = 0;
+        if self.json {
+            // leave the object open for the frames
+            write!(out, "{{\"file\":{},\"frames\":[", json!(file.path))?;
+        } else {
+            writeln!(out, "{}", COLUMNS.join(","))?;
+        }
+        Ok(())
+    }
+
+    fn pre_tag_size(&mut self, _: &mut dyn Write, _: usize, _: u32) -> Result<(), Exception> {
+        Ok(())
+    }
+
+    fn tag(&mut self, out: &mut dyn Write, index: usize, tag: &Tag) -> Result<(), Exception> {
+        let frame = match frame_json(index, tag) {
+            Some(frame) => frame,
+            None => return Ok(()),
+        };
+        if self.json {
+            if self.frames > 0 {
+                out.write_all(b",")?;
+            }
+            serde_json::to_writer(&mut *out, &frame)?;
+        } else {
+            let row: Vec<String> = COLUMNS
+                .iter()
+                .map(|column| match &frame[*column] {
+                    Value::String(s) => s.clone(),
+                    value => value.to_string(),
+                })
+                .collect();
+            writeln!(out, "{}", row.join(","))?;
+        }
+        self.frames += 1;
+        Ok(())
+    }
+
+    fn stats(&mut self, _: &mut dyn Write, _: &Stats) -> Result<(), Exception> {
+        Err("Statistics can not be exported with the timeline".into())
+    }
+
+    // The rows and frames all have the same fields, warnings go to stderr instead.
+    fn warning(&mut self, _: &mut dyn Write, finding: &Finding) -> Result<(), Exception> {
+        eprintln!("{}", finding);
+        Ok(())
+    }
+
+    fn finish(&mut self, out: &mut dyn Write) -> Result<(), Exception> {
+        if self.json {
+            writeln!(out, "]}}")?;
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,141 @@
+use crate::findings::Finding;
+use crate::reader::{CodecId, SoundFormat, Tag, TagData};
+use crate::time;
+use serde_json::{json, Map, Value};
+use std::sync::mpsc::{sync_channel, SyncSender, TrySendError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, SystemTime};
+
+// Alerts waiting to be posted, more are dropped rather than holding up the dump.
+const WEBHOOK_QUEUE: usize = 64;
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Posts alerts as JSON objects to a webhook URL from a separate thread, so that a slow or
+/// unreachable endpoint never holds up reading a live stream. Every alert has the `event`
+/// (`finding`, `stall` or `codec_change`), the `file`, the UTC `time` and a `message`.
+pub struct Webhook {
+    path: String,
+    alerts: Option<SyncSender<Value>>,
+    thread: Option<JoinHandle<()>>,
+}
+
+impl Webhook {
+    pub fn spawn(url: &str, path: &str) -> Self {
+        let (tx, rx) = sync_channel::<Value>(WEBHOOK_QUEUE);
+        let url = url.to_owned();
+        let thread = thread::spawn(move || {
+            let agent = ureq::AgentBuilder::new().timeout(WEBHOOK_TIMEOUT).build();
+            for alert in rx {
+                let result = agent
+                    .post(&url)
+                    .set("Content-Type", "application/json")
+                    .send_string(&alert.to_string());
+                if let Err(e) = result {
+                    eprintln!("Webhook {}: {}", url, e);
+                }
+            }
+        });
+        Self {
+            path: path.to_owned(),
+            alerts: Some(tx),
+            thread: Some(thread),
+        }
+    }
+
+    fn send(&self, event: &str, message: String, details: Value) {
+        let mut alert = Map::new();
+        alert.insert("event".into(), json!(event));
+        alert.insert("file".into(), json!(self.path));
+        alert.insert("time".into(), json!(time::format_utc(SystemTime::now())));
+        alert.insert("message".into(), json!(message));
+        if let Value::Object(details) = details {
+            alert.extend(details);
+        }
+        if let Some(alerts) = &self.alerts {
+            if let Err(TrySendError::Full(_)) = alerts.try_send(Value::Object(alert)) {
+                eprintln!("Webhook: too many alerts waiting, dropped one: {}", message);
+            }
+        }
+    }
+
+    pub fn finding(&self, finding: &Finding) {
+        self.send(
+            "finding",
+            finding.message.clone(),
+            json!({ "rule": finding.rule.id(), "severity": finding.severity().name() }),
+        );
+    }
+
+    /// No data arrived for `timeout`.
+    pub fn stall(&self, timeout: Duration) {
+        self.send(
+            "stall",
+            format!("No data for {} s", timeout.as_secs()),
+            json!({ "seconds": timeout.as_secs() }),
+        );
+    }
+
+    pub fn codec_change(&self, index: usize, tag: &Tag, change: &CodecChange) {
+        self.send(
+            "codec_change",
+            format!(
+                "The {} codec changed from {} to {} at tag {}",
+                change.stream, change.from, change.to, index
+            ),
+            json!({
+                "stream": change.stream,
+                "from": change.from,
+                "to": change.to,
+                "index": index,
+                "timestamp": tag.header.timestamp,
+            }),
+        );
+    }
+
+    /// Waits until the alerts sent so far have been posted.
+    pub fn finish(mut self) {
+        self.alerts.take();
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+pub struct CodecChange {
+    pub stream: &'static str,
+    pub from: String,
+    pub to: String,
+}
+
+/// Tracks the SoundFormat and CodecID of the streams to tell when one of them changes.
+#[derive(Default)]
+pub struct Codecs {
+    audio: Option<SoundFormat>,
+    video: Option<CodecId>,
+}
+
+impl Codecs {
+    pub fn push(&mut self, tag: &Tag) -> Option<CodecChange> {
+        match &tag.data {
+            TagData::Audio(audio) => {
+                let format = audio.header.sound_format;
+                let previous = self.audio.replace(format)?;
+                (previous != format).then(|| CodecChange {
+                    stream: "audio",
+                    from: format!("{:?}", previous),
+                    to: format!("{:?}", format),
+                })
+            }
+            TagData::Video(video) => {
+                let codec = video.header.codec_id;
+                let previous = self.video.replace(codec)?;
+                (previous != codec).then(|| CodecChange {
+                    stream: "video",
+                    from: format!("{:?}", previous),
+                    to: format!("{:?}", codec),
+                })
+            }
+            _ => None,
+        }
+    }
+}
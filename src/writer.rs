@@ -0,0 +1,245 @@
+use crate::reader::{AudioData, BodyDecoder, Header, Tag, TagData, TagHeader, TagType, VideoData};
+use crate::Exception;
+use bytes::{BufMut, BytesMut};
+use std::convert::TryFrom;
+use tokio::io::{AsyncWrite, AsyncWriteExt};
+
+/// The largest DataSize a tag header can hold, a UI24.
+pub const MAX_DATA_SIZE: u32 = 0xFF_FFFF;
+
+/// The size of the tag header preceding every tag body.
+pub const TAG_HEADER_SIZE: usize = BodyDecoder::TAG_HEADER_SIZE;
+
+impl Header {
+    pub fn encode(&self, dst: &mut BytesMut) {
+        dst.put_slice(b"FLV");
+        dst.put_u8(self.version);
+        dst.put_u8(self.type_);
+        dst.put_u32(self.offset);
+        // The bytes up to DataOffset as read, padded with zeros.
+        dst.put_slice(&self.extra);
+        for _ in 9 + self.extra.len() as u32..self.offset {
+            dst.put_u8(0);
+        }
+    }
+}
+
+impl TagHeader {
+    /// Encodes the 11 byte tag header, StreamID is always 0. Fails if DataSize does not fit in
+    /// 24 bits.
+    pub fn encode(&self, dst: &mut BytesMut) -> Result<(), Exception> {
+        if self.data_size > MAX_DATA_SIZE {
+            return Err(format!(
+                "DataSize {} exceeds the maximum of {} bytes",
+                self.data_size, MAX_DATA_SIZE
+            )
+            .into());
+        }
+        let tag_type = match self.tag_type {
+            TagType::Audio => 8,
+            TagType::Video => 9,
+            TagType::Script => 18,
+            TagType::Reserved(n) => n,
+        };
+        let [t0, t1, t2, t3] = self.timestamp.to_be_bytes();
+        let [_, s1, s2, s3] = self.data_size.to_be_bytes();
+        dst.put_slice(&[tag_type, s1, s2, s3, t1, t2, t3, t0, 0, 0, 0]);
+        Ok(())
+    }
+}
+
+impl AudioData {
+    /// Encodes the audio tag body: the SoundFormat byte followed by the data.
+    pub fn encode(&self, dst: &mut BytesMut) {
+        dst.put_u8(self.header.into());
+        dst.put_slice(&self.data);
+    }
+}
+
+impl VideoData {
+    /// Encodes the video tag body: the FrameType/CodecID byte followed by the data.
+    pub fn encode(&self, dst: &mut BytesMut) {
+        dst.put_u8(self.header.into());
+        dst.put_slice(&self.data);
+    }
+}
+
+impl TagData {
+    /// Size of the encoded tag body.
+    pub fn encoded_len(&self) -> usize {
+        match self {
+            TagData::Audio(audio) => audio.data.len() + 1,
+            TagData::Video(video) => video.data.len() + 1,
+            TagData::Script(script) => script.raw.len(),
+            TagData::Reserved(data) => data.len(),
+            TagData::Invalid(invalid) => invalid.data.len(),
+        }
+    }
+
+    /// Encodes the tag body, i.e. everything following the 11 byte tag header.
+    pub fn encode(&self, dst: &mut BytesMut) {
+        match self {
+            TagData::Audio(audio) => audio.encode(dst),
+            TagData::Video(video) => video.encode(dst),
+            TagData::Script(script) => dst.put_slice(&script.raw),
+            TagData::Reserved(data) => dst.put_slice(data),
+            TagData::Invalid(invalid) => dst.put_slice(&invalid.data),
+        }
+    }
+}
+
+impl Tag {
+    /// Encodes the tag and returns its size, which is what the following PreviousTagSize holds.
+    /// DataSize is recomputed from the payload rather than taken from the header, bodies larger
+    /// than `MAX_DATA_SIZE` are rejected.
+    pub fn encode(&self, dst: &mut BytesMut) -> Result<u32, Exception> {
+        let len = self.data.encoded_len();
+        let data_size = u32::try_from(len)
+            .ok()
+            .filter(|&size| size <= MAX_DATA_SIZE)
+            .ok_or_else(|| {
+                format!(
+                    "The tag body of {} bytes exceeds the maximum DataSize of {} bytes",
+                    len, MAX_DATA_SIZE
+                )
+            })?;
+
+        dst.reserve(TAG_HEADER_SIZE + len);
+        let header = TagHeader {
+            data_size,
+            ..self.header.clone()
+        };
+        header.encode(dst)?;
+        self.data.encode(dst);
+
+        Ok(TAG_HEADER_SIZE as u32 + data_size)
+    }
+}
+
+/// Writes a FLV file tag by tag, keeping track of the PreviousTagSize fields.
+pub struct FlvWriter<W> {
+    inner: W,
+    buf: BytesMut,
+    pre_tag_size: u32,
+}
+
+impl<W: AsyncWrite + Unpin> FlvWriter<W> {
+    pub async fn new(inner: W, header: &Header) -> Result<Self, Exception> {
+        let mut writer = Self {
+            inner,
+            buf: BytesMut::new(),
+            pre_tag_size: 0,
+        };
+        header.encode(&mut writer.buf);
+        writer.flush_buf().await?;
+        Ok(writer)
+    }
+
+    pub async fn write_tag(&mut self, tag: &Tag) -> Result<(), Exception> {
+        self.buf.put_u32(self.pre_tag_size);
+        let size = tag.encode(&mut self.buf);
+        self.pre_tag_size = match size {
+            Ok(size) => size,
+            Err(e) => {
+                // nothing of the tag is written, not even the PreviousTagSize before it
+                self.buf.clear();
+                return Err(e);
+            }
+        };
+        self.flush_buf().await
+    }
+
+    /// Writes the tag as it was read if its original bytes were kept, see
+    /// `ParseOptions::keep_raw`, and encodes it otherwise.
+    pub async fn copy_tag(&mut self, tag: &Tag) -> Result<(), Exception> {
+        let raw = match &tag.raw {
+            Some(raw) => raw,
+            None => return self.write_tag(tag).await,
+        };
+        self.buf.put_u32(self.pre_tag_size);
+        self.buf.extend_from_slice(raw);
+        self.pre_tag_size = raw.len() as u32;
+        self.flush_buf().await
+    }
+
+    /// Makes the next PreviousTagSize wrong, for producing files with that defect.
+    pub fn corrupt_pre_tag_size(&mut self) {
+        self.pre_tag_size = self.pre_tag_size.wrapping_add(1);
+    }
+
+    pub async fn finish(mut self) -> Result<W, Exception> {
+        self.buf.put_u32(self.pre_tag_size);
+        self.flush_buf().await?;
+        self.inner.flush().await?;
+        Ok(self.inner)
+    }
+
+    async fn flush_buf(&mut self) -> Result<(), Exception> {
+        self.inner.write_all(&self.buf).await?;
+        self.buf.clear();
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::reader::{CodecId, VideoDataHeader, VideoFrameType};
+    use bytes::Bytes;
+
+    fn video_tag(body_len: usize) -> Tag {
+        Tag {
+            header: TagHeader {
+                tag_type: TagType::Video,
+                data_size: 0,
+                timestamp: 0x0102_0304,
+                offset: 0,
+            },
+            data: TagData::Video(VideoData {
+                header: VideoDataHeader {
+                    frame_type: VideoFrameType::KeyFrame,
+                    codec_id: CodecId::AVC,
+                },
+                data: Bytes::from(vec![0; body_len - 1]),
+            }),
+            raw: None,
+            received: None,
+        }
+    }
+
+    #[test]
+    fn largest_data_size() {
+        let mut buf = BytesMut::new();
+        let size = video_tag(MAX_DATA_SIZE as usize).encode(&mut buf).unwrap();
+        assert_eq!(size, 11 + MAX_DATA_SIZE);
+        assert_eq!(&buf[..11], &[9, 0xff, 0xff, 0xff, 2, 3, 4, 1, 0, 0, 0]);
+    }
+
+    #[test]
+    fn data_size_overflow() {
+        let tag = video_tag(MAX_DATA_SIZE as usize + 1);
+        let mut buf = BytesMut::new();
+        assert!(tag.encode(&mut buf).is_err());
+        assert!(buf.is_empty());
+
+        let header = TagHeader {
+            data_size: MAX_DATA_SIZE + 1,
+            ..tag.header.clone()
+        };
+        assert!(header.encode(&mut buf).is_err());
+
+        let header = Header {
+            version: 1,
+            type_: 5,
+            offset: 9,
+            extra: Bytes::new(),
+        };
+        let written = futures::executor::block_on(async {
+            let mut writer = FlvWriter::new(Vec::new(), &header).await.unwrap();
+            assert!(writer.write_tag(&tag).await.is_err());
+            writer.finish().await.unwrap()
+        });
+        // the header and the final PreviousTagSize only
+        assert_eq!(written.len(), 9 + 4);
+    }
+}